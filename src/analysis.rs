@@ -0,0 +1,614 @@
+use std;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use calibration::Calibration;
+use cancel::CancelToken;
+use grid::Grid9;
+use limits::Limits;
+use logic::{LogicalSolver, SolveStep, Technique};
+use puzzle::{self, GridString, Puzzle};
+use sudoku::{Solver, SquareValue, State, StartState, ALL_SQUARES, BOX_SIZE, GRID_SIZE, SQUARE_COUNT};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert
+}
+
+impl Difficulty {
+
+    pub fn all() -> Vec<Difficulty> {
+        vec![Difficulty::Easy, Difficulty::Medium, Difficulty::Hard, Difficulty::Expert]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+            Difficulty::Expert => "expert"
+        }
+    }
+}
+
+impl std::fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// The name, in any case.
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(name : &str) -> Result<Difficulty, String> {
+        Difficulty::all().into_iter()
+                         .find(|d| d.name().eq_ignore_ascii_case(name.trim()))
+                         .ok_or_else(|| format!("Unknown difficulty {}, expected one of {}", name,
+                                                Difficulty::all().iter().map(|d| d.name()).collect::<Vec<&str>>().join(", ")))
+    }
+}
+
+// Serialized by name, like the save format.
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Difficulty {
+    fn serialize<S: ::serde::Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for Difficulty {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer : D) -> Result<Difficulty, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(::serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    pub distribution : [usize; 10],
+    pub unsolved : usize,
+    pub information : f64
+}
+
+// Number of squares per candidate count, indexed 0..=9 (index 0 is always empty after propagation).
+pub fn candidate_distribution(state : &State) -> [usize; 10] {
+    let mut distribution = [0; 10];
+    for s in state.config().squares() {
+        let n = state.candidates(s).len();
+        if n < distribution.len() {
+            distribution[n] += 1;
+        }
+    }
+    distribution
+}
+
+// log2 of the candidate counts of a classic grid, written out: `f64::log2` comes from the
+// platform's maths library and may round differently, which would tip seeded generation.
+const LOG2 : [f64; 10] = [0.0, 0.0, 1.0, 1.584962500721156, 2.0, 2.321928094887362, 2.584962500721156,
+                          2.807354922057604, 3.0, 3.169925001442312];
+
+// Bits still needed to pin down the grid, i.e. log2 of the product of all candidate counts.
+// A solved grid has 0 bits left, an empty grid 81 * log2(9).
+pub fn information_remaining(state : &State) -> f64 {
+    state.config().squares().iter()
+                            .map(|s| state.candidates(s).len())
+                            .filter(|&n| n > 1)
+                            .map(|n| LOG2.get(n).cloned().unwrap_or_else(|| (n as f64).log2()))
+                            .fold(0.0, |sum, bits| sum + bits)
+}
+
+// The squares of the row, column and box through square i, in A1..I9 indices.
+fn unit_indices(i : usize) -> [[usize; GRID_SIZE]; 3] {
+    let (r, c) = (i / GRID_SIZE, i % GRID_SIZE);
+    let (br, bc) = (r - r % BOX_SIZE, c - c % BOX_SIZE);
+    let mut units = [[0; GRID_SIZE]; 3];
+    let [row, column, square] = &mut units;
+    for (k, ((rk, ck), bk)) in row.iter_mut().zip(column.iter_mut()).zip(square.iter_mut()).enumerate() {
+        *rk = r * GRID_SIZE + k;
+        *ck = k * GRID_SIZE + c;
+        *bk = (br + k / BOX_SIZE) * GRID_SIZE + bc + k % BOX_SIZE;
+    }
+    units
+}
+
+// Candidate masks (bit v - 1 for v) in A1..I9 order with every given ruled out of its peers and
+// the start state's eliminations applied: a player's first pencilmarks. This is one round, not
+// the chains of singles `Grid9::from_start_state` follows, which fill in easy puzzles outright
+// and would leave nothing to tell the squares apart. None when a square has nothing left.
+fn pencilmarks(start_state : &StartState) -> Option<[u16; SQUARE_COUNT]> {
+    let values = puzzle::to_values(start_state);
+    let mut masks = [(1 << GRID_SIZE) - 1; SQUARE_COUNT];
+    for (i, &v) in values.iter().enumerate().filter(|&(_, &v)| v != 0) {
+        masks[i] = 1 << (v - 1);
+        for unit in &unit_indices(i) {
+            for &j in unit.iter().filter(|&&j| j != i) {
+                masks[j] &= ! (1 << (v - 1));
+            }
+        }
+    }
+    for &(s, v) in start_state.eliminations() {
+        if let Some(i) = ALL_SQUARES.iter().position(|&square| square == s) {
+            if values[i] == 0 && v >= 1 && v as usize <= GRID_SIZE {
+                masks[i] &= ! (1 << (v - 1));
+            }
+        }
+    }
+    if masks.contains(&0) { None } else { Some(masks) }
+}
+
+// Candidates left per square in A1..I9 order, 1 for givens, after the first round of
+// propagation described at `pencilmarks`. None when the givens leave a square without any.
+pub fn candidate_counts(start_state : &StartState) -> Option<[u8; SQUARE_COUNT]> {
+    let masks = pencilmarks(start_state)?;
+    let mut counts = [0; SQUARE_COUNT];
+    for (count, mask) in counts.iter_mut().zip(masks.iter()) {
+        *count = mask.count_ones() as u8;
+    }
+    Some(counts)
+}
+
+// How constrained each open square is, from 0 to 1 in A1..I9 order, for colouring a grid to lead
+// beginners to squares worth a look: 1 where a single fills it (one candidate left, or a digit
+// with no other place in one of its units), falling to 1/9 with nine candidates and nothing
+// forced. Givens are 0, and so is every square of a start state whose givens contradict.
+pub fn constraint_heatmap(start_state : &StartState) -> [f32; SQUARE_COUNT] {
+    let mut heat = [0.0; SQUARE_COUNT];
+    let masks = match pencilmarks(start_state) {
+        Some(masks) => masks,
+        None => return heat
+    };
+    let values = puzzle::to_values(start_state);
+    for i in (0..SQUARE_COUNT).filter(|&i| values[i] == 0) {
+        let units = unit_indices(i);
+        let hidden_single = (0..GRID_SIZE).filter(|&d| masks[i] & (1 << d) != 0).any(|d| {
+            units.iter().any(|unit| unit.iter().filter(|&&j| masks[j] & (1 << d) != 0).count() == 1)
+        });
+        let count = masks[i].count_ones() as f32;
+        heat[i] = if hidden_single { 1.0 } else { (GRID_SIZE as f32 + 1.0 - count) / GRID_SIZE as f32 };
+    }
+    heat
+}
+
+// How near the clue pattern is to symmetric, from 0 to 1: for the rotations by a quarter and a
+// half turn and the mirrors in the middle lines and diagonals, the share of clues whose image is
+// a clue too, and the best of those. 1 for a fully symmetric pattern (and for no clues at all);
+// publishers turn away puzzles that score low as looking lopsided, however well they solve.
+pub fn symmetry_score(start_state : &StartState) -> f32 {
+    let values = puzzle::to_values(start_state);
+    let clues : Vec<(usize, usize)> = (0..SQUARE_COUNT).filter(|&i| values[i] != 0).map(|i| (i / GRID_SIZE, i % GRID_SIZE)).collect();
+    if clues.is_empty() {
+        return 1.0;
+    }
+    let last = GRID_SIZE - 1;
+    let images : [&dyn Fn(usize, usize) -> (usize, usize); 7] = [&|r, c| (last - r, last - c),
+                                                                  &|r, c| (c, last - r),
+                                                                  &|r, c| (last - c, r),
+                                                                  &|r, c| (r, last - c),
+                                                                  &|r, c| (last - r, c),
+                                                                  &|r, c| (c, r),
+                                                                  &|r, c| (last - c, last - r)];
+    let best = images.iter().map(|image| {
+        clues.iter().filter(|&&(r, c)| {
+            let (r2, c2) = image(r, c);
+            values[r2 * GRID_SIZE + c2] != 0
+        }).count()
+    }).max().unwrap_or(0);
+    best as f32 / clues.len() as f32
+}
+
+pub fn analyze(solver : &Solver, start_state : StartState) -> Result<Metrics, String> {
+    let state = solver.propagate(start_state)?;
+    let distribution = candidate_distribution(&state);
+    Ok(Metrics {
+        distribution,
+        unsolved : distribution[2..].iter().sum(),
+        information : information_remaining(&state)
+    })
+}
+
+// Cheap estimate based on how much propagation alone leaves undecided. Only generation uses it,
+// to skip rating digs propagation alone solves; the levels reported anywhere come from
+// `Calibration::rate`, which this estimate need not agree with above easy.
+pub(crate) fn fast_difficulty(metrics : &Metrics) -> Difficulty {
+    if metrics.unsolved == 0 {
+        Difficulty::Easy
+    } else if metrics.information < 50.0 {
+        Difficulty::Medium
+    } else if metrics.information < 110.0 {
+        Difficulty::Hard
+    } else {
+        Difficulty::Expert
+    }
+}
+
+// Continuous rating on top of the technique rater. The hardest technique needed sets the whole
+// part, the weight of all steps the fraction, so puzzles needing the same technique still order
+// by how much work they take.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Score {
+    pub value : f64,
+    pub hardest : Option<Technique>,
+    pub steps : usize
+}
+
+pub fn technique_weight(technique : Technique) -> f64 {
+    match technique {
+        Technique::NakedSingle => 1.0,
+        Technique::HiddenSingle => 1.2,
+        Technique::LockedCandidates => 2.6,
+        Technique::NakedPair => 3.0,
+        Technique::HiddenPair => 3.4,
+        Technique::XWing => 4.2,
+        Technique::Reveal => 8.0
+    }
+}
+
+pub fn path_score(path : &[SolveStep]) -> Score {
+    weighted_score(path, &technique_weight)
+}
+
+fn weighted_score(path : &[SolveStep], weight : &dyn Fn(Technique) -> f64) -> Score {
+    let hardest = path.iter().map(|step| step.technique).max_by(|a, b| weight(*a).total_cmp(&weight(*b)));
+    let total = path.iter().fold(0.0, |sum, step| sum + weight(step.technique));
+    Score {
+        value : hardest.map_or(0.0, weight) + total / (total + 100.0),
+        hardest,
+        steps : path.len()
+    }
+}
+
+pub fn score(logical_solver : &LogicalSolver, start_state : &StartState) -> Result<Score, String> {
+    logical_solver.full_path(start_state).map(|path| path_score(&path))
+}
+
+// Scores a solve path, so a publisher with a house scale can rate on the same step engine. The
+// built-in model is `WeightedModel::default()`, i.e. `path_score`; levels for another model come
+// from fitting a `Calibration` to reference puzzles scored with it.
+pub trait DifficultyModel {
+    fn score(&self, path : &[SolveStep]) -> f64;
+}
+
+// `path_score` with weights of one's own per technique, the built-in ones for the rest.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WeightedModel {
+    pub weights : BTreeMap<Technique, f64>
+}
+
+impl WeightedModel {
+
+    pub fn with_weight(mut self, technique : Technique, weight : f64) -> WeightedModel {
+        self.weights.insert(technique, weight);
+        self
+    }
+
+    pub fn weight(&self, technique : Technique) -> f64 {
+        self.weights.get(&technique).cloned().unwrap_or_else(|| technique_weight(technique))
+    }
+
+    pub fn path_score(&self, path : &[SolveStep]) -> Score {
+        weighted_score(path, &|technique| self.weight(technique))
+    }
+}
+
+impl DifficultyModel for WeightedModel {
+    fn score(&self, path : &[SolveStep]) -> f64 {
+        self.path_score(path).value
+    }
+}
+
+impl<F : Fn(&[SolveStep]) -> f64> DifficultyModel for F {
+    fn score(&self, path : &[SolveStep]) -> f64 {
+        self(path)
+    }
+}
+
+pub fn score_with(model : &dyn DifficultyModel, logical_solver : &LogicalSolver, start_state : &StartState) -> Result<f64, String> {
+    logical_solver.full_path(start_state).map(|path| model.score(&path))
+}
+
+// Easiest first. Ties on score fall back to the grid string so the order is the same every run;
+// puzzles without a solution have no score and are left out.
+pub fn rate_and_sort(puzzles : &[Puzzle]) -> Vec<(Puzzle, Score)> {
+    let logical_solver = LogicalSolver::new();
+    let mut rated : Vec<(Puzzle, Score)> = puzzles.iter()
+                                                  .filter_map(|p| score(&logical_solver, p.start_state()).ok().map(|s| (p.clone(), s)))
+                                                  .collect();
+    rated.sort_by(|a, b| a.1.value.total_cmp(&b.1.value).then_with(|| a.0.to_grid_string().cmp(&b.0.to_grid_string())));
+    rated
+}
+
+// Up to `per_level` puzzles of every level, picked at random but the same for the same seed, for
+// balanced playtesting sets: indices with their level, easiest level first and in the order
+// drawn. `puzzle` gives the i:th of `count` puzzles (e.g. from a `Pack`); they are rated in a
+// seeded random order until every level is full, so a big collection is only rated as far as
+// its rarest level needs. Puzzles that don't rate within the default limits are passed over.
+pub fn sample_stratified<F : Fn(usize) -> Option<Puzzle>>(count : usize, puzzle : F, seed : u64, per_level : usize) -> Vec<(usize, Difficulty)> {
+    let mut order : Vec<usize> = (0..count).collect();
+    // xorshift from the seed, shuffling the whole order up front
+    let mut x = seed.wrapping_mul(0x9e3779b97f4a7c15) | 1;
+    for i in (1..order.len()).rev() {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        order.swap(i, (x % (i as u64 + 1)) as usize);
+    }
+    let logical_solver = LogicalSolver::new();
+    let timeout = Limits::default().max_solve_time;
+    let mut levels : BTreeMap<Difficulty, Vec<usize>> = Difficulty::all().into_iter().map(|d| (d, Vec::new())).collect();
+    for i in order {
+        if levels.values().all(|picked| picked.len() >= per_level) {
+            break;
+        }
+        let level = match puzzle(i).map(|p| rate_within(&logical_solver, &p, timeout).rating) {
+            Some(Rating::Rated(_, level)) => level,
+            _ => continue
+        };
+        let picked = levels.get_mut(&level).unwrap();
+        if picked.len() < per_level {
+            picked.push(i);
+        }
+    }
+    levels.into_iter().flat_map(|(level, picked)| picked.into_iter().map(move |i| (i, level))).collect()
+}
+
+// How rating one puzzle of a batch went.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rating {
+    Rated(Score, Difficulty),
+    // no solution, or givens that contradict each other
+    Failed(String),
+    TimedOut,
+    // stopped by its `CancelToken` before it was done
+    Cancelled
+}
+
+#[derive(Clone, Debug)]
+pub struct RatedPuzzle {
+    pub rating : Rating,
+    pub elapsed : Duration
+}
+
+impl RatedPuzzle {
+    // The level, or "unrated", "timeout" and "cancelled" for the puzzles that have none.
+    pub fn bucket(&self) -> &'static str {
+        match self.rating {
+            Rating::Rated(_, level) => level.name(),
+            Rating::Failed(_) => "unrated",
+            Rating::TimedOut => "timeout",
+            Rating::Cancelled => "cancelled"
+        }
+    }
+}
+
+// Rates one puzzle as `rate_batch` does, for callers that rate as they go.
+pub fn rate_within(logical_solver : &LogicalSolver, puzzle : &Puzzle, timeout : Duration) -> RatedPuzzle {
+    rate_cancellable(logical_solver, puzzle, timeout, &CancelToken::new())
+}
+
+// `rate_within` that also stops when `cancel` does, as `Rating::Cancelled`; a deadline of the
+// token's own that comes first counts as running out of time.
+pub fn rate_cancellable(logical_solver : &LogicalSolver, puzzle : &Puzzle, timeout : Duration, cancel : &CancelToken) -> RatedPuzzle {
+    let started = Instant::now();
+    let cancel = cancel.clone().with_timeout(timeout);
+    let rating = match logical_solver.full_path_cancellable(puzzle.start_state(), &cancel) {
+        Ok(path) => {
+            let score = path_score(&path);
+            Rating::Rated(score, Calibration::default().level(score.value))
+        },
+        Err(_) if cancel.is_cancelled() => Rating::Cancelled,
+        Err(_) if cancel.is_expired() => Rating::TimedOut,
+        Err(e) => Rating::Failed(e)
+    };
+    RatedPuzzle {
+        rating,
+        elapsed : started.elapsed()
+    }
+}
+
+// Rates every puzzle, each given at most `timeout`, with results in input order. A batch's time
+// goes to its few monsters, so with the "parallel" feature the puzzles are spread over rayon's
+// pool, whose work stealing keeps the other threads going while one grinds; either way a puzzle
+// out of time ends up in the timeout bucket instead of holding up the rest.
+pub fn rate_batch(puzzles : &[Puzzle], timeout : Duration) -> Vec<RatedPuzzle> {
+    rate_batch_cancellable(puzzles, timeout, &CancelToken::new())
+}
+
+// `rate_batch` under a `CancelToken`: once it is cancelled the puzzle being rated and every one
+// after it come back as `Rating::Cancelled` straight away, still one result per puzzle.
+#[cfg(feature = "parallel")]
+pub fn rate_batch_cancellable(puzzles : &[Puzzle], timeout : Duration, cancel : &CancelToken) -> Vec<RatedPuzzle> {
+    use rayon::prelude::*;
+    puzzles.par_iter()
+           .map_init(LogicalSolver::new, |logical_solver, puzzle| rate_cancellable(logical_solver, puzzle, timeout, cancel))
+           .collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn rate_batch_cancellable(puzzles : &[Puzzle], timeout : Duration, cancel : &CancelToken) -> Vec<RatedPuzzle> {
+    let logical_solver = LogicalSolver::new();
+    puzzles.iter().map(|puzzle| rate_cancellable(&logical_solver, puzzle, timeout, cancel)).collect()
+}
+
+// Steps of each technique in a solve path.
+pub fn technique_counts(path : &[SolveStep]) -> BTreeMap<Technique, usize> {
+    let mut counts = BTreeMap::new();
+    for step in path {
+        *counts.entry(step.technique).or_insert(0) += 1;
+    }
+    counts
+}
+
+// A move a puzzle can't be solved without. The easier techniques are stuck after the first
+// `position` steps of the path, and `step` is the technique's move there. `pencilmarks` is the
+// grid the move is made on, as a 729 character pencilmark grid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrucialStep {
+    pub position : usize,
+    pub step : SolveStep,
+    pub pencilmarks : GridString
+}
+
+// Where `technique` first becomes indispensable, for tutorials that need a puzzle where it is the
+// move to make. The puzzle must have a unique solution that the techniques up to and including
+// this one reach. None when the easier ones solve it alone, when it needs a harder technique
+// somewhere, or for `Reveal`. The solver takes the easiest step every time, so the path before
+// the first step of the technique is the one the easier techniques take until they get stuck.
+pub fn crucial_step(start_state : &StartState, technique : Technique) -> Option<CrucialStep> {
+    let techniques = Technique::all();
+    let upto = techniques.iter().position(|&t| t == technique)?;
+    let logical_solver = LogicalSolver::with_techniques(&techniques[..upto + 1]);
+    let path = logical_solver.certify(start_state)?;
+    let position = path.iter().position(|step| step.technique == technique)?;
+    let mut state = logical_solver.start(start_state).ok()?;
+    for step in &path[..position] {
+        step.apply(&mut state);
+    }
+    let mut pencilmarks = String::with_capacity(SQUARE_COUNT * GRID_SIZE);
+    for square in ALL_SQUARES.iter() {
+        let candidates = state.candidates(square);
+        for d in 1..GRID_SIZE as SquareValue + 1 {
+            pencilmarks.push(if candidates.contains(&d) { std::char::from_digit(d, 10).unwrap() } else { '.' });
+        }
+    }
+    Some(CrucialStep {
+        position,
+        step : path[position].clone(),
+        pencilmarks
+    })
+}
+
+// How often a corpus needs each technique. `needed` counts puzzles using a technique at least
+// once, `steps` every step of it and `hardest` the puzzles where it is the hardest technique used.
+// A puzzle needing `Reveal` got stuck with the enabled techniques.
+#[derive(Clone, Debug, Default)]
+pub struct TechniqueFrequency {
+    pub puzzles : usize,
+    pub failed : usize,
+    pub needed : BTreeMap<Technique, usize>,
+    pub steps : BTreeMap<Technique, usize>,
+    pub hardest : BTreeMap<Technique, usize>
+}
+
+impl TechniqueFrequency {
+
+    pub fn add(&mut self, path : &[SolveStep]) {
+        self.puzzles += 1;
+        for (technique, n) in technique_counts(path) {
+            *self.needed.entry(technique).or_insert(0) += 1;
+            *self.steps.entry(technique).or_insert(0) += n;
+        }
+        if let Some(hardest) = path_score(path).hardest {
+            *self.hardest.entry(hardest).or_insert(0) += 1;
+        }
+    }
+
+    pub fn add_failure(&mut self) {
+        self.puzzles += 1;
+        self.failed += 1;
+    }
+
+    // Share of the analysed puzzles that needed `technique`, 0 to 1.
+    pub fn share(&self, technique : Technique) -> f64 {
+        let analysed = self.puzzles - self.failed;
+        if analysed == 0 {
+            return 0.0;
+        }
+        *self.needed.get(&technique).unwrap_or(&0) as f64 / analysed as f64
+    }
+}
+
+impl std::fmt::Display for TechniqueFrequency {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "puzzles: {} (failed: {})", self.puzzles, self.failed)?;
+        writeln!(f, "{:<18} {:>8} {:>7} {:>8} {:>8}", "technique", "puzzles", "share", "steps", "hardest")?;
+        for technique in Technique::all().into_iter().chain(Some(Technique::Reveal)) {
+            writeln!(f, "{:<18} {:>8} {:>6.1}% {:>8} {:>8}", technique.id(), self.needed.get(&technique).unwrap_or(&0),
+                     self.share(technique) * 100.0, self.steps.get(&technique).unwrap_or(&0),
+                     self.hardest.get(&technique).unwrap_or(&0))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchStats {
+    pub puzzles : usize,
+    pub failed : usize,
+    // solved by naked and hidden singles alone, without any search
+    pub singles_only : usize,
+    pub clue_heatmap : [usize; 81],
+    pub given_digits : [usize; 10],
+    pub clue_counts : BTreeMap<usize, usize>,
+    pub difficulty : BTreeMap<Difficulty, usize>
+}
+
+pub fn batch_stats(solver : &Solver, puzzles : &[StartState]) -> BatchStats {
+    let squares = solver.config().squares();
+    let logical_solver = LogicalSolver::new();
+    let calibration = Calibration::default();
+    let mut stats = BatchStats {
+        puzzles : puzzles.len(),
+        failed : 0,
+        singles_only : 0,
+        clue_heatmap : [0; 81],
+        given_digits : [0; 10],
+        clue_counts : BTreeMap::new(),
+        difficulty : BTreeMap::new()
+    };
+    for puzzle in puzzles {
+        let mut clues = 0;
+        for &(s, v) in puzzle.iter().filter(|&&(_, v)| v != 0) {
+            if let Some(i) = squares.iter().position(|&s2| s2 == s) {
+                stats.clue_heatmap[i] += 1;
+            }
+            if (v as usize) < stats.given_digits.len() {
+                stats.given_digits[v as usize] += 1;
+            }
+            clues += 1;
+        }
+        *stats.clue_counts.entry(clues).or_insert(0) += 1;
+        if solved_by_singles(puzzle) {
+            stats.singles_only += 1;
+        }
+        match calibration.rate(&logical_solver, puzzle) {
+            Ok(difficulty) => *stats.difficulty.entry(difficulty).or_insert(0) += 1,
+            Err(_) => stats.failed += 1
+        }
+    }
+    stats
+}
+
+// True when repeated naked and hidden singles fill the whole grid, i.e. a solve never searches.
+pub fn solved_by_singles(start_state : &StartState) -> bool {
+    Grid9::from_start_state(start_state).is_some_and(|grid| grid.is_solved())
+}
+
+impl std::fmt::Display for BatchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "puzzles: {} (failed: {})", self.puzzles, self.failed)?;
+        writeln!(f, "solved by singles alone: {}", self.singles_only)?;
+        writeln!(f, "clue heatmap:")?;
+        for row in self.clue_heatmap.chunks(9) {
+            let cells : Vec<String> = row.iter().map(|n| format!("{:5}", n)).collect();
+            writeln!(f, "{}", cells.join(""))?;
+        }
+        writeln!(f, "given digits:")?;
+        for d in 1..10 {
+            writeln!(f, "  {}: {}", d, self.given_digits[d])?;
+        }
+        writeln!(f, "clue counts:")?;
+        for (clues, n) in &self.clue_counts {
+            writeln!(f, "  {}: {}", clues, n)?;
+        }
+        writeln!(f, "difficulty:")?;
+        for (difficulty, n) in &self.difficulty {
+            writeln!(f, "  {:?}: {}", difficulty, n)?;
+        }
+        Ok(())
+    }
+}
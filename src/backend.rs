@@ -0,0 +1,169 @@
+use std::time::Instant;
+use dlx::Dlx;
+use json::Json;
+use grid::Grid9;
+use sudoku::{Config, Solver, StartState};
+
+pub struct SolveReport {
+    pub solution : Option<StartState>,
+    pub guesses : usize
+}
+
+pub trait Backend {
+    fn name(&self) -> &'static str;
+    fn solve(&self, start_state : &StartState) -> SolveReport;
+}
+
+// The HashMap based constraint propagation solver behind `Solver`.
+pub struct PropagateBackend {
+    solver : Solver
+}
+
+impl Backend for PropagateBackend {
+    fn name(&self) -> &'static str {
+        "propagate"
+    }
+
+    fn solve(&self, start_state : &StartState) -> SolveReport {
+        match self.solver.solve_counting(start_state.clone()) {
+            Ok((state, guesses)) => SolveReport {
+                solution : Some(self.solver.config().squares().iter().filter_map(|s| state.value(s).map(|v| (*s, v))).collect()),
+                guesses
+            },
+            Err(_) => SolveReport { solution : None, guesses : 0 }
+        }
+    }
+}
+
+pub struct BitmaskBackend;
+
+impl Backend for BitmaskBackend {
+    fn name(&self) -> &'static str {
+        "bitmask"
+    }
+
+    fn solve(&self, start_state : &StartState) -> SolveReport {
+        match Grid9::from_start_state(start_state).map(|grid| grid.solve_counting()) {
+            Some((solution, guesses)) => SolveReport {
+                solution : solution.map(|grid| grid.to_start_state()),
+                guesses
+            },
+            None => SolveReport { solution : None, guesses : 0 }
+        }
+    }
+}
+
+pub struct DlxBackend;
+
+impl Backend for DlxBackend {
+    fn name(&self) -> &'static str {
+        "dlx"
+    }
+
+    fn solve(&self, start_state : &StartState) -> SolveReport {
+        match Dlx::solve(start_state) {
+            Some((solution, guesses)) => SolveReport { solution : Some(solution), guesses },
+            None => SolveReport { solution : None, guesses : 0 }
+        }
+    }
+}
+
+pub fn available() -> Vec<&'static str> {
+    vec!["propagate", "bitmask", "dlx"]
+}
+
+pub fn backend(name : &str) -> Option<Box<dyn Backend>> {
+    match name {
+        "propagate" => Some(Box::new(PropagateBackend { solver : Solver::new() })),
+        "bitmask" => Some(Box::new(BitmaskBackend)),
+        "dlx" => Some(Box::new(DlxBackend)),
+        _ => None
+    }
+}
+
+// A complete grid agreeing with the givens and holding every digit once per unit.
+pub fn is_valid_solution(config : &Config, start_state : &StartState, solution : &StartState) -> bool {
+    let value = |s| solution.iter().find(|&&(s2, _)| s2 == s).map(|&(_, v)| v);
+    start_state.iter().all(|&(s, v)| v == 0 || value(s) == Some(v))
+        && config.unit_list().iter().all(|u| {
+            let mut digits : Vec<Option<u32>> = u.iter().map(|&s| value(s)).collect();
+            digits.sort();
+            digits == (1..10).map(Some).collect::<Vec<Option<u32>>>()
+        })
+}
+
+#[derive(Clone, Debug)]
+pub struct BenchRow {
+    pub backend : String,
+    pub available : bool,
+    pub solved : usize,
+    pub failed : usize,
+    pub total_ns : u64,
+    pub max_ns : u64,
+    pub guesses : usize
+}
+
+impl BenchRow {
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![("backend", Json::string(&self.backend)),
+                          ("available", Json::Bool(self.available)),
+                          ("solved", Json::Number(self.solved as f64)),
+                          ("failed", Json::Number(self.failed as f64)),
+                          ("total_ns", Json::Number(self.total_ns as f64)),
+                          ("max_ns", Json::Number(self.max_ns as f64)),
+                          ("guesses", Json::Number(self.guesses as f64))])
+    }
+}
+
+pub fn bench(names : &[&str], corpus : &[StartState]) -> Vec<BenchRow> {
+    let config = Config::new();
+    names.iter().map(|&name| {
+        let mut row = BenchRow {
+            backend : name.to_string(),
+            available : false,
+            solved : 0,
+            failed : 0,
+            total_ns : 0,
+            max_ns : 0,
+            guesses : 0
+        };
+        if let Some(b) = backend(name) {
+            row.available = true;
+            for puzzle in corpus {
+                let start = Instant::now();
+                let report = b.solve(puzzle);
+                let elapsed = nanos(start);
+                row.total_ns += elapsed;
+                row.max_ns = row.max_ns.max(elapsed);
+                row.guesses += report.guesses;
+                match report.solution {
+                    Some(ref solution) if is_valid_solution(&config, puzzle, solution) => row.solved += 1,
+                    _ => row.failed += 1
+                }
+            }
+        }
+        row
+    }).collect()
+}
+
+pub fn format_table(rows : &[BenchRow]) -> String {
+    let mut table = format!("{:<10} {:>7} {:>7} {:>12} {:>12} {:>12} {:>9}\n",
+                            "backend", "solved", "failed", "total (s)", "mean (s)", "max (s)", "guesses");
+    for row in rows {
+        if ! row.available {
+            table.push_str(&format!("{:<10} unavailable\n", row.backend));
+            continue;
+        }
+        let n = (row.solved + row.failed).max(1) as f64;
+        table.push_str(&format!("{:<10} {:>7} {:>7} {:>12.6} {:>12.6} {:>12.6} {:>9}\n",
+                                row.backend, row.solved, row.failed,
+                                row.total_ns as f64 / 1e9, row.total_ns as f64 / 1e9 / n, row.max_ns as f64 / 1e9,
+                                row.guesses));
+    }
+    table
+}
+
+fn nanos(start : Instant) -> u64 {
+    let elapsed = start.elapsed();
+    elapsed.as_secs() * 1000000000 + elapsed.subsec_nanos() as u64
+}
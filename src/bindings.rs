@@ -0,0 +1,128 @@
+use std;
+use std::time::Instant;
+use analysis::{self, Difficulty, Score};
+use calibration::Calibration;
+use limits::Limits;
+use logic::LogicalSolver;
+use puzzle::{GridString, Puzzle};
+use sudoku::Solver;
+#[cfg(feature = "generator")]
+use sudoku::Generator;
+
+// Entry points for bindings to other languages, e.g. the pyo3 module in `python`: grids in and
+// out as strings, the default `Limits` on every call, and errors of a kind a binding can map to
+// its own exception types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    // arguments that don't go together or are out of range
+    Argument,
+    Parse,
+    Unsolvable,
+    Timeout,
+    // no puzzle of the requested difficulty within the attempts
+    Generate
+}
+
+impl ErrorKind {
+
+    pub fn all() -> Vec<ErrorKind> {
+        vec![ErrorKind::Argument, ErrorKind::Parse, ErrorKind::Unsolvable, ErrorKind::Timeout, ErrorKind::Generate]
+    }
+
+    // Stable identifier for bindings that pass errors on as data.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ErrorKind::Argument => "argument",
+            ErrorKind::Parse => "parse",
+            ErrorKind::Unsolvable => "unsolvable",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Generate => "generate"
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub kind : ErrorKind,
+    pub message : String
+}
+
+impl Error {
+    pub fn new(kind : ErrorKind, message : String) -> Error {
+        Error {
+            kind,
+            message
+        }
+    }
+}
+
+// Rated generation attempts per `generate` call.
+#[cfg(feature = "generator")]
+const ATTEMPTS : usize = 20;
+
+// A grid within the default `Limits`, as every entry point takes it.
+pub fn parse(grid : &str) -> Result<Puzzle, Error> {
+    Limits::default().check_input(grid).map_err(|e| Error::new(ErrorKind::Argument, e))?;
+    grid.parse().map_err(|e| Error::new(ErrorKind::Parse, e))
+}
+
+// The solution as an 81 character grid.
+pub fn solve(grid : &str) -> Result<GridString, Error> {
+    let puzzle = parse(grid)?;
+    let limits = Limits::default();
+    let started = Instant::now();
+    let solver = Solver::new();
+    match limits.solve(&solver, puzzle.start_state().clone()) {
+        Ok(state) => Ok(solver.config().squares().iter().map(|s| state.value(s).map_or('.', |v| (b'0' + v as u8) as char)).collect()),
+        Err(e) if started.elapsed() >= limits.max_solve_time => Err(Error::new(ErrorKind::Timeout, e)),
+        Err(e) => Err(Error::new(ErrorKind::Unsolvable, e))
+    }
+}
+
+// A unique puzzle: of the difficulty if one is given, otherwise dug at random down to `clues`
+// (17 when None) or as far as it stays unique. A seed repeats the puzzle, see `Generator`.
+#[cfg(feature = "generator")]
+pub fn generate(clues : Option<usize>, difficulty : Option<&str>, seed : Option<u64>) -> Result<GridString, Error> {
+    let generator = match seed {
+        Some(seed) => Generator::new().with_seed(seed),
+        None => Generator::new()
+    };
+    match (clues, difficulty) {
+        (Some(_), Some(_)) => Err(Error::new(ErrorKind::Argument, "Give clues or a difficulty, not both".to_string())),
+        (_, Some(name)) => {
+            let level : Difficulty = name.parse().map_err(|e| Error::new(ErrorKind::Argument, e))?;
+            match generator.generate_rated(level, ATTEMPTS) {
+                Some(result) => Ok(Puzzle::new(result.puzzle).to_grid_string()),
+                None => Err(Error::new(ErrorKind::Generate, format!("No {} puzzle in {} attempts", level.name(), ATTEMPTS)))
+            }
+        },
+        (Some(n), None) if ! (17..=81).contains(&n) => Err(Error::new(ErrorKind::Argument, format!("Clues must be 17 to 81, got {}", n))),
+        (clues, None) => {
+            let mut machine = generator.dig_machine(clues.unwrap_or(17));
+            while machine.step() {}
+            Ok(Puzzle::new(machine.puzzle()).to_grid_string())
+        }
+    }
+}
+
+// The calibrated level and the technique score behind it.
+pub fn rate(grid : &str) -> Result<(Difficulty, Score), Error> {
+    let puzzle = parse(grid)?;
+    let deadline = Instant::now() + Limits::default().max_solve_time;
+    match LogicalSolver::new().full_path_until(puzzle.start_state(), deadline) {
+        Ok(path) => {
+            let score = analysis::path_score(&path);
+            Ok((Calibration::default().level(score.value), score))
+        },
+        Err(e) if Instant::now() >= deadline => Err(Error::new(ErrorKind::Timeout, e)),
+        Err(e) => Err(Error::new(ErrorKind::Unsolvable, e))
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Error {}
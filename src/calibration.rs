@@ -0,0 +1,365 @@
+use std;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use analysis::{self, Difficulty, DifficultyModel, WeightedModel};
+use logic::{LogicalSolver, SolveStep, Technique};
+use puzzle::Puzzle;
+use sudoku::StartState;
+
+// Rated reference puzzles shipped with the crate, see data/calibration.txt.
+const REFERENCE : &str = include_str!("../data/calibration.txt");
+
+// Lowest technique score (`analysis::path_score`) of medium, hard and expert puzzles, fitted on
+// the reference set. The tests below and `check` fail when the reference set or the scoring drifts
+// from these, so a level means the same thing from one release to the next.
+const THRESHOLDS : [f64; 3] = [2.2869, 3.6362, 6.5323];
+
+// Decimal places the fitted thresholds are rounded to.
+const PRECISION : f64 = 10000.0;
+
+// Maps technique scores onto the named levels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Calibration {
+    pub thresholds : [f64; 3]
+}
+
+impl Default for Calibration {
+    fn default() -> Calibration {
+        Calibration {
+            thresholds : THRESHOLDS
+        }
+    }
+}
+
+impl Calibration {
+
+    // Places each threshold in the widest gap between scores that misplaces the fewest reference
+    // puzzles, halfway across it.
+    pub fn fit(scored : &[(f64, Difficulty)]) -> Calibration {
+        let levels = Difficulty::all();
+        let mut scores : Vec<f64> = scored.iter().map(|&(score, _)| score).collect();
+        scores.sort_by(|a, b| a.total_cmp(b));
+        scores.dedup();
+        let mut thresholds = [0.0; 3];
+        for (k, threshold) in thresholds.iter_mut().enumerate() {
+            let upper = levels[k + 1];
+            let mut best : Option<(usize, f64, f64)> = None;
+            for gap in scores.windows(2) {
+                let cut = (gap[0] + gap[1]) / 2.0;
+                let misplaced = scored.iter().filter(|&&(score, level)| (score < cut) == (level >= upper)).count();
+                let width = gap[1] - gap[0];
+                if best.is_none_or(|(fewest, widest, _)| misplaced < fewest || (misplaced == fewest && width > widest)) {
+                    best = Some((misplaced, width, cut));
+                }
+            }
+            *threshold = best.map_or(0.0, |(_, _, cut)| (cut * PRECISION).round() / PRECISION);
+        }
+        Calibration {
+            thresholds
+        }
+    }
+
+    pub fn level(&self, score : f64) -> Difficulty {
+        let levels = Difficulty::all();
+        let above = self.thresholds.iter().filter(|&&threshold| score >= threshold).count();
+        levels[above]
+    }
+
+    pub fn rate(&self, logical_solver : &LogicalSolver, start_state : &StartState) -> Result<Difficulty, String> {
+        analysis::score(logical_solver, start_state).map(|score| self.level(score.value))
+    }
+
+    // Rates with another model's scores; the thresholds have to be fitted to that model, see
+    // `score_reference_with`.
+    pub fn rate_with(&self, model : &dyn DifficultyModel, logical_solver : &LogicalSolver, start_state : &StartState) -> Result<Difficulty, String> {
+        analysis::score_with(model, logical_solver, start_state).map(|score| self.level(score))
+    }
+}
+
+// Estimated human solve time from the techniques a solve path uses: an overhead for taking in the
+// grid plus seconds for every step of each technique. The defaults are rough figures for a
+// practised player; `scaled_to` fits them to times measured on real players, e.g. from replays.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeModel {
+    pub overhead : f64,
+    pub seconds : BTreeMap<Technique, f64>
+}
+
+impl Default for TimeModel {
+    fn default() -> TimeModel {
+        TimeModel {
+            overhead : 30.0,
+            seconds : vec![(Technique::NakedSingle, 4.0), (Technique::HiddenSingle, 5.0), (Technique::LockedCandidates, 20.0),
+                           (Technique::NakedPair, 30.0), (Technique::HiddenPair, 40.0), (Technique::XWing, 60.0),
+                           (Technique::Reveal, 120.0)].into_iter().collect()
+        }
+    }
+}
+
+impl TimeModel {
+
+    pub fn with_overhead(self, seconds : f64) -> TimeModel {
+        TimeModel {
+            overhead : seconds,
+            ..self
+        }
+    }
+
+    pub fn with_seconds(mut self, technique : Technique, seconds : f64) -> TimeModel {
+        self.seconds.insert(technique, seconds);
+        self
+    }
+
+    pub fn estimate(&self, path : &[SolveStep]) -> Duration {
+        let seconds = path.iter().fold(self.overhead, |sum, step| sum + self.seconds.get(&step.technique).cloned().unwrap_or(0.0));
+        Duration::from_millis((seconds.max(0.0) * 1000.0) as u64)
+    }
+
+    pub fn estimate_puzzle(&self, logical_solver : &LogicalSolver, start_state : &StartState) -> Result<Duration, String> {
+        logical_solver.full_path(start_state).map(|path| self.estimate(&path))
+    }
+
+    // The model scaled as a whole to best fit measured times (least squares), keeping the ratios
+    // between techniques; the samples are solve paths with how long a player took.
+    pub fn scaled_to(self, samples : &[(Vec<SolveStep>, Duration)]) -> TimeModel {
+        let seconds = |d : Duration| d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9;
+        let (mut products, mut squares) = (0.0, 0.0);
+        for &(ref path, measured) in samples {
+            let estimated = seconds(self.estimate(path));
+            products += estimated * seconds(measured);
+            squares += estimated * estimated;
+        }
+        if squares == 0.0 {
+            return self;
+        }
+        let scale = products / squares;
+        TimeModel {
+            overhead : self.overhead * scale,
+            seconds : self.seconds.into_iter().map(|(t, s)| (t, s * scale)).collect()
+        }
+    }
+}
+
+// Reference puzzles in the format of data/calibration.txt: `<grid> <level>` per line, '#' for
+// comments.
+pub fn parse_reference(text : &str) -> Result<Vec<(Puzzle, Difficulty)>, String> {
+    let mut reference = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields : Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 2 {
+            return Err(format!("line {}: expected a grid and a level", i + 1));
+        }
+        let puzzle : Puzzle = fields[0].parse().map_err(|e| format!("line {}: {}", i + 1, e))?;
+        let level : Difficulty = fields[1].parse().map_err(|e| format!("line {}: {}", i + 1, e))?;
+        reference.push((puzzle, level));
+    }
+    Ok(reference)
+}
+
+pub fn reference() -> Vec<(Puzzle, Difficulty)> {
+    // the shipped file is checked by `check`
+    parse_reference(REFERENCE).unwrap_or_default()
+}
+
+pub fn score_reference(logical_solver : &LogicalSolver, reference : &[(Puzzle, Difficulty)]) -> Result<Vec<(f64, Difficulty)>, String> {
+    score_reference_with(&WeightedModel::default(), logical_solver, reference)
+}
+
+// Reference puzzles scored by a model of one's own, ready for `Calibration::fit`.
+pub fn score_reference_with(model : &dyn DifficultyModel, logical_solver : &LogicalSolver, reference : &[(Puzzle, Difficulty)])
+                            -> Result<Vec<(f64, Difficulty)>, String> {
+    reference.iter()
+             .map(|&(ref puzzle, level)| analysis::score_with(model, logical_solver, puzzle.start_state()).map(|score| (score, level))
+                                                                                                          .map_err(|e| format!("{}: {}", puzzle, e)))
+             .collect()
+}
+
+// Fits the shipped reference set again and compares with the built-in thresholds, then rates
+// every reference puzzle with them. Any difference is an error naming it.
+pub fn check() -> Result<(), String> {
+    let reference = parse_reference(REFERENCE)?;
+    let scored = score_reference(&LogicalSolver::new(), &reference)?;
+    let fitted = Calibration::fit(&scored);
+    let calibration = Calibration::default();
+    if fitted != calibration {
+        return Err(format!("Reference set fits thresholds {:?}, built in are {:?}", fitted.thresholds, calibration.thresholds));
+    }
+    let misplaced : Vec<String> = reference.iter()
+                                           .zip(scored.iter())
+                                           .filter(|&(_, &(score, level))| calibration.level(score) != level)
+                                           .map(|(&(ref puzzle, level), &(score, _))| {
+                                               format!("{} scores {:.4}, {} instead of {}", puzzle, score, calibration.level(score).name(), level.name())
+                                           })
+                                           .collect();
+    if ! misplaced.is_empty() {
+        return Err(format!("Reference puzzles off their level: {}", misplaced.join("; ")));
+    }
+    Ok(())
+}
+
+// Puzzles with their Sudoku Explainer rating, as SE's batch rater (serate) writes them,
+// `<grid> ED=7.1/1.2/1.2`, or as rating lists pass them around, `<grid> 7.1`, `<grid>,7.1` or
+// `<grid>\t7.1`; anything after the rating is ignored, '#' starts a comment line. The rating is
+// the ER, the first of serate's three numbers.
+pub fn parse_se_ratings(text : &str) -> Result<Vec<(Puzzle, f64)>, String> {
+    let mut rated = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message : String| format!("line {}: {}", i + 1, message);
+        let mut fields = line.split(|c : char| c.is_whitespace() || c == ',').filter(|f| ! f.is_empty());
+        let puzzle : Puzzle = fields.next().unwrap_or("").parse().map_err(&error)?;
+        let rating = fields.next().ok_or_else(|| error("expected a grid and a rating".to_string()))?;
+        let number = rating.trim_start_matches("ED=").trim_start_matches("ER=").split('/').next().unwrap_or("");
+        match number.parse::<f64>() {
+            Ok(se) if se >= 0.0 && se.is_finite() => rated.push((puzzle, se)),
+            _ => return Err(error(format!("Invalid rating {}", rating)))
+        }
+    }
+    Ok(rated)
+}
+
+// The SE ratings of the puzzles this crate put on one level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SeRange {
+    pub level : Difficulty,
+    pub count : usize,
+    pub min : f64,
+    pub mean : f64,
+    pub max : f64
+}
+
+// How this crate's technique scores line up with Sudoku Explainer ratings of the same puzzles.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeComparison {
+    // puzzles scored by both
+    pub compared : usize,
+    // puzzles the logical solver couldn't score, left out of the statistics
+    pub unscored : usize,
+    pub pearson : f64,
+    // correlation of the rankings alone, the fairer measure as neither scale is linear in the other
+    pub spearman : f64,
+    // by level, for the levels that got any puzzles
+    pub levels : Vec<SeRange>,
+    // the SE ratings at which the levels change, fitted like the score thresholds
+    pub se_thresholds : Calibration
+}
+
+// Scores the puzzles of an SE rating list and compares; None scores nothing in common.
+pub fn compare_se(logical_solver : &LogicalSolver, rated : &[(Puzzle, f64)]) -> Option<SeComparison> {
+    let calibration = Calibration::default();
+    let scored : Vec<(f64, f64)> = rated.iter()
+                                        .filter_map(|&(ref puzzle, se)| analysis::score(logical_solver, puzzle.start_state()).ok().map(|score| (score.value, se)))
+                                        .collect();
+    if scored.is_empty() {
+        return None;
+    }
+    let scores : Vec<f64> = scored.iter().map(|&(score, _)| score).collect();
+    let ratings : Vec<f64> = scored.iter().map(|&(_, se)| se).collect();
+    let mut levels = Vec::new();
+    for &level in Difficulty::all().iter() {
+        let se : Vec<f64> = scored.iter().filter(|&&(score, _)| calibration.level(score) == level).map(|&(_, se)| se).collect();
+        if ! se.is_empty() {
+            levels.push(SeRange {
+                level,
+                count : se.len(),
+                min : se.iter().cloned().fold(f64::INFINITY, f64::min),
+                mean : se.iter().sum::<f64>() / se.len() as f64,
+                max : se.iter().cloned().fold(f64::NEG_INFINITY, f64::max)
+            });
+        }
+    }
+    let by_level : Vec<(f64, Difficulty)> = scored.iter().map(|&(score, se)| (se, calibration.level(score))).collect();
+    Some(SeComparison {
+        compared : scored.len(),
+        unscored : rated.len() - scored.len(),
+        pearson : correlation(&scores, &ratings),
+        spearman : correlation(&ranks(&scores), &ranks(&ratings)),
+        levels,
+        se_thresholds : Calibration::fit(&by_level)
+    })
+}
+
+// Pearson's correlation coefficient, 0 when either side doesn't vary.
+fn correlation(xs : &[f64], ys : &[f64]) -> f64 {
+    let n = xs.len() as f64;
+    let (mean_x, mean_y) = (xs.iter().sum::<f64>() / n, ys.iter().sum::<f64>() / n);
+    let (mut xy, mut xx, mut yy) = (0.0, 0.0, 0.0);
+    for (x, y) in xs.iter().zip(ys.iter()) {
+        xy += (x - mean_x) * (y - mean_y);
+        xx += (x - mean_x) * (x - mean_x);
+        yy += (y - mean_y) * (y - mean_y);
+    }
+    if xx == 0.0 || yy == 0.0 { 0.0 } else { xy / (xx * yy).sqrt() }
+}
+
+// 1-based ranks, ties sharing the mean of their ranks.
+fn ranks(values : &[f64]) -> Vec<f64> {
+    let mut order : Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let end = (start..order.len()).find(|&k| values[order[k]] != values[order[start]]).unwrap_or(order.len());
+        for &i in &order[start..end] {
+            ranks[i] = (start + end + 1) as f64 / 2.0;
+        }
+        start = end;
+    }
+    ranks
+}
+
+impl std::fmt::Display for SeComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "{} puzzles compared, {} unscored", self.compared, self.unscored)?;
+        writeln!(f, "pearson  {:.4}", self.pearson)?;
+        writeln!(f, "spearman {:.4}", self.spearman)?;
+        for range in &self.levels {
+            writeln!(f, "{:<7} {:>6}  SE {:.1} to {:.1}, mean {:.2}", range.level.name(), range.count, range.min, range.max, range.mean)?;
+        }
+        let levels = Difficulty::all();
+        for (level, threshold) in levels.iter().skip(1).zip(self.se_thresholds.thresholds.iter()) {
+            writeln!(f, "{:<7} from SE {:.2}", level.name(), threshold)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Calibration {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let levels = Difficulty::all();
+        writeln!(f, "{:<7} below {:.4}", levels[0].name(), self.thresholds[0])?;
+        for (level, threshold) in levels.iter().skip(1).zip(self.thresholds.iter()) {
+            writeln!(f, "{:<7} from  {:.4}", level.name(), threshold)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reference_puzzles_rate_at_their_pinned_level() {
+        let reference = parse_reference(REFERENCE).unwrap();
+        assert!(! reference.is_empty());
+        let logical_solver = LogicalSolver::new();
+        let calibration = Calibration::default();
+        for &(ref puzzle, level) in &reference {
+            assert_eq!(calibration.rate(&logical_solver, puzzle.start_state()), Ok(level), "{}", puzzle);
+        }
+    }
+
+    #[test]
+    fn reference_set_fits_the_built_in_thresholds() {
+        let scored = score_reference(&LogicalSolver::new(), &parse_reference(REFERENCE).unwrap()).unwrap();
+        assert_eq!(Calibration::fit(&scored), Calibration::default());
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// What a stopped call gives as its error, or starts it with.
+pub const CANCELLED : &str = "Cancelled";
+pub const OUT_OF_TIME : &str = "Out of time";
+
+// One "Stop" for everything working on a request. Clones share the flag: a UI keeps one, hands
+// clones to the solving, rating, generating and batch calls of a pipeline, and `cancel` stops all
+// of them at their next check, however deep they are. A clone can also carry a deadline, which is
+// how the calls taking one time out, so running out of time and being stopped end the same way.
+// Calls check between steps of their work and return promptly with an error starting with
+// `CANCELLED` or `OUT_OF_TIME` (or `Rating::Cancelled` and `Rating::TimedOut` in batches).
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled : Arc<AtomicBool>,
+    deadline : Option<Instant>
+}
+
+impl CancelToken {
+
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    // The same flag with a deadline, the earlier one if the token already has one.
+    pub fn with_deadline(self, deadline : Instant) -> CancelToken {
+        CancelToken {
+            deadline : Some(self.deadline.map_or(deadline, |earlier| earlier.min(deadline))),
+            ..self
+        }
+    }
+
+    pub fn with_timeout(self, timeout : Duration) -> CancelToken {
+        let deadline = Instant::now() + timeout;
+        self.with_deadline(deadline)
+    }
+
+    // Stops every call holding this token or a clone of it; there is no taking it back.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    // Cancelled or out of time.
+    pub fn is_stopped(&self) -> bool {
+        self.is_cancelled() || self.is_expired()
+    }
+
+    // For `?` between steps.
+    pub fn check(&self) -> Result<(), String> {
+        if self.is_cancelled() {
+            Err(CANCELLED.to_string())
+        } else if self.is_expired() {
+            Err(OUT_OF_TIME.to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use puzzle::{GridString, Puzzle};
+use sudoku::StartState;
+
+type Cells = [[u8; 9]; 9];
+
+const PERMUTATIONS : [[usize; 3]; 6] = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+
+// Smallest grid string among all equivalent puzzles: transposition, band and stack order, row and
+// column order within them, and relabelling of the digits in order of first appearance. Two
+// puzzles that are the same up to these symmetries get the same form, which makes it the key
+// for deduplicating collections. Blanks are written as '0' so they sort first.
+pub fn canonical_form(start_state : &StartState) -> GridString {
+    let mut cells = [[0u8; 9]; 9];
+    for &((row, col), v) in start_state {
+        let r = (row as u32).wrapping_sub('A' as u32) as usize;
+        let c = (col as u32).wrapping_sub('1' as u32) as usize;
+        if r < 9 && c < 9 && v <= 9 {
+            cells[r][c] = v as u8;
+        }
+    }
+    let mut transposed = [[0u8; 9]; 9];
+    for r in 0..9 {
+        for c in 0..9 {
+            transposed[c][r] = cells[r][c];
+        }
+    }
+    let mut best : Option<Cells> = None;
+    for grid in [cells, transposed].iter() {
+        for stacks in PERMUTATIONS.iter() {
+            for a in PERMUTATIONS.iter() {
+                for b in PERMUTATIONS.iter() {
+                    for c in PERMUTATIONS.iter() {
+                        let inner = [a, b, c];
+                        let mut columns = [0; 9];
+                        for i in 0..9 {
+                            columns[i] = stacks[i / 3] * 3 + inner[i / 3][i % 3];
+                        }
+                        let mut search = Search {
+                            grid : [[0u8; 9]; 9],
+                            used : [false; 9],
+                            output : [[0u8; 9]; 9],
+                            best
+                        };
+                        for (row, from) in search.grid.iter_mut().zip(grid.iter()) {
+                            for (v, &column) in row.iter_mut().zip(columns.iter()) {
+                                *v = from[column];
+                            }
+                        }
+                        search.order_rows(0, 0, &[0; 10], 1);
+                        best = search.best;
+                    }
+                }
+            }
+        }
+    }
+    best.unwrap().iter().flat_map(|row| row.iter().map(|&v| (b'0' + v) as char)).collect()
+}
+
+pub fn canonical(puzzle : &Puzzle) -> GridString {
+    canonical_form(puzzle.start_state())
+}
+
+// 128 bit FNV-1a hash of a grid string, canonical or not. Keeping fingerprints instead of the
+// strings lets deduplication remember millions of puzzles in 16 bytes each; a collision between
+// different puzzles is far less likely than a disk error.
+pub fn fingerprint(grid : &str) -> u128 {
+    grid.bytes().fold(0x6c62272e07bb014262b821756295c58d, |hash, b| (hash ^ b as u128).wrapping_mul(0x0000000001000000000000000000013b))
+}
+
+// 64 bit FNV-1a of a grid string from a seed, stable across runs and platforms unlike the std
+// hasher, so sorting by it gives a seeded order that is the same everywhere.
+pub fn seeded_hash(seed : u64, grid : &str) -> u64 {
+    grid.bytes().fold(0xcbf29ce484222325 ^ seed, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+struct Search {
+    grid : Cells,
+    used : [bool; 9],
+    output : Cells,
+    best : Option<Cells>
+}
+
+impl Search {
+
+    // Places source rows into output rows one at a time, keeping bands together, and abandons a
+    // branch as soon as its rows compare greater than the best grid found so far.
+    fn order_rows(&mut self, i : usize, band : usize, labels : &[u8; 10], next_label : u8) {
+        if i == 9 {
+            self.best = Some(self.output);
+            return;
+        }
+        for source in 0..9 {
+            // the first row of an output band may come from any unused band, the others from its band
+            let first = source / 3 * 3;
+            if self.used[source] || (i.is_multiple_of(3) && self.used[first..first + 3].iter().any(|&u| u))
+                                 || (!i.is_multiple_of(3) && source / 3 != band) {
+                continue;
+            }
+            let mut row_labels = *labels;
+            let mut label = next_label;
+            for c in 0..9 {
+                let v = self.grid[source][c] as usize;
+                if v != 0 && row_labels[v] == 0 {
+                    row_labels[v] = label;
+                    label += 1;
+                }
+                self.output[i][c] = row_labels[v];
+            }
+            if let Some(ref best) = self.best {
+                if self.output[..i + 1] > best[..i + 1] {
+                    continue;
+                }
+            }
+            self.used[source] = true;
+            self.order_rows(i + 1, source / 3, &row_labels, label);
+            self.used[source] = false;
+        }
+    }
+}
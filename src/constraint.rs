@@ -0,0 +1,396 @@
+#[cfg(feature = "arena")]
+use std::cell::RefCell;
+#[cfg(feature = "arena")]
+use bumpalo::Bump;
+use sudoku::{SquareId, SquareValue, State};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Progress {
+    Unchanged,
+    Changed
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Contradiction;
+
+// A rule beyond the units and peers of the Config. The solver calls `prune` on every registered
+// constraint after propagation, repeating until none of them changes anything, and on the solved
+// grid before accepting it. Candidates should be removed with `State::prune_candidate` so the
+// classic propagation follows.
+pub trait Constraint : Send + Sync {
+    fn prune(&self, state : &mut State) -> Result<Progress, Contradiction>;
+}
+
+// Room for the vectors one pass of a constraint needs. With the "arena" feature they come out of
+// a bump arena kept per thread and emptied after every pass, so once it has grown to the largest
+// pass a solve stops going to the global allocator for them, which threads of a busy server
+// otherwise contend on; without it they are ordinary vectors.
+#[cfg(feature = "arena")]
+type Scratch = Bump;
+#[cfg(feature = "arena")]
+type ScratchVec<'s, T> = ::bumpalo::collections::Vec<'s, T>;
+#[cfg(not(feature = "arena"))]
+struct Scratch;
+#[cfg(not(feature = "arena"))]
+type ScratchVec<'s, T> = Vec<T>;
+
+#[cfg(feature = "arena")]
+thread_local! {
+    static ARENA : RefCell<Bump> = RefCell::new(Bump::new());
+}
+
+#[cfg(feature = "arena")]
+fn scratch_vec<'s, T>(scratch : &'s Scratch, capacity : usize) -> ScratchVec<'s, T> {
+    ScratchVec::with_capacity_in(capacity, scratch)
+}
+
+#[cfg(not(feature = "arena"))]
+fn scratch_vec<'s, T>(_ : &'s Scratch, capacity : usize) -> ScratchVec<'s, T> {
+    Vec::with_capacity(capacity)
+}
+
+// Runs a pass with the thread's arena and empties it afterwards. A constraint solving another
+// grid within its own pass finds the arena in use and gets a fresh one for the inner passes.
+#[cfg(feature = "arena")]
+fn with_scratch<R, F : FnOnce(&Scratch) -> R>(pass : F) -> R {
+    ARENA.with(|arena| match arena.try_borrow_mut() {
+        Ok(mut arena) => {
+            let result = pass(&arena);
+            arena.reset();
+            result
+        },
+        Err(_) => pass(&Bump::new())
+    })
+}
+
+#[cfg(not(feature = "arena"))]
+fn with_scratch<R, F : FnOnce(&Scratch) -> R>(pass : F) -> R {
+    pass(&Scratch)
+}
+
+// Pairs of squares that must hold the same digit, e.g. clone regions matched cell for cell. Each
+// square keeps only the candidates its partner still has, in both directions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Equal {
+    pairs : Vec<(SquareId, SquareId)>
+}
+
+impl Equal {
+
+    pub fn new(pairs : Vec<(SquareId, SquareId)>) -> Equal {
+        Equal {
+            pairs
+        }
+    }
+
+    // Two regions given in matching order.
+    pub fn clones(region : &[SquareId], clone : &[SquareId]) -> Equal {
+        Equal::new(region.iter().cloned().zip(clone.iter().cloned()).collect())
+    }
+
+    // A line reading the same from both ends.
+    pub fn palindrome(line : &[SquareId]) -> Equal {
+        Equal::new((0..line.len() / 2).map(|i| (line[i], line[line.len() - 1 - i])).collect())
+    }
+
+    pub fn pairs(&self) -> &Vec<(SquareId, SquareId)> {
+        &self.pairs
+    }
+}
+
+impl Constraint for Equal {
+    fn prune(&self, state : &mut State) -> Result<Progress, Contradiction> {
+        let mut progress = Progress::Unchanged;
+        for &(a, b) in &self.pairs {
+            for &(from, to) in [(a, b), (b, a)].iter() {
+                for v in digits(state.candidate_mask(&from) & ! state.candidate_mask(&to)) {
+                    if ! state.prune_candidate(&from, &v) {
+                        return Err(Contradiction);
+                    }
+                    progress = Progress::Changed;
+                }
+            }
+        }
+        Ok(progress)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Total {
+    Fixed(u32),
+    // the digit in another square, as in an arrow's circle
+    Square(SquareId)
+}
+
+// Squares whose digits add up to a total, optionally without repeats (killer cages, arrows on a
+// line within one unit) or with them (little killers). Shared by every arithmetic variant.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sum {
+    cells : Vec<SquareId>,
+    total : Total,
+    distinct : bool
+}
+
+impl Sum {
+
+    pub fn new(cells : Vec<SquareId>, total : Total, distinct : bool) -> Sum {
+        Sum {
+            cells,
+            total,
+            distinct
+        }
+    }
+
+    pub fn cage(cells : Vec<SquareId>, total : u32) -> Sum {
+        Sum::new(cells, Total::Fixed(total), true)
+    }
+
+    pub fn arrow(circle : SquareId, cells : Vec<SquareId>) -> Sum {
+        Sum::new(cells, Total::Square(circle), false)
+    }
+
+    pub fn little_killer(cells : Vec<SquareId>, total : u32) -> Sum {
+        Sum::new(cells, Total::Fixed(total), false)
+    }
+}
+
+impl Constraint for Sum {
+    fn prune(&self, state : &mut State) -> Result<Progress, Contradiction> {
+        with_scratch(|scratch| {
+            let mut candidates = scratch_vec(scratch, self.cells.len());
+            candidates.extend(self.cells.iter().map(|s| state.candidate_mask(s)));
+            let mut totals = scratch_vec(scratch, 1);
+            match self.total {
+                Total::Fixed(total) => totals.push(total),
+                Total::Square(ref s) => totals.extend(digits(state.candidate_mask(s)))
+            }
+            let mut supported = scratch_vec(scratch, self.cells.len());
+            supported.resize(self.cells.len(), 0);
+            let mut supported_totals = scratch_vec(scratch, totals.len());
+            for &total in totals.iter() {
+                if let Some(supports) = supports_in(scratch, &candidates, total, self.distinct) {
+                    supported_totals.push(total);
+                    for (all, some) in supported.iter_mut().zip(supports.iter()) {
+                        *all |= some;
+                    }
+                }
+            }
+            let mut removals = scratch_vec(scratch, 0);
+            for (i, &mask) in candidates.iter().enumerate() {
+                removals.extend(digits(mask & ! supported[i]).map(|v| (self.cells[i], v)));
+            }
+            if let Total::Square(s) = self.total {
+                removals.extend(totals.iter().filter(|t| ! supported_totals.contains(t)).map(|&t| (s, t)));
+            }
+            prune_all(state, &removals)
+        })
+    }
+}
+
+// Sandwich clue: the digits between the 1 and the 9 of a row or column add up to the total.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sandwich {
+    line : Vec<SquareId>,
+    total : u32
+}
+
+impl Sandwich {
+
+    pub fn new(line : Vec<SquareId>, total : u32) -> Sandwich {
+        Sandwich {
+            line,
+            total
+        }
+    }
+}
+
+impl Constraint for Sandwich {
+    fn prune(&self, state : &mut State) -> Result<Progress, Contradiction> {
+        with_scratch(|scratch| {
+            let n = self.line.len();
+            let mut candidates = scratch_vec(scratch, n);
+            candidates.extend(self.line.iter().map(|s| state.candidate_mask(s)));
+            let (low, high) = (1, n as SquareValue);
+            let ends = 1 << low | 1 << high;
+            let mut supported = scratch_vec(scratch, n);
+            supported.resize(n, 0u32);
+            let mut inside = scratch_vec(scratch, n);
+            for i in 0..n {
+                for j in i + 1..n {
+                    for &(a, b) in [(low, high), (high, low)].iter() {
+                        if candidates[i] & 1 << a == 0 || candidates[j] & 1 << b == 0 {
+                            continue;
+                        }
+                        inside.clear();
+                        inside.extend(candidates[i + 1..j].iter().map(|&mask| mask & ! ends));
+                        let supports = match supports_in(scratch, &inside, self.total, true) {
+                            Some(supports) => supports,
+                            None => continue
+                        };
+                        for (k, &mask) in candidates.iter().enumerate() {
+                            supported[k] |= if k == i {
+                                1 << a
+                            } else if k == j {
+                                1 << b
+                            } else if k > i && k < j {
+                                supports[k - i - 1]
+                            } else {
+                                mask & ! ends
+                            };
+                        }
+                    }
+                }
+            }
+            let mut removals = scratch_vec(scratch, 0);
+            for (k, &mask) in candidates.iter().enumerate() {
+                removals.extend(digits(mask & ! supported[k]).map(|v| (self.line[k], v)));
+            }
+            prune_all(state, &removals)
+        })
+    }
+}
+
+fn bits(values : &[SquareValue]) -> u32 {
+    values.iter().fold(0, |mask, &v| mask | 1 << v)
+}
+
+// The digits of a mask as `bits` makes them, in ascending order.
+fn digits(mask : u32) -> impl Iterator<Item = SquareValue> {
+    (1..32).filter(move |&v| mask & 1 << v != 0)
+}
+
+fn prune_all(state : &mut State, removals : &[(SquareId, SquareValue)]) -> Result<Progress, Contradiction> {
+    for &(s, v) in removals {
+        if ! state.prune_candidate(&s, &v) {
+            return Err(Contradiction);
+        }
+    }
+    Ok(if removals.is_empty() { Progress::Unchanged } else { Progress::Changed })
+}
+
+// For every square the candidates that take part in at least one way of reaching `total`, picking
+// one candidate per square, all different when `distinct`. None when the total can't be reached.
+pub fn sum_supports(candidates : &[Vec<SquareValue>], total : u32, distinct : bool) -> Option<Vec<Vec<SquareValue>>> {
+    let masks : Vec<u32> = candidates.iter().map(|vs| bits(vs)).collect();
+    with_scratch(|scratch| {
+        supports_in(scratch, &masks, total, distinct).map(|supports| supports.iter().map(|&mask| digits(mask).collect()).collect())
+    })
+}
+
+// `sum_supports` over candidate masks, giving masks. Works through the squares in order over
+// (used digits, partial sum) states, kept sorted so the way back can search them; with distinct
+// digits the used set fixes the sum, so there are at most 2^9 states.
+fn supports_in<'s>(scratch : &'s Scratch, candidates : &[u32], total : u32, distinct : bool) -> Option<ScratchVec<'s, u32>> {
+    let n = candidates.len();
+    let mask = |v : SquareValue| if distinct { 1u32 << v } else { 0 };
+    // reachable[k] holds the states after k squares
+    let mut reachable = scratch_vec(scratch, n + 1);
+    let mut start = scratch_vec(scratch, 1);
+    start.push((0u32, 0u32));
+    reachable.push(start);
+    for k in 0..n {
+        let mut next = scratch_vec(scratch, reachable[k].len() * 2);
+        for &(used, sum) in reachable[k].iter() {
+            next.extend(digits(candidates[k]).filter(|&v| used & mask(v) == 0 && sum + v <= total).map(|v| (used | mask(v), sum + v)));
+        }
+        next.sort_unstable();
+        next.dedup();
+        reachable.push(next);
+    }
+    // the states after k + 1 squares from which the rest can reach the total
+    let mut completable = scratch_vec(scratch, reachable[n].len());
+    completable.extend(reachable[n].iter().cloned().filter(|&(_, sum)| sum == total));
+    if completable.is_empty() {
+        return None;
+    }
+    let mut supports = scratch_vec(scratch, n);
+    supports.resize(n, 0);
+    for k in (0..n).rev() {
+        let mut states = scratch_vec(scratch, reachable[k].len());
+        for &(used, sum) in reachable[k].iter() {
+            let supporting = digits(candidates[k]).filter(|&v| used & mask(v) == 0 && completable.binary_search(&(used | mask(v), sum + v)).is_ok())
+                                                  .fold(0, |all, v| all | 1 << v);
+            if supporting != 0 {
+                states.push((used, sum));
+                supports[k] |= supporting;
+            }
+        }
+        completable = states;
+    }
+    Some(supports)
+}
+
+// Squares restricted to a subset of the digits, e.g. parity (even/odd) clues.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Allowed {
+    squares : Vec<(SquareId, Vec<SquareValue>)>
+}
+
+impl Allowed {
+
+    pub fn new(squares : Vec<(SquareId, Vec<SquareValue>)>) -> Allowed {
+        Allowed {
+            squares
+        }
+    }
+}
+
+impl Constraint for Allowed {
+    fn prune(&self, state : &mut State) -> Result<Progress, Contradiction> {
+        with_scratch(|scratch| {
+            let mut removals = scratch_vec(scratch, 0);
+            for &(s, ref values) in &self.squares {
+                removals.extend(digits(state.candidate_mask(&s) & ! bits(values)).map(|v| (s, v)));
+            }
+            prune_all(state, &removals)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sudoku::Config;
+
+    fn all() -> Vec<SquareValue> {
+        (1..10).collect()
+    }
+
+    #[test]
+    fn bounds_prune_to_the_digits_that_reach_the_total() {
+        assert_eq!(sum_supports(&[all(), all()], 3, true), Some(vec![vec![1, 2], vec![1, 2]]));
+        assert_eq!(sum_supports(&[all(), all()], 17, true), Some(vec![vec![8, 9], vec![8, 9]]));
+        assert_eq!(sum_supports(&[vec![1, 2], all(), all()], 24, true), None);
+        assert_eq!(sum_supports(&[vec![7], all(), all()], 24, true), Some(vec![vec![7], vec![8, 9], vec![8, 9]]));
+    }
+
+    #[test]
+    fn unreachable_totals_are_infeasible() {
+        assert_eq!(sum_supports(&[vec![1, 2], vec![1, 2]], 5, false), None);
+        assert_eq!(sum_supports(&[all(), all(), all()], 25, true), None);
+        assert_eq!(sum_supports(&[all(), all()], 2, true), None);
+        assert_eq!(sum_supports(&[vec![2, 4], vec![6, 8]], 11, false), None);
+    }
+
+    #[test]
+    fn repeats_only_where_allowed() {
+        assert_eq!(sum_supports(&[all(), all()], 2, false), Some(vec![vec![1], vec![1]]));
+        assert_eq!(sum_supports(&[all(), all()], 4, true), Some(vec![vec![1, 3], vec![1, 3]]));
+        assert_eq!(sum_supports(&[all(), all()], 4, false), Some(vec![vec![1, 2, 3], vec![1, 2, 3]]));
+        assert_eq!(sum_supports(&[vec![5], vec![5]], 10, true), None);
+        assert_eq!(sum_supports(&[vec![5], vec![5]], 10, false), Some(vec![vec![5], vec![5]]));
+    }
+
+    #[test]
+    fn sum_prunes_the_state() {
+        let config = Config::new();
+        let mut state = State::new(&config);
+        let cage = Sum::cage(vec![('A', '1'), ('A', '2')], 3);
+        assert_eq!(cage.prune(&mut state), Ok(Progress::Changed));
+        assert_eq!(state.candidates(&('A', '1')), vec![1, 2]);
+        assert_eq!(state.candidates(&('A', '2')), vec![1, 2]);
+        assert_eq!(cage.prune(&mut state), Ok(Progress::Unchanged));
+        let impossible = Sum::cage(vec![('B', '1'), ('B', '2')], 18);
+        assert_eq!(impossible.prune(&mut state), Err(Contradiction));
+    }
+}
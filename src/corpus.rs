@@ -0,0 +1,313 @@
+use std;
+use std::cell::OnceCell;
+use std::collections::HashSet;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::time::Duration;
+use analysis::{self, Difficulty, Rating};
+use cancel::CancelToken;
+use canonical;
+use formats::{self, Format};
+use logic::LogicalSolver;
+use puzzle::Puzzle;
+
+// A puzzle file read once and kept in memory for exploring it: canonical fingerprints and ratings
+// are worked out the first time a query needs them and kept, so asking again costs nothing.
+//
+//   let corpus = Corpus::open(Path::new("big.sdm"))?;
+//   let hard : Vec<&Puzzle> = corpus.iter().clues(25..=28).difficulty(Difficulty::Hard).map(|e| e.puzzle()).collect();
+//
+// The caches fill in on one thread; a corpus is not shared between threads.
+pub struct Corpus {
+    puzzles : Vec<Puzzle>,
+    format : Option<Format>,
+    timeout : Duration,
+    logical_solver : LogicalSolver,
+    fingerprints : Vec<OnceCell<u128>>,
+    ratings : Vec<OnceCell<Rating>>
+}
+
+// A puzzle of a corpus with its place in it.
+#[derive(Clone, Copy)]
+pub struct Entry<'a> {
+    corpus : &'a Corpus,
+    index : usize
+}
+
+// The puzzles of a corpus that pass every filter, in file order. Filters on clues and symmetry
+// are checked before those that need a rating, whatever order they were added in, so narrowing
+// by those first keeps puzzles from being rated for nothing.
+pub struct Query<'a> {
+    corpus : &'a Corpus,
+    next : usize,
+    clues : (usize, usize),
+    min_symmetry : f32,
+    difficulty : Option<Difficulty>,
+    rated : bool,
+    // fingerprints seen so far, with `distinct`
+    seen : Option<HashSet<u128>>
+}
+
+impl Corpus {
+
+    pub fn new(puzzles : Vec<Puzzle>) -> Corpus {
+        Corpus {
+            fingerprints : puzzles.iter().map(|_| OnceCell::new()).collect(),
+            ratings : puzzles.iter().map(|_| OnceCell::new()).collect(),
+            puzzles,
+            format : None,
+            timeout : Duration::from_millis(10000),
+            logical_solver : LogicalSolver::new()
+        }
+    }
+
+    // Reads a puzzle file in any format `formats::detect_format` recognises.
+    pub fn open(path : &Path) -> Result<Corpus, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let (format, puzzles) = formats::read_detected(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Corpus {
+            format : Some(format),
+            ..Corpus::new(puzzles)
+        })
+    }
+
+    // The time each puzzle gets to be rated, 10 seconds unless set; one out of time rates as
+    // `Rating::TimedOut`. Ratings already worked out are kept.
+    pub fn with_timeout(self, timeout : Duration) -> Corpus {
+        Corpus {
+            timeout,
+            ..self
+        }
+    }
+
+    // The format the file was read as, None for a corpus made with `new`.
+    pub fn format(&self) -> Option<Format> {
+        self.format
+    }
+
+    pub fn len(&self) -> usize {
+        self.puzzles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.puzzles.is_empty()
+    }
+
+    pub fn puzzles(&self) -> &[Puzzle] {
+        &self.puzzles
+    }
+
+    pub fn get(&self, i : usize) -> Option<Entry<'_>> {
+        if i < self.puzzles.len() {
+            Some(Entry { corpus : self, index : i })
+        } else {
+            None
+        }
+    }
+
+    pub fn iter(&self) -> Query<'_> {
+        Query {
+            corpus : self,
+            next : 0,
+            clues : (0, usize::MAX),
+            min_symmetry : 0.0,
+            difficulty : None,
+            rated : false,
+            seen : None
+        }
+    }
+
+    // Rates every puzzle not rated yet, all at once with `analysis::rate_batch` and so in parallel
+    // with the "parallel" feature, for when most of the corpus is going to be asked about anyway.
+    pub fn rate_all(&self) {
+        self.rate_all_cancellable(&CancelToken::new());
+    }
+
+    // `rate_all` until `cancel` is cancelled; the puzzles it didn't get to stay unrated, to be
+    // rated when asked about or by the next `rate_all`.
+    pub fn rate_all_cancellable(&self, cancel : &CancelToken) {
+        let unrated : Vec<usize> = (0..self.len()).filter(|&i| self.ratings[i].get().is_none()).collect();
+        let puzzles : Vec<Puzzle> = unrated.iter().map(|&i| self.puzzles[i].clone()).collect();
+        for (i, rated) in unrated.into_iter().zip(analysis::rate_batch_cancellable(&puzzles, self.timeout, cancel)) {
+            if rated.rating != Rating::Cancelled {
+                let _ = self.ratings[i].set(rated.rating);
+            }
+        }
+    }
+
+    // How many puzzles have been rated so far.
+    pub fn rated_count(&self) -> usize {
+        self.ratings.iter().filter(|rating| rating.get().is_some()).count()
+    }
+}
+
+impl<'a> Entry<'a> {
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn puzzle(&self) -> &'a Puzzle {
+        &self.corpus.puzzles[self.index]
+    }
+
+    pub fn clues(&self) -> usize {
+        self.puzzle().clues()
+    }
+
+    // The fingerprint of the canonical form, the same for puzzles equal up to symmetry and
+    // relabelling, as `dedup --canonical` compares them.
+    pub fn fingerprint(&self) -> u128 {
+        *self.corpus.fingerprints[self.index].get_or_init(|| canonical::fingerprint(&canonical::canonical(self.puzzle())))
+    }
+
+    pub fn rating(&self) -> &'a Rating {
+        let corpus = self.corpus;
+        corpus.ratings[self.index].get_or_init(|| analysis::rate_within(&corpus.logical_solver, self.puzzle(), corpus.timeout).rating)
+    }
+
+    // The level, None for puzzles that fail to rate or run out of time.
+    pub fn difficulty(&self) -> Option<Difficulty> {
+        match *self.rating() {
+            Rating::Rated(_, level) => Some(level),
+            _ => None
+        }
+    }
+}
+
+impl<'a> Query<'a> {
+
+    // Puzzles with a clue count in the range, e.g. `clues(25..=28)`.
+    pub fn clues<R : RangeBounds<usize>>(self, range : R) -> Query<'a> {
+        let low = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0
+        };
+        let high = match range.end_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n.saturating_sub(1),
+            Bound::Unbounded => usize::MAX
+        };
+        Query {
+            clues : (low.max(self.clues.0), high.min(self.clues.1)),
+            ..self
+        }
+    }
+
+    // Puzzles whose clue pattern has an `analysis::symmetry_score` of at least `min`.
+    pub fn symmetry(self, min : f32) -> Query<'a> {
+        Query {
+            min_symmetry : min.max(self.min_symmetry),
+            ..self
+        }
+    }
+
+    // Puzzles rated at the level, in place of any level asked for before.
+    pub fn difficulty(self, level : Difficulty) -> Query<'a> {
+        Query {
+            difficulty : Some(level),
+            ..self
+        }
+    }
+
+    // Puzzles the logical solver rates, leaving out those that fail or run out of time.
+    pub fn rated(self) -> Query<'a> {
+        Query {
+            rated : true,
+            ..self
+        }
+    }
+
+    // Only the first of the puzzles equal up to symmetry and relabelling, by `Entry::fingerprint`.
+    pub fn distinct(self) -> Query<'a> {
+        Query {
+            seen : Some(self.seen.unwrap_or_default()),
+            ..self
+        }
+    }
+
+    fn passes(&mut self, entry : &Entry) -> bool {
+        let clues = entry.clues();
+        if clues < self.clues.0 || clues > self.clues.1 {
+            return false;
+        }
+        if self.min_symmetry > 0.0 && analysis::symmetry_score(entry.puzzle().start_state()) < self.min_symmetry {
+            return false;
+        }
+        if self.difficulty.is_some() || self.rated {
+            let level = entry.difficulty();
+            if level.is_none() || (self.difficulty.is_some() && level != self.difficulty) {
+                return false;
+            }
+        }
+        match self.seen {
+            Some(ref mut seen) => seen.insert(entry.fingerprint()),
+            None => true
+        }
+    }
+}
+
+impl<'a> Iterator for Query<'a> {
+    type Item = Entry<'a>;
+
+    fn next(&mut self) -> Option<Entry<'a>> {
+        while self.next < self.corpus.len() {
+            let entry = Entry { corpus : self.corpus, index : self.next };
+            self.next += 1;
+            if self.passes(&entry) {
+                return Some(entry);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SOLVED less its diagonal, the same with 1 and 2 swapped, and two clues that clash.
+    fn text() -> String {
+        let diagonal : String = SOLVED.char_indices().map(|(i, c)| if i % 10 == 0 { '0' } else { c }).collect();
+        let swapped : String = diagonal.chars().map(|c| match c { '1' => '2', '2' => '1', c => c }).collect();
+        format!("# author: Jane Doe\n{}\n{}\n{:0>81}\n", diagonal, swapped, "99")
+    }
+
+    #[test]
+    fn corpora_open_in_the_format_detected() {
+        let path = std::env::temp_dir().join(format!("sudoku-solver-corpus-{}.sdm", std::process::id()));
+        std::fs::write(&path, text()).unwrap();
+        let corpus = Corpus::open(&path);
+        let unrecognised = std::fs::write(&path, "# nothing but comments\n").map(|_| Corpus::open(&path));
+        std::fs::remove_file(&path).unwrap();
+        let corpus = corpus.unwrap();
+        assert_eq!((corpus.format(), corpus.len()), (Some(Format::Sdm), 3));
+        assert_eq!(corpus.get(1).unwrap().puzzle().provenance().author.as_deref(), Some("Jane Doe"));
+        assert!(corpus.get(3).is_none());
+        assert!(unrecognised.unwrap().err().unwrap().starts_with(&format!("{}: Unrecognised puzzle format, tried ", path.display())));
+        assert!(Corpus::open(&path).err().unwrap().starts_with(&format!("{}: ", path.display())));
+        assert_eq!(Corpus::new(Vec::new()).format(), None);
+    }
+
+    #[test]
+    fn queries_filter_and_only_rate_what_they_must() {
+        let puzzles = formats::read_detected(&text()).unwrap().1;
+        let corpus = Corpus::new(puzzles);
+        let indices = |query : Query| query.map(|entry| entry.index()).collect::<Vec<_>>();
+        assert_eq!(indices(corpus.iter().clues(..17)), vec![2]);
+        assert_eq!(indices(corpus.iter().clues(72..=72).distinct()), vec![0]);
+        assert_eq!(corpus.get(0).unwrap().fingerprint(), corpus.get(1).unwrap().fingerprint());
+        assert_eq!(indices(corpus.iter().symmetry(1.0).clues(70..)), vec![0, 1]);
+        assert_eq!(corpus.rated_count(), 0);
+        // the clue filter goes first, so the clashing clues are never rated
+        let level = corpus.get(0).unwrap().difficulty().unwrap();
+        assert_eq!(indices(corpus.iter().difficulty(level).clues(17..)), vec![0, 1]);
+        assert_eq!(corpus.rated_count(), 2);
+        corpus.rate_all();
+        assert_eq!((corpus.rated_count(), corpus.get(2).unwrap().difficulty()), (3, None));
+        assert_eq!(indices(corpus.iter().rated()), vec![0, 1]);
+    }
+
+    const SOLVED : &str = "534678912672195348198342567859761423426853791713924856961537284287419635345286179";
+}
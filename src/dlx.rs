@@ -0,0 +1,216 @@
+use std;
+use sudoku::{SquareValue, StartState};
+
+const COLUMNS : usize = 4 * 81;
+const ROWS : usize = 9 * 81;
+
+// Knuth's dancing links over the 324 exact cover constraints of a 9x9 sudoku (cell filled, digit
+// in row, digit in column, digit in box); every one of the 729 rows places a digit in a cell.
+pub struct Dlx {
+    left : Vec<usize>,
+    right : Vec<usize>,
+    up : Vec<usize>,
+    down : Vec<usize>,
+    column : Vec<usize>,
+    row : Vec<usize>,
+    size : Vec<usize>,
+    row_start : Vec<usize>,
+    covered : Vec<bool>,
+    guesses : usize
+}
+
+impl Default for Dlx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dlx {
+
+    pub fn new() -> Dlx {
+        // node 0 is the root, 1..=COLUMNS the column headers
+        let headers = COLUMNS + 1;
+        let mut dlx = Dlx {
+            left : (0..headers).map(|i| if i == 0 { COLUMNS } else { i - 1 }).collect(),
+            right : (0..headers).map(|i| if i == COLUMNS { 0 } else { i + 1 }).collect(),
+            up : (0..headers).collect(),
+            down : (0..headers).collect(),
+            column : (0..headers).collect(),
+            row : vec![ROWS; headers],
+            size : vec![0; headers],
+            row_start : Vec::with_capacity(ROWS),
+            covered : vec![false; headers],
+            guesses : 0
+        };
+        for r in 0..ROWS {
+            let (cell, d) = (r / 9, r % 9);
+            let (rr, cc) = (cell / 9, cell % 9);
+            let b = (rr / 3) * 3 + cc / 3;
+            let first = dlx.left.len();
+            dlx.row_start.push(first);
+            for (k, &c) in [cell, 81 + rr * 9 + d, 162 + cc * 9 + d, 243 + b * 9 + d].iter().enumerate() {
+                let c = c + 1;
+                let n = dlx.left.len();
+                dlx.left.push(if k == 0 { first + 3 } else { n - 1 });
+                dlx.right.push(if k == 3 { first } else { n + 1 });
+                dlx.up.push(dlx.up[c]);
+                dlx.down.push(c);
+                let last = dlx.up[c];
+                dlx.down[last] = n;
+                dlx.up[c] = n;
+                dlx.column.push(c);
+                dlx.row.push(r);
+                dlx.size[c] += 1;
+            }
+        }
+        dlx
+    }
+
+    // Solution and the number of branch points where more than one row had to be tried.
+    pub fn solve(start_state : &StartState) -> Option<(StartState, usize)> {
+        let mut dlx = Dlx::new();
+        let mut removed = vec![false; ROWS];
+        for &(s, v) in start_state.eliminations() {
+            let r = row_of(s, v)?;
+            if ! removed[r] {
+                dlx.remove(r);
+                removed[r] = true;
+            }
+        }
+        let mut rows = Vec::new();
+        for &(s, v) in start_state.iter().filter(|&&(_, v)| v != 0) {
+            let r = row_of(s, v)?;
+            if removed[r] || ! dlx.select(r) {
+                return None;
+            }
+            rows.push(r);
+        }
+        if ! dlx.search(&mut rows) {
+            return None;
+        }
+        let solution : StartState = rows.iter().map(|&r| square_value(r)).collect();
+        Some((solution, dlx.guesses))
+    }
+
+    // Takes a row out of the matrix for good, for a candidate the puzzle rules out. Only before
+    // anything is selected.
+    fn remove(&mut self, r : usize) {
+        let first = self.row_start[r];
+        for n in first..first + 4 {
+            let (u, d) = (self.up[n], self.down[n]);
+            self.down[u] = d;
+            self.up[d] = u;
+            self.size[self.column[n]] -= 1;
+        }
+    }
+
+    fn select(&mut self, r : usize) -> bool {
+        let first = self.row_start[r];
+        let columns : Vec<usize> = (0..4).map(|k| self.column[first + k]).collect();
+        if columns.iter().any(|&c| self.covered[c]) {
+            return false;
+        }
+        for c in columns {
+            self.cover(c);
+        }
+        true
+    }
+
+    fn search(&mut self, rows : &mut Vec<usize>) -> bool {
+        if self.right[0] == 0 {
+            return true;
+        }
+        let mut c = self.right[0];
+        let mut j = self.right[c];
+        while j != 0 {
+            if self.size[j] < self.size[c] {
+                c = j;
+            }
+            j = self.right[j];
+        }
+        if self.size[c] == 0 {
+            return false;
+        }
+        if self.size[c] > 1 {
+            self.guesses += 1;
+        }
+        self.cover(c);
+        let mut r = self.down[c];
+        while r != c {
+            rows.push(self.row[r]);
+            let mut j = self.right[r];
+            while j != r {
+                let cj = self.column[j];
+                self.cover(cj);
+                j = self.right[j];
+            }
+            if self.search(rows) {
+                return true;
+            }
+            rows.pop();
+            let mut j = self.left[r];
+            while j != r {
+                let cj = self.column[j];
+                self.uncover(cj);
+                j = self.left[j];
+            }
+            r = self.down[r];
+        }
+        self.uncover(c);
+        false
+    }
+
+    fn cover(&mut self, c : usize) {
+        self.covered[c] = true;
+        let (l, r) = (self.left[c], self.right[c]);
+        self.right[l] = r;
+        self.left[r] = l;
+        let mut i = self.down[c];
+        while i != c {
+            let mut j = self.right[i];
+            while j != i {
+                let (u, d) = (self.up[j], self.down[j]);
+                self.down[u] = d;
+                self.up[d] = u;
+                self.size[self.column[j]] -= 1;
+                j = self.right[j];
+            }
+            i = self.down[i];
+        }
+    }
+
+    fn uncover(&mut self, c : usize) {
+        let mut i = self.up[c];
+        while i != c {
+            let mut j = self.left[i];
+            while j != i {
+                self.size[self.column[j]] += 1;
+                let (u, d) = (self.up[j], self.down[j]);
+                self.down[u] = j;
+                self.up[d] = j;
+                j = self.left[j];
+            }
+            i = self.up[i];
+        }
+        let (l, r) = (self.left[c], self.right[c]);
+        self.right[l] = c;
+        self.left[r] = c;
+        self.covered[c] = false;
+    }
+}
+
+// The row placing the value in the square, None outside the grid.
+fn row_of((row, col) : (char, char), v : SquareValue) -> Option<usize> {
+    let r = (row as u32).wrapping_sub('A' as u32) as usize;
+    let c = (col as u32).wrapping_sub('1' as u32) as usize;
+    if r >= 9 || c >= 9 || !(1..=9).contains(&v) {
+        return None;
+    }
+    Some((r * 9 + c) * 9 + v as usize - 1)
+}
+
+fn square_value(r : usize) -> ((char, char), SquareValue) {
+    let (cell, d) = (r / 9, r % 9);
+    ((std::char::from_u32('A' as u32 + (cell / 9) as u32).unwrap(),
+      std::char::from_u32('1' as u32 + (cell % 9) as u32).unwrap()), d as SquareValue + 1)
+}
@@ -0,0 +1,159 @@
+use calibration;
+use grid::Grid9;
+use puzzle::Puzzle;
+use stress;
+use sudoku::SquareValue;
+
+// Fault injection for the bitmask engine: grids part way through a solve get one square's
+// candidates corrupted the way a propagation bug would, and `Grid::check` and `check_against`
+// have to notice. Healthy grids at the same point must pass both, so the checks are known to
+// be neither blind nor jumpy before they guard the engine that replaces the hash map state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    // the solution's digit ruled out of an open square
+    Lost(usize, usize, SquareValue),
+    // every candidate of a square ruled out
+    Emptied(usize, usize),
+    // a solved square's digit left as a candidate of a peer, the square given here
+    Unremoved(usize, usize, SquareValue)
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultReport {
+    pub rounds : usize,
+    pub caught : usize,
+    // faults the checks let through, with the puzzle
+    pub missed : Vec<String>,
+    // healthy grids that failed the checks, or failed to take a digit of their solution
+    pub false_alarms : Vec<String>
+}
+
+impl FaultReport {
+    pub fn passed(&self) -> bool {
+        self.missed.is_empty() && self.false_alarms.is_empty()
+    }
+}
+
+// xorshift, seeded so that a failing round can be run again.
+struct Random {
+    state : u64
+}
+
+impl Random {
+    fn new(seed : u64) -> Random {
+        Random {
+            state : seed.wrapping_mul(0x9e3779b97f4a7c15) | 1
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn below(&mut self, n : usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+// Corrupts one square of the grid, which must still agree with the solution.
+pub fn inject(grid : &mut Grid9, solution : &Grid9, seed : u64) -> Fault {
+    let mut random = Random::new(seed);
+    let squares : Vec<(usize, usize)> = (0..81).map(|i| (i / 9, i % 9)).collect();
+    let (solved, open) : (Vec<_>, Vec<_>) = squares.iter().partition(|&&(r, c)| grid.value(r, c).is_some());
+    let fault = match random.below(3) {
+        0 if ! open.is_empty() => {
+            let (r, c) = open[random.below(open.len())];
+            Fault::Lost(r, c, solution.value(r, c).unwrap_or(1))
+        },
+        1 if ! solved.is_empty() => {
+            // any other square of the row, propagation took the digit out of all of them
+            let (r, c) = solved[random.below(solved.len())];
+            Fault::Unremoved(r, (c + 1 + random.below(8)) % 9, grid.value(r, c).unwrap_or(1))
+        },
+        _ => {
+            let (r, c) = squares[random.below(squares.len())];
+            Fault::Emptied(r, c)
+        }
+    };
+    let bit = |v : SquareValue| 1 << (v - 1);
+    match fault {
+        Fault::Lost(r, c, v) => {
+            let mask = grid.candidates(r, c) & ! bit(v);
+            grid.set_candidates(r, c, mask);
+        },
+        Fault::Emptied(r, c) => grid.set_candidates(r, c, 0),
+        Fault::Unremoved(r, c, v) => {
+            let mask = grid.candidates(r, c) | bit(v);
+            grid.set_candidates(r, c, mask);
+        }
+    }
+    fault
+}
+
+// For each round a puzzle of the list, in turn, is propagated and a random share of its open
+// squares filled in from the solution, then checked while healthy and again after `inject`.
+pub fn run(puzzles : &[Puzzle], rounds : usize, seed : u64) -> FaultReport {
+    let mut random = Random::new(seed);
+    let mut report = FaultReport::default();
+    if puzzles.is_empty() {
+        return report;
+    }
+    for round in 0..rounds {
+        let puzzle = &puzzles[round % puzzles.len()];
+        let (mut grid, solution) = match Grid9::from_start_state(puzzle.start_state()) {
+            Some(grid) => match grid.solve() {
+                Some(solution) => (grid, solution),
+                None => continue
+            },
+            None => continue
+        };
+        let open : Vec<(usize, usize)> = (0..81).map(|i| (i / 9, i % 9)).filter(|&(r, c)| grid.value(r, c).is_none()).collect();
+        report.rounds += 1;
+        let filled = random.below(open.len() + 1);
+        let mut assigned = Ok(());
+        for _ in 0..filled {
+            let (r, c) = open[random.below(open.len())];
+            let v = solution.value(r, c).unwrap();
+            if ! grid.assign(r, c, v) {
+                assigned = Err(format!("r{}c{}={} from the solution contradicts", r + 1, c + 1, v));
+                break;
+            }
+        }
+        let checked = |grid : &Grid9| grid.check().and_then(|_| grid.check_against(&solution));
+        if let Err(e) = assigned.and_then(|_| checked(&grid)) {
+            report.false_alarms.push(format!("{}: {}", puzzle, e));
+            continue;
+        }
+        let fault = inject(&mut grid, &solution, random.next());
+        match checked(&grid) {
+            Ok(()) => report.missed.push(format!("{}: {:?}", puzzle, fault)),
+            Err(_) => report.caught += 1
+        }
+    }
+    report
+}
+
+// `run` over the calibration reference set and the solvable stress puzzles.
+pub fn check(rounds : usize, seed : u64) -> FaultReport {
+    let puzzles : Vec<Puzzle> = calibration::reference().into_iter()
+                                                        .map(|(puzzle, _)| puzzle)
+                                                        .chain(stress::corpus().into_iter().filter(|entry| entry.solvable).map(|entry| entry.puzzle))
+                                                        .collect();
+    run(&puzzles, rounds, seed)
+}
+
+#[cfg(all(test, feature = "fault-injection"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_catch_every_fault_without_false_alarms() {
+        let report = check(40, 20261016);
+        assert_eq!(report.rounds, 40);
+        assert!(report.passed(), "missed {:?}, false alarms {:?}", report.missed, report.false_alarms);
+        assert_eq!(report.caught, 40);
+    }
+}
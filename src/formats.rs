@@ -0,0 +1,672 @@
+use std;
+use std::io::{self, BufRead, Lines, Write};
+use json::Json;
+use puzzle::{self, Provenance, Puzzle};
+use sudoku::StringStartStateHandler;
+
+// Files holding collections of puzzles:
+//
+//   sdm    one 81 character grid per line, 0 or '.' for blanks (Simple Sudoku libraries)
+//   sukaku one 729 character pencilmark grid per line (`puzzle::parse_candidates`), with sdm's
+//          headers
+//   sdk    nine rows of nine per puzzle (SadMan Sudoku), with `#A` author, `#U` source URL and
+//          `#B` date lines; the license goes in a `#C license: ...` comment
+//   block  nine rows with box borders, as people draw them
+//   csv    a `puzzle,author,source,license,created` header and a row per puzzle
+//   json   an array of `Puzzle::to_json` objects, or an object with a "puzzles" array such as
+//          `curate` writes
+//
+// sdm and block carry provenance in `# key: value` comments, see `Provenance::parse_header`. In
+// sdm a header holds for the puzzles that follow it; in the formats with several lines per puzzle
+// it belongs to the next puzzle only. `detect_format` tells them apart for input of unknown
+// format. A `License` for the collection as a whole goes with any of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Sdm,
+    Sukaku,
+    Sdk,
+    Block,
+    Csv,
+    Json
+}
+
+const SUKAKU_LENGTH : usize = 729;
+
+const CSV_COLUMNS : [&str; 5] = ["puzzle", "author", "source", "license", "created"];
+
+// The comment headers of a `License`, in the order they are written.
+const LICENSE_HEADERS : [&str; 4] = ["collection-license", "collection-holder", "collection-url", "collection-terms"];
+
+// SadMan Sudoku header codes and the provenance keys they carry.
+const SDK_CODES : [(char, &str); 3] = [('A', "author"), ('U', "source"), ('B', "created")];
+
+impl Format {
+
+    pub fn all() -> Vec<Format> {
+        vec![Format::Sdm, Format::Sukaku, Format::Sdk, Format::Block, Format::Csv, Format::Json]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Format::Sdm => "sdm",
+            Format::Sukaku => "sukaku",
+            Format::Sdk => "sdk",
+            Format::Block => "block",
+            Format::Csv => "csv",
+            Format::Json => "json"
+        }
+    }
+
+    pub fn read(&self, text : &str) -> Result<Vec<Puzzle>, String> {
+        match *self {
+            Format::Sdm => read_sdm(text),
+            Format::Sukaku => read_sukaku(text),
+            Format::Sdk => read_rows(text, sdk_header),
+            Format::Block => read_rows(text, |provenance, comment| provenance.parse_header(comment).map(|_| ())),
+            Format::Csv => read_csv(text),
+            Format::Json => read_json(text)
+        }
+    }
+
+    pub fn write(&self, puzzles : &[Puzzle]) -> String {
+        self.write_licensed(puzzles, None)
+    }
+
+    // The license of the collection in the text, None when it has none.
+    pub fn read_license(&self, text : &str) -> Result<Option<License>, String> {
+        match *self {
+            Format::Json => match Json::parse(text)?.get("license") {
+                None | Some(&Json::Null) => Ok(None),
+                Some(license) => License::from_json(license).map(Some)
+            },
+            Format::Sdk => License::from_headers(text, "#C"),
+            _ => License::from_headers(text, "#")
+        }
+    }
+
+    // `write` with the collection's license, if any, at the top or, in JSON, in an object
+    // holding the puzzles, so that `read_license` gets it back.
+    pub fn write_licensed(&self, puzzles : &[Puzzle], license : Option<&License>) -> String {
+        let puzzles_text = match *self {
+            Format::Sdm => write_sdm(puzzles, SdmWriter::new(Vec::new())),
+            Format::Sukaku => write_sdm(puzzles, SdmWriter::new(Vec::new()).with_pencilmarks()),
+            Format::Sdk => write_rows(puzzles, sdk_headers, false),
+            Format::Block => write_rows(puzzles, comment_headers, true),
+            Format::Csv => write_csv(puzzles),
+            Format::Json => {
+                let list = Json::Array(puzzles.iter().map(|p| p.to_json()).collect());
+                return match license {
+                    Some(license) => format!("{}\n", Json::object(vec![("license", license.to_json()), ("puzzles", list)])),
+                    None => format!("{}\n", list)
+                };
+            }
+        };
+        let prefix = if *self == Format::Sdk { "#C" } else { "# " };
+        let mut text : String = license.map_or(Vec::new(), |license| license.headers())
+                                       .into_iter()
+                                       .map(|header| format!("{}{}\n", prefix, header))
+                                       .collect();
+        text.push_str(&puzzles_text);
+        text
+    }
+}
+
+// The terms a collection of puzzles as a whole is distributed under, where `Provenance::license`
+// is a single puzzle's. JSON keeps it as a "license" object next to the "puzzles" array, the other
+// formats as comment headers at the top, `# collection-license: CC-BY-4.0` (`#C` in sdk) with a
+// `collection-terms` line per line of the terms; packs keep it in their license block. Readers
+// that don't know about it pass over the headers as comments.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct License {
+    // an SPDX identifier such as "CC-BY-4.0", or the title of the terms
+    pub name : String,
+    pub holder : Option<String>,
+    pub url : Option<String>,
+    pub terms : Option<String>
+}
+
+impl License {
+
+    pub fn new(name : &str) -> License {
+        License {
+            name : name.to_string(),
+            ..License::default()
+        }
+    }
+
+    // {"name": "CC-BY-4.0", "holder": ..., "url": ..., "terms": ...} with the fields that are set.
+    pub fn to_json(&self) -> Json {
+        let mut fields = vec![("name", Json::string(&self.name))];
+        for &(key, value) in [("holder", &self.holder), ("url", &self.url), ("terms", &self.terms)].iter() {
+            if let Some(ref value) = *value {
+                fields.push((key, Json::string(value)));
+            }
+        }
+        Json::object(fields)
+    }
+
+    pub fn from_json(json : &Json) -> Result<License, String> {
+        let field = |key : &str| match json.get(key) {
+            None | Some(&Json::Null) => Ok(None),
+            Some(Json::String(value)) => Ok(Some(value.clone())),
+            Some(value) => Err(format!("License {} must be a string, got {}", key, value))
+        };
+        let name = field("name")?.filter(|name| ! name.trim().is_empty()).ok_or_else(|| "License needs a name".to_string())?;
+        Ok(License {
+            name,
+            holder : field("holder")?,
+            url : field("url")?,
+            terms : field("terms")?
+        })
+    }
+
+    fn headers(&self) -> Vec<String> {
+        let mut headers = vec![format!("{}: {}", LICENSE_HEADERS[0], self.name)];
+        for (key, value) in LICENSE_HEADERS[1..3].iter().zip([&self.holder, &self.url].iter()) {
+            if let Some(ref value) = **value {
+                headers.push(format!("{}: {}", key, value));
+            }
+        }
+        if let Some(ref terms) = self.terms {
+            headers.extend(terms.lines().map(|line| format!("{}: {}", LICENSE_HEADERS[3], line)));
+        }
+        headers
+    }
+
+    // Gathers the license headers from the comment lines starting with `prefix`, wherever they are.
+    fn from_headers(text : &str, prefix : &str) -> Result<Option<License>, String> {
+        let mut license = License::default();
+        let mut found = false;
+        for (i, line) in text.lines().enumerate() {
+            let header = match line.trim().strip_prefix(prefix) {
+                Some(header) => header,
+                None => continue
+            };
+            let (key, value) = match header.find(':') {
+                Some(at) => (header[..at].trim(), header[at + 1..].trim()),
+                None => continue
+            };
+            match LICENSE_HEADERS.iter().position(|&k| k == key) {
+                Some(0) => license.name = value.to_string(),
+                Some(1) => license.holder = Some(value.to_string()),
+                Some(2) => license.url = Some(value.to_string()),
+                Some(_) => license.terms = Some(match license.terms.take() {
+                    Some(terms) => format!("{}\n{}", terms, value),
+                    None => value.to_string()
+                }),
+                None => continue
+            }
+            if value.is_empty() && key != LICENSE_HEADERS[3] {
+                return Err(line_error(i + 1, format!("{} needs a value", key)));
+            }
+            found = true;
+        }
+        if found && license.name.is_empty() {
+            return Err(format!("License headers without a {} header", LICENSE_HEADERS[0]));
+        }
+        Ok(if found { Some(license) } else { None })
+    }
+}
+
+impl std::fmt::Display for License {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(ref holder) = self.holder {
+            write!(f, ", {}", holder)?;
+        }
+        Ok(())
+    }
+}
+
+fn line_error(line : usize, message : String) -> String {
+    format!("line {}: {}", line, message)
+}
+
+fn read_sdm(text : &str) -> Result<Vec<Puzzle>, String> {
+    SdmReader::new(text.as_bytes()).collect()
+}
+
+// The sdm reader takes pencilmark lines as well; sukaku only them.
+fn read_sukaku(text : &str) -> Result<Vec<Puzzle>, String> {
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let length = line.chars().count();
+        if ! line.is_empty() && ! line.starts_with('#') && length != SUKAKU_LENGTH {
+            return Err(line_error(i + 1, format!("Expected a pencilmark grid of {} characters, got {}", SUKAKU_LENGTH, length)));
+        }
+    }
+    read_sdm(text)
+}
+
+fn write_sdm(puzzles : &[Puzzle], mut writer : SdmWriter<Vec<u8>>) -> String {
+    for puzzle in puzzles {
+        // writing to memory can't fail
+        writer.write(puzzle).unwrap();
+    }
+    String::from_utf8(writer.into_inner()).unwrap()
+}
+
+// Puzzles of an sdm file one at a time, for files too big to hold in memory.
+pub struct SdmReader<R : BufRead> {
+    lines : Lines<R>,
+    line : usize,
+    provenance : Provenance
+}
+
+impl<R : BufRead> SdmReader<R> {
+
+    pub fn new(input : R) -> SdmReader<R> {
+        SdmReader {
+            lines : input.lines(),
+            line : 0,
+            provenance : Provenance::default()
+        }
+    }
+
+    // Line of the puzzle last returned, counting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl<R : BufRead> Iterator for SdmReader<R> {
+    type Item = Result<Puzzle, String>;
+
+    fn next(&mut self) -> Option<Result<Puzzle, String>> {
+        for line in &mut self.lines {
+            self.line += 1;
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(line_error(self.line, e.to_string())))
+            };
+            let line = line.trim();
+            if let Some(header) = line.strip_prefix('#') {
+                if let Err(e) = self.provenance.parse_header(header) {
+                    return Some(Err(line_error(self.line, e)));
+                }
+            } else if ! line.is_empty() {
+                let provenance = &self.provenance;
+                return Some(line.parse::<Puzzle>()
+                                .map(|p| p.with_provenance(provenance.clone()))
+                                .map_err(|e| line_error(self.line, e)));
+            }
+        }
+        None
+    }
+}
+
+// Writes sdm with headers only where the provenance changes, and an empty value for a field that
+// is no longer set.
+pub struct SdmWriter<W : Write> {
+    output : W,
+    provenance : Provenance,
+    pencilmarks : bool
+}
+
+impl<W : Write> SdmWriter<W> {
+
+    pub fn new(output : W) -> SdmWriter<W> {
+        SdmWriter {
+            output,
+            provenance : Provenance::default(),
+            pencilmarks : false
+        }
+    }
+
+    // Every puzzle as a pencilmark grid, for sukaku files; otherwise only those with eliminations.
+    pub fn with_pencilmarks(self) -> SdmWriter<W> {
+        SdmWriter {
+            pencilmarks : true,
+            ..self
+        }
+    }
+
+    pub fn write(&mut self, puzzle : &Puzzle) -> io::Result<()> {
+        let provenance = puzzle.provenance();
+        for key in CSV_COLUMNS[1..].iter() {
+            match provenance.get(key) {
+                value if value == self.provenance.get(key) => (),
+                Some(value) => writeln!(self.output, "# {}: {}", key, value)?,
+                None => writeln!(self.output, "# {}:", key)?
+            }
+        }
+        self.provenance = provenance.clone();
+        if self.pencilmarks {
+            return writeln!(self.output, "{}", puzzle::candidates_string(puzzle.start_state()));
+        }
+        let handler = StringStartStateHandler::new().with_blank('0').map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        writeln!(self.output, "{}", puzzle.display(handler))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+fn sdk_header(provenance : &mut Provenance, comment : &str) -> Result<(), String> {
+    let mut chars = comment.chars();
+    let code = chars.next();
+    let value = chars.as_str().trim();
+    match SDK_CODES.iter().find(|&&(c, _)| Some(c) == code) {
+        Some(&(_, key)) => provenance.set(key, value),
+        // descriptions, levels and other comments have no provenance field
+        None if code == Some('C') => provenance.parse_header(value).map(|_| ()),
+        None => Ok(())
+    }
+}
+
+fn sdk_headers(provenance : &Provenance) -> String {
+    let mut text = String::new();
+    for (key, value) in provenance.fields() {
+        match SDK_CODES.iter().find(|&&(_, k)| k == key) {
+            Some(&(code, _)) => text.push_str(&format!("#{}{}\n", code, value)),
+            None => text.push_str(&format!("#C{}: {}\n", key, value))
+        }
+    }
+    text
+}
+
+fn comment_headers(provenance : &Provenance) -> String {
+    provenance.fields().iter().map(|&(key, value)| format!("# {}: {}\n", key, value)).collect()
+}
+
+// Markers sdk and block files use for blank squares, besides 0.
+const ROW_BLANKS : &str = "._*xX";
+
+// Reads puzzles drawn over nine lines. Box borders and spaces are dropped, so both sdk and block
+// rows reduce to their nine squares; `[Puzzle]` section lines are skipped.
+fn read_rows<F>(text : &str, header : F) -> Result<Vec<Puzzle>, String>
+    where F : Fn(&mut Provenance, &str) -> Result<(), String> {
+    let mut provenance = Provenance::default();
+    let mut grid = String::new();
+    let mut rows = 0;
+    let mut puzzles = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            header(&mut provenance, comment).map_err(|e| line_error(i + 1, e))?;
+            continue;
+        }
+        if line.starts_with('[') || line.chars().all(|c| "-+| ".contains(c)) {
+            continue;
+        }
+        let row : String = line.chars().filter(|&c| c != '|' && c != ' ').collect();
+        if row.chars().count() != 9 {
+            return Err(line_error(i + 1, format!("Expected a row of nine squares, got {}", line)));
+        }
+        if let Some(c) = row.chars().find(|&c| ! c.is_ascii_digit() && ! ROW_BLANKS.contains(c)) {
+            return Err(line_error(i + 1, format!("Expected a digit or one of {} for a blank, got {}", ROW_BLANKS, c)));
+        }
+        grid.push_str(&row);
+        rows += 1;
+        if rows == 9 {
+            let puzzle : Puzzle = grid.parse().map_err(|e| line_error(i + 1, e))?;
+            puzzles.push(puzzle.with_provenance(provenance));
+            provenance = Provenance::default();
+            grid.clear();
+            rows = 0;
+        }
+    }
+    if rows > 0 {
+        return Err(format!("Last puzzle has {} of 9 rows", rows));
+    }
+    Ok(puzzles)
+}
+
+// Puzzles separated by a blank line, each with its headers first.
+fn write_rows<H>(puzzles : &[Puzzle], headers : H, borders : bool) -> String
+    where H : Fn(&Provenance) -> String {
+    let mut text = String::new();
+    for (i, puzzle) in puzzles.iter().enumerate() {
+        if i > 0 {
+            text.push('\n');
+        }
+        text.push_str(&headers(puzzle.provenance()));
+        let squares : Vec<char> = puzzle.to_grid_string().chars().collect();
+        for (r, cells) in squares.chunks(9).enumerate() {
+            if borders && r > 0 && r % 3 == 0 {
+                text.push_str("------+-------+------\n");
+            }
+            if borders {
+                text.push_str(&block_row(cells));
+            } else {
+                text.extend(cells.iter());
+            }
+            text.push('\n');
+        }
+    }
+    text
+}
+
+fn block_row(cells : &[char]) -> String {
+    let mut row = String::new();
+    for (c, ch) in cells.iter().enumerate() {
+        if c > 0 {
+            row.push_str(if c % 3 == 0 { " | " } else { " " });
+        }
+        row.push(*ch);
+    }
+    row
+}
+
+// Splits one CSV record, honouring double quoted fields with "" for a quote. Records don't span
+// lines.
+fn csv_fields(line : &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if ! quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c)
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field".to_string());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+fn csv_field(value : &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Columns are found by the header, so they may come in any order and unknown ones are ignored.
+fn read_csv(text : &str) -> Result<Vec<Puzzle>, String> {
+    // `#` lines such as the license headers are comments
+    let mut lines = text.lines().enumerate().filter(|&(_, line)| ! line.trim().is_empty() && ! line.trim().starts_with('#'));
+    let header = match lines.next() {
+        Some((_, line)) => csv_fields(line.trim())?,
+        None => return Ok(Vec::new())
+    };
+    let column = |name : &str| header.iter().position(|h| h.trim() == name);
+    let grid = match column("puzzle") {
+        Some(grid) => grid,
+        None => return Err("CSV header needs a puzzle column".to_string())
+    };
+    let mut puzzles = Vec::new();
+    for (i, line) in lines {
+        let fields = csv_fields(line.trim()).map_err(|e| line_error(i + 1, e))?;
+        let mut puzzle : Puzzle = match fields.get(grid) {
+            Some(grid) => grid.parse().map_err(|e| line_error(i + 1, e))?,
+            None => return Err(line_error(i + 1, "Missing puzzle column".to_string()))
+        };
+        for key in CSV_COLUMNS[1..].iter() {
+            match column(key).and_then(|c| fields.get(c)) {
+                Some(value) if ! value.is_empty() => puzzle.provenance_mut().set(key, value).map_err(|e| line_error(i + 1, e))?,
+                _ => ()
+            }
+        }
+        puzzles.push(puzzle);
+    }
+    Ok(puzzles)
+}
+
+fn write_csv(puzzles : &[Puzzle]) -> String {
+    let mut text = CSV_COLUMNS.join(",");
+    text.push('\n');
+    for puzzle in puzzles {
+        let provenance = puzzle.provenance();
+        let values = [Some(puzzle.to_grid_string()), provenance.author.clone(), provenance.source.clone(),
+                      provenance.license.clone(), provenance.created.clone()];
+        let fields : Vec<String> = values.iter().map(|v| v.as_ref().map_or(String::new(), |v| csv_field(v))).collect();
+        text.push_str(&fields.join(","));
+        text.push('\n');
+    }
+    text
+}
+
+fn read_json(text : &str) -> Result<Vec<Puzzle>, String> {
+    let json = Json::parse(text)?;
+    let list = match json.get("puzzles").unwrap_or(&json).as_array() {
+        Some(list) => list,
+        None => return Err("Expected an array of puzzles".to_string())
+    };
+    list.iter().enumerate().map(|(i, p)| Puzzle::from_json(p).map_err(|e| format!("puzzle {}: {}", i + 1, e))).collect()
+}
+
+// The format of text such as a user pastes, going by its shape: JSON, CSV with its header, lines
+// of 729 or 81 characters, or rows of nine with or without box borders. Formats that fit the
+// shape are tried first and the others after, and the first to read at least one puzzle wins.
+// When none does, the error lists every format tried and what went wrong with it.
+pub fn detect_format(text : &str) -> Result<Format, String> {
+    read_detected(text).map(|(format, _)| format)
+}
+
+// The puzzles of text in the format `detect_format` finds.
+pub fn read_detected(text : &str) -> Result<(Format, Vec<Puzzle>), String> {
+    let mut tried = Vec::new();
+    for format in likely_formats(text) {
+        match format.read(text) {
+            Ok(ref puzzles) if puzzles.is_empty() => tried.push(format!("{}: no puzzles", format.name())),
+            Ok(puzzles) => return Ok((format, puzzles)),
+            Err(e) => tried.push(format!("{}: {}", format.name(), e))
+        }
+    }
+    Err(format!("Unrecognised puzzle format, tried {}", tried.join("; ")))
+}
+
+// Every format, those the first line of content suggests first. sdk and block read each other's
+// rows, so which of the two comes first decides the name: block where there are box borders.
+fn likely_formats(text : &str) -> Vec<Format> {
+    let content : Vec<&str> = text.lines().map(|line| line.trim()).filter(|line| ! line.is_empty() && ! line.starts_with('#')).collect();
+    let first = content.first().cloned().unwrap_or("");
+    let bordered = content.iter().any(|line| line.contains('|') || line.chars().all(|c| "-+ ".contains(c)));
+    let rows = if bordered { vec![Format::Block, Format::Sdk] } else { vec![Format::Sdk, Format::Block] };
+    let mut likely = if first.starts_with('{') || (first.starts_with('[') && first != "[Puzzle]") {
+        vec![Format::Json]
+    } else if first.contains(',') {
+        vec![Format::Csv]
+    } else {
+        match first.chars().count() {
+            SUKAKU_LENGTH => vec![Format::Sukaku],
+            81 => vec![Format::Sdm],
+            _ => rows
+        }
+    };
+    for format in Format::all() {
+        if ! likely.contains(&format) {
+            likely.push(format);
+        }
+    }
+    likely
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK : &str = "\
+4 . . | . . . | 8 . 5
+. 3 . | . . . | . . .
+. . . | 7 . . | . . .
+------+-------+------
+. 2 . | . . . | . 6 .
+. . . | . 8 . | 4 . .
+. . . | . 1 . | . . .
+------+-------+------
+. . . | 6 . 3 | . 7 .
+5 . . | 2 . . | . . .
+1 . 4 | . . . | . . .
+";
+
+    #[test]
+    fn rows_are_counted_by_line() {
+        assert_eq!(Format::Block.read(BLOCK).unwrap().len(), 1);
+        let accented = BLOCK.replacen("4 . .", "4 é .", 1);
+        assert_eq!(Format::Block.read(&accented), Err("line 1: Expected a digit or one of ._*xX for a blank, got é".to_string()));
+        let short : String = BLOCK.lines().take(5).map(|line| format!("{}\n", line)).collect();
+        assert_eq!(Format::Block.read(&short), Err("Last puzzle has 4 of 9 rows".to_string()));
+    }
+
+    fn puzzles() -> Vec<Puzzle> {
+        let mut first = Format::Block.read(BLOCK).unwrap().remove(0);
+        first.provenance_mut().set("author", "Doe, Jane").unwrap();
+        first.provenance_mut().set("created", "2024-02-29").unwrap();
+        let second : Puzzle = format!("{:.>81}", "9").parse().unwrap();
+        vec![first, second]
+    }
+
+    #[test]
+    fn the_collection_license_is_written_as_headers_and_read_back() {
+        let license = License {
+            holder : Some("Example Puzzles".to_string()),
+            url : Some("https://example.com/terms".to_string()),
+            terms : Some("Attribution required.\nNo resale.".to_string()),
+            ..License::new("CC-BY-4.0")
+        };
+        for format in [Format::Sdm, Format::Sdk, Format::Csv, Format::Json].iter() {
+            let text = format.write_licensed(&puzzles(), Some(&license));
+            assert_eq!(format.read_license(&text), Ok(Some(license.clone())), "{}", format.name());
+            assert_eq!(format.read(&text), Ok(puzzles()), "{}", format.name());
+            assert_eq!(format.read_license(&format.write(&puzzles())), Ok(None), "{}", format.name());
+        }
+        let sdm = Format::Sdm.write_licensed(&[], Some(&license));
+        assert!(sdm.starts_with("# collection-license: CC-BY-4.0\n# collection-holder: Example Puzzles\n"));
+        assert!(sdm.ends_with("# collection-terms: Attribution required.\n# collection-terms: No resale.\n"));
+        assert!(Format::Sdk.write_licensed(&[], Some(&license)).starts_with("#Ccollection-license: CC-BY-4.0\n"));
+
+        assert_eq!(Format::Sdm.read_license("# collection-holder: Example Puzzles\n"),
+                   Err("License headers without a collection-license header".to_string()));
+        assert_eq!(Format::Sdm.read_license("# author: Jane\n# collection-url:\n"), Err("line 2: collection-url needs a value".to_string()));
+        assert_eq!(Format::Json.read_license("{\"license\": {\"holder\": \"Example Puzzles\"}, \"puzzles\": []}"),
+                   Err("License needs a name".to_string()));
+    }
+
+    #[test]
+    fn every_format_reads_back_what_it_writes() {
+        // by grid, since sukaku reads blanks as left out rather than as zeros
+        let fields = |puzzles : &[Puzzle]| puzzles.iter().map(|p| (p.to_grid_string(), p.provenance().clone())).collect::<Vec<_>>();
+        for format in Format::all() {
+            let read = format.read(&format.write(&puzzles())).unwrap();
+            assert_eq!(fields(&read), fields(&puzzles()), "{}", format.name());
+        }
+    }
+
+    #[test]
+    fn every_format_is_detected_from_what_it_writes() {
+        for format in Format::all() {
+            let text = format.write(&puzzles());
+            let (detected, read) = read_detected(&text).unwrap();
+            assert_eq!((detected.name(), read.len()), (format.name(), 2));
+        }
+        // rows without borders are sdk, with them block
+        assert_eq!(detect_format(&BLOCK.replace(" | ", " ").replace("------+-------+------\n", "")), Ok(Format::Sdk));
+        assert_eq!(detect_format(BLOCK), Ok(Format::Block));
+        assert_eq!(detect_format("# author: Jane\n\n"),
+                   Err("Unrecognised puzzle format, tried sdk: no puzzles; block: no puzzles; sdm: no puzzles; sukaku: no puzzles; \
+                        csv: no puzzles; json: Unexpected character at offset 0".to_string()));
+    }
+}
@@ -0,0 +1,1115 @@
+use std;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use analysis::{self, Difficulty};
+use calibration::Calibration;
+use logic::{LogicalSolver, SolveStep, Technique};
+use pack::Pack;
+use sudoku::{parse_square, square_name, Config, Digit, Solver, SquareId, SquareValue, StartState, StartValue};
+
+// Version of the `Game::save` format.
+pub const SAVE_VERSION : u32 = 3;
+
+// Entries carry the pencil marks toggled alongside them by the candidate mode, so undo restores both.
+#[derive(Clone, Debug, PartialEq)]
+enum Move {
+    Entry(SquareId, Option<SquareValue>, Option<SquareValue>, Vec<(SquareId, SquareValue)>),
+    Marks(Vec<(SquareId, SquareValue)>),
+    Given(SquareId, Option<SquareValue>, Option<SquareValue>)
+}
+
+// Whether placing and erasing change the givens or the player's entries. A game made with
+// `Game::setup` starts with modifiable givens, entered like in an editor, until `lock_givens`
+// checks the puzzle and fixes them; from then on nothing the player does, undo included, can
+// touch a given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Givens {
+    Locked,
+    Modifiable
+}
+
+impl Givens {
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Givens::Locked => "locked",
+            Givens::Modifiable => "modifiable"
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CandidateMode {
+    // Pencil marks are only changed by the player.
+    Manual,
+    // Placing a digit removes it from the marks of the square's peers.
+    AutoPrune,
+    // Marks always hold every digit the current entries allow, until the player removes some.
+    AutoFill
+}
+
+impl CandidateMode {
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CandidateMode::Manual => "manual",
+            CandidateMode::AutoPrune => "auto_prune",
+            CandidateMode::AutoFill => "auto_fill"
+        }
+    }
+}
+
+// What `Game::smart_hint` gives away, a little more on each call for the same deduction.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Hint {
+    // An entry that disagrees with the solution, pointed out before any other help.
+    Mistake(SquareId),
+    // The squares the next deduction is about.
+    Region(Vec<SquareId>),
+    Technique(Technique),
+    // The candidates the deduction rules out.
+    Eliminations(Vec<StartValue>),
+    // The digit it leads to, now entered in the grid.
+    Placed(SquareId, SquareValue)
+}
+
+// A hint as `Game::hint_log` keeps it, for statistics and achievements that care how a game was
+// won and not just whether.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HintRecord {
+    // the play time it was asked for at, see `Game::elapsed`
+    pub elapsed : u64,
+    // the placement it leads to, made by the hint once `revealed` is 4
+    pub square : SquareId,
+    pub value : SquareValue,
+    // the hardest technique of the deduction for `smart_hint`, None for `hint` filling in a square
+    pub technique : Option<Technique>,
+    // how far it went, the stages of `smart_hint`: 1 the region, 2 the technique, 3 the
+    // eliminations, 4 the digit placed; always 4 for `hint`
+    pub revealed : u8
+}
+
+#[derive(Clone, Debug)]
+pub struct Scoring {
+    pub base : [(Difficulty, u64); 4],
+    pub par_seconds : [(Difficulty, u64); 4],
+    pub time_bonus_per_second : u64,
+    pub hint_penalty : u64,
+    pub mistake_penalty : u64
+}
+
+impl Default for Scoring {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scoring {
+
+    pub fn new() -> Scoring {
+        Scoring {
+            base : [(Difficulty::Easy, 1000), (Difficulty::Medium, 2000), (Difficulty::Hard, 4000), (Difficulty::Expert, 8000)],
+            par_seconds : [(Difficulty::Easy, 300), (Difficulty::Medium, 600), (Difficulty::Hard, 1200), (Difficulty::Expert, 2400)],
+            time_bonus_per_second : 1,
+            hint_penalty : 200,
+            mistake_penalty : 100
+        }
+    }
+
+    fn lookup(table : &[(Difficulty, u64)], difficulty : Difficulty) -> u64 {
+        table.iter().find(|&&(d, _)| d == difficulty).map_or(0, |&(_, v)| v)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    squares : Vec<SquareId>,
+    peers : HashMap<SquareId, Vec<SquareId>>,
+    difficulty : Difficulty,
+    candidate_mode : CandidateMode,
+    givens : HashMap<SquareId, SquareValue>,
+    solution : HashMap<SquareId, SquareValue>,
+    entries : HashMap<SquareId, SquareValue>,
+    marks : HashMap<SquareId, BTreeSet<SquareValue>>,
+    undo_stack : Vec<Move>,
+    redo_stack : Vec<Move>,
+    mistake_count : usize,
+    hints_used : usize,
+    // every hint counted in `hints_used`, in order
+    hint_log : Vec<HintRecord>,
+    // the position of the last smart hint, the steps behind it and how far it has gone
+    hint_progress : Option<(StartState, Vec<SolveStep>, usize)>,
+    // xorshift state choosing among equally easy hints, see `set_hint_seed`
+    hint_random : Option<u64>,
+    givens_mode : Givens,
+    elapsed : u64,
+    // the key of the pack the puzzle came from and its index there, see `from_pack`
+    pack_puzzle : Option<(u32, usize)>
+}
+
+impl Game {
+
+    pub fn new(solver : &Solver, start_state : StartState) -> Result<Game, String> {
+        start_state.validate(solver.config()).map_err(|e| e.to_string())?;
+        if solver.count_solutions(start_state.clone(), 2) != 1 {
+            return Err("Puzzle does not have a unique solution".to_string());
+        }
+        let solution = solver.solve(start_state.clone())?;
+        let squares = solver.config().squares().clone();
+        let difficulty = Calibration::default().rate(&LogicalSolver::new(), &start_state)?;
+        Ok(Game {
+            solution : squares.iter().map(|s| (*s, solution.value(s).unwrap())).collect(),
+            peers : peer_lists(solver.config()),
+            squares,
+            difficulty,
+            candidate_mode : CandidateMode::Manual,
+            givens : start_state.into_iter().filter(|&(_, v)| v != 0).collect(),
+            entries : HashMap::new(),
+            marks : HashMap::new(),
+            undo_stack : Vec::new(),
+            redo_stack : Vec::new(),
+            mistake_count : 0,
+            hints_used : 0,
+            hint_log : Vec::new(),
+            hint_progress : None,
+            hint_random : None,
+            givens_mode : Givens::Locked,
+            elapsed : 0,
+            pack_puzzle : None
+        })
+    }
+
+    // The i:th puzzle of the pack, remembered through `save` and `load` so the finished game can
+    // go to the pack's `PackProgress`.
+    pub fn from_pack(solver : &Solver, pack : &Pack, i : usize) -> Result<Game, String> {
+        let puzzle = pack.get(i).ok_or_else(|| format!("Pack has no puzzle {}", i + 1))?;
+        let mut game = Game::new(solver, puzzle.start_state().clone())?;
+        game.pack_puzzle = Some((pack.key(), i));
+        Ok(game)
+    }
+
+    // The pack key and index of a game made with `from_pack`.
+    pub fn pack_puzzle(&self) -> Option<(u32, usize)> {
+        self.pack_puzzle
+    }
+
+    // An empty grid whose givens are entered with `place` and `erase`. There is no solution, so
+    // no hints or mistakes, and the difficulty is a placeholder until `lock_givens`.
+    pub fn setup() -> Game {
+        let config = Config::new();
+        Game {
+            squares : config.squares().clone(),
+            peers : peer_lists(&config),
+            difficulty : Difficulty::Easy,
+            candidate_mode : CandidateMode::Manual,
+            givens : HashMap::new(),
+            solution : HashMap::new(),
+            entries : HashMap::new(),
+            marks : HashMap::new(),
+            undo_stack : Vec::new(),
+            redo_stack : Vec::new(),
+            mistake_count : 0,
+            hints_used : 0,
+            hint_log : Vec::new(),
+            hint_progress : None,
+            hint_random : None,
+            givens_mode : Givens::Modifiable,
+            elapsed : 0,
+            pack_puzzle : None
+        }
+    }
+
+    // Fixes the givens for play once they make a puzzle with a unique solution, keeping the
+    // candidate mode and the time spent. The undo history goes with the setup.
+    pub fn lock_givens(&mut self, solver : &Solver) -> Result<(), String> {
+        if self.givens_mode == Givens::Locked {
+            return Ok(());
+        }
+        let start_state : StartState = self.givens.iter().map(|(s, v)| (*s, *v)).collect();
+        let game = Game::new(solver, start_state)?;
+        *self = Game {
+            candidate_mode : self.candidate_mode,
+            hint_random : self.hint_random,
+            elapsed : self.elapsed,
+            ..game
+        };
+        // AutoFill had nothing to fill in while the givens could change; its marks start here,
+        // without a move to undo
+        if self.candidate_mode == CandidateMode::AutoFill {
+            let toggles = self.missing_candidates();
+            self.apply(&Move::Marks(toggles), true);
+        }
+        Ok(())
+    }
+
+    pub fn givens_mode(&self) -> Givens {
+        self.givens_mode
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    pub fn candidate_mode(&self) -> CandidateMode {
+        self.candidate_mode
+    }
+
+    // Switching to AutoFill fills in the marks right away, as one undoable move; in setup they
+    // are filled in by `lock_givens`.
+    pub fn set_candidate_mode(&mut self, mode : CandidateMode) {
+        self.candidate_mode = mode;
+        if mode == CandidateMode::AutoFill && self.givens_mode == Givens::Locked {
+            let toggles = self.missing_candidates();
+            if ! toggles.is_empty() {
+                self.perform(Move::Marks(toggles));
+            }
+        }
+    }
+
+    // The marks AutoFill would add: every digit an empty square allows that isn't marked yet.
+    fn missing_candidates(&self) -> Vec<(SquareId, SquareValue)> {
+        self.squares.iter()
+                    .filter(|s| self.value(s).is_none())
+                    .flat_map(|s| (1..10).map(move |v| (*s, v)))
+                    .filter(|&(s, v)| ! self.has_mark(&s, v) && self.allows(&s, v))
+                    .collect()
+    }
+
+    pub fn given(&self, square : &SquareId) -> Option<SquareValue> {
+        self.givens.get(square).cloned()
+    }
+
+    // The given or the player's entry, whichever the square holds.
+    pub fn value(&self, square : &SquareId) -> Option<SquareValue> {
+        self.given(square).or_else(|| self.entries.get(square).cloned())
+    }
+
+    pub fn marks(&self, square : &SquareId) -> Vec<SquareValue> {
+        self.marks.get(square).map(|ms| ms.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    // Enters a digit, or sets a given while the givens are modifiable.
+    pub fn place(&mut self, square : &SquareId, value : SquareValue) -> Result<(), String> {
+        self.check_square(square)?;
+        Digit::try_from(value)?;
+        if self.givens_mode == Givens::Modifiable {
+            let before = self.givens.get(square).cloned();
+            self.perform(Move::Given(*square, before, Some(value)));
+            return Ok(());
+        }
+        self.set_entry(square, Some(value));
+        if self.solution.get(square) != Some(&value) {
+            self.mistake_count += 1;
+        }
+        Ok(())
+    }
+
+    pub fn erase(&mut self, square : &SquareId) -> Result<(), String> {
+        self.check_square(square)?;
+        if self.givens_mode == Givens::Modifiable {
+            if let Some(&before) = self.givens.get(square) {
+                self.perform(Move::Given(*square, Some(before), None));
+            }
+            return Ok(());
+        }
+        if self.entries.contains_key(square) {
+            self.set_entry(square, None);
+        }
+        Ok(())
+    }
+
+    pub fn toggle_mark(&mut self, square : &SquareId, value : SquareValue) -> Result<(), String> {
+        self.check_square(square)?;
+        if self.givens_mode == Givens::Modifiable {
+            return Err("Marks wait until the givens are locked".to_string());
+        }
+        Digit::try_from(value)?;
+        self.perform(Move::Marks(vec![(*square, value)]));
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> bool {
+        match self.undo_stack.pop() {
+            Some(m) => {
+                self.apply(&m, false);
+                self.redo_stack.push(m);
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        match self.redo_stack.pop() {
+            Some(m) => {
+                self.apply(&m, true);
+                self.undo_stack.push(m);
+                true
+            },
+            None => false
+        }
+    }
+
+    // Squares whose current entry disagrees with the solution.
+    pub fn mistakes(&self) -> Vec<SquareId> {
+        self.squares.iter()
+                    .filter(|s| self.entries.get(s).is_some_and(|v| self.solution.get(s) != Some(v)))
+                    .cloned()
+                    .collect()
+    }
+
+    // Pairs of squares sharing a unit and holding the same digit, givens and entries alike, each
+    // pair once and in A1..I9 order. Unlike `mistakes` this checks the board against itself, not
+    // the solution, so it also works while setting up givens and for players who'd rather not
+    // be told about wrong digits that break no rule yet.
+    pub fn conflicts(&self) -> Vec<(SquareId, SquareId)> {
+        let mut conflicts = Vec::new();
+        for a in &self.squares {
+            if let Some(v) = self.value(a) {
+                conflicts.extend(self.peers.get(a).unwrap().iter()
+                                                           .filter(|b| *b > a && self.value(b) == Some(v))
+                                                           .map(|b| (*a, *b)));
+            }
+        }
+        conflicts
+    }
+
+    // Whether putting the value in the square would repeat a digit already in one of its units,
+    // whatever the square holds now; a look at its twenty peers, cheap enough to run for every
+    // digit button each time a square is selected.
+    pub fn would_conflict(&self, square : &SquareId, value : SquareValue) -> bool {
+        self.peers.get(square).is_some_and(|peers| peers.iter().any(|p| self.value(p) == Some(value)))
+    }
+
+    // Wrong entries made over the whole game, including those since erased or undone.
+    pub fn mistake_count(&self) -> usize {
+        self.mistake_count
+    }
+
+    // With a seed, hints choose among the equally easy ones with a generator started from it, so
+    // repeated hints don't keep going to the same corner while a seeded game still plays the same
+    // every time. None, the default, always takes the first. The seed is not saved.
+    pub fn set_hint_seed(&mut self, seed : Option<u64>) {
+        self.hint_random = seed.map(|seed| seed.wrapping_mul(0x9e3779b97f4a7c15) | 1);
+    }
+
+    // Fills in the first empty or wrong square from the solution, or any of them with a hint seed.
+    pub fn hint(&mut self) -> Option<SquareId> {
+        if self.givens_mode == Givens::Modifiable {
+            return None;
+        }
+        let open : Vec<SquareId> = self.squares.iter()
+                                               .filter(|s| self.value(s) != self.solution.get(s).cloned())
+                                               .cloned()
+                                               .collect();
+        let square = match open.len() {
+            0 => None,
+            n => Some(open[self.choose(n)])
+        };
+        if let Some(s) = square {
+            let value = self.solution.get(&s).cloned();
+            self.set_entry(&s, value);
+            self.hints_used += 1;
+            self.hint_log.push(HintRecord {
+                elapsed : self.elapsed,
+                square : s,
+                value : value.unwrap(),
+                technique : None,
+                revealed : 4
+            });
+        }
+        square
+    }
+
+    // Hints that teach before they tell. Asked again for the same position, it first shows where
+    // to look, then names the technique, then the eliminations, and last places the digit; a
+    // move in between starts over from the new position. Wrong entries are pointed out first.
+    // Counts as one hint used however far it goes.
+    pub fn smart_hint(&mut self) -> Option<Hint> {
+        if self.givens_mode == Givens::Modifiable {
+            return None;
+        }
+        if let Some(square) = self.mistakes().into_iter().next() {
+            self.hint_progress = None;
+            return Some(Hint::Mistake(square));
+        }
+        let start_state : StartState = self.squares.iter().filter_map(|s| self.value(s).map(|v| (*s, v))).collect();
+        let (steps, stage) = match self.hint_progress.take() {
+            Some((position, steps, stage)) if position == start_state => (steps, stage + 1),
+            _ => {
+                let steps = match self.hint_random {
+                    Some(_) => LogicalSolver::new().next_placement_choosing(&start_state, &mut |n| self.choose(n)).ok()?,
+                    None => LogicalSolver::new().next_placement(&start_state).ok()?
+                };
+                if steps.is_empty() {
+                    return None;
+                }
+                (steps, 0)
+            }
+        };
+        let (square, value) = steps.last().and_then(|step| step.placements.first().cloned())?;
+        if stage == 0 {
+            self.hints_used += 1;
+            self.hint_log.push(HintRecord {
+                elapsed : self.elapsed,
+                square,
+                value,
+                technique : Some(analysis::path_score(&steps).hardest.unwrap_or(Technique::Reveal)),
+                revealed : 1
+            });
+        } else if let Some(record) = self.hint_log.last_mut() {
+            record.revealed = (stage as u8 + 1).min(4);
+        }
+        let hint = match stage {
+            0 => {
+                let mut cells : Vec<SquareId> = Vec::new();
+                for &s in steps.iter().flat_map(|step| step.cells.iter()) {
+                    if ! cells.contains(&s) {
+                        cells.push(s);
+                    }
+                }
+                Hint::Region(cells)
+            },
+            1 => Hint::Technique(analysis::path_score(&steps).hardest.unwrap_or(Technique::Reveal)),
+            2 => Hint::Eliminations(steps.iter().flat_map(|step| step.eliminations.iter().cloned()).collect()),
+            _ => {
+                self.set_entry(&square, Some(value));
+                self.hint_progress = None;
+                return Some(Hint::Placed(square, value));
+            }
+        };
+        self.hint_progress = Some((start_state, steps, stage));
+        Some(hint)
+    }
+
+    // One of n equally easy choices: the first without a hint seed.
+    fn choose(&mut self, n : usize) -> usize {
+        match self.hint_random {
+            Some(ref mut x) => {
+                *x ^= *x << 13;
+                *x ^= *x >> 7;
+                *x ^= *x << 17;
+                (*x % n as u64) as usize
+            },
+            None => 0
+        }
+    }
+
+    pub fn hints_used(&self) -> usize {
+        self.hints_used
+    }
+
+    // The hints behind `hints_used`, oldest first. Games saved before version 3 have none.
+    pub fn hint_log(&self) -> &[HintRecord] {
+        &self.hint_log
+    }
+
+    // Whether the square holds the digit a hint placed there, rather than one the player entered.
+    // Entering the same digit again after erasing it still counts as the hint's.
+    pub fn filled_by_hint(&self, square : &SquareId) -> bool {
+        self.entries.get(square).is_some_and(|&v| {
+            self.hint_log.iter().any(|record| record.revealed == 4 && record.square == *square && record.value == v)
+        })
+    }
+
+    // Whether the square holds an entry of the player's own, see `filled_by_hint`.
+    pub fn filled_by_player(&self, square : &SquareId) -> bool {
+        self.entries.contains_key(square) && ! self.filled_by_hint(square)
+    }
+
+    // Complete with no hint asked for along the way, even one that only showed where to look.
+    // Pointing out a mistake doesn't count as a hint.
+    pub fn completed_without_hints(&self) -> bool {
+        self.is_complete() && self.hints_used == 0
+    }
+
+    // Play time in milliseconds, advanced by the frontend since the game has no clock of its own.
+    pub fn add_elapsed(&mut self, ms : u64) {
+        self.elapsed += ms;
+    }
+
+    pub fn elapsed(&self) -> u64 {
+        self.elapsed
+    }
+
+    // Base points for the difficulty plus a bonus for every second under par, minus the penalties.
+    pub fn score(&self, scoring : &Scoring) -> u64 {
+        let seconds = self.elapsed / 1000;
+        let par = Scoring::lookup(&scoring.par_seconds, self.difficulty);
+        let bonus = par.saturating_sub(seconds) * scoring.time_bonus_per_second;
+        let penalty = self.hints_used as u64 * scoring.hint_penalty + self.mistake_count as u64 * scoring.mistake_penalty;
+        (Scoring::lookup(&scoring.base, self.difficulty) + bonus).saturating_sub(penalty)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.givens_mode == Givens::Locked && self.squares.iter().all(|s| self.value(s) == self.solution.get(s).cloned())
+    }
+
+    // 0 to 3 for a completed game: three without hints or mistakes, two without hints, else one.
+    pub fn stars(&self) -> u8 {
+        match (self.is_complete(), self.hints_used, self.mistake_count) {
+            (false, _, _) => 0,
+            (true, 0, 0) => 3,
+            (true, 0, _) => 2,
+            _ => 1
+        }
+    }
+
+    // v3;givens;solution;entries;marks;elapsed;hints;mistakes;difficulty;mode;givens mode;pack;hint log where the
+    // grids are 81 chars with '.' for blanks and the marks are a 3 hex digit bit mask per square. The solution is
+    // blank while the givens are modifiable. Undo history is not kept. The pack is its key in hex and the puzzle's
+    // index, "1c2b3a4d:17", or blank for games not from a pack. The hint log is square:digit:elapsed:revealed:technique
+    // per hint, "C4:7:95000:2:x_wing,A1:5:120000:4:", the technique blank for `hint`.
+    pub fn save(&self) -> String {
+        fn grid(squares : &[SquareId], values : &HashMap<SquareId, SquareValue>) -> String {
+            squares.iter()
+                   .map(|s| values.get(s).and_then(|&v| std::char::from_digit(v, 10)).unwrap_or('.'))
+                   .collect()
+        }
+        let marks : String = self.squares.iter()
+                                         .map(|s| format!("{:03x}", self.marks(s).iter().fold(0, |m, v| m | (1 << (v - 1)))))
+                                         .collect();
+        let pack = self.pack_puzzle.map_or(String::new(), |(key, i)| format!("{:08x}:{}", key, i));
+        let hint_log : Vec<String> = self.hint_log.iter()
+                                                  .map(|h| format!("{}:{}:{}:{}:{}", square_name(&h.square), h.value, h.elapsed, h.revealed,
+                                                                   h.technique.map_or("", |t| t.id())))
+                                                  .collect();
+        format!("v{};{};{};{};{};{};{};{};{};{};{};{};{}", SAVE_VERSION, grid(&self.squares, &self.givens), grid(&self.squares, &self.solution),
+                grid(&self.squares, &self.entries), marks, self.elapsed, self.hints_used, self.mistake_count,
+                self.difficulty.name(), self.candidate_mode.name(), self.givens_mode.name(), pack, hint_log.join(","))
+    }
+
+    // Reads saves of any version so far. Version 1 had no version field, and ended after the
+    // mode or the givens mode (then locked) or the pack. Version 2 had no hint log.
+    pub fn load(saved : &str) -> Result<Game, String> {
+        fn grid(squares : &[SquareId], s : &str) -> Result<HashMap<SquareId, SquareValue>, String> {
+            if s.chars().count() != squares.len() {
+                return Err("Incorrect grid length".to_string());
+            }
+            Ok(squares.iter().cloned()
+                      .zip(s.chars())
+                      .filter_map(|(sq, c)| c.to_digit(10).and_then(|v| if v > 0 { Some((sq, v)) } else { None }))
+                      .collect())
+        }
+        fn number(s : &str) -> Result<u64, String> {
+            s.parse().map_err(|_| format!("Invalid number {}", s))
+        }
+        let mut fields : Vec<&str> = saved.trim().split(';').collect();
+        let version = match fields[0].strip_prefix('v') {
+            Some(version) => version.parse::<u32>().map_err(|_| format!("Invalid save version {}", fields[0]))?,
+            None => 1
+        };
+        if version > SAVE_VERSION {
+            return Err(format!("Save version {} is newer than this build reads, up to {}", version, SAVE_VERSION));
+        }
+        if version > 1 {
+            fields.remove(0);
+            if fields.len() != if version > 2 { 12 } else { 11 } {
+                return Err("Incorrect number of fields".to_string());
+            }
+        } else if fields.len() < 9 || fields.len() > 11 {
+            return Err("Incorrect number of fields".to_string());
+        }
+        let config = Config::new();
+        let squares = config.squares().clone();
+        if fields[3].len() != squares.len() * 3 {
+            return Err("Incorrect marks length".to_string());
+        }
+        let mut marks = HashMap::new();
+        for (i, s) in squares.iter().enumerate() {
+            // by bytes, so a multi-byte character is invalid marks rather than a slice off a char boundary
+            let hex = fields[3].get(i * 3..i * 3 + 3).ok_or_else(|| "Invalid marks".to_string())?;
+            let mask = u32::from_str_radix(hex, 16).map_err(|_| "Invalid marks".to_string())?;
+            if mask != 0 {
+                marks.insert(*s, (1..10).filter(|v| mask & (1 << (v - 1)) != 0).collect());
+            }
+        }
+        let difficulty : Difficulty = fields[7].parse()?;
+        let candidate_mode = match [CandidateMode::Manual, CandidateMode::AutoPrune, CandidateMode::AutoFill].iter().find(|m| m.name() == fields[8]) {
+            Some(&m) => m,
+            None => return Err(format!("Unknown candidate mode {}", fields[8]))
+        };
+        let givens_mode = match fields.get(9) {
+            None => Givens::Locked,
+            Some(name) => match [Givens::Locked, Givens::Modifiable].iter().find(|g| g.name() == *name) {
+                Some(&g) => g,
+                None => return Err(format!("Unknown givens mode {}", name))
+            }
+        };
+        let pack_puzzle = match fields.get(10) {
+            None | Some(&"") => None,
+            Some(pack) => {
+                let invalid = || format!("Invalid pack puzzle {}", pack);
+                let mut parts = pack.splitn(2, ':');
+                let key = u32::from_str_radix(parts.next().unwrap_or(""), 16).map_err(|_| invalid())?;
+                let i : usize = parts.next().unwrap_or("").parse().map_err(|_| invalid())?;
+                Some((key, i))
+            }
+        };
+        let mut hint_log = Vec::new();
+        for record in fields.get(11).map_or("", |log| *log).split(',').filter(|record| ! record.is_empty()) {
+            let invalid = || format!("Invalid hint record {}", record);
+            let parts : Vec<&str> = record.split(':').collect();
+            if parts.len() != 5 {
+                return Err(invalid());
+            }
+            let revealed : u8 = parts[3].parse().map_err(|_| invalid())?;
+            hint_log.push(HintRecord {
+                elapsed : number(parts[2])?,
+                square : parse_square(parts[0]).ok_or_else(invalid)?,
+                value : Digit::new(number(parts[1])? as SquareValue).ok_or_else(invalid)?.get(),
+                technique : match parts[4] {
+                    "" => None,
+                    id => Some(Technique::from_id(id).ok_or_else(invalid)?)
+                },
+                revealed : if (1..=4).contains(&revealed) { revealed } else { return Err(invalid()) }
+            });
+        }
+        let game = Game {
+            peers : peer_lists(&config),
+            difficulty,
+            candidate_mode,
+            givens : grid(&squares, fields[0])?,
+            solution : grid(&squares, fields[1])?,
+            entries : grid(&squares, fields[2])?,
+            marks,
+            squares,
+            undo_stack : Vec::new(),
+            redo_stack : Vec::new(),
+            mistake_count : number(fields[6])? as usize,
+            hints_used : number(fields[5])? as usize,
+            hint_log,
+            hint_progress : None,
+            hint_random : None,
+            givens_mode,
+            elapsed : number(fields[4])?,
+            pack_puzzle
+        };
+        if game.givens_mode == Givens::Locked && game.solution.len() != game.squares.len() {
+            return Err("Incomplete solution".to_string());
+        }
+        Ok(game)
+    }
+
+    fn check_square(&self, square : &SquareId) -> Result<(), String> {
+        if ! self.peers.contains_key(square) {
+            return Err(format!("Unknown square {}", square_name(square)));
+        }
+        if self.givens_mode == Givens::Locked && self.givens.contains_key(square) {
+            return Err(format!("Square {} is a given", square_name(square)));
+        }
+        Ok(())
+    }
+
+    fn has_mark(&self, square : &SquareId, value : SquareValue) -> bool {
+        self.marks.get(square).is_some_and(|ms| ms.contains(&value))
+    }
+
+    // Whether no peer currently holds the value.
+    fn allows(&self, square : &SquareId, value : SquareValue) -> bool {
+        self.peers.get(square).unwrap().iter().all(|p| self.value(p) != Some(value))
+    }
+
+    fn set_entry(&mut self, square : &SquareId, after : Option<SquareValue>) {
+        let before = self.entries.get(square).cloned();
+        self.apply(&Move::Entry(*square, before, after, Vec::new()), true);
+        let toggles = self.candidate_updates(square, before, after);
+        self.apply(&Move::Marks(toggles.clone()), true);
+        self.undo_stack.push(Move::Entry(*square, before, after, toggles));
+        self.redo_stack.clear();
+    }
+
+    // Marks to toggle after the entry in `square` changed from `before` to `after`.
+    fn candidate_updates(&self, square : &SquareId, before : Option<SquareValue>, after : Option<SquareValue>) -> Vec<(SquareId, SquareValue)> {
+        let mut toggles = Vec::new();
+        if self.candidate_mode == CandidateMode::Manual {
+            return toggles;
+        }
+        let peers = self.peers.get(square).unwrap();
+        if let Some(v) = after {
+            toggles.extend(peers.iter().filter(|p| self.has_mark(p, v)).map(|p| (*p, v)));
+        }
+        if self.candidate_mode == CandidateMode::AutoFill {
+            if let Some(b) = before.and_then(|b| if after != Some(b) { Some(b) } else { None }) {
+                toggles.extend(peers.iter()
+                                    .filter(|p| self.value(p).is_none() && ! self.has_mark(p, b) && self.allows(p, b))
+                                    .map(|p| (*p, b)));
+            }
+            if after.is_none() {
+                toggles.extend((1..10).filter(|&v| ! self.has_mark(square, v) && self.allows(square, v))
+                                      .map(|v| (*square, v)));
+            }
+        }
+        toggles
+    }
+
+    fn perform(&mut self, m : Move) {
+        self.apply(&m, true);
+        self.undo_stack.push(m);
+        self.redo_stack.clear();
+    }
+
+    fn apply(&mut self, m : &Move, forward : bool) {
+        match *m {
+            Move::Entry(s, before, after, ref toggles) => {
+                match if forward { after } else { before } {
+                    Some(v) => self.entries.insert(s, v),
+                    None => self.entries.remove(&s)
+                };
+                self.apply(&Move::Marks(toggles.clone()), forward);
+            },
+            Move::Marks(ref toggles) => {
+                for &(s, v) in toggles {
+                    let ms = self.marks.entry(s).or_default();
+                    if ! ms.remove(&v) {
+                        ms.insert(v);
+                    }
+                }
+            },
+            Move::Given(s, before, after) => {
+                match if forward { after } else { before } {
+                    Some(v) => self.givens.insert(s, v),
+                    None => self.givens.remove(&s)
+                };
+            }
+        }
+    }
+}
+
+fn peer_lists(config : &Config) -> HashMap<SquareId, Vec<SquareId>> {
+    config.squares().iter()
+                    .map(|s| {
+                        let mut peers : Vec<SquareId> = config.peers(s).iter().cloned().collect();
+                        peers.sort();
+                        (*s, peers)
+                    })
+                    .collect()
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Game {
+    fn serialize<S: ::serde::Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.save())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for Game {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer : D) -> Result<Game, D::Error> {
+        let saved = String::deserialize(deserializer)?;
+        Game::load(&saved).map_err(::serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SOLVED with the squares of the main diagonal, A1 to I9, left blank: one single after another.
+    fn game() -> Game {
+        let solver = Solver::new();
+        let start_state = solver.config().squares().iter().cloned().zip(SOLVED.chars().map(|c| c.to_digit(10).unwrap()))
+                                .enumerate().map(|(i, (s, v))| (s, if i % 10 == 0 { 0 } else { v })).collect();
+        Game::new(&solver, start_state).unwrap()
+    }
+
+    // The blank squares of `game` and their digits in the solution.
+    fn blanks() -> Vec<(SquareId, SquareValue)> {
+        (0..9u8).map(|i| (((b'A' + i) as char, (b'1' + i) as char), (SOLVED.as_bytes()[i as usize * 10] - b'0') as SquareValue)).collect()
+    }
+
+    fn finish(game : &mut Game) {
+        for (s, v) in blanks() {
+            game.place(&s, v).unwrap();
+        }
+    }
+
+    #[test]
+    fn multi_byte_marks_fail_to_load() {
+        let saved = game().save();
+        assert!(Game::load(&saved).is_ok());
+        let mut fields : Vec<String> = saved.split(';').map(|f| f.to_string()).collect();
+        // same byte length, but the second mark starts inside the two-byte character
+        fields[4] = format!("00é{}", &fields[4][4..]);
+        assert_eq!(Game::load(&fields.join(";")).err(), Some("Invalid marks".to_string()));
+    }
+
+    #[test]
+    fn undo_and_redo_step_through_entries_erasures_and_marks() {
+        let mut game = game();
+        let a1 = ('A', '1');
+        game.place(&a1, 4).unwrap();
+        assert_eq!(game.mistakes(), vec![a1]);
+        game.erase(&a1).unwrap();
+        game.toggle_mark(&a1, 5).unwrap();
+        game.place(&a1, 5).unwrap();
+        assert!(game.mistakes().is_empty());
+        assert!(game.undo());
+        assert_eq!((game.value(&a1), game.marks(&a1)), (None, vec![5]));
+        assert!(game.undo());
+        assert_eq!((game.value(&a1), game.marks(&a1)), (None, vec![]));
+        assert!(game.undo());
+        assert_eq!(game.value(&a1), Some(4));
+        assert!(game.undo());
+        assert_eq!(game.value(&a1), None);
+        assert!(! game.undo());
+        while game.redo() {}
+        assert_eq!((game.value(&a1), game.marks(&a1)), (Some(5), vec![5]));
+        // the wrong entry counts even though it's gone
+        assert_eq!(game.mistake_count(), 1);
+        game.undo();
+        game.toggle_mark(&('B', '2'), 7).unwrap();
+        assert!(! game.redo());
+        assert_eq!(game.place(&('A', '2'), 3), Err("Square A2 is a given".to_string()));
+    }
+
+    #[test]
+    fn saves_reload_and_older_versions_load() {
+        let mut game = game();
+        game.set_candidate_mode(CandidateMode::AutoPrune);
+        game.place(&('A', '1'), 5).unwrap();
+        game.toggle_mark(&('C', '3'), 8).unwrap();
+        game.add_elapsed(65000);
+        assert_eq!(game.hint(), Some(('B', '2')));
+        let saved = game.save();
+        assert!(saved.starts_with("v3;"));
+        let loaded = Game::load(&saved).unwrap();
+        assert_eq!(loaded.save(), saved);
+        assert_eq!((loaded.value(&('A', '1')), loaded.value(&('B', '2')), loaded.marks(&('C', '3'))), (Some(5), Some(7), vec![8]));
+        assert_eq!((loaded.elapsed(), loaded.hints_used(), loaded.hint_log()), (65000, 1, game.hint_log()));
+        assert_eq!(loaded.candidate_mode(), CandidateMode::AutoPrune);
+        // version 2 ended before the hint log
+        let v2 = format!("v2{}", &saved[2..saved.rfind(';').unwrap()]);
+        let loaded = Game::load(&v2).unwrap();
+        assert_eq!((loaded.value(&('B', '2')), loaded.hints_used(), loaded.hint_log().len()), (Some(7), 1, 0));
+        // version 1 had no version field and could end after the candidate mode
+        let v1 : Vec<&str> = saved.split(';').skip(1).take(9).collect();
+        assert_eq!(Game::load(&v1.join(";")).unwrap().givens_mode(), Givens::Locked);
+        assert_eq!(Game::load(&format!("v4{}", &saved[2..])).err(),
+                   Some("Save version 4 is newer than this build reads, up to 3".to_string()));
+        assert_eq!(Game::load(&v2.replacen("v2", "v3", 1)).err(), Some("Incorrect number of fields".to_string()));
+    }
+
+    #[test]
+    fn score_and_stars_follow_time_hints_and_mistakes() {
+        let scoring = Scoring::new();
+        let mut game = game();
+        let base = Scoring::lookup(&scoring.base, game.difficulty());
+        let par = Scoring::lookup(&scoring.par_seconds, game.difficulty());
+        game.add_elapsed(100 * 1000);
+        assert_eq!(game.score(&scoring), base + par - 100);
+        game.add_elapsed(par * 1000);
+        assert_eq!(game.score(&scoring), base);
+        assert_eq!(game.stars(), 0);
+        finish(&mut game);
+        assert_eq!(game.stars(), 3);
+
+        let mut game = self::game();
+        game.place(&('A', '1'), 4).unwrap();
+        finish(&mut game);
+        assert_eq!((game.stars(), game.score(&scoring)), (2, base + par - scoring.mistake_penalty));
+
+        let mut game = self::game();
+        game.hint();
+        finish(&mut game);
+        assert_eq!((game.stars(), game.score(&scoring)), (1, base + par - scoring.hint_penalty));
+        let harsh = Scoring {
+            hint_penalty : base + par + 1,
+            ..Scoring::new()
+        };
+        assert_eq!(game.score(&harsh), 0);
+    }
+
+    #[test]
+    fn candidate_modes_keep_the_marks() {
+        let (a1, b2, c3) = (('A', '1'), ('B', '2'), ('C', '3'));
+        let mut game = game();
+        game.toggle_mark(&a1, 5).unwrap();
+        game.toggle_mark(&a1, 8).unwrap();
+        game.toggle_mark(&b2, 8).unwrap();
+        game.place(&c3, 8).unwrap();
+        assert_eq!((game.marks(&a1), game.marks(&b2)), (vec![5, 8], vec![8]));
+        game.undo();
+        game.set_candidate_mode(CandidateMode::AutoPrune);
+        game.place(&c3, 8).unwrap();
+        assert_eq!((game.marks(&a1), game.marks(&b2)), (vec![5], Vec::new()));
+        game.undo();
+        assert_eq!((game.marks(&a1), game.marks(&b2)), (vec![5, 8], vec![8]));
+
+        let mut game = self::game();
+        game.set_candidate_mode(CandidateMode::AutoFill);
+        for (s, v) in blanks() {
+            assert_eq!(game.marks(&s), vec![v]);
+        }
+        // a wrong 5 in B2 rules out A1's only candidate until it's erased
+        game.place(&b2, 5).unwrap();
+        assert!(game.marks(&a1).is_empty());
+        game.erase(&b2).unwrap();
+        assert_eq!((game.marks(&a1), game.marks(&b2)), (vec![5], vec![7]));
+        // filling in was one move
+        game.undo();
+        game.undo();
+        game.undo();
+        assert!(blanks().iter().all(|&(s, _)| game.marks(&s).is_empty()));
+    }
+
+    #[test]
+    fn smart_hints_tell_a_little_more_each_time() {
+        let mut game = game();
+        let a1 = ('A', '1');
+        game.place(&a1, 4).unwrap();
+        assert_eq!(game.smart_hint(), Some(Hint::Mistake(a1)));
+        assert_eq!(game.hints_used(), 0);
+        game.erase(&a1).unwrap();
+        match game.smart_hint() {
+            Some(Hint::Region(cells)) => assert!(! cells.is_empty()),
+            hint => panic!("expected the region, got {:?}", hint)
+        }
+        assert!(matches!(game.smart_hint(), Some(Hint::Technique(_))));
+        assert!(matches!(game.smart_hint(), Some(Hint::Eliminations(_))));
+        match game.smart_hint() {
+            Some(Hint::Placed(s, v)) => {
+                assert!(blanks().contains(&(s, v)));
+                assert_eq!(game.value(&s), Some(v));
+            },
+            hint => panic!("expected the digit, got {:?}", hint)
+        }
+        assert_eq!((game.hints_used(), game.hint_log().len()), (1, 1));
+        // a move in between starts over at the region
+        assert!(matches!(game.smart_hint(), Some(Hint::Region(_))));
+        game.place(&('I', '9'), 9).unwrap();
+        assert!(matches!(game.smart_hint(), Some(Hint::Region(_))));
+        assert_eq!(game.hints_used(), 3);
+    }
+
+    #[test]
+    fn givens_are_entered_in_setup_and_fixed_by_locking() {
+        let solver = Solver::new();
+        let mut game = Game::setup();
+        assert_eq!(game.givens_mode(), Givens::Modifiable);
+        let a2 = ('A', '2');
+        game.place(&a2, 4).unwrap();
+        game.place(&a2, 3).unwrap();
+        assert_eq!(game.given(&a2), Some(3));
+        assert_eq!(game.toggle_mark(&a2, 1), Err("Marks wait until the givens are locked".to_string()));
+        assert_eq!(game.hint(), None);
+        assert_eq!(game.lock_givens(&solver), Err("Puzzle does not have a unique solution".to_string()));
+        for ((s, c), i) in solver.config().squares().iter().zip(SOLVED.chars()).zip(0..) {
+            if i % 10 != 0 && s != &a2 {
+                game.place(s, c.to_digit(10).unwrap()).unwrap();
+            }
+        }
+        game.erase(&('B', '1')).unwrap();
+        game.undo();
+        assert_eq!(game.given(&('B', '1')), Some(6));
+        game.add_elapsed(3000);
+        game.lock_givens(&solver).unwrap();
+        assert_eq!((game.givens_mode(), game.elapsed()), (Givens::Locked, 3000));
+        // the same givens as `game`
+        assert_eq!(game.save().split(';').nth(1), self::game().save().split(';').nth(1));
+        assert!(! game.undo());
+        assert_eq!(game.place(&a2, 1), Err("Square A2 is a given".to_string()));
+        assert_eq!(game.erase(&a2), Err("Square A2 is a given".to_string()));
+        finish(&mut game);
+        assert!(game.is_complete());
+    }
+
+    #[test]
+    fn conflicts_pair_squares_repeating_a_digit() {
+        let mut game = game();
+        let (a1, a2, b2) = (('A', '1'), ('A', '2'), ('B', '2'));
+        assert!(game.conflicts().is_empty());
+        // a 3 in A1 repeats the givens in its row and column
+        game.place(&a1, 3).unwrap();
+        assert_eq!(game.conflicts(), vec![(a1, a2), (a1, ('I', '1'))]);
+        game.place(&b2, 3).unwrap();
+        assert_eq!(game.conflicts(), vec![(a1, a2), (a1, b2), (a1, ('I', '1')), (a2, b2), (b2, ('B', '7'))]);
+        game.erase(&a1).unwrap();
+        game.erase(&b2).unwrap();
+        assert!(game.conflicts().is_empty());
+
+        let mut setup = Game::setup();
+        setup.place(&a1, 9).unwrap();
+        setup.place(&('I', '1'), 9).unwrap();
+        assert_eq!(setup.conflicts(), vec![(a1, ('I', '1'))]);
+    }
+
+    #[test]
+    fn would_conflict_looks_at_the_peers() {
+        let mut game = game();
+        let (a1, b2) = (('A', '1'), ('B', '2'));
+        assert!(game.would_conflict(&a1, 3));
+        assert!(! game.would_conflict(&a1, 5));
+        game.place(&b2, 5).unwrap();
+        assert!(game.would_conflict(&a1, 5));
+        // whatever the square holds itself
+        game.place(&a1, 5).unwrap();
+        assert!(game.would_conflict(&a1, 5));
+        assert!(! game.would_conflict(&b2, 7));
+        assert!(! game.would_conflict(&('Z', '1'), 1));
+    }
+
+    #[test]
+    fn hints_are_logged_and_cost_points() {
+        let scoring = Scoring::new();
+        let a1 = ('A', '1');
+        let mut game = game();
+        game.add_elapsed(5000);
+        assert_eq!(game.hint(), Some(a1));
+        assert_eq!(game.hint_log(), &[HintRecord {
+            elapsed : 5000,
+            square : a1,
+            value : 5,
+            technique : None,
+            revealed : 4
+        }]);
+        assert!(game.filled_by_hint(&a1) && ! game.filled_by_player(&a1));
+        // entering the hint's digit again keeps it the hint's
+        game.erase(&a1).unwrap();
+        game.place(&a1, 5).unwrap();
+        assert!(game.filled_by_hint(&a1));
+        finish(&mut game);
+        assert!(game.is_complete() && ! game.completed_without_hints());
+        assert!(game.filled_by_player(&('B', '2')));
+
+        let mut unhinted = self::game();
+        unhinted.add_elapsed(5000);
+        finish(&mut unhinted);
+        assert!(unhinted.completed_without_hints());
+        assert_eq!(unhinted.score(&scoring) - game.score(&scoring), scoring.hint_penalty);
+
+        // a smart hint counts once, however far it goes, even if it only showed where to look
+        let mut game = self::game();
+        game.smart_hint();
+        game.smart_hint();
+        assert_eq!((game.hints_used(), game.hint_log()[0].revealed), (1, 2));
+        assert!(game.hint_log()[0].technique.is_some());
+        finish(&mut game);
+        assert!(! game.completed_without_hints());
+    }
+
+    #[test]
+    fn auto_fill_waits_for_the_givens_to_be_locked() {
+        let solver = Solver::new();
+        let mut game = Game::setup();
+        game.set_candidate_mode(CandidateMode::AutoFill);
+        assert!(! game.undo());
+        for ((s, c), i) in solver.config().squares().iter().zip(SOLVED.chars()).zip(0..) {
+            if i % 10 != 0 {
+                game.place(s, c.to_digit(10).unwrap()).unwrap();
+            }
+        }
+        assert!(game.marks(&('A', '1')).is_empty());
+        game.lock_givens(&solver).unwrap();
+        assert_eq!(game.candidate_mode(), CandidateMode::AutoFill);
+        // each blank allows only its digit in the solution
+        for (s, v) in blanks() {
+            assert_eq!(game.marks(&s), vec![v]);
+        }
+        assert!(! game.undo());
+        finish(&mut game);
+        assert!(game.is_complete());
+    }
+
+    const SOLVED : &str = "534678912672195348198342567859761423426853791713924856961537284287419635345286179";
+}
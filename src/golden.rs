@@ -0,0 +1,184 @@
+use std;
+use analysis::{self, Difficulty};
+use calibration::Calibration;
+use logic::{LogicalSolver, Technique};
+use puzzle::{GridString, Puzzle};
+use sudoku::Generator;
+
+// Seeded puzzles every build has to reproduce byte for byte, see data/golden.txt.
+const GOLDEN : &str = include_str!("../data/golden.txt");
+
+// The version data/golden.txt has to carry. A change meant to alter what a seed gives, or how
+// such a puzzle rates, bumps it and rewrites the file with `regenerate`; the bump is what tells
+// downstream daily puzzle services that their seeds no longer give the puzzles they published.
+pub const GOLDEN_VERSION : u32 = 2;
+
+// Attempts `generate_rated` gets for the pinned rated puzzles.
+const ATTEMPTS : usize = 20;
+
+// What a line of data/golden.txt asks the generator for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Request {
+    // `Generator::generate` with at least this many filled squares
+    Fill(usize),
+    Rated(Difficulty)
+}
+
+// How the logical solver takes a pinned puzzle: the calibrated level, the score to four places,
+// the steps taken and the hardest technique among them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    pub level : Difficulty,
+    pub score : f64,
+    pub steps : usize,
+    pub hardest : Option<Technique>
+}
+
+// One line of data/golden.txt. Puzzles the logical solver can't take, such as fills with several
+// solutions, have no stats.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pinned {
+    pub seed : u64,
+    pub request : Request,
+    pub grid : GridString,
+    pub clues : usize,
+    pub stats : Option<Stats>
+}
+
+// Pinned outputs in the format of data/golden.txt: a `version <n>` line, then per puzzle
+// `<seed> generate <squares>` or `<seed> rated <level>`, followed by the grid, its clue count and
+// `<level> <score> <steps> <hardest technique>` (or `unrated`); '#' for comments.
+pub fn parse_golden(text : &str) -> Result<(u32, Vec<Pinned>), String> {
+    let mut version = None;
+    let mut golden = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message : String| format!("line {}: {}", i + 1, message);
+        let fields : Vec<&str> = line.split_whitespace().collect();
+        if version.is_none() {
+            version = match (fields.len(), fields[0], fields.get(1).map(|v| v.parse::<u32>())) {
+                (2, "version", Some(Ok(version))) => Some(version),
+                _ => return Err(error("expected a version line first".to_string()))
+            };
+            continue;
+        }
+        if fields.len() != 6 && fields.len() != 9 {
+            return Err(error("expected a seed, a request, a grid, its clues and its stats".to_string()));
+        }
+        let seed : u64 = fields[0].parse().map_err(|_| error(format!("Invalid seed {}", fields[0])))?;
+        let request = match fields[1] {
+            "generate" => Request::Fill(fields[2].parse().map_err(|_| error(format!("Invalid square count {}", fields[2])))?),
+            "rated" => Request::Rated(fields[2].parse().map_err(&error)?),
+            other => return Err(error(format!("Unknown request {}", other)))
+        };
+        let clues : usize = fields[4].parse().map_err(|_| error(format!("Invalid clue count {}", fields[4])))?;
+        let stats = match fields.len() {
+            9 => Some(Stats {
+                level : fields[5].parse().map_err(&error)?,
+                score : fields[6].parse().map_err(|_| error(format!("Invalid score {}", fields[6])))?,
+                steps : fields[7].parse().map_err(|_| error(format!("Invalid step count {}", fields[7])))?,
+                hardest : match fields[8] {
+                    "-" => None,
+                    id => Some(Technique::from_id(id).ok_or_else(|| error(format!("Unknown technique {}", id)))?)
+                }
+            }),
+            _ if fields[5] == "unrated" => None,
+            _ => return Err(error(format!("Expected stats or unrated, got {}", fields[5])))
+        };
+        golden.push(Pinned {
+            seed,
+            request,
+            grid : fields[3].to_string(),
+            clues,
+            stats
+        });
+    }
+    match version {
+        Some(version) => Ok((version, golden)),
+        None => Err("No version line".to_string())
+    }
+}
+
+// The grid a seeded generator hands out for the request, None when rated generation misses.
+pub fn generate(seed : u64, request : Request) -> Option<GridString> {
+    let generator = Generator::new().with_seed(seed);
+    let start_state = match request {
+        Request::Fill(n) => Some(generator.generate(n)),
+        Request::Rated(level) => generator.generate_rated(level, ATTEMPTS).map(|result| result.puzzle)
+    };
+    start_state.map(|s| Puzzle::new(s).to_grid_string())
+}
+
+// The request's puzzle as this build makes and rates it. The logical solver runs without a time
+// limit, so the stats don't depend on the machine.
+pub fn pin(seed : u64, request : Request) -> Option<Pinned> {
+    let grid = generate(seed, request)?;
+    let puzzle : Puzzle = grid.parse().ok()?;
+    let stats = LogicalSolver::new().full_path(puzzle.start_state()).ok().map(|path| {
+        let score = analysis::path_score(&path);
+        Stats {
+            level : Calibration::default().level(score.value),
+            score : (score.value * 1e4).round() / 1e4,
+            steps : score.steps,
+            hardest : score.hardest
+        }
+    });
+    Some(Pinned {
+        seed,
+        request,
+        clues : puzzle.clues(),
+        grid,
+        stats
+    })
+}
+
+// Generates and rates every pinned puzzle again; any that comes out different is an error naming
+// it, and so is a file of another version than this build's. Returns how many were checked.
+pub fn check() -> Result<usize, String> {
+    let (version, golden) = parse_golden(GOLDEN)?;
+    if version != GOLDEN_VERSION {
+        return Err(format!("data/golden.txt is version {}, this build pins version {}; regenerate it", version, GOLDEN_VERSION));
+    }
+    let differing : Vec<String> = golden.iter()
+                                        .filter(|pinned| pin(pinned.seed, pinned.request).as_ref() != Some(pinned))
+                                        .map(|pinned| format!("seed {} {:?}", pinned.seed, pinned.request))
+                                        .collect();
+    if ! differing.is_empty() {
+        return Err(format!("Seeded generation differs from data/golden.txt for {}; if that is meant, bump GOLDEN_VERSION and regenerate it",
+                           differing.join("; ")));
+    }
+    Ok(golden.len())
+}
+
+// data/golden.txt as this build would write it: the same comments, seeds and requests, at
+// `GOLDEN_VERSION`, with every puzzle generated and rated afresh. Fails when rated generation
+// misses for a pinned seed, which then needs another seed.
+pub fn regenerate() -> Result<String, String> {
+    let (_, golden) = parse_golden(GOLDEN)?;
+    let mut text : String = GOLDEN.lines().take_while(|line| line.starts_with('#')).map(|line| format!("{}\n", line)).collect();
+    text.push_str(&format!("version {}\n", GOLDEN_VERSION));
+    for pinned in golden {
+        match pin(pinned.seed, pinned.request) {
+            Some(pinned) => text.push_str(&format!("{}\n", pinned)),
+            None => return Err(format!("No puzzle for seed {} {:?}", pinned.seed, pinned.request))
+        }
+    }
+    Ok(text)
+}
+
+impl std::fmt::Display for Pinned {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.request {
+            Request::Fill(n) => write!(f, "{} generate {}", self.seed, n)?,
+            Request::Rated(level) => write!(f, "{} rated {}", self.seed, level.name())?
+        }
+        write!(f, " {} {}", self.grid, self.clues)?;
+        match self.stats {
+            Some(stats) => write!(f, " {} {:.4} {} {}", stats.level.name(), stats.score, stats.steps, stats.hardest.map_or("-", |t| t.id())),
+            None => write!(f, " unrated")
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use std;
+use sudoku::{SquareValue, StartState};
+
+// Candidate bit mask, bit v - 1 set when v is still possible.
+pub type Mask = u32;
+
+// Fixed size candidate grid for N x N puzzles (N a square number up to 25), stored as plain
+// arrays so copying a grid during search is a memcpy rather than a rebuild of hash maps.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Grid<const N: usize> {
+    cells : [[Mask; N]; N]
+}
+
+pub type Grid9 = Grid<9>;
+pub type Grid16 = Grid<16>;
+
+const fn box_size(n : usize) -> usize {
+    let mut b = 1;
+    while (b + 1) * (b + 1) <= n {
+        b += 1;
+    }
+    b
+}
+
+impl<const N: usize> Default for Grid<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Grid<N> {
+
+    pub const BOX : usize = box_size(N);
+    pub const ALL : Mask = if N >= 32 { !0 } else { (1 << N) - 1 };
+    // Evaluated by `new`, which every grid comes from, so a size that isn't a square or doesn't
+    // fit the mask fails to compile instead of giving boxes that don't tile the grid.
+    const SQUARE : () = assert!(box_size(N) * box_size(N) == N && N <= 32, "Grid size must be a square number up to 25");
+
+    pub fn new() -> Grid<N> {
+        let () = Grid::<N>::SQUARE;
+        Grid {
+            cells : [[Grid::<N>::ALL; N]; N]
+        }
+    }
+
+    // Row-major values, 0 for blanks. None if the givens contradict each other.
+    pub fn from_values(values : &[[SquareValue; N]; N]) -> Option<Grid<N>> {
+        let mut grid = Grid::new();
+        for (r, row) in values.iter().enumerate() {
+            for (c, &v) in row.iter().enumerate() {
+                if v != 0 && (v as usize > N || ! grid.assign(r, c, v)) {
+                    return None;
+                }
+            }
+        }
+        Some(grid)
+    }
+
+    pub fn to_values(&self) -> [[SquareValue; N]; N] {
+        let mut values = [[0; N]; N];
+        for (r, row) in values.iter_mut().enumerate() {
+            for (c, v) in row.iter_mut().enumerate() {
+                *v = self.value(r, c).unwrap_or(0);
+            }
+        }
+        values
+    }
+
+    pub fn candidates(&self, r : usize, c : usize) -> Mask {
+        self.cells[r][c]
+    }
+
+    pub fn value(&self, r : usize, c : usize) -> Option<SquareValue> {
+        let m = self.cells[r][c];
+        if m.count_ones() == 1 {
+            Some(m.trailing_zeros() + 1)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_solved(&self) -> bool {
+        self.cells.iter().all(|row| row.iter().all(|m| m.count_ones() == 1))
+    }
+
+    // False for values outside 1..N, which no square can take.
+    pub fn assign(&mut self, r : usize, c : usize, v : SquareValue) -> bool {
+        if v == 0 || v as usize > N {
+            return false;
+        }
+        let others = self.cells[r][c] & ! bit(v);
+        (1..N as SquareValue + 1).filter(|&d| others & bit(d) != 0)
+                                 .all(|d| self.eliminate(r, c, d))
+    }
+
+    // Values outside 1..N are never candidates, so there is nothing to eliminate.
+    pub fn eliminate(&mut self, r : usize, c : usize, v : SquareValue) -> bool {
+        if v == 0 || v as usize > N || self.cells[r][c] & bit(v) == 0 {
+            return true; // already eliminated
+        }
+        self.cells[r][c] &= ! bit(v);
+        let m = self.cells[r][c];
+        // (1) If a square is reduced to one value, eliminate it from the peers.
+        if m == 0 {
+            return false;
+        } else if m.count_ones() == 1 {
+            let d = m.trailing_zeros() + 1;
+            for kind in 0..3 {
+                for i in 0..N {
+                    let (r2, c2) = unit_square::<N>(kind, r, c, i);
+                    // box squares sharing the row or column were covered by the first two units
+                    if (r2, c2) == (r, c) || (kind == 2 && (r2 == r || c2 == c)) {
+                        continue;
+                    }
+                    if ! self.eliminate(r2, c2, d) {
+                        return false;
+                    }
+                }
+            }
+        }
+        // (2) If a unit has only one place left for the value, put it there.
+        for kind in 0..3 {
+            let mut count = 0;
+            let mut place = (r, c);
+            for i in 0..N {
+                let (r2, c2) = unit_square::<N>(kind, r, c, i);
+                if self.cells[r2][c2] & bit(v) != 0 {
+                    count += 1;
+                    place = (r2, c2);
+                }
+            }
+            if count == 0 || (count == 1 && ! self.assign(place.0, place.1, v)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn solve(&self) -> Option<Grid<N>> {
+        self.search(&mut 0)
+    }
+
+    // Like `solve`, also returning how many times the search had to guess.
+    pub fn solve_counting(&self) -> (Option<Grid<N>>, usize) {
+        let mut guesses = 0;
+        let solution = self.search(&mut guesses);
+        (solution, guesses)
+    }
+
+    fn search(&self, guesses : &mut usize) -> Option<Grid<N>> {
+        let (r, c) = match self.branch_square() {
+            Some(square) => square,
+            None => return Some(*self)
+        };
+        *guesses += 1;
+        let m = self.cells[r][c];
+        for d in (1..N as SquareValue + 1).filter(|&d| m & bit(d) != 0) {
+            let mut child = *self;
+            if child.assign(r, c, d) {
+                if let Some(solution) = child.search(guesses) {
+                    return Some(solution);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn count_solutions(&self, limit : usize) -> usize {
+        let (r, c) = match self.branch_square() {
+            Some(square) => square,
+            None => return 1
+        };
+        let m = self.cells[r][c];
+        let mut n = 0;
+        for d in (1..N as SquareValue + 1).filter(|&d| m & bit(d) != 0) {
+            let mut child = *self;
+            if child.assign(r, c, d) {
+                n += child.count_solutions(limit - n);
+                if n >= limit {
+                    break;
+                }
+            }
+        }
+        n
+    }
+
+    // Up to `limit` solutions, in the order the search finds them.
+    pub fn solutions(&self, limit : usize) -> Vec<Grid<N>> {
+        let mut found = Vec::new();
+        self.collect_solutions(limit, &mut found);
+        found
+    }
+
+    fn collect_solutions(&self, limit : usize, found : &mut Vec<Grid<N>>) {
+        let (r, c) = match self.branch_square() {
+            Some(square) => square,
+            None => return found.push(*self)
+        };
+        let m = self.cells[r][c];
+        for d in (1..N as SquareValue + 1).filter(|&d| m & bit(d) != 0) {
+            let mut child = *self;
+            if child.assign(r, c, d) {
+                child.collect_solutions(limit, found);
+                if found.len() >= limit {
+                    break;
+                }
+            }
+        }
+    }
+
+    // Errors naming the first broken invariant of a propagated grid: a square without candidates,
+    // a solved square's digit still a candidate of a peer, or a unit without a place for a digit.
+    // Squares are written r1c1.
+    pub fn check(&self) -> Result<(), String> {
+        for r in 0..N {
+            for c in 0..N {
+                if self.cells[r][c] == 0 {
+                    return Err(format!("r{}c{} has no candidates", r + 1, c + 1));
+                }
+                if let Some(v) = self.value(r, c) {
+                    for kind in 0..3 {
+                        for i in 0..N {
+                            let (r2, c2) = unit_square::<N>(kind, r, c, i);
+                            if (r2, c2) != (r, c) && self.cells[r2][c2] & bit(v) != 0 {
+                                return Err(format!("{} at r{}c{} is still a candidate at r{}c{}", v, r + 1, c + 1, r2 + 1, c2 + 1));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let b = Grid::<N>::BOX;
+        for kind in 0..3 {
+            for k in 0..N {
+                let (r, c) = [(k, 0), (0, k), (k / b * b, k % b * b)][kind];
+                let covered = (0..N).map(|i| unit_square::<N>(kind, r, c, i)).fold(0, |m, (r2, c2)| m | self.cells[r2][c2]);
+                if let Some(v) = (1..N as SquareValue + 1).find(|&v| covered & bit(v) == 0) {
+                    return Err(format!("{} {} has no place for {}", ["row", "column", "box"][kind], k + 1, v));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Errors unless every digit of the solution is still a candidate, i.e. unless propagation has
+    // only ever ruled out what was wrong.
+    pub fn check_against(&self, solution : &Grid<N>) -> Result<(), String> {
+        for r in 0..N {
+            for c in 0..N {
+                if let Some(v) = solution.value(r, c) {
+                    if self.cells[r][c] & bit(v) == 0 {
+                        return Err(format!("r{}c{} lost {}, its digit in the solution", r + 1, c + 1, v));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Overwrites a square's candidates with no propagation, to corrupt a grid on purpose.
+    #[cfg(feature = "fault-injection")]
+    pub fn set_candidates(&mut self, r : usize, c : usize, mask : Mask) {
+        self.cells[r][c] = mask & Grid::<N>::ALL;
+    }
+
+    // Unsolved square with the fewest candidates.
+    fn branch_square(&self) -> Option<(usize, usize)> {
+        let mut best = None;
+        let mut best_count = N as u32 + 1;
+        for r in 0..N {
+            for c in 0..N {
+                let n = self.cells[r][c].count_ones();
+                if n > 1 && n < best_count {
+                    best = Some((r, c));
+                    best_count = n;
+                }
+            }
+        }
+        best
+    }
+}
+
+impl Grid<9> {
+
+    // The givens placed and the start state's eliminations ruled out, both propagated.
+    pub fn from_start_state(start_state : &StartState) -> Option<Grid9> {
+        let index = |&(row, col) : &(char, char)| {
+            let r = (row as u32).wrapping_sub('A' as u32) as usize;
+            let c = (col as u32).wrapping_sub('1' as u32) as usize;
+            if r < 9 && c < 9 { Some((r, c)) } else { None }
+        };
+        let mut values = [[0; 9]; 9];
+        for &(s, v) in start_state {
+            let (r, c) = index(&s)?;
+            values[r][c] = v;
+        }
+        let mut grid = Grid::from_values(&values)?;
+        for &(s, v) in start_state.eliminations() {
+            let (r, c) = index(&s)?;
+            if !(1..=9).contains(&v) || ! grid.eliminate(r, c, v) {
+                return None;
+            }
+        }
+        Some(grid)
+    }
+
+    pub fn to_start_state(&self) -> StartState {
+        let mut start_state = Vec::new();
+        for r in 0..9 {
+            for c in 0..9 {
+                if let Some(v) = self.value(r, c) {
+                    start_state.push(((std::char::from_u32('A' as u32 + r as u32).unwrap(),
+                                       std::char::from_u32('1' as u32 + c as u32).unwrap()), v));
+                }
+            }
+        }
+        StartState::new(start_state)
+    }
+}
+
+impl<const N: usize> std::fmt::Debug for Grid<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for row in self.cells.iter() {
+            let cells : Vec<String> = row.iter().map(|m| format!("{:0width$b}", m, width = N)).collect();
+            writeln!(f, "{}", cells.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+fn bit(v : SquareValue) -> Mask {
+    1 << (v - 1)
+}
+
+// The i-th square of the row (kind 0), column (kind 1) or box (kind 2) through (r, c).
+fn unit_square<const N: usize>(kind : usize, r : usize, c : usize, i : usize) -> (usize, usize) {
+    let b = Grid::<N>::BOX;
+    match kind {
+        0 => (r, i),
+        1 => (i, c),
+        _ => (r - r % b + i / b, c - c % b + i % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_outside_the_digits_are_no_candidates() {
+        let mut grid = Grid9::new();
+        assert!(! grid.assign(0, 0, 0));
+        assert!(! grid.assign(0, 0, 10));
+        assert!(grid.eliminate(0, 0, 0));
+        assert!(grid.eliminate(0, 0, 10));
+        assert_eq!(grid, Grid9::new());
+        let mut grid = Grid16::new();
+        assert!(! grid.assign(0, 0, 17));
+        assert!(grid.assign(0, 0, 16));
+        assert_eq!(grid.value(0, 0), Some(16));
+    }
+
+    #[test]
+    fn boxes_tile_the_grid() {
+        assert_eq!(Grid9::BOX, 3);
+        assert_eq!(Grid16::BOX, 4);
+        let mut grid = Grid16::new();
+        assert!(grid.assign(0, 0, 1));
+        assert_eq!(grid.candidates(3, 3) & 1, 0);
+        assert_eq!(grid.candidates(4, 4) & 1, 1);
+    }
+}
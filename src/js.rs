@@ -0,0 +1,194 @@
+use analysis::{Difficulty, Score};
+use bindings::{self, Error, ErrorKind};
+use game::Hint;
+use json::Json;
+use logic::{path_to_json, LogicalSolver, SolveStep, Technique};
+use puzzle::Puzzle;
+use sudoku::{square_name, StartValue};
+
+// The object model a WASM build hands to JavaScript, on top of `bindings`: every call returns a
+// JSON document of a shape `typescript_definitions` declares, so web code gets typed objects
+// instead of parsing strings of its own. Results come as `{"ok": ...}` or `{"error": {"kind":
+// ..., "message": ...}}` rather than as exceptions, which keeps the error kinds typed too.
+//
+// The `wasm` feature exports these through wasm-bindgen, see `wasm`; a package built with it
+// ships the output of `typescript_definitions` (`sudoku-solver typescript`) as its .d.ts.
+
+fn outcome(result : Result<Json, Error>) -> Json {
+    match result {
+        Ok(value) => Json::object(vec![("ok", value)]),
+        Err(e) => Json::object(vec![("error", error_to_json(&e))])
+    }
+}
+
+pub fn error_to_json(error : &Error) -> Json {
+    Json::object(vec![("kind", Json::string(error.kind.name())), ("message", Json::string(&error.message))])
+}
+
+// {"grid": "<81 characters>", "clues": 24, "author": ...} with the provenance fields that are set.
+pub fn puzzle_to_json(puzzle : &Puzzle) -> Json {
+    let mut fields = vec![("grid", Json::String(puzzle.to_grid_string())), ("clues", Json::Number(puzzle.clues() as f64))];
+    fields.extend(puzzle.provenance().to_json_fields());
+    Json::object(fields)
+}
+
+// {"difficulty": "hard", "score": 4.31, "steps": 57, "hardest": "x_wing"}
+pub fn rating_to_json(level : Difficulty, score : &Score) -> Json {
+    Json::object(vec![("difficulty", Json::string(level.name())),
+                      ("score", Json::Number(score.value)),
+                      ("steps", Json::Number(score.steps as f64)),
+                      ("hardest", score.hardest.map_or(Json::Null, |t| Json::string(t.id())))])
+}
+
+fn candidates_to_json(values : &[StartValue]) -> Json {
+    Json::Array(values.iter()
+                      .map(|&(s, v)| Json::object(vec![("cell", Json::String(square_name(&s))), ("digit", Json::Number(v as f64))]))
+                      .collect())
+}
+
+// A `Game::smart_hint` as {"kind": "region", "cells": [...]} and so on, one kind per variant.
+pub fn hint_to_json(hint : &Hint) -> Json {
+    match *hint {
+        Hint::Mistake(s) => Json::object(vec![("kind", Json::string("mistake")), ("cell", Json::String(square_name(&s)))]),
+        Hint::Region(ref cells) => Json::object(vec![("kind", Json::string("region")),
+                                                     ("cells", Json::Array(cells.iter().map(|s| Json::String(square_name(s))).collect()))]),
+        Hint::Technique(t) => Json::object(vec![("kind", Json::string("technique")), ("technique", Json::string(t.id()))]),
+        Hint::Eliminations(ref eliminations) => Json::object(vec![("kind", Json::string("eliminations")),
+                                                                  ("eliminations", candidates_to_json(eliminations))]),
+        Hint::Placed(s, v) => Json::object(vec![("kind", Json::string("placed")),
+                                                ("cell", Json::String(square_name(&s))),
+                                                ("digit", Json::Number(v as f64))])
+    }
+}
+
+// Outcome<string>: the solution grid.
+pub fn solve(grid : &str) -> Json {
+    outcome(bindings::solve(grid).map(Json::String))
+}
+
+// Outcome<Puzzle> from GenerateOptions, `{"clues": 24}`, `{"difficulty": "hard", "seed": 7}` or
+// `{}`; see `bindings::generate`.
+#[cfg(feature = "generator")]
+pub fn generate(options : &str) -> Json {
+    outcome(Json::parse(options).map_err(|e| Error::new(ErrorKind::Argument, e)).and_then(|options| {
+        let number = |key : &str| match options.get(key) {
+            None | Some(&Json::Null) => Ok(None),
+            Some(value) => match value.as_f64() {
+                Some(n) if n >= 0.0 && n.fract() == 0.0 => Ok(Some(n as u64)),
+                _ => Err(Error::new(ErrorKind::Argument, format!("{} must be a whole number, got {}", key, value)))
+            }
+        };
+        let difficulty = match options.get("difficulty") {
+            None | Some(&Json::Null) => None,
+            Some(value) => Some(value.as_str().ok_or_else(|| Error::new(ErrorKind::Argument, format!("difficulty must be a string, got {}", value)))?)
+        };
+        let grid = bindings::generate(number("clues")?.map(|n| n as usize), difficulty, number("seed")?)?;
+        Ok(puzzle_to_json(&bindings::parse(&grid)?))
+    }))
+}
+
+// Outcome<Rating>.
+pub fn rate(grid : &str) -> Json {
+    outcome(bindings::rate(grid).map(|(level, score)| rating_to_json(level, &score)))
+}
+
+// Outcome<SolveStep[]>: the steps up to and including the next placement, as a stateless hint.
+pub fn next_placement(grid : &str) -> Json {
+    outcome(bindings::parse(grid).and_then(|puzzle| {
+        LogicalSolver::new().next_placement(puzzle.start_state())
+                            .map(|steps| Json::Array(steps.iter().map(SolveStep::to_json).collect()))
+                            .map_err(|e| Error::new(ErrorKind::Unsolvable, e))
+    }))
+}
+
+// Outcome<SolvePath>: every step from the givens to the solution.
+pub fn solve_path(grid : &str) -> Json {
+    outcome(bindings::parse(grid).and_then(|puzzle| {
+        LogicalSolver::new().full_path(puzzle.start_state())
+                            .map(|path| path_to_json(&path))
+                            .map_err(|e| Error::new(ErrorKind::Unsolvable, e))
+    }))
+}
+
+fn union<I : IntoIterator<Item = String>>(names : I) -> String {
+    names.into_iter().map(|name| format!("\"{}\"", name)).collect::<Vec<String>>().join(" | ")
+}
+
+// The TypeScript declarations of the shapes above and the functions returning them. The string
+// unions come from the enums of this build, so a new technique or level shows up in them.
+pub fn typescript_definitions() -> String {
+    let techniques = union(Technique::all().into_iter().chain(Some(Technique::Reveal)).map(|t| t.id().to_string()));
+    let levels = union(Difficulty::all().into_iter().map(|d| d.name().to_string()));
+    let kinds = union(ErrorKind::all().into_iter().map(|k| k.name().to_string()));
+    let mut text = String::new();
+    text.push_str("// Generated by `sudoku-solver typescript`; do not edit.\n\n");
+    text.push_str(&format!("export type Difficulty = {};\n", levels));
+    text.push_str(&format!("export type TechniqueId = {};\n", techniques));
+    text.push_str(&format!("export type ErrorKind = {};\n", kinds));
+    text.push_str(r#"
+/** A square, row letter and column digit: "A1" to "I9". */
+export type Cell = string;
+export type Digit = 1 | 2 | 3 | 4 | 5 | 6 | 7 | 8 | 9;
+
+export interface Candidate {
+  cell: Cell;
+  digit: Digit;
+}
+
+export interface SudokuError {
+  kind: ErrorKind;
+  message: string;
+}
+
+export type Outcome<T> = { ok: T } | { error: SudokuError };
+
+export interface Puzzle {
+  /** 81 characters, '.' for blanks */
+  grid: string;
+  clues: number;
+  author?: string;
+  source?: string;
+  license?: string;
+  created?: string;
+}
+
+export interface Rating {
+  difficulty: Difficulty;
+  score: number;
+  steps: number;
+  hardest: TechniqueId | null;
+}
+
+export interface SolveStep {
+  technique: TechniqueId;
+  cells: Cell[];
+  placements: Candidate[];
+  eliminations: Candidate[];
+}
+
+export interface SolvePath {
+  steps: SolveStep[];
+}
+
+export type Hint =
+  | { kind: "mistake"; cell: Cell }
+  | { kind: "region"; cells: Cell[] }
+  | { kind: "technique"; technique: TechniqueId }
+  | { kind: "eliminations"; eliminations: Candidate[] }
+  | { kind: "placed"; cell: Cell; digit: Digit };
+
+/** At most one of clues and difficulty; the same seed gives the same puzzle. */
+export interface GenerateOptions {
+  clues?: number;
+  difficulty?: Difficulty;
+  seed?: number;
+}
+
+export function solve(grid: string): Outcome<string>;
+export function generate(options: GenerateOptions): Outcome<Puzzle>;
+export function rate(grid: string): Outcome<Rating>;
+export function nextPlacement(grid: string): Outcome<SolveStep[]>;
+export function solvePath(grid: string): Outcome<SolvePath>;
+"#);
+    text
+}
@@ -0,0 +1,313 @@
+use std;
+
+// Arrays and objects nested deeper than this are rejected by `Json::parse`: the parser recurses
+// once per level, and a few hundred kilobytes of '[' would otherwise overflow the stack.
+pub const MAX_DEPTH : usize = 128;
+
+// Minimal JSON value, enough for the crate's machine-readable input and output. Object fields
+// keep their insertion order so the emitted documents are stable.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>)
+}
+
+impl Json {
+
+    pub fn object(fields : Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    pub fn string(s : &str) -> Json {
+        Json::String(s.to_string())
+    }
+
+    pub fn get(&self, key : &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref fields) => fields.iter().find(|&(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(n) => Some(n),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(b) => Some(b),
+            _ => None
+        }
+    }
+
+    // The "version" field of a stored object, 0 when it was written before the crate's formats
+    // carried one. Newer versions than `current` are errors rather than guesses at their layout.
+    pub fn format_version(&self, current : u32) -> Result<u32, String> {
+        let version = match self.get("version") {
+            None => 0,
+            Some(v) => match v.as_f64() {
+                Some(n) if n >= 1.0 && n.fract() == 0.0 => n as u32,
+                _ => return Err(format!("Invalid format version {}", v))
+            }
+        };
+        if version > current {
+            return Err(format!("Format version {} is newer than this build reads, up to {}", version, current));
+        }
+        Ok(version)
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match *self {
+            Json::Array(ref values) => Some(values),
+            _ => None
+        }
+    }
+
+    pub fn parse(input : &str) -> Result<Json, String> {
+        Json::parse_nested(input, MAX_DEPTH)
+    }
+
+    // `parse` with arrays and objects nested at most `max_depth` deep.
+    pub fn parse_nested(input : &str, max_depth : usize) -> Result<Json, String> {
+        let mut parser = Parser {
+            chars : input.chars().collect(),
+            pos : 0,
+            max_depth
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("Trailing characters"));
+        }
+        Ok(value)
+    }
+}
+
+struct Parser {
+    chars : Vec<char>,
+    pos : usize,
+    max_depth : usize
+}
+
+impl Parser {
+
+    fn error(&self, message : &str) -> String {
+        format!("{} at offset {}", message, self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, c : char) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("Expected '{}'", c)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn keyword(&mut self, word : &str, value : Json) -> Result<Json, String> {
+        let end = self.pos + word.len();
+        if end > self.chars.len() || self.chars[self.pos..end].iter().cloned().collect::<String>() != word {
+            return Err(self.error("Unexpected character"));
+        }
+        self.pos = end;
+        Ok(value)
+    }
+
+    // `depth` counts the arrays and objects around the value.
+    fn value(&mut self, depth : usize) -> Result<Json, String> {
+        match self.peek() {
+            Some('{') | Some('[') if depth >= self.max_depth => Err(self.error("JSON nested too deeply")),
+            Some('{') => self.object(depth + 1),
+            Some('[') => self.array(depth + 1),
+            Some('"') => self.string().map(Json::String),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('n') => self.keyword("null", Json::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end of input"))
+        }
+    }
+
+    fn object(&mut self, depth : usize) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            if self.peek() != Some('"') {
+                return Err(self.error("Expected key"));
+            }
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.value(depth)?));
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                },
+                _ => return Err(self.error("Expected ',' or '}'"))
+            }
+        }
+    }
+
+    fn array(&mut self, depth : usize) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value(depth)?);
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(values));
+                },
+                _ => return Err(self.error("Expected ',' or ']'"))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let c = match self.chars.get(self.pos) {
+                Some(&c) => c,
+                None => return Err(self.error("Unterminated string"))
+            };
+            self.pos += 1;
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let escaped = match self.chars.get(self.pos) {
+                        Some(&c) => c,
+                        None => return Err(self.error("Unterminated string"))
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        '"' | '\\' | '/' => s.push(escaped),
+                        'n' => s.push('\n'),
+                        'r' => s.push('\r'),
+                        't' => s.push('\t'),
+                        'b' => s.push('\u{8}'),
+                        'f' => s.push('\u{c}'),
+                        'u' => {
+                            let hex : String = self.chars.iter().skip(self.pos).take(4).cloned().collect();
+                            let code = u32::from_str_radix(&hex, 16).map_err(|_| self.error("Invalid escape"))?;
+                            self.pos += 4;
+                            s.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                        },
+                        _ => return Err(self.error("Invalid escape"))
+                    }
+                },
+                c => s.push(c)
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while self.pos < self.chars.len() && (self.chars[self.pos].is_ascii_digit() || "+-.eE".contains(self.chars[self.pos])) {
+            self.pos += 1;
+        }
+        let text : String = self.chars[start..self.pos].iter().cloned().collect();
+        text.parse().map(Json::Number).map_err(|_| format!("Invalid number {} at offset {}", text, start))
+    }
+}
+
+fn write_string(f : &mut std::fmt::Formatter, s : &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?
+        }
+    }
+    write!(f, "\"")
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(ref s) => write_string(f, s),
+            Json::Array(ref values) => {
+                write!(f, "[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(ref fields) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let deep = "[".repeat(200000);
+        assert_eq!(Json::parse(&deep), Err("JSON nested too deeply at offset 128".to_string()));
+        let nested = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(Json::parse(&nested).is_ok());
+        let objects = format!("{}1{}", "{\"a\":".repeat(MAX_DEPTH + 1), "}".repeat(MAX_DEPTH + 1));
+        assert!(Json::parse(&objects).unwrap_err().starts_with("JSON nested too deeply"));
+        assert!(Json::parse_nested("[[1]]", 1).is_err());
+        assert_eq!(Json::parse_nested("[[1]]", 2), Ok(Json::Array(vec![Json::Array(vec![Json::Number(1.0)])])));
+    }
+}
@@ -0,0 +1,79 @@
+#[cfg(feature = "generator")]
+extern crate rand;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "metrics")]
+extern crate metrics;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "arena")]
+extern crate bumpalo;
+#[cfg(feature = "pyo3")]
+extern crate pyo3;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "wasm")]
+extern crate js_sys;
+// the code pyo3's and wasm-bindgen's macros expand to names `::core`, which a 2015 crate has to
+// bring in itself
+#[cfg(any(feature = "pyo3", feature = "wasm"))]
+extern crate core;
+
+pub mod sudoku;
+pub mod analysis;
+pub mod logic;
+pub mod json;
+pub mod game;
+pub mod puzzle;
+pub mod grid;
+pub mod dlx;
+pub mod backend;
+pub mod variant;
+pub mod constraint;
+pub mod canonical;
+pub mod render;
+pub mod limits;
+pub mod cancel;
+pub mod formats;
+pub mod calibration;
+pub mod replay;
+pub mod stress;
+pub mod pack;
+pub mod corpus;
+pub mod worker;
+pub mod bindings;
+pub mod js;
+#[cfg(feature = "generator")]
+pub mod pool;
+#[cfg(feature = "generator")]
+pub mod golden;
+#[cfg(feature = "generator")]
+pub mod pipeline;
+#[cfg(feature = "fault-injection")]
+pub mod fault;
+#[cfg(feature = "pyo3")]
+pub mod python;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+mod telemetry;
+
+// Grid geometry, see `sudoku::GRID_SIZE`.
+pub use sudoku::{ALL_SQUARES, BOXES, BOX_SIZE, COLS, GRID_SIZE, ROWS, SQUARE_COUNT};
+
+pub mod prelude {
+    pub use analysis::{Difficulty, DifficultyModel};
+    pub use cancel::CancelToken;
+    pub use constraint::{Constraint, Contradiction, Progress};
+    pub use corpus::Corpus;
+    pub use game::{Game, Givens, Hint, HintRecord};
+    pub use logic::{LogicalSolver, Messages, SolveStep, Technique};
+    pub use pack::{Pack, PackProgress};
+    pub use puzzle::{GridString, Puzzle};
+    #[cfg(feature = "generator")]
+    pub use sudoku::Generator;
+    #[cfg(feature = "generator")]
+    pub use pool::GeneratorPool;
+    pub use sudoku::{Digit, Notation, SolveMachine, SolveStatus, Solver, SquareId as Square, SquareValue, StartState, State};
+}
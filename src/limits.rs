@@ -0,0 +1,103 @@
+use std::time::Duration;
+use cancel::{CancelToken, OUT_OF_TIME};
+use json::{self, Json};
+use sudoku::{Solver, StartState, State};
+use variant::Variant;
+
+// Guards for input from untrusted sources, checked where it enters the crate: the size of the
+// text handed to a parser and how deeply its JSON nests, the number of rules a variant brings, and
+// how long a solve may run.
+// The defaults are generous for real puzzles and small enough to keep one request cheap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub max_input_bytes : usize,
+    pub max_json_depth : usize,
+    pub max_constraints : usize,
+    pub max_solve_time : Duration
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_input_bytes : 1 << 20,
+            max_json_depth : json::MAX_DEPTH,
+            max_constraints : 1000,
+            max_solve_time : Duration::from_secs(10)
+        }
+    }
+}
+
+impl Limits {
+
+    pub fn check_input(&self, input : &str) -> Result<(), String> {
+        if input.len() > self.max_input_bytes {
+            return Err(format!("Input of {} bytes is over the limit of {}", input.len(), self.max_input_bytes));
+        }
+        self.check_nesting(input)
+    }
+
+    // Brackets and braces outside strings, counted the way the JSON parser would nest them, so
+    // input that would fail to parse for its depth is turned away as over the limit before any
+    // parser sees it. Text that isn't JSON rarely nests at all.
+    pub fn check_nesting(&self, input : &str) -> Result<(), String> {
+        let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+        for c in input.chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = ! in_string,
+                _ if in_string => (),
+                '[' | '{' => {
+                    depth += 1;
+                    if depth > self.max_json_depth {
+                        return Err(format!("Input nests deeper than the limit of {} levels", self.max_json_depth));
+                    }
+                },
+                ']' | '}' => depth = depth.saturating_sub(1),
+                _ => ()
+            }
+        }
+        Ok(())
+    }
+
+    // Regions, cages, lines and every other clue count as one rule each.
+    pub fn check_variant(&self, variant : &Variant) -> Result<(), String> {
+        let rules = variant.regions.len() + variant.cages.len() + variant.kropki.len() + variant.thermos.len() +
+                    variant.clones.len() + variant.palindromes.len() + variant.little_killers.len() + variant.arrows.len() +
+                    variant.sandwiches.len() + variant.even.len() + variant.odd.len();
+        if rules > self.max_constraints {
+            return Err(format!("Variant has {} rules, over the limit of {}", rules, self.max_constraints));
+        }
+        Ok(())
+    }
+
+    // Parses a variant descriptor (JSON or the text format), checking the size before parsing
+    // and the rule count after.
+    pub fn parse_variant(&self, descriptor : &str) -> Result<Variant, String> {
+        self.check_input(descriptor)?;
+        let variant = if descriptor.trim_start().starts_with('{') {
+            Variant::from_json(&Json::parse_nested(descriptor, self.max_json_depth)?)?
+        } else {
+            Variant::parse_text(descriptor)?
+        };
+        self.check_variant(&variant)?;
+        Ok(variant)
+    }
+
+    // Solves by stepping a `SolveMachine`, giving up once `max_solve_time` has passed.
+    pub fn solve<'a>(&self, solver : &'a Solver, start_state : StartState) -> Result<State<'a>, String> {
+        self.solve_cancellable(solver, start_state, &CancelToken::new())
+    }
+
+    // `solve` that also stops when `cancel` does, see `Solver::solve_cancellable`.
+    pub fn solve_cancellable<'a>(&self, solver : &'a Solver, start_state : StartState, cancel : &CancelToken) -> Result<State<'a>, String> {
+        let limited = cancel.clone().with_timeout(self.max_solve_time);
+        solver.solve_cancellable(start_state, &limited).map_err(|e| {
+            if e == OUT_OF_TIME && ! cancel.is_expired() {
+                format!("No solution within {} ms", self.max_solve_time.as_secs() * 1000 + self.max_solve_time.subsec_nanos() as u64 / 1000000)
+            } else {
+                e
+            }
+        })
+    }
+}
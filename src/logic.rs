@@ -0,0 +1,582 @@
+use std;
+use std::collections::HashMap;
+use std::time::Instant;
+use cancel::CancelToken;
+use json::Json;
+use sudoku::{square_name, Config, Notation, Solver, SquareId, SquareValue, StartState, StartValue, State, Unit};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Technique {
+    NakedSingle,
+    HiddenSingle,
+    LockedCandidates,
+    NakedPair,
+    HiddenPair,
+    XWing,
+    Reveal
+}
+
+impl Technique {
+
+    // Every deductive technique, easiest first. `Reveal` is not a deduction and is never searched for.
+    pub fn all() -> Vec<Technique> {
+        vec![Technique::NakedSingle,
+             Technique::HiddenSingle,
+             Technique::LockedCandidates,
+             Technique::NakedPair,
+             Technique::HiddenPair,
+             Technique::XWing]
+    }
+
+    // Stable identifier used in machine-readable output.
+    pub fn id(&self) -> &'static str {
+        match *self {
+            Technique::NakedSingle => "naked_single",
+            Technique::HiddenSingle => "hidden_single",
+            Technique::LockedCandidates => "locked_candidates",
+            Technique::NakedPair => "naked_pair",
+            Technique::HiddenPair => "hidden_pair",
+            Technique::XWing => "x_wing",
+            Technique::Reveal => "reveal"
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Technique::NakedSingle => "Naked single",
+            Technique::HiddenSingle => "Hidden single",
+            Technique::LockedCandidates => "Locked candidates",
+            Technique::NakedPair => "Naked pair",
+            Technique::HiddenPair => "Hidden pair",
+            Technique::XWing => "X-Wing",
+            Technique::Reveal => "Reveal"
+        }
+    }
+
+    pub fn from_id(id : &str) -> Option<Technique> {
+        Technique::all().into_iter().chain(Some(Technique::Reveal)).find(|t| t.id() == id)
+    }
+
+    // Key of the technique's display name in a `Messages` table, "technique.<id>".
+    pub fn name_key(&self) -> String {
+        format!("technique.{}", self.id())
+    }
+}
+
+type Callback = Box<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+// Text shown to players, looked up by key so apps can present names and hints in any language
+// without matching on English strings. Keys are "technique.<id>" for names and "explain.<id>" for
+// step explanations, which may use {cells}, {placements} and {eliminations}. Lookups try the
+// table, then the callback, then the built-in English text, then fall back to the key itself.
+// Squares in explanations are written in the messages' notation, A1 unless set otherwise.
+pub struct Messages {
+    table : HashMap<String, String>,
+    callback : Option<Callback>,
+    notation : Notation
+}
+
+impl Messages {
+
+    pub fn english() -> Messages {
+        Messages {
+            table : HashMap::new(),
+            callback : None,
+            notation : Notation::Chess
+        }
+    }
+
+    pub fn with_table(table : HashMap<String, String>) -> Messages {
+        Messages {
+            table,
+            callback : None,
+            notation : Notation::Chess
+        }
+    }
+
+    pub fn with_callback<F : Fn(&str) -> Option<String> + Send + Sync + 'static>(callback : F) -> Messages {
+        Messages {
+            table : HashMap::new(),
+            callback : Some(Box::new(callback)),
+            notation : Notation::Chess
+        }
+    }
+
+    pub fn with_notation(self, notation : Notation) -> Messages {
+        Messages {
+            notation,
+            ..self
+        }
+    }
+
+    pub fn notation(&self) -> Notation {
+        self.notation
+    }
+
+    pub fn text(&self, key : &str) -> String {
+        self.table.get(key).cloned()
+                  .or_else(|| self.callback.as_ref().and_then(|callback| callback(key)))
+                  .or_else(|| english(key).map(|text| text.to_string()))
+                  .unwrap_or_else(|| key.to_string())
+    }
+}
+
+fn english(key : &str) -> Option<&'static str> {
+    if let Some(id) = key.strip_prefix("technique.") {
+        return Technique::from_id(id).map(|t| t.name());
+    }
+    match key {
+        "explain.naked_single" => Some("{cells} has a single candidate left: {placements}"),
+        "explain.hidden_single" => Some("{cells} is the only place left for the digit in one of its units: {placements}"),
+        "explain.locked_candidates" => Some("The digit is locked into {cells}, so it goes from the rest of the unit: {eliminations}"),
+        "explain.naked_pair" => Some("{cells} share the same two candidates, which leave their common peers: {eliminations}"),
+        "explain.hidden_pair" => Some("Two digits only fit in {cells}, so the other candidates there go: {eliminations}"),
+        "explain.x_wing" => Some("The digit forms an X-Wing on {cells}: {eliminations}"),
+        "explain.reveal" => Some("Revealed from the solution: {placements}"),
+        _ => None
+    }
+}
+
+impl std::fmt::Display for Technique {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SolveStep {
+    pub technique : Technique,
+    pub cells : Vec<SquareId>,
+    pub placements : Vec<StartValue>,
+    pub eliminations : Vec<StartValue>
+}
+
+impl SolveStep {
+
+    fn new(technique : Technique, cells : Vec<SquareId>, placements : Vec<StartValue>, mut eliminations : Vec<StartValue>) -> SolveStep {
+        eliminations.sort();
+        eliminations.dedup();
+        SolveStep {
+            technique,
+            cells,
+            placements,
+            eliminations
+        }
+    }
+
+    pub fn apply(&self, state : &mut State) -> bool {
+        self.eliminations.iter().all(|&(s, v)| state.remove_candidate(&s, &v))
+            && self.placements.iter().all(|&(s, v)| state.place(&s, &v))
+    }
+
+    // Key of the explanation in a `Messages` table, "explain.<technique id>".
+    pub fn explanation_key(&self) -> String {
+        format!("explain.{}", self.technique.id())
+    }
+
+    pub fn explain(&self, messages : &Messages) -> String {
+        let notation = messages.notation();
+        let digits = |values : &[StartValue], sep : &str| values.iter().map(|&(s, v)| format!("{}{}{}", notation.name(&s), sep, v))
+                                                                .collect::<Vec<String>>().join(" ");
+        messages.text(&self.explanation_key())
+                .replace("{cells}", &self.cells.iter().map(|s| notation.name(s)).collect::<Vec<String>>().join(" "))
+                .replace("{placements}", &digits(&self.placements, "="))
+                .replace("{eliminations}", &digits(&self.eliminations, "<>"))
+    }
+
+    // The step as `Display` writes it, "Hidden single: B3=5", with squares in the notation.
+    pub fn describe(&self, notation : Notation) -> String {
+        let mut text = format!("{}:", self.technique);
+        for &(s, v) in &self.placements {
+            text.push_str(&format!(" {}={}", notation.name(&s), v));
+        }
+        for &(s, v) in &self.eliminations {
+            text.push_str(&format!(" {}<>{}", notation.name(&s), v));
+        }
+        text
+    }
+
+    // {"technique": "hidden_single", "cells": ["B3"], "placements": [{"cell": "B3", "digit": 5}], "eliminations": [...]}
+    pub fn to_json(&self) -> Json {
+        fn digits(values : &[StartValue]) -> Json {
+            Json::Array(values.iter()
+                              .map(|&(s, v)| Json::object(vec![("cell", Json::String(square_name(&s))),
+                                                               ("digit", Json::Number(v as f64))]))
+                              .collect())
+        }
+        Json::object(vec![("technique", Json::string(self.technique.id())),
+                          ("cells", Json::Array(self.cells.iter().map(|s| Json::String(square_name(s))).collect())),
+                          ("placements", digits(&self.placements)),
+                          ("eliminations", digits(&self.eliminations))])
+    }
+}
+
+pub fn path_to_json(path : &[SolveStep]) -> Json {
+    Json::object(vec![("steps", Json::Array(path.iter().map(|step| step.to_json()).collect()))])
+}
+
+impl std::fmt::Display for SolveStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.describe(Notation::Chess))
+    }
+}
+
+pub struct LogicalSolver {
+    solver : Solver,
+    techniques : Vec<Technique>
+}
+
+impl Default for LogicalSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogicalSolver {
+
+    pub fn new() -> LogicalSolver {
+        LogicalSolver::with_techniques(&Technique::all())
+    }
+
+    pub fn with_techniques(techniques : &[Technique]) -> LogicalSolver {
+        LogicalSolver {
+            solver : Solver::new(),
+            techniques : techniques.iter().cloned().filter(|&t| t != Technique::Reveal).collect()
+        }
+    }
+
+    pub fn techniques(&self) -> &Vec<Technique> {
+        &self.techniques
+    }
+
+    // Candidates after placing the givens, with no deductions applied yet.
+    pub fn start(&self, start_state : &StartState) -> Result<State<'_>, String> {
+        start_state.validate(self.solver.config()).map_err(|e| e.to_string())?;
+        let mut state = State::new(self.solver.config());
+        for &(s, v) in start_state.iter().filter(|&&(_, v)| v != 0) {
+            if ! state.place(&s, &v) {
+                return Err("Conflicting givens".to_string());
+            }
+        }
+        if ! state.impose(start_state.eliminations()) {
+            return Err("Eliminations leave a square without candidates".to_string());
+        }
+        Ok(state)
+    }
+
+    pub fn find_step(&self, state : &State) -> Option<SolveStep> {
+        let config = self.solver.config();
+        self.techniques.iter().filter_map(|&t| technique_step(config, state, t)).next()
+    }
+
+    // One application of the easiest technique that applies, with the state it leaves, for
+    // tutorials that show a move and then let the player go on; `state` itself is left as it
+    // was. None when the enabled techniques are stuck (`next_placement` reveals a square there)
+    // or the step contradicts the state, as wrong entries can.
+    pub fn next_step<'a>(&self, state : &State<'a>) -> Option<(SolveStep, State<'a>)> {
+        let step = self.find_step(state)?;
+        let mut next = state.clone();
+        if step.apply(&mut next) {
+            Some((step, next))
+        } else {
+            None
+        }
+    }
+
+    // Every step of the first enabled technique that has one, so a caller can choose among
+    // equally easy hints. Singles give each square they place (a hidden single once, however many
+    // units show it); the other techniques only the step `find_step` finds.
+    pub fn find_easiest_steps(&self, state : &State) -> Vec<SolveStep> {
+        let config = self.solver.config();
+        for &t in &self.techniques {
+            let steps = match t {
+                Technique::NakedSingle => naked_singles(config, state),
+                Technique::HiddenSingle => hidden_singles(config, state),
+                t => technique_step(config, state, t).into_iter().collect()
+            };
+            if ! steps.is_empty() {
+                return steps;
+            }
+        }
+        Vec::new()
+    }
+
+    // Every step from the givens to the solution. Where the enabled techniques get stuck the path
+    // reveals a square from the solution, so the path always reaches the end.
+    pub fn full_path(&self, start_state : &StartState) -> Result<Vec<SolveStep>, String> {
+        self.path(start_state, true, None)
+    }
+
+    // `full_path` giving up once `deadline` has passed, checked while the solution is searched for
+    // and between steps.
+    pub fn full_path_until(&self, start_state : &StartState, deadline : Instant) -> Result<Vec<SolveStep>, String> {
+        self.path(start_state, true, Some(&CancelToken::new().with_deadline(deadline)))
+    }
+
+    // `full_path` giving up once `cancel` is cancelled or out of time, checked in the same places.
+    pub fn full_path_cancellable(&self, start_state : &StartState, cancel : &CancelToken) -> Result<Vec<SolveStep>, String> {
+        self.path(start_state, true, Some(cancel))
+    }
+
+    // A path using only the enabled techniques, proving the puzzle needs no guessing. Replaying it
+    // from the givens with `SolveStep::apply` reaches the unique solution.
+    pub fn certify(&self, start_state : &StartState) -> Option<Vec<SolveStep>> {
+        if self.solver.count_solutions(start_state.clone(), 2) != 1 {
+            return None;
+        }
+        self.path(start_state, false, None).ok()
+    }
+
+    // Steps from the givens up to and including the first that places a digit, revealing a square
+    // from the solution where the enabled techniques get stuck. Empty when the grid is full.
+    pub fn next_placement(&self, start_state : &StartState) -> Result<Vec<SolveStep>, String> {
+        self.placement(start_state, None)
+    }
+
+    // `next_placement` where `choose` picks which of the equally easy steps to take at each point
+    // (see `find_easiest_steps`), given how many there are.
+    pub fn next_placement_choosing(&self, start_state : &StartState, choose : &mut dyn FnMut(usize) -> usize) -> Result<Vec<SolveStep>, String> {
+        self.placement(start_state, Some(choose))
+    }
+
+    fn placement(&self, start_state : &StartState, mut choose : Option<&mut dyn FnMut(usize) -> usize>) -> Result<Vec<SolveStep>, String> {
+        let solution = self.solver.solve(start_state.clone())?;
+        let mut state = self.start(start_state)?;
+        let mut steps = Vec::new();
+        loop {
+            let found = match choose {
+                Some(ref mut choose) => {
+                    let mut easiest = self.find_easiest_steps(&state);
+                    if easiest.is_empty() {
+                        None
+                    } else {
+                        let i = choose(easiest.len()) % easiest.len();
+                        Some(easiest.swap_remove(i))
+                    }
+                },
+                None => self.find_step(&state)
+            };
+            let step = match found {
+                Some(step) => step,
+                None => match reveal(self.solver.config(), &state, &solution) {
+                    Some(step) => step,
+                    None => return Ok(steps)
+                }
+            };
+            if ! step.apply(&mut state) {
+                return Err(format!("Contradiction after {}", step));
+            }
+            let placed = ! step.placements.is_empty();
+            steps.push(step);
+            if placed {
+                return Ok(steps);
+            }
+        }
+    }
+
+    fn path(&self, start_state : &StartState, allow_reveal : bool, cancel : Option<&CancelToken>) -> Result<Vec<SolveStep>, String> {
+        let solution = match cancel {
+            Some(cancel) => self.solver.solve_cancellable(start_state.clone(), cancel)?,
+            None => self.solver.solve(start_state.clone())?
+        };
+        let mut state = self.start(start_state)?;
+        let mut path = Vec::new();
+        loop {
+            if let Some(cancel) = cancel {
+                cancel.check().map_err(|e| format!("{} after {} steps", e, path.len()))?;
+            }
+            let step = match self.find_step(&state) {
+                Some(step) => step,
+                None if allow_reveal => match reveal(self.solver.config(), &state, &solution) {
+                    Some(step) => step,
+                    None => break
+                },
+                None => break
+            };
+            if ! step.apply(&mut state) {
+                return Err(format!("Contradiction after {}", step));
+            }
+            path.push(step);
+        }
+        self.validate_path(start_state, &path, &solution)?;
+        Ok(path)
+    }
+
+    fn validate_path(&self, start_state : &StartState, path : &[SolveStep], solution : &State) -> Result<(), String> {
+        let mut state = self.start(start_state)?;
+        for (i, step) in path.iter().enumerate() {
+            let eliminations_valid = step.eliminations.iter().all(|&(s, v)| solution.value(&s) != Some(v));
+            let placements_valid = step.placements.iter().all(|&(s, v)| solution.value(&s) == Some(v) && state.candidates(&s).contains(&v));
+            if ! eliminations_valid || ! placements_valid || ! step.apply(&mut state) {
+                return Err(format!("Invalid step {}: {}", i + 1, step));
+            }
+        }
+        if ! self.solver.config().squares().iter().all(|s| state.is_placed(s)) {
+            return Err("Solve path does not reach the solution".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn place_step(technique : Technique, config : &Config, state : &State, square : &SquareId, value : SquareValue) -> SolveStep {
+    let mut eliminations : Vec<StartValue> = state.candidates(square).into_iter()
+                                                                     .filter(|&v| v != value)
+                                                                     .map(|v| (*square, v))
+                                                                     .collect();
+    eliminations.extend(config.peers(square).iter()
+                                            .filter(|s2| state.candidates(s2).contains(&value))
+                                            .map(|s2| (*s2, value)));
+    SolveStep::new(technique, vec![*square], vec![(*square, value)], eliminations)
+}
+
+fn places(state : &State, unit : &Unit, value : SquareValue) -> Vec<SquareId> {
+    unit.iter().filter(|s| state.candidates(s).contains(&value)).cloned().collect()
+}
+
+fn reveal(config : &Config, state : &State, solution : &State) -> Option<SolveStep> {
+    config.squares().iter()
+                    .find(|s| ! state.is_placed(s))
+                    .and_then(|s| solution.value(s).map(|v| place_step(Technique::Reveal, config, state, s, v)))
+}
+
+fn technique_step(config : &Config, state : &State, technique : Technique) -> Option<SolveStep> {
+    match technique {
+        Technique::NakedSingle => naked_single(config, state),
+        Technique::HiddenSingle => hidden_single(config, state),
+        Technique::LockedCandidates => locked_candidates(state),
+        Technique::NakedPair => naked_pair(config, state),
+        Technique::HiddenPair => hidden_pair(config, state),
+        Technique::XWing => x_wing(config, state),
+        Technique::Reveal => None
+    }
+}
+
+fn naked_singles(config : &Config, state : &State) -> Vec<SolveStep> {
+    config.squares().iter()
+                    .filter(|s| state.value(s).is_some() && ! state.is_placed(s))
+                    .map(|s| place_step(Technique::NakedSingle, config, state, s, state.value(s).unwrap()))
+                    .collect()
+}
+
+fn hidden_singles(config : &Config, state : &State) -> Vec<SolveStep> {
+    let mut steps : Vec<SolveStep> = Vec::new();
+    for u in config.unit_list() {
+        for d in config.digits() {
+            let ps = places(state, u, d);
+            if ps.len() == 1 && ! state.is_placed(&ps[0]) && ! steps.iter().any(|step| step.cells[0] == ps[0]) {
+                steps.push(place_step(Technique::HiddenSingle, config, state, &ps[0], d));
+            }
+        }
+    }
+    steps
+}
+
+fn naked_single(config : &Config, state : &State) -> Option<SolveStep> {
+    config.squares().iter()
+                    .find(|s| state.value(s).is_some() && ! state.is_placed(s))
+                    .map(|s| place_step(Technique::NakedSingle, config, state, s, state.value(s).unwrap()))
+}
+
+fn hidden_single(config : &Config, state : &State) -> Option<SolveStep> {
+    for u in config.unit_list() {
+        for d in config.digits() {
+            let ps = places(state, u, d);
+            if ps.len() == 1 && ! state.is_placed(&ps[0]) {
+                return Some(place_step(Technique::HiddenSingle, config, state, &ps[0], d));
+            }
+        }
+    }
+    None
+}
+
+// Pointing and claiming: when a digit is confined to the overlap of two units it can be removed
+// from the rest of the other unit.
+fn locked_candidates(state : &State) -> Option<SolveStep> {
+    state.first_locked_candidate().map(|locked| {
+        let eliminations = locked.eliminations.iter().map(|s| (*s, locked.digit)).collect();
+        SolveStep::new(Technique::LockedCandidates, locked.cells, vec![], eliminations)
+    })
+}
+
+fn naked_pair(config : &Config, state : &State) -> Option<SolveStep> {
+    for u in config.unit_list() {
+        let pairs : Vec<(SquareId, Vec<SquareValue>)> = u.iter()
+                                                          .map(|s| (*s, state.candidates(s)))
+                                                          .filter(|(_, vs)| vs.len() == 2)
+                                                          .collect();
+        for (i, &(s1, ref vs)) in pairs.iter().enumerate() {
+            for &(s2, ref vs2) in &pairs[i + 1..] {
+                if vs != vs2 {
+                    continue;
+                }
+                let eliminations : Vec<StartValue> = u.iter()
+                                                      .filter(|&&s| s != s1 && s != s2)
+                                                      .flat_map(|s| vs.iter()
+                                                                      .filter(move |v| state.candidates(s).contains(v))
+                                                                      .map(move |&v| (*s, v)))
+                                                      .collect();
+                if ! eliminations.is_empty() {
+                    return Some(SolveStep::new(Technique::NakedPair, vec![s1, s2], vec![], eliminations));
+                }
+            }
+        }
+    }
+    None
+}
+
+fn hidden_pair(config : &Config, state : &State) -> Option<SolveStep> {
+    let digits = config.digits();
+    for u in config.unit_list() {
+        for (i, &d1) in digits.iter().enumerate() {
+            let ps = places(state, u, d1);
+            if ps.len() != 2 {
+                continue;
+            }
+            for &d2 in &digits[i + 1..] {
+                if places(state, u, d2) != ps {
+                    continue;
+                }
+                let eliminations : Vec<StartValue> = ps.iter()
+                                                       .flat_map(|s| state.candidates(s).into_iter()
+                                                                                        .filter(|&v| v != d1 && v != d2)
+                                                                                        .map(move |v| (*s, v)))
+                                                       .collect();
+                if ! eliminations.is_empty() {
+                    return Some(SolveStep::new(Technique::HiddenPair, ps, vec![], eliminations));
+                }
+            }
+        }
+    }
+    None
+}
+
+fn x_wing(config : &Config, state : &State) -> Option<SolveStep> {
+    for &(lines, covers) in &[(config.rows(), config.cols()), (config.cols(), config.rows())] {
+        for d in config.digits() {
+            let positions : Vec<Vec<usize>> = lines.iter()
+                                                   .map(|l| (0..9).filter(|&i| state.candidates(&l[i]).contains(&d)).collect())
+                                                   .collect();
+            for i in 0..lines.len() {
+                if positions[i].len() != 2 {
+                    continue;
+                }
+                for j in i + 1..lines.len() {
+                    if positions[j] != positions[i] {
+                        continue;
+                    }
+                    let eliminations : Vec<StartValue> = positions[i].iter()
+                                                                     .flat_map(|&c| covers[c].iter())
+                                                                     .filter(|s| ! lines[i].contains(s) && ! lines[j].contains(s))
+                                                                     .filter(|s| state.candidates(s).contains(&d))
+                                                                     .map(|s| (*s, d))
+                                                                     .collect();
+                    if ! eliminations.is_empty() {
+                        let cells = positions[i].iter().flat_map(|&c| vec![lines[i][c], lines[j][c]]).collect();
+                        return Some(SolveStep::new(Technique::XWing, cells, vec![], eliminations));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
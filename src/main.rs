@@ -1,22 +1,1189 @@
-extern crate time;
-extern crate rand;
+extern crate sudoku_solver;
 
-mod sudoku;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use sudoku_solver::analysis::{self, Difficulty};
+use sudoku_solver::backend;
+use sudoku_solver::calibration::{self, Calibration, TimeModel};
+use sudoku_solver::canonical;
+#[cfg(feature = "fault-injection")]
+use sudoku_solver::fault;
+use sudoku_solver::formats::{self, Format, License, SdmReader, SdmWriter};
+use sudoku_solver::golden;
+use sudoku_solver::grid::Grid9;
+use sudoku_solver::js;
+use sudoku_solver::json::Json;
+use sudoku_solver::limits::Limits;
+use sudoku_solver::logic::{LogicalSolver, Technique};
+use sudoku_solver::pack::{self, Pack};
+use sudoku_solver::pipeline::Job;
+use sudoku_solver::puzzle::{Provenance, Puzzle};
+use sudoku_solver::render;
+use sudoku_solver::stress;
+use sudoku_solver::sudoku;
+use sudoku_solver::variant::Variant;
 
-fn time_solve(solver : &sudoku::Solver, puzzle: &str) -> u64 {
-    let start = time::precise_time_ns();
+// What a command produced, both for people and for scripts (--json).
+struct Report {
+    text : String,
+    json : Json
+}
+
+struct CliError {
+    code : &'static str,
+    message : String,
+    // output for the inputs that were handled before a batch failed
+    partial : Option<Report>
+}
+
+impl CliError {
+    fn new(code : &'static str, message : String) -> CliError {
+        CliError {
+            code,
+            message,
+            partial : None
+        }
+    }
+
+    // Distinct per cause so pipelines can tell a bad puzzle from bad usage:
+    //   1 unsolvable   puzzle without a solution, or one that can't be rated
+    //   2 usage        unknown command or option, or a bad option value
+    //   3 parse        input that can't be read as puzzles
+    //   4 io           file that can't be read or written
+    //   5 timeout      no answer within --timeout, or no sparse puzzle within the budget
+    //   6 limit        input or solve over a size, nesting or memory limit
+    //   7 license      pack input whose license isn't the one required
+    //   8 wrong        fault injection run that missed faults or raised false alarms
+    //   9 golden       seeded generation differs from data/golden.txt
+    //  10 generate     no puzzle matching the request within the attempts
+    //  11 calibration  reference puzzles rated off their pinned level
+    //  70 anything else, a bug
+    fn exit_code(&self) -> i32 {
+        match self.code {
+            "unsolvable" => 1,
+            "usage" => 2,
+            "parse" => 3,
+            "io" => 4,
+            "timeout" => 5,
+            "limit" => 6,
+            "license" => 7,
+            "wrong" => 8,
+            "golden" => 9,
+            "generate" => 10,
+            "calibration" => 11,
+            _ => 70
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        Json::object(vec![("code", Json::string(self.code)),
+                          ("exit_code", Json::Number(self.exit_code() as f64)),
+                          ("message", Json::string(&self.message))])
+    }
+
+    // One tab separated line per error: `error <code> <message>`.
+    fn report(&self) {
+        eprintln!("error\t{}\t{}", self.code, self.message.replace('\n', " "));
+    }
+}
+
+fn time_solve(solver : &sudoku::Solver, puzzle: &str) -> f64 {
+    let start = Instant::now();
     solver.solve_str(puzzle).unwrap();
-    time::precise_time_ns() - start
+    let elapsed = start.elapsed();
+    elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1000000000.0
 }
 
-fn main() {
+// Value following `--name` on the command line.
+fn option<'a>(args : &'a [String], name : &str) -> Option<&'a str> {
+    args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).map(|s| s.as_str())
+}
+
+// Options that take a value, so the value isn't mistaken for a positional argument.
+const VALUE_OPTIONS : [&str; 24] = ["--corpus", "--backends", "--clues", "--file", "--timeout", "--variant", "--max-memory",
+                                            "--in", "--out", "--per-difficulty", "--seed", "--svg", "--from", "--to", "--blank",
+                                            "--minutes", "--revision", "--count", "--format", "--se", "--faults", "--technique",
+                                            "--min-symmetry", "--license"];
+
+fn positional(args : &[String]) -> Option<&str> {
+    let mut skip = false;
+    for arg in args {
+        if skip {
+            skip = false;
+        } else if arg.starts_with("--") {
+            skip = VALUE_OPTIONS.contains(&arg.as_str());
+        } else {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+fn flag(args : &[String], name : &str) -> bool {
+    args.iter().any(|a| a == name)
+}
+
+// Whether a batch stops at its first failure: --fail-fast, or --continue (the default) to
+// attempt every puzzle.
+fn fail_fast(args : &[String]) -> Result<bool, CliError> {
+    match (flag(args, "--fail-fast"), flag(args, "--continue")) {
+        (true, true) => Err(CliError::new("usage", "--fail-fast and --continue exclude each other".to_string())),
+        (fail_fast, _) => Ok(fail_fast)
+    }
+}
+
+fn number_option(args : &[String], name : &str, default : usize) -> Result<usize, CliError> {
+    match option(args, name) {
+        Some(n) => n.parse().map_err(|_| CliError::new("usage", format!("{} expects a number, got {}", name, n))),
+        None => Ok(default)
+    }
+}
+
+fn parse_puzzle(grid : &str) -> Result<Puzzle, CliError> {
+    grid.parse().map_err(|e| CliError::new("parse", format!("{}: {}", grid, e)))
+}
+
+// Puzzles numbered by the line they were read from.
+type Lines = Vec<(usize, Result<Puzzle, CliError>)>;
+
+// One puzzle per line; blank lines and lines starting with '#' are skipped. Comments of the form
+// `# author: Jane Doe` (also source, license and created) credit the puzzles that follow them.
+// Lines that don't parse are kept as errors so batch commands can decide whether to carry on.
+fn read_lines(path : &str) -> Result<Lines, CliError> {
+    let io_error = |e : std::io::Error| CliError::new("io", format!("{}: {}", path, e));
+    let file = File::open(path).map_err(&io_error)?;
+    let mut provenance = Provenance::default();
+    let mut puzzles = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(&io_error)?;
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('#') {
+            provenance.parse_header(header).map_err(|e| CliError::new("parse", format!("{}:{}: {}", path, i + 1, e)))?;
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let puzzle = line.parse::<Puzzle>()
+                         .map(|p| p.with_provenance(provenance.clone()))
+                         .map_err(|e| CliError::new("parse", format!("{}:{}: {}", path, i + 1, e)));
+        puzzles.push((i + 1, puzzle));
+    }
+    Ok(puzzles)
+}
+
+fn read_corpus(path : &str) -> Result<Vec<sudoku::StartState>, CliError> {
+    let mut puzzles = Vec::new();
+    for (_, puzzle) in read_lines(path)? {
+        puzzles.push(puzzle?.into_start_state());
+    }
+    Ok(puzzles)
+}
+
+// With --stress, runs the shipped worst cases instead of a corpus, each within --timeout ms
+// (default 10000); any timeout or wrong answer fails the command. With --faults (builds with
+// "fault-injection" only), checks that corrupted candidates are caught, see `bench_faults`.
+fn bench(args : &[String]) -> Result<Report, CliError> {
+    if flag(args, "--stress") {
+        return bench_stress(args);
+    }
+    #[cfg(feature = "fault-injection")]
+    {
+        if option(args, "--faults").is_some() {
+            return bench_faults(args);
+        }
+    }
+    let corpus = match option(args, "--corpus") {
+        Some(path) => read_corpus(path)?,
+        None => return Err(CliError::new("usage", "bench needs --corpus <file>".to_string()))
+    };
+    let backends : Vec<&str> = match option(args, "--backends") {
+        Some(names) => names.split(',').collect(),
+        None => backend::available()
+    };
+    let rows = backend::bench(&backends, &corpus);
+    let singles_only = corpus.iter().filter(|p| analysis::solved_by_singles(p)).count();
+    Ok(Report {
+        text : format!("{}{} of {} puzzles solved by singles alone\n", backend::format_table(&rows), singles_only, corpus.len()),
+        json : Json::object(vec![("puzzles", Json::Number(corpus.len() as f64)),
+                                 ("singles_only", Json::Number(singles_only as f64)),
+                                 ("backends", Json::Array(rows.iter().map(|row| row.to_json()).collect()))])
+    })
+}
+
+fn bench_stress(args : &[String]) -> Result<Report, CliError> {
+    let timeout_ms = number_option(args, "--timeout", 10000)?;
+    let backends : Vec<&str> = match option(args, "--backends") {
+        Some(names) => names.split(',').collect(),
+        None => backend::available()
+    };
+    let results = stress::stress(&backends, Duration::from_millis(timeout_ms as u64)).map_err(|e| CliError::new("usage", e))?;
+    let report = Report {
+        text : stress::format_table(&results),
+        json : Json::object(vec![("timeout_ms", Json::Number(timeout_ms as f64)),
+                                 ("results", Json::Array(results.iter().map(|r| r.to_json()).collect()))])
+    };
+    let failed : Vec<&stress::StressResult> = results.iter().filter(|r| r.outcome != stress::Outcome::Passed).collect();
+    if failed.is_empty() {
+        return Ok(report);
+    }
+    let code = if failed.iter().any(|r| r.outcome == stress::Outcome::Wrong) { "wrong" } else { "timeout" };
+    let names : Vec<String> = failed.iter().map(|r| format!("{} on {} {}", r.backend, r.puzzle, r.outcome.name())).collect();
+    let err = CliError::new(code, names.join(", "));
+    err.report();
+    Err(CliError { partial : Some(report), ..err })
+}
+
+// Corrupts --faults grids mid-solve (see `fault`), from --seed (default 1); a fault the checks
+// miss, or a check failing a healthy grid, fails the command.
+#[cfg(feature = "fault-injection")]
+fn bench_faults(args : &[String]) -> Result<Report, CliError> {
+    let rounds = number_option(args, "--faults", 1000)?;
+    let seed = number_option(args, "--seed", 1)? as u64;
+    let report = fault::check(rounds, seed);
+    let mut text = format!("{} rounds, {} faults caught\n", report.rounds, report.caught);
+    for missed in &report.missed {
+        text.push_str(&format!("missed {}\n", missed));
+    }
+    for alarm in &report.false_alarms {
+        text.push_str(&format!("false alarm {}\n", alarm));
+    }
+    let strings = |list : &[String]| Json::Array(list.iter().map(|s| Json::string(s)).collect());
+    let result = Report {
+        text,
+        json : Json::object(vec![("rounds", Json::Number(report.rounds as f64)),
+                                 ("caught", Json::Number(report.caught as f64)),
+                                 ("missed", strings(&report.missed)),
+                                 ("false_alarms", strings(&report.false_alarms))])
+    };
+    if report.passed() {
+        return Ok(result);
+    }
+    let err = CliError::new("wrong", format!("{} faults missed, {} false alarms", report.missed.len(), report.false_alarms.len()));
+    err.report();
+    Err(CliError { partial : Some(result), ..err })
+}
+
+struct Solved {
+    grid : String,
+    solution : String,
+    guesses : usize
+}
+
+// Solves on a worker thread so a time limit can be enforced; a timed out search is abandoned.
+fn solve_within(puzzle : &Puzzle, variant : Option<&Variant>, timeout_ms : Option<u64>) -> Result<Solved, CliError> {
+    let start_state = puzzle.start_state().clone();
+    let variant = variant.cloned();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let solver = match variant {
+            Some(variant) => variant.solver(),
+            None => sudoku::Solver::new()
+        };
+        let result = solver.solve_counting(start_state).map(|(state, guesses)| {
+            let solution = solver.config().squares().iter().filter_map(|s| state.value(s).map(|v| (*s, v))).collect();
+            Solved {
+                grid : format!("{}", state),
+                solution : Puzzle::new(solution).to_string(),
+                guesses
+            }
+        });
+        let _ = sender.send(result);
+    });
+    let received = match timeout_ms {
+        Some(ms) => receiver.recv_timeout(Duration::from_millis(ms))
+                            .map_err(|_| CliError::new("timeout", format!("No solution within {} ms", ms)))?,
+        None => receiver.recv().map_err(|_| CliError::new("unsolvable", "Solver stopped".to_string()))?
+    };
+    received.map_err(|e| CliError::new("unsolvable", e))
+}
+
+fn solved_json(puzzle : &Puzzle, solved : &Solved, blank : sudoku::StringStartStateHandler) -> Json {
+    let mut fields = vec![("puzzle", Json::String(puzzle.display(blank).to_string())),
+                          ("solution", Json::String(solved.solution.clone())),
+                          ("guesses", Json::Number(solved.guesses as f64))];
+    fields.extend(puzzle.provenance().to_json_fields());
+    Json::object(fields)
+}
+
+fn read_variant(path : &str) -> Result<Variant, CliError> {
+    let limits = Limits::default();
+    let mut descriptor = String::new();
+    File::open(path).and_then(|mut file| file.read_to_string(&mut descriptor))
+                    .map_err(|e| CliError::new("io", format!("{}: {}", path, e)))?;
+    limits.check_input(&descriptor).map_err(|e| CliError::new("limit", format!("{}: {}", path, e)))?;
+    let variant = Variant::parse(&descriptor).map_err(|e| CliError::new("parse", format!("{}: {}", path, e)))?;
+    limits.check_variant(&variant).map_err(|e| CliError::new("limit", format!("{}: {}", path, e)))?;
+    Ok(variant)
+}
+
+// `solve -` reads the puzzle from stdin in any format `formats::detect_format` knows; given
+// several it solves them all, as with --file.
+fn solve(args : &[String]) -> Result<Report, CliError> {
+    let timeout = match option(args, "--timeout") {
+        Some(_) => Some(number_option(args, "--timeout", 0)? as u64),
+        None => None
+    };
+    let variant = match option(args, "--variant") {
+        Some(path) => Some(read_variant(path)?),
+        None => None
+    };
+    if option(args, "--max-memory").is_some() {
+        let limit = number_option(args, "--max-memory", 0)?;
+        let needed = variant.as_ref().map_or(sudoku::Solver::new(), |v| v.solver()).memory_bound();
+        if needed > limit {
+            return Err(CliError::new("limit", format!("A solve may need {} bytes, more than --max-memory {}", needed, limit)));
+        }
+    }
+    if let Some(path) = option(args, "--file") {
+        return solve_batch(path, read_lines(path)?, variant.as_ref(), timeout, fail_fast(args)?, blank_handler(args)?);
+    }
+    let puzzle = match (positional(args), variant.as_ref()) {
+        (Some("-"), _) => {
+            let mut puzzles = read_stdin()?;
+            if puzzles.len() > 1 {
+                let numbered = puzzles.into_iter().enumerate().map(|(i, puzzle)| (i + 1, Ok(puzzle))).collect();
+                return solve_batch("stdin", numbered, variant.as_ref(), timeout, fail_fast(args)?, blank_handler(args)?);
+            }
+            puzzles.remove(0)
+        },
+        (Some(grid), _) => parse_puzzle(grid)?,
+        (None, Some(variant)) => variant.puzzle.clone(),
+        (None, None) => return Err(CliError::new("usage", "solve needs a puzzle, --variant <file> or --file <file>".to_string()))
+    };
+    let solved = solve_within(&puzzle, variant.as_ref(), timeout)?;
+    Ok(Report {
+        text : solved.grid.clone(),
+        json : solved_json(&puzzle, &solved, blank_handler(args)?)
+    })
+}
+
+// The puzzles pasted or piped in, whatever their format.
+fn read_stdin() -> Result<Vec<Puzzle>, CliError> {
+    let mut text = String::new();
+    io::stdin().read_to_string(&mut text).map_err(|e| CliError::new("io", format!("stdin: {}", e)))?;
+    Limits::default().check_input(&text).map_err(|e| CliError::new("limit", format!("stdin: {}", e)))?;
+    formats::read_detected(&text).map(|(_, puzzles)| puzzles).map_err(|e| CliError::new("parse", format!("stdin: {}", e)))
+}
+
+// Every puzzle is attempted unless --fail-fast, see `fail_fast`; failures are reported on stderr as they happen
+// and the first one decides the exit code. Puzzles are numbered by their line in `path`, or in
+// turn for input read all at once.
+fn solve_batch(path : &str, puzzles : Lines, variant : Option<&Variant>, timeout : Option<u64>, fail_fast : bool,
+               blank : sudoku::StringStartStateHandler) -> Result<Report, CliError> {
+    let mut text = String::new();
+    let mut results = Vec::new();
+    let mut failure : Option<CliError> = None;
+    for (line, puzzle) in puzzles {
+        let outcome = puzzle.and_then(|puzzle| solve_within(&puzzle, variant, timeout).map(|solved| (puzzle, solved)));
+        match outcome {
+            Ok((puzzle, solved)) => {
+                text.push_str(&format!("{}\n", solved.solution));
+                results.push(solved_json(&puzzle, &solved, blank));
+            }
+            Err(err) => {
+                let err = match err.code {
+                    "parse" => err,
+                    code => CliError::new(code, format!("{}:{}: {}", path, line, err.message))
+                };
+                err.report();
+                results.push(Json::object(vec![("line", Json::Number(line as f64)), ("error", err.to_json())]));
+                if failure.is_none() {
+                    failure = Some(err);
+                }
+                if fail_fast {
+                    break;
+                }
+            }
+        }
+    }
+    let report = Report {
+        text,
+        json : Json::object(vec![("results", Json::Array(results))])
+    };
+    match failure {
+        Some(err) => Err(CliError { partial : Some(report), ..err }),
+        None => Ok(report)
+    }
+}
+
+// Draws a variant with its rules, solved with --solve, as text and optionally as SVG (--svg).
+fn render_variant(args : &[String]) -> Result<Report, CliError> {
+    let variant = match option(args, "--variant") {
+        Some(path) => read_variant(path)?,
+        None => return Err(CliError::new("usage", "render needs --variant <file>".to_string()))
+    };
+    let solution = if flag(args, "--solve") {
+        let solved = solve_within(&variant.puzzle, Some(&variant), None)?;
+        Some(parse_puzzle(&solved.solution)?.into_start_state())
+    } else {
+        None
+    };
+    if let Some(path) = option(args, "--svg") {
+        File::create(path).and_then(|mut file| file.write_all(render::svg(&variant, solution.as_ref()).as_bytes()))
+                          .map_err(|e| CliError::new("io", format!("{}: {}", path, e)))?;
+    }
+    let text = render::text(&variant, solution.as_ref());
+    Ok(Report {
+        json : Json::object(vec![("text", Json::string(&text))]),
+        text
+    })
+}
+
+// Grid strings with the blank marker given by --blank, '.' by default, for every command that
+// prints puzzles.
+fn blank_handler(args : &[String]) -> Result<sudoku::StringStartStateHandler, CliError> {
+    let handler = sudoku::StringStartStateHandler::new();
+    match option(args, "--blank").map(|b| b.chars().collect::<Vec<char>>()) {
+        None => Ok(handler),
+        Some(ref chars) if chars.len() == 1 => handler.with_blank(chars[0]).map_err(|e| CliError::new("usage", e)),
+        Some(_) => Err(CliError::new("usage", "--blank expects a single character other than 1-9".to_string()))
+    }
+}
+
+// `--minutes 5-8` as the range in seconds; a single number is that minute, e.g. 5 for 5-6.
+fn minutes_option(args : &[String]) -> Result<Option<(f64, f64)>, CliError> {
+    let value = match option(args, "--minutes") {
+        Some(value) => value,
+        None => return Ok(None)
+    };
+    let usage = || CliError::new("usage", format!("--minutes expects a range like 5-8, got {}", value));
+    let bounds : Vec<f64> = value.split('-').map(|m| m.trim().parse()).collect::<Result<Vec<f64>, _>>().map_err(|_| usage())?;
+    match bounds[..] {
+        [low] if low >= 0.0 => Ok(Some((low * 60.0, (low + 1.0) * 60.0))),
+        [low, high] if low >= 0.0 && low <= high => Ok(Some((low * 60.0, high * 60.0))),
+        _ => Err(usage())
+    }
+}
+
+// With --sparse the puzzle is unique and has at most --clues clues (18 to 21 are reachable),
+// searched for up to --timeout milliseconds. With --minutes it is dug until the estimated solve
+// time (`calibration::TimeModel`) falls in the range. A --seed gives the same puzzle every time,
+// anywhere, except with --sparse; --golden checks that against the puzzles in data/golden.txt, or
+// with --out writes the file again as this build generates and rates them.
+//
+// --count puzzles are generated, the i-th from --seed + i. With --format jsonl each is a line of
+// JSON, and with --stream too each line is written as soon as its puzzle is done, so a consumer
+// can rate or store puzzles while a long run goes on.
+fn generate(args : &[String]) -> Result<Report, CliError> {
+    if flag(args, "--golden") {
+        if let Some(output) = option(args, "--out") {
+            let golden = golden::regenerate().map_err(|e| CliError::new("golden", e))?;
+            File::create(output).and_then(|mut file| file.write_all(golden.as_bytes()))
+                                .map_err(|e| CliError::new("io", format!("{}: {}", output, e)))?;
+            return Ok(Report {
+                text : format!("Pinned puzzles written to {} at version {}\n", output, golden::GOLDEN_VERSION),
+                json : Json::object(vec![("out", Json::string(output)), ("version", Json::Number(golden::GOLDEN_VERSION as f64))])
+            });
+        }
+        let checked = golden::check().map_err(|e| CliError::new("golden", e))?;
+        return Ok(Report {
+            text : format!("{} pinned puzzles reproduced\n", checked),
+            json : Json::object(vec![("reproduced", Json::Number(checked as f64))])
+        });
+    }
+    let count = number_option(args, "--count", 1)?;
+    let lines = match option(args, "--format") {
+        None | Some("text") => false,
+        Some("jsonl") => true,
+        Some(other) => return Err(CliError::new("usage", format!("--format expects text or jsonl, got {}", other)))
+    };
+    let stream = flag(args, "--stream");
+    if stream && (! lines || flag(args, "--json")) {
+        return Err(CliError::new("usage", "--stream needs --format jsonl and no --json".to_string()));
+    }
+    let seed : Option<u64> = match option(args, "--seed") {
+        Some(seed) => Some(seed.parse().map_err(|_| CliError::new("usage", format!("--seed expects a number, got {}", seed)))?),
+        None => None
+    };
+    let stdout = io::stdout();
+    let mut text = String::new();
+    let mut puzzles = Vec::new();
+    for i in 0..count {
+        let report = match generate_one(args, seed.map(|seed| seed.wrapping_add(i as u64))) {
+            Ok(report) => report,
+            Err(err) if stream || puzzles.is_empty() => return Err(err),
+            Err(err) => {
+                err.report();
+                let report = Report {
+                    text,
+                    json : Json::object(vec![("puzzles", Json::Array(puzzles))])
+                };
+                return Err(CliError { partial : Some(report), ..err });
+            }
+        };
+        if stream {
+            let mut out = stdout.lock();
+            writeln!(out, "{}", report.json).and_then(|_| out.flush())
+                                            .map_err(|e| CliError::new("io", format!("stdout: {}", e)))?;
+            continue;
+        }
+        if lines {
+            text.push_str(&format!("{}\n", report.json));
+        } else {
+            text.push_str(&report.text);
+        }
+        puzzles.push(report.json);
+    }
+    let json = if count == 1 && ! lines { puzzles.pop().unwrap_or(Json::Null) } else { Json::object(vec![("puzzles", Json::Array(puzzles))]) };
+    Ok(Report {
+        text,
+        json
+    })
+}
+
+fn generate_one(args : &[String], seed : Option<u64>) -> Result<Report, CliError> {
+    let clues = number_option(args, "--clues", 17)?;
+    let generator = match seed {
+        Some(seed) => sudoku::Generator::new().with_seed(seed),
+        None => sudoku::Generator::new()
+    };
+    let (puzzle, text, mut fields) = if let Some((low, high)) = minutes_option(args)? {
+        let model = TimeModel::default();
+        let result = generator.generate_timed(&model, Duration::from_millis((low * 1000.0) as u64),
+                                              Duration::from_millis((high * 1000.0) as u64), 20);
+        let result = result.ok_or_else(|| CliError::new("generate", format!("No puzzle estimated at {}-{} minutes in 20 attempts",
+                                                                              low / 60.0, high / 60.0)))?;
+        let puzzle = Puzzle::new(result.puzzle);
+        let estimate = model.estimate_puzzle(&LogicalSolver::new(), puzzle.start_state()).map_err(|e| CliError::new("unsolvable", e))?;
+        let text = format!("\nestimated {}:{:02}, {}", estimate.as_secs() / 60, estimate.as_secs() % 60, result.difficulty.name());
+        (puzzle, text, vec![("estimated_seconds", Json::Number(estimate.as_secs() as f64)),
+                            ("difficulty", Json::string(result.difficulty.name()))])
+    } else if flag(args, "--sparse") {
+        let timeout = number_option(args, "--timeout", 10000)? as u64;
+        match generator.generate_sparse(clues, Duration::from_millis(timeout)) {
+            Some(result) => (Puzzle::new(result.puzzle), String::new(), Vec::new()),
+            None => return Err(CliError::new("timeout", format!("No puzzle with {} clues or fewer within {} ms", clues, timeout)))
+        }
+    } else {
+        (generator.generate_puzzle(clues), String::new(), Vec::new())
+    };
+    let grid = puzzle.display(blank_handler(args)?).to_string();
+    if let Some(seed) = seed {
+        fields.push(("seed", Json::Number(seed as f64)));
+    }
+    let mut json = vec![("puzzle", Json::String(grid.clone())), ("clues", Json::Number(puzzle.clues() as f64))];
+    json.extend(fields);
+    Ok(Report {
+        text : format!("{}{}\n", grid, text),
+        json : Json::object(json)
+    })
+}
+
+// Picks up to --per-difficulty puzzles of each difficulty from a pool. Puzzles without a unique
+// solution and repeats of an earlier puzzle up to symmetry are dropped; within a difficulty the
+// order is fixed by the seed, so the same pool and seed always give the same set. With
+// --min-symmetry, puzzles whose clue pattern scores lower (see `analysis::symmetry_score`) are
+// dropped as well. A license of the pool (see `formats::License`) goes with the set.
+fn curate(args : &[String]) -> Result<Report, CliError> {
+    let (input, output) = match (option(args, "--in"), option(args, "--out")) {
+        (Some(input), Some(output)) => (input, output),
+        _ => return Err(CliError::new("usage", "curate needs --in <file> and --out <file>".to_string()))
+    };
+    let per_difficulty = number_option(args, "--per-difficulty", 20)?;
+    let seed = number_option(args, "--seed", 0)? as u64;
+    let min_symmetry = match option(args, "--min-symmetry") {
+        Some(x) => match x.parse::<f32>() {
+            Ok(x) if (0.0..=1.0).contains(&x) => x,
+            _ => return Err(CliError::new("usage", format!("--min-symmetry expects a number from 0 to 1, got {}", x)))
+        },
+        None => 0.0
+    };
+    let license = std::fs::read_to_string(input).map_err(|e| CliError::new("io", format!("{}: {}", input, e)))
+                                                .and_then(|text| Format::Sdm.read_license(&text).map_err(|e| CliError::new("parse", format!("{}: {}", input, e))))?;
+    let blank = blank_handler(args)?;
+    let logical_solver = LogicalSolver::new();
+    let calibration = Calibration::default();
+    let mut seen = HashSet::new();
+    let (mut duplicates, mut rejected, mut lopsided) = (0, 0, 0);
+    let mut buckets : BTreeMap<Difficulty, Vec<(u64, Puzzle, String)>> = BTreeMap::new();
+    for (_, puzzle) in read_lines(input)? {
+        let puzzle = puzzle?;
+        let unique = Grid9::from_start_state(puzzle.start_state()).is_some_and(|grid| grid.count_solutions(2) == 1);
+        if ! unique {
+            rejected += 1;
+            continue;
+        }
+        if analysis::symmetry_score(puzzle.start_state()) < min_symmetry {
+            lopsided += 1;
+            continue;
+        }
+        let form = canonical::canonical(&puzzle);
+        if ! seen.insert(form.clone()) {
+            duplicates += 1;
+            continue;
+        }
+        let difficulty = calibration.rate(&logical_solver, puzzle.start_state()).map_err(|e| CliError::new("unsolvable", e))?;
+        buckets.entry(difficulty).or_default().push((canonical::seeded_hash(seed, &form), puzzle, form));
+    }
+    let mut text = String::new();
+    let mut selected = Vec::new();
+    let mut counts = Vec::new();
+    for difficulty in Difficulty::all() {
+        let mut bucket = buckets.remove(&difficulty).unwrap_or_default();
+        bucket.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.2.cmp(&b.2)));
+        bucket.truncate(per_difficulty);
+        text.push_str(&format!("{:<7} {:>4}", difficulty.name(), bucket.len()));
+        if bucket.len() < per_difficulty {
+            text.push_str(&format!(" (short by {})", per_difficulty - bucket.len()));
+        }
+        text.push('\n');
+        counts.push((difficulty.name(), Json::Number(bucket.len() as f64)));
+        selected.extend(bucket.into_iter().map(|(_, puzzle, _)| {
+            let mut fields = vec![("puzzle", Json::String(puzzle.display(blank).to_string())),
+                                  ("difficulty", Json::string(difficulty.name()))];
+            fields.extend(puzzle.provenance().to_json_fields());
+            Json::object(fields)
+        }));
+    }
+    let mut set = vec![("seed", Json::Number(seed as f64)), ("per_difficulty", Json::Number(per_difficulty as f64))];
+    if let Some(ref license) = license {
+        set.push(("license", license.to_json()));
+    }
+    set.push(("puzzles", Json::Array(selected)));
+    let set = Json::object(set);
+    File::create(output).and_then(|mut file| writeln!(file, "{}", set))
+                        .map_err(|e| CliError::new("io", format!("{}: {}", output, e)))?;
+    text.push_str(&format!("{} duplicates and {} puzzles without a unique solution skipped\n", duplicates, rejected));
+    if lopsided > 0 {
+        text.push_str(&format!("{} puzzles less symmetric than {} skipped\n", lopsided, min_symmetry));
+    }
+    Ok(Report {
+        text,
+        json : Json::object(vec![("out", Json::string(output)),
+                                 ("counts", Json::object(counts)),
+                                 ("duplicates", Json::Number(duplicates as f64)),
+                                 ("rejected", Json::Number(rejected as f64)),
+                                 ("lopsided", Json::Number(lopsided as f64))])
+    })
+}
+
+fn format_option(args : &[String], name : &str) -> Result<Format, CliError> {
+    let names : Vec<&str> = Format::all().iter().map(|f| f.name()).collect();
+    match option(args, name) {
+        Some(value) => Format::all().into_iter().find(|f| f.name() == value).ok_or_else(|| {
+            CliError::new("usage", format!("Unknown format {}, expected one of {}", value, names.join(", ")))
+        }),
+        None => Err(CliError::new("usage", format!("convert needs {} <{}>", name, names.join("|"))))
+    }
+}
+
+// Rewrites a whole file of puzzles in another format, keeping their provenance and the license of
+// the collection. Without --out the converted file is the output; without --from the input format
+// is detected.
+fn convert(args : &[String]) -> Result<Report, CliError> {
+    let to = format_option(args, "--to")?;
+    let input = match option(args, "--in") {
+        Some(input) => input,
+        None => return Err(CliError::new("usage", "convert needs --in <file>".to_string()))
+    };
+    let mut text = String::new();
+    File::open(input).and_then(|mut file| file.read_to_string(&mut text))
+                     .map_err(|e| CliError::new("io", format!("{}: {}", input, e)))?;
+    Limits::default().check_nesting(&text).map_err(|e| CliError::new("limit", format!("{}: {}", input, e)))?;
+    let read = match option(args, "--from") {
+        Some(_) => format_option(args, "--from").and_then(|from| from.read(&text).map(|puzzles| (from, puzzles))
+                                                                         .map_err(|e| CliError::new("parse", format!("{}: {}", input, e)))),
+        None => formats::read_detected(&text).map_err(|e| CliError::new("parse", format!("{}: {}", input, e)))
+    };
+    let (from, puzzles) = read?;
+    let license = from.read_license(&text).map_err(|e| CliError::new("parse", format!("{}: {}", input, e)))?;
+    let converted = to.write_licensed(&puzzles, license.as_ref());
+    let mut json = vec![("puzzles", Json::Number(puzzles.len() as f64)),
+                        ("from", Json::string(from.name())),
+                        ("to", Json::string(to.name())),
+                        ("license", license.as_ref().map_or(Json::Null, |license| license.to_json()))];
+    let text = match option(args, "--out") {
+        Some(output) => {
+            File::create(output).and_then(|mut file| file.write_all(converted.as_bytes()))
+                                .map_err(|e| CliError::new("io", format!("{}: {}", output, e)))?;
+            json.push(("out", Json::string(output)));
+            format!("{} puzzles converted from {} to {}{}\n", puzzles.len(), from.name(), to.name(),
+                    license.as_ref().map_or(String::new(), |license| format!(", licensed {}", license)))
+        },
+        None => {
+            json.push(("converted", Json::string(&converted)));
+            converted
+        }
+    };
+    Ok(Report {
+        text,
+        json : Json::object(json)
+    })
+}
+
+// Copies an sdm file leaving out puzzles seen earlier in it: exact repeats of the grid, or with
+// --canonical any puzzle equal to an earlier one up to symmetry and relabelling. Reads and writes
+// as it goes and remembers only a fingerprint per puzzle, so files of millions of puzzles fit.
+fn dedup(args : &[String]) -> Result<Report, CliError> {
+    let (input, output) = match (option(args, "--in"), option(args, "--out")) {
+        (Some(input), Some(output)) => (input, output),
+        _ => return Err(CliError::new("usage", "dedup needs --in <file> and --out <file>".to_string()))
+    };
+    let by_canonical = flag(args, "--canonical");
+    let reader = File::open(input).map(|file| SdmReader::new(BufReader::new(file)))
+                                  .map_err(|e| CliError::new("io", format!("{}: {}", input, e)))?;
+    let write_error = |e : std::io::Error| CliError::new("io", format!("{}: {}", output, e));
+    let mut writer = File::create(output).map(|file| SdmWriter::new(BufWriter::new(file))).map_err(&write_error)?;
+    let mut seen = HashSet::new();
+    let (mut kept, mut duplicates) = (0, 0);
+    for puzzle in reader {
+        let puzzle = puzzle.map_err(|e| CliError::new("parse", format!("{}: {}", input, e)))?;
+        let key = if by_canonical { canonical::canonical(&puzzle) } else { puzzle.to_grid_string() };
+        if seen.insert(canonical::fingerprint(&key)) {
+            writer.write(&puzzle).map_err(&write_error)?;
+            kept += 1;
+        } else {
+            duplicates += 1;
+        }
+    }
+    writer.into_inner().flush().map_err(&write_error)?;
+    Ok(Report {
+        text : format!("{} puzzles kept, {} duplicates removed\n", kept, duplicates),
+        json : Json::object(vec![("out", Json::string(output)),
+                                 ("kept", Json::Number(kept as f64)),
+                                 ("duplicates", Json::Number(duplicates as f64)),
+                                 ("canonical", Json::Bool(by_canonical))])
+    })
+}
+
+// Which techniques the logical solver needed for every puzzle of a file, and how often each was
+// needed over the whole file, to hold generated puzzles up against collections of known ones.
+fn analyze_techniques(args : &[String]) -> Result<Report, CliError> {
+    let input = match option(args, "--in") {
+        Some(input) => input,
+        None => return Err(CliError::new("usage", "analyze-techniques needs --in <file>".to_string()))
+    };
+    let blank = blank_handler(args)?;
+    let logical_solver = LogicalSolver::new();
+    let mut frequency = analysis::TechniqueFrequency::default();
+    let mut text = String::new();
+    let mut results = Vec::new();
+    for (line, puzzle) in read_lines(input)? {
+        let puzzle = puzzle?;
+        let path = match logical_solver.full_path(puzzle.start_state()) {
+            Ok(path) => path,
+            Err(e) => {
+                frequency.add_failure();
+                text.push_str(&format!("{}: failed: {}\n", line, e));
+                results.push(Json::object(vec![("line", Json::Number(line as f64)),
+                                               ("puzzle", Json::String(puzzle.display(blank).to_string())),
+                                               ("error", Json::String(e))]));
+                continue;
+            }
+        };
+        frequency.add(&path);
+        let counts = analysis::technique_counts(&path);
+        let hardest = analysis::path_score(&path).hardest;
+        let listed : Vec<String> = counts.iter().map(|(t, n)| format!("{} {}", t.id(), n)).collect();
+        text.push_str(&format!("{}: {} ({})\n", line, hardest.map_or("none", |t| t.id()), listed.join(", ")));
+        results.push(Json::object(vec![("line", Json::Number(line as f64)),
+                                       ("puzzle", Json::String(puzzle.display(blank).to_string())),
+                                       ("hardest", hardest.map_or(Json::Null, |t| Json::string(t.id()))),
+                                       ("techniques", Json::Object(counts.iter()
+                                                                         .map(|(t, &n)| (t.id().to_string(), Json::Number(n as f64)))
+                                                                         .collect()))]));
+    }
+    text.push_str(&format!("\n{}", frequency));
+    let totals = Technique::all().into_iter().chain(Some(Technique::Reveal)).map(|t| {
+        (t.id(), Json::object(vec![("puzzles", Json::Number(*frequency.needed.get(&t).unwrap_or(&0) as f64)),
+                                   ("share", Json::Number(frequency.share(t))),
+                                   ("steps", Json::Number(*frequency.steps.get(&t).unwrap_or(&0) as f64)),
+                                   ("hardest", Json::Number(*frequency.hardest.get(&t).unwrap_or(&0) as f64))]))
+    }).collect();
+    Ok(Report {
+        text,
+        json : Json::object(vec![("puzzles", Json::Array(results)),
+                                 ("analysed", Json::Number((frequency.puzzles - frequency.failed) as f64)),
+                                 ("failed", Json::Number(frequency.failed as f64)),
+                                 ("techniques", Json::object(totals))])
+    })
+}
+
+// Puzzles of a file (--in) where --technique is the crucial move, see `analysis::crucial_step`,
+// for tutorials on it: each with the step number of the move, the move and the pencilmarks it is
+// made on. Stops after --count examples when given; --out writes them as JSON.
+fn mine(args : &[String]) -> Result<Report, CliError> {
+    let input = match option(args, "--in") {
+        Some(input) => input,
+        None => return Err(CliError::new("usage", "mine needs --in <file>".to_string()))
+    };
+    let technique = match option(args, "--technique") {
+        Some(id) => match Technique::from_id(id).or_else(|| Technique::all().into_iter().find(|t| t.id().replace('_', "") == id)) {
+            Some(Technique::Reveal) | None => return Err(CliError::new("usage", format!("Unknown technique {}, expected one of {}", id,
+                                                                                       Technique::all().iter().map(|t| t.id()).collect::<Vec<&str>>().join(", ")))),
+            Some(technique) => technique
+        },
+        None => return Err(CliError::new("usage", "mine needs --technique <id>".to_string()))
+    };
+    let limit = number_option(args, "--count", usize::MAX)?;
+    let blank = blank_handler(args)?;
+    let mut text = String::new();
+    let mut examples = Vec::new();
+    let mut searched = 0;
+    for (line, puzzle) in read_lines(input)? {
+        if examples.len() >= limit {
+            break;
+        }
+        let puzzle = puzzle?;
+        searched += 1;
+        if let Some(crucial) = analysis::crucial_step(puzzle.start_state(), technique) {
+            text.push_str(&format!("{}: step {}: {}\n", line, crucial.position + 1, crucial.step));
+            examples.push(Json::object(vec![("line", Json::Number(line as f64)),
+                                            ("puzzle", Json::String(puzzle.display(blank).to_string())),
+                                            ("position", Json::Number(crucial.position as f64 + 1.0)),
+                                            ("step", crucial.step.to_json()),
+                                            ("pencilmarks", Json::String(crucial.pencilmarks))]));
+        }
+    }
+    text.push_str(&format!("{} of {} puzzles need {}\n", examples.len(), searched, technique.name()));
+    let mut json = vec![("technique", Json::string(technique.id())),
+                        ("searched", Json::Number(searched as f64)),
+                        ("examples", Json::Array(examples))];
+    if let Some(output) = option(args, "--out") {
+        File::create(output).and_then(|mut file| file.write_all(format!("{}\n", Json::object(json.clone())).as_bytes()))
+                            .map_err(|e| CliError::new("io", format!("{}: {}", output, e)))?;
+        json.push(("out", Json::string(output)));
+    }
+    Ok(Report {
+        text,
+        json : Json::object(json)
+    })
+}
+
+// Rates a file of puzzles (--in) with a time limit of --timeout ms each (default 10000), on all
+// cores when built with the "parallel" feature. One line per puzzle with its level, score and
+// time; puzzles that fail or run out of time are counted as unrated and timeout.
+fn rate(args : &[String]) -> Result<Report, CliError> {
+    let input = match option(args, "--in") {
+        Some(input) => input,
+        None => return Err(CliError::new("usage", "rate needs --in <file>".to_string()))
+    };
+    let timeout = number_option(args, "--timeout", 10000)?;
+    let blank = blank_handler(args)?;
+    let mut lines = Vec::new();
+    let mut puzzles = Vec::new();
+    for (line, puzzle) in read_lines(input)? {
+        lines.push(line);
+        puzzles.push(puzzle?);
+    }
+    let started = Instant::now();
+    let rated = analysis::rate_batch(&puzzles, Duration::from_millis(timeout as u64));
+    let elapsed = started.elapsed();
+    let mut text = String::new();
+    let mut results = Vec::new();
+    let mut buckets : BTreeMap<&str, usize> = BTreeMap::new();
+    for ((line, puzzle), rated) in lines.iter().zip(puzzles.iter()).zip(rated.iter()) {
+        let puzzle = puzzle.display(blank).to_string();
+        let ms = rated.elapsed.as_secs() as f64 * 1000.0 + rated.elapsed.subsec_nanos() as f64 / 1e6;
+        *buckets.entry(rated.bucket()).or_insert(0) += 1;
+        let mut fields = vec![("line", Json::Number(*line as f64)),
+                              ("puzzle", Json::String(puzzle.clone())),
+                              ("level", Json::string(rated.bucket())),
+                              ("ms", Json::Number(ms))];
+        match rated.rating {
+            analysis::Rating::Rated(score, _) => {
+                text.push_str(&format!("{} {:<8} {:>7.4} {:>10.1} ms\n", puzzle, rated.bucket(), score.value, ms));
+                fields.push(("score", Json::Number(score.value)));
+            },
+            analysis::Rating::Failed(ref e) => {
+                text.push_str(&format!("{} {:<8} {:>7} {:>10.1} ms  {}\n", puzzle, rated.bucket(), "-", ms, e));
+                fields.push(("error", Json::string(e)));
+            },
+            analysis::Rating::TimedOut | analysis::Rating::Cancelled => text.push_str(&format!("{} {:<8} {:>7} {:>10.1} ms\n", puzzle, rated.bucket(), "-", ms))
+        }
+        results.push(Json::object(fields));
+    }
+    let order : Vec<&str> = Difficulty::all().iter().map(|d| d.name()).chain(vec!["unrated", "timeout", "cancelled"]).collect();
+    text.push('\n');
+    for name in &order {
+        text.push_str(&format!("{:<8} {:>6}\n", name, buckets.get(name).unwrap_or(&0)));
+    }
+    text.push_str(&format!("{} puzzles in {:.3} s\n", puzzles.len(), elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9));
+    Ok(Report {
+        text,
+        json : Json::object(vec![("puzzles", Json::Array(results)),
+                                 ("levels", Json::object(order.iter().map(|&name| (name, Json::Number(*buckets.get(name).unwrap_or(&0) as f64))).collect()))])
+    })
+}
+
+// Fits level thresholds to a file of rated reference puzzles (--in, in the format of
+// data/calibration.txt), or checks the shipped reference set against the built-in thresholds.
+// With --se, compares the ratings of a Sudoku Explainer rating list with this crate's instead.
+fn calibrate(args : &[String]) -> Result<Report, CliError> {
+    if let Some(path) = option(args, "--se") {
+        return compare_se(path);
+    }
+    let reference = match option(args, "--in") {
+        Some(path) => {
+            let mut text = String::new();
+            File::open(path).and_then(|mut file| file.read_to_string(&mut text))
+                            .map_err(|e| CliError::new("io", format!("{}: {}", path, e)))?;
+            calibration::parse_reference(&text).map_err(|e| CliError::new("parse", format!("{}: {}", path, e)))?
+        },
+        None => {
+            calibration::check().map_err(|e| CliError::new("calibration", e))?;
+            calibration::reference()
+        }
+    };
+    let scored = calibration::score_reference(&LogicalSolver::new(), &reference).map_err(|e| CliError::new("unsolvable", e))?;
+    let fitted = Calibration::fit(&scored);
+    Ok(Report {
+        text : format!("{} reference puzzles\n{}", reference.len(), fitted),
+        json : Json::object(vec![("puzzles", Json::Number(reference.len() as f64)),
+                                 ("thresholds", Json::Array(fitted.thresholds.iter().map(|&t| Json::Number(t)).collect()))])
+    })
+}
+
+fn compare_se(path : &str) -> Result<Report, CliError> {
+    let mut text = String::new();
+    File::open(path).and_then(|mut file| file.read_to_string(&mut text))
+                    .map_err(|e| CliError::new("io", format!("{}: {}", path, e)))?;
+    let rated = calibration::parse_se_ratings(&text).map_err(|e| CliError::new("parse", format!("{}: {}", path, e)))?;
+    let comparison = match calibration::compare_se(&LogicalSolver::new(), &rated) {
+        Some(comparison) => comparison,
+        None => return Err(CliError::new("unsolvable", format!("{}: no puzzle could be scored", path)))
+    };
+    let levels = comparison.levels.iter()
+                                  .map(|range| Json::object(vec![("level", Json::string(range.level.name())),
+                                                                 ("count", Json::Number(range.count as f64)),
+                                                                 ("min", Json::Number(range.min)),
+                                                                 ("mean", Json::Number(range.mean)),
+                                                                 ("max", Json::Number(range.max))]))
+                                  .collect();
+    Ok(Report {
+        text : comparison.to_string(),
+        json : Json::object(vec![("compared", Json::Number(comparison.compared as f64)),
+                                 ("unscored", Json::Number(comparison.unscored as f64)),
+                                 ("pearson", Json::Number(comparison.pearson)),
+                                 ("spearman", Json::Number(comparison.spearman)),
+                                 ("levels", Json::Array(levels)),
+                                 ("se_thresholds", Json::Array(comparison.se_thresholds.thresholds.iter().map(|&t| Json::Number(t)).collect()))])
+    })
+}
+
+// `pipeline run <job>` runs the generate, rate, dedup and curate stages of a job file (see
+// `pipeline::Job`) and writes the curated puzzles to its output path, if it has one.
+fn pipeline(args : &[String]) -> Result<Report, CliError> {
+    let path = match (args.first().map(|s| s.as_str()), args.get(1)) {
+        (Some("run"), Some(path)) => path,
+        _ => return Err(CliError::new("usage", "pipeline needs run <job file>".to_string()))
+    };
+    let mut text = String::new();
+    File::open(path).and_then(|mut file| file.read_to_string(&mut text))
+                    .map_err(|e| CliError::new("io", format!("{}: {}", path, e)))?;
+    let job = Job::parse(&text).map_err(|e| CliError::new("parse", format!("{}: {}", path, e)))?;
+    let report = job.run();
+    let puzzles : Vec<Puzzle> = report.puzzles.iter().map(|(puzzle, _, _)| puzzle.clone()).collect();
+    if let Some(ref output) = job.output {
+        File::create(output).and_then(|mut file| file.write_all(job.format.write(&puzzles).as_bytes()))
+                            .map_err(|e| CliError::new("io", format!("{}: {}", output, e)))?;
+    }
+    let stages = report.stages.iter().map(|stage| Json::object(vec![("stage", Json::string(stage.stage)),
+                                                                     ("input", Json::Number(stage.input as f64)),
+                                                                     ("output", Json::Number(stage.output as f64)),
+                                                                     ("ms", Json::Number(stage.elapsed.as_secs() as f64 * 1000.0 + stage.elapsed.subsec_millis() as f64))]));
+    let counts = report.levels().into_iter().map(|(level, count)| (level.name(), Json::Number(count as f64))).collect();
+    Ok(Report {
+        text : report.to_string(),
+        json : Json::object(vec![("stages", Json::Array(stages.collect())),
+                                 ("counts", Json::object(counts)),
+                                 ("out", job.output.as_ref().map_or(Json::Null, |output| Json::string(output)))])
+    })
+}
+
+// `pack build` writes the puzzles of --in (json as `curate` writes it, or --from another format)
+// to the binary pack --out, with --solutions and the publisher's --revision. The license of the
+// input goes in the pack, or one named by --license for input without one; --require-license
+// refuses to build a pack without a license or with puzzles licensed otherwise. `pack verify --in`
+// checks a pack's checksum and every puzzle in it; `pack sample --in` picks --per-difficulty
+// (default 10) puzzles of every level at random from --seed (default 0).
+fn pack_command(args : &[String]) -> Result<Report, CliError> {
+    let input = match option(args, "--in") {
+        Some(input) => input,
+        None => return Err(CliError::new("usage", "pack needs --in <file>".to_string()))
+    };
+    match positional(args) {
+        Some("build") => pack_build(args, input),
+        Some("verify") => pack_verify(input),
+        Some("sample") => pack_sample(args, input),
+        _ => Err(CliError::new("usage", "pack needs build, verify or sample".to_string()))
+    }
+}
+
+fn pack_build(args : &[String], input : &str) -> Result<Report, CliError> {
+    let output = match option(args, "--out") {
+        Some(output) => output,
+        None => return Err(CliError::new("usage", "pack build needs --out <file>".to_string()))
+    };
+    let from = if option(args, "--from").is_some() { format_option(args, "--from")? } else { Format::Json };
+    let revision = number_option(args, "--revision", 0)? as u32;
+    let mut text = String::new();
+    File::open(input).and_then(|mut file| file.read_to_string(&mut text))
+                     .map_err(|e| CliError::new("io", format!("{}: {}", input, e)))?;
+    Limits::default().check_nesting(&text).map_err(|e| CliError::new("limit", format!("{}: {}", input, e)))?;
+    let puzzles = from.read(&text).map_err(|e| CliError::new("parse", format!("{}: {}", input, e)))?;
+    let license = match (from.read_license(&text).map_err(|e| CliError::new("parse", format!("{}: {}", input, e)))?, option(args, "--license")) {
+        (Some(license), Some(name)) if license.name != name => {
+            return Err(CliError::new("usage", format!("{} is licensed {}, not {}", input, license.name, name)));
+        },
+        (Some(license), _) => Some(license),
+        (None, name) => name.map(License::new)
+    };
+    if flag(args, "--require-license") {
+        let license = license.as_ref().ok_or_else(|| {
+            CliError::new("license", format!("{} has no license; add one to it or give --license", input))
+        })?;
+        if let Some(i) = puzzles.iter().position(|p| p.provenance().license.as_ref().is_some_and(|own| *own != license.name)) {
+            return Err(CliError::new("license", format!("{}: puzzle {} is licensed {}, not {}", input, i + 1,
+                                                        puzzles[i].provenance().license.as_ref().unwrap(), license.name)));
+        }
+    }
+    let solutions = if flag(args, "--solutions") {
+        let mut solutions = Vec::new();
+        for (i, puzzle) in puzzles.iter().enumerate() {
+            let mut found = Grid9::from_start_state(puzzle.start_state()).map(|grid| grid.solutions(2)).unwrap_or_default();
+            if found.len() != 1 {
+                return Err(CliError::new("unsolvable", format!("{}: puzzle {} has no unique solution", input, i + 1)));
+            }
+            solutions.push(found.remove(0).to_start_state());
+        }
+        Some(solutions)
+    } else {
+        None
+    };
+    let bytes = pack::build(&puzzles, solutions.as_ref().map(|s| &s[..]), revision, license.as_ref()).map_err(|e| CliError::new("parse", format!("{}: {}", input, e)))?;
+    File::create(output).and_then(|mut file| file.write_all(&bytes))
+                        .map_err(|e| CliError::new("io", format!("{}: {}", output, e)))?;
+    Ok(Report {
+        text : format!("{} puzzles{} in {} bytes, revision {}{}\n", puzzles.len(), if solutions.is_some() { " with solutions" } else { "" },
+                       bytes.len(), revision, license.as_ref().map_or(String::new(), |license| format!(", licensed {}", license))),
+        json : Json::object(vec![("out", Json::string(output)),
+                                 ("puzzles", Json::Number(puzzles.len() as f64)),
+                                 ("solutions", Json::Bool(solutions.is_some())),
+                                 ("bytes", Json::Number(bytes.len() as f64)),
+                                 ("revision", Json::Number(revision as f64)),
+                                 ("license", license.as_ref().map_or(Json::Null, |license| license.to_json()))])
+    })
+}
+
+fn pack_verify(input : &str) -> Result<Report, CliError> {
+    std::fs::metadata(input).map_err(|e| CliError::new("io", format!("{}: {}", input, e)))?;
+    let pack = Pack::open(std::path::Path::new(input)).map_err(|e| CliError::new("parse", e))?;
+    pack.verify().map_err(|e| CliError::new("parse", format!("{}: {}", input, e)))?;
+    let checksum = pack.checksum().map_or("none".to_string(), |c| format!("{:08x}", c));
+    let license = pack.license();
+    Ok(Report {
+        text : format!("{} puzzles{}, version {}, revision {}, checksum {}{}\n", pack.len(), if pack.has_solutions() { " with solutions" } else { "" },
+                       pack.version(), pack.revision(), checksum, license.as_ref().map_or(String::new(), |license| format!(", licensed {}", license))),
+        json : Json::object(vec![("puzzles", Json::Number(pack.len() as f64)),
+                                 ("solutions", Json::Bool(pack.has_solutions())),
+                                 ("version", Json::Number(pack.version() as f64)),
+                                 ("revision", Json::Number(pack.revision() as f64)),
+                                 ("checksum", Json::String(checksum)),
+                                 ("license", license.as_ref().map_or(Json::Null, |license| license.to_json()))])
+    })
+}
+
+fn pack_sample(args : &[String], input : &str) -> Result<Report, CliError> {
+    let per_level = number_option(args, "--per-difficulty", 10)?;
+    let seed = number_option(args, "--seed", 0)? as u64;
+    std::fs::metadata(input).map_err(|e| CliError::new("io", format!("{}: {}", input, e)))?;
+    let pack = Pack::open(std::path::Path::new(input)).map_err(|e| CliError::new("parse", e))?;
+    let sample = pack.sample_stratified(seed, per_level);
+    let blank = blank_handler(args)?;
+    let mut text = String::new();
+    let mut puzzles = Vec::new();
+    for &(i, level) in &sample {
+        let puzzle = pack.get(i).map_or(String::new(), |p| p.display(blank).to_string());
+        text.push_str(&format!("{:>6} {:<7} {}\n", i, level.name(), puzzle));
+        puzzles.push(Json::object(vec![("index", Json::Number(i as f64)),
+                                       ("level", Json::string(level.name())),
+                                       ("puzzle", Json::String(puzzle))]));
+    }
+    Ok(Report {
+        text,
+        json : Json::object(vec![("seed", Json::Number(seed as f64)), ("puzzles", Json::Array(puzzles))])
+    })
+}
+
+// The TypeScript declarations for WASM builds, see `js`; written to --out when given.
+fn typescript(args : &[String]) -> Result<Report, CliError> {
+    let definitions = js::typescript_definitions();
+    match option(args, "--out") {
+        Some(output) => {
+            File::create(output).and_then(|mut file| file.write_all(definitions.as_bytes()))
+                                .map_err(|e| CliError::new("io", format!("{}: {}", output, e)))?;
+            Ok(Report {
+                text : format!("TypeScript definitions written to {}\n", output),
+                json : Json::object(vec![("out", Json::string(output))])
+            })
+        },
+        None => Ok(Report {
+            json : Json::object(vec![("definitions", Json::string(&definitions))]),
+            text : definitions
+        })
+    }
+}
+
+fn demo() -> Result<Report, CliError> {
     let easy = "..3.2.6..9..3.5..1..18.64....81.29..7.......8..67.82....26.95..8..2.3..9..5.1.3..";
     let hard = "4.....8.5.3..........7......2.....6.....8.4......1.......6.3.7.5..2.....1.4......";
     let hardest = ".....6....59.....82....8....45........3........6..3.54...325..6..................";
     let solver = sudoku::Solver::new();
     let generator = sudoku::Generator::new();
-    println!("{:.6}", time_solve(&solver, easy) as f64 / 1000000000.0);
-    println!("{:.6}", time_solve(&solver, hard) as f64 / 1000000000.0);
-    println!("{:.6}", time_solve(&solver, hardest) as f64 / 1000000000.0);
-    println!("{:.6}", time_solve(&solver, &generator.generate_str(17)) as f64 / 1000000000.0);
+    let times : Vec<f64> = [easy, hard, hardest, &generator.generate_str(17)].iter()
+                                                                            .map(|p| time_solve(&solver, p))
+                                                                            .collect();
+    Ok(Report {
+        text : times.iter().map(|t| format!("{:.6}\n", t)).collect(),
+        json : Json::object(vec![("seconds", Json::Array(times.iter().map(|&t| Json::Number(t)).collect()))])
+    })
+}
+
+fn main() {
+    let args : Vec<String> = env::args().skip(1).collect();
+    let json = flag(&args, "--json");
+    let result = match args.first().map(|s| s.as_str()) {
+        Some("bench") => bench(&args[1..]),
+        Some("solve") => solve(&args[1..]),
+        Some("generate") => generate(&args[1..]),
+        Some("curate") => curate(&args[1..]),
+        Some("render") => render_variant(&args[1..]),
+        Some("convert") => convert(&args[1..]),
+        Some("dedup") => dedup(&args[1..]),
+        Some("analyze-techniques") => analyze_techniques(&args[1..]),
+        Some("mine") => mine(&args[1..]),
+        Some("calibrate") => calibrate(&args[1..]),
+        Some("rate") => rate(&args[1..]),
+        Some("pack") => pack_command(&args[1..]),
+        Some("pipeline") => pipeline(&args[1..]),
+        Some("typescript") => typescript(&args[1..]),
+        Some(command) if ! command.starts_with("--") => Err(CliError::new("usage", format!("Unknown command {}", command))),
+        _ => demo()
+    };
+    match result {
+        Ok(ref report) if json => println!("{}", report.json),
+        Ok(report) => print!("{}", report.text),
+        Err(err) => {
+            match err.partial {
+                // batch failures were reported on stderr as they happened
+                Some(ref report) if json => println!("{}", report.json),
+                Some(ref report) => print!("{}", report.text),
+                None => {
+                    err.report();
+                    if json {
+                        println!("{}", Json::object(vec![("error", err.to_json())]));
+                    }
+                }
+            }
+            process::exit(err.exit_code());
+        }
+    }
 }
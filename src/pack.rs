@@ -0,0 +1,693 @@
+use std;
+use std::collections::BTreeMap;
+use std::path::Path;
+use analysis::{self, Difficulty};
+use backend;
+use formats::License;
+use game::Game;
+use json::Json;
+use puzzle::{self, Puzzle};
+use sudoku::{Config, StartState, SQUARE_COUNT};
+
+// A compact binary collection of puzzles that apps embed (`include_bytes!`) or ship next to the
+// binary, a few hundred KB for tens of thousands of puzzles, read one puzzle at a time without
+// decoding the rest:
+//
+//   header    "SDKP", version (u8), flags (u8, 1 = solutions, 2 = license), two zero bytes, puzzle
+//             count (u32), revision (u32), CRC-32 of everything after the header (u32)
+//   license   with flag 2, the length (u32) and UTF-8 JSON of the collection's `formats::License`
+//   index     the offset of every BLOCK-th puzzle from the start of the records (u32 each)
+//   records   per puzzle the number of clue bytes (u8), the clue bytes and, with solutions, the
+//             digits of the blank squares in A1..I9 order, two to a byte, high nibble first
+//
+// A clue byte below 243 is `gap * 9 + digit - 1`, gap being the blank squares since the previous
+// clue (or A1), so a puzzle takes about a byte per clue; 255 skips 27 blank squares. Numbers are
+// little endian. The provenance of single puzzles is not kept, the license of the whole is; being
+// under the checksum, it can't be changed without `verify` noticing.
+//
+// The revision is the publisher's, so an app can tell which edition of its puzzles it has. Version
+// 1 packs have neither revision nor checksum, their header ends after the count; version 2 packs
+// have no license. Both still open.
+
+const MAGIC : &[u8] = b"SDKP";
+
+const VERSION : u8 = 3;
+
+const SOLUTIONS : u8 = 1;
+
+const LICENSED : u8 = 2;
+
+fn header_len(version : u8) -> usize {
+    if version == 1 { 12 } else { 20 }
+}
+
+// Puzzles per index entry; `get` walks at most BLOCK - 1 records past the indexed one.
+const BLOCK : usize = 64;
+
+const MAX_GAP : usize = 27;
+
+const SKIP : u8 = 255;
+
+enum Bytes {
+    Static(&'static [u8]),
+    Owned(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(::memmap2::Mmap)
+}
+
+impl std::ops::Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match *self {
+            Bytes::Static(bytes) => bytes,
+            Bytes::Owned(ref bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            Bytes::Mapped(ref map) => map
+        }
+    }
+}
+
+pub struct Pack {
+    bytes : Bytes,
+    version : u8,
+    count : usize,
+    solutions : bool,
+    // start of the index, after the license block if there is one
+    index : usize,
+    // start of the records
+    records : usize
+}
+
+impl Pack {
+
+    // A pack compiled into the binary, e.g. `Pack::from_static(include_bytes!("puzzles.pack"))`.
+    pub fn from_static(bytes : &'static [u8]) -> Result<Pack, String> {
+        Pack::new(Bytes::Static(bytes))
+    }
+
+    pub fn from_vec(bytes : Vec<u8>) -> Result<Pack, String> {
+        Pack::new(Bytes::Owned(bytes))
+    }
+
+    // Memory maps the file, so only the pages of the puzzles asked for are read. The file must
+    // not change while the pack is open.
+    #[cfg(feature = "mmap")]
+    pub fn open(path : &Path) -> Result<Pack, String> {
+        let error = |e : std::io::Error| format!("{}: {}", path.display(), e);
+        let file = std::fs::File::open(path).map_err(&error)?;
+        let map = unsafe { ::memmap2::Mmap::map(&file) }.map_err(&error)?;
+        Pack::new(Bytes::Mapped(map)).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Reads the whole file; with the "mmap" feature it is memory mapped instead.
+    #[cfg(not(feature = "mmap"))]
+    pub fn open(path : &Path) -> Result<Pack, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Pack::new(Bytes::Owned(bytes)).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Checks the header and index only, so opening stays cheap for big mapped files; `verify`
+    // reads everything.
+    fn new(bytes : Bytes) -> Result<Pack, String> {
+        if bytes.len() < 5 || &bytes[..4] != MAGIC {
+            return Err("Not a puzzle pack".to_string());
+        }
+        let version = bytes[4];
+        if version == 0 || version > VERSION {
+            return Err(format!("Unsupported pack version {}, this build reads up to {}", version, VERSION));
+        }
+        let header = header_len(version);
+        if bytes.len() < header {
+            return Err("Pack is cut short in its header".to_string());
+        }
+        let count = read_u32(&bytes, 8) as usize;
+        let index = if version > 2 && bytes[5] & LICENSED != 0 {
+            if bytes.len() < header + 4 {
+                return Err("Pack is cut short in its license".to_string());
+            }
+            let index = header + 4 + read_u32(&bytes, header) as usize;
+            if bytes.len() < index {
+                return Err("Pack is cut short in its license".to_string());
+            }
+            index
+        } else {
+            header
+        };
+        let records = index + count.div_ceil(BLOCK) * 4;
+        if bytes.len() < records {
+            return Err(format!("Pack of {} puzzles is cut short in its index", count));
+        }
+        if (index..records).step_by(4).any(|at| records + read_u32(&bytes, at) as usize >= bytes.len()) {
+            return Err("Pack index points past the end".to_string());
+        }
+        Ok(Pack {
+            solutions : bytes[5] & SOLUTIONS != 0,
+            bytes,
+            version,
+            count,
+            index,
+            records
+        })
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    // The publisher's revision, 0 in version 1 packs.
+    pub fn revision(&self) -> u32 {
+        if self.version == 1 { 0 } else { read_u32(&self.bytes, 12) }
+    }
+
+    // The license of the collection, None if the pack has none or it doesn't read.
+    pub fn license(&self) -> Option<License> {
+        self.read_license().ok().and_then(|license| license)
+    }
+
+    fn read_license(&self) -> Result<Option<License>, String> {
+        let header = header_len(self.version);
+        if self.index == header {
+            return Ok(None);
+        }
+        let text = std::str::from_utf8(&self.bytes[header + 4..self.index]).map_err(|_| "License is not UTF-8".to_string())?;
+        License::from_json(&Json::parse(text)?).map(Some)
+    }
+
+    // The stored checksum, None in version 1 packs.
+    pub fn checksum(&self) -> Option<u32> {
+        if self.version == 1 { None } else { Some(read_u32(&self.bytes, 16)) }
+    }
+
+    // What tells this pack apart from others and from other editions of itself: the stored
+    // checksum, or for version 1 packs one computed over the content.
+    pub fn key(&self) -> u32 {
+        self.checksum().unwrap_or_else(|| crc32(&self.bytes[header_len(self.version)..]))
+    }
+
+    // Compares the checksum with the content, then decodes every puzzle and checks that the index
+    // points at them, that the records end with the file and that each solution solves its puzzle.
+    pub fn verify(&self) -> Result<(), String> {
+        if let Some(stored) = self.checksum() {
+            let computed = crc32(&self.bytes[header_len(self.version)..]);
+            if computed != stored {
+                return Err(format!("Checksum mismatch, stored {:08x} but the content gives {:08x}", stored, computed));
+            }
+        }
+        self.read_license().map_err(|e| format!("Corrupt license: {}", e))?;
+        let config = Config::new();
+        let mut at = self.records;
+        for i in 0..self.count {
+            if i % BLOCK == 0 && self.block_start(i) != at {
+                return Err(format!("Index entry of puzzle {} points at {}, the record is at {}", i + 1, self.block_start(i), at));
+            }
+            let corrupt = || format!("Puzzle {} is corrupt", i + 1);
+            let (values, end) = self.decode_at(at).ok_or_else(&corrupt)?;
+            let givens = puzzle::from_values(&values).map_err(|_| corrupt())?;
+            at = end;
+            if self.solutions {
+                let (solution, end) = self.solution_at(values, at).ok_or_else(&corrupt)?;
+                if ! backend::is_valid_solution(&config, &givens, &solution) {
+                    return Err(format!("Puzzle {} has a wrong solution", i + 1));
+                }
+                at = end;
+            }
+        }
+        if at != self.bytes.len() {
+            return Err(format!("{} bytes left after the last puzzle", self.bytes.len() as isize - at as isize));
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn has_solutions(&self) -> bool {
+        self.solutions
+    }
+
+    // A balanced random set of the pack's puzzles, `per_level` of every level where the pack has
+    // that many, the same for the same seed; see `analysis::sample_stratified`.
+    pub fn sample_stratified(&self, seed : u64, per_level : usize) -> Vec<(usize, Difficulty)> {
+        analysis::sample_stratified(self.count, |i| self.get(i), seed, per_level)
+    }
+
+    // The i:th puzzle, None past the end or if its record is corrupt.
+    pub fn get(&self, i : usize) -> Option<Puzzle> {
+        let (values, _) = self.decode(i)?;
+        puzzle::from_values(&values).ok().map(Puzzle::new)
+    }
+
+    // The solution of the i:th puzzle, None if the pack has no solutions.
+    pub fn solution(&self, i : usize) -> Option<StartState> {
+        if ! self.solutions {
+            return None;
+        }
+        let (values, at) = self.decode(i)?;
+        self.solution_at(values, at).map(|(solution, _)| solution)
+    }
+
+    // The givens of the i:th puzzle as flat values, and where its solution digits start.
+    fn decode(&self, i : usize) -> Option<([u8; SQUARE_COUNT], usize)> {
+        if i >= self.count {
+            return None;
+        }
+        let mut at = self.block_start(i);
+        for _ in 0..i % BLOCK {
+            at = self.skip(at)?;
+        }
+        self.decode_at(at)
+    }
+
+    // Where the record of the first puzzle in the block of the i:th starts.
+    fn block_start(&self, i : usize) -> usize {
+        self.records + read_u32(&self.bytes, self.index + i / BLOCK * 4) as usize
+    }
+
+    fn decode_at(&self, at : usize) -> Option<([u8; SQUARE_COUNT], usize)> {
+        let length = *self.bytes.get(at)? as usize;
+        let mut values = [0; SQUARE_COUNT];
+        let mut square = 0;
+        for &byte in self.bytes.get(at + 1..at + 1 + length)? {
+            if byte == SKIP {
+                square += MAX_GAP;
+                continue;
+            }
+            square += (byte / 9) as usize;
+            *values.get_mut(square)? = byte % 9 + 1;
+            square += 1;
+        }
+        Some((values, at + 1 + length))
+    }
+
+    // The givens filled in from the solution digits at `at`, and where the next record starts.
+    fn solution_at(&self, mut values : [u8; SQUARE_COUNT], at : usize) -> Option<(StartState, usize)> {
+        let blanks = values.iter().filter(|&&v| v == 0).count();
+        let end = at + blanks.div_ceil(2);
+        let mut digits = self.bytes.get(at..end)?.iter().flat_map(|&byte| vec![byte >> 4, byte & 0xf]);
+        for value in values.iter_mut().filter(|v| **v == 0) {
+            *value = digits.next()?;
+        }
+        puzzle::from_values(&values).ok().map(|solution| (solution, end))
+    }
+
+    // The start of the record after the one at `at`.
+    fn skip(&self, at : usize) -> Option<usize> {
+        let length = *self.bytes.get(at)? as usize;
+        let clue_bytes = self.bytes.get(at + 1..at + 1 + length)?;
+        let mut next = at + 1 + length;
+        if self.solutions {
+            let clues = clue_bytes.iter().filter(|&&b| b != SKIP).count();
+            next += SQUARE_COUNT.checked_sub(clues)?.div_ceil(2);
+        }
+        Some(next)
+    }
+}
+
+// Version of the `PackProgress::save` format.
+pub const PROGRESS_VERSION : u32 = 1;
+
+// The player's best result on a puzzle: the fastest time in milliseconds and the most stars,
+// which need not come from the same game, see `Game::stars`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Completion {
+    pub best_time : u64,
+    pub stars : u8
+}
+
+// What the player has solved in a pack, kept apart from the pack itself since packs are read
+// only and often compiled in. Tied to the pack's `key`, so progress on one edition of a pack
+// isn't taken for progress on the next.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackProgress {
+    key : u32,
+    count : usize,
+    completed : BTreeMap<usize, Completion>
+}
+
+impl PackProgress {
+
+    pub fn new(pack : &Pack) -> PackProgress {
+        PackProgress {
+            key : pack.key(),
+            count : pack.len(),
+            completed : BTreeMap::new()
+        }
+    }
+
+    // Errors unless the progress was made on this pack.
+    pub fn check(&self, pack : &Pack) -> Result<(), String> {
+        if pack.key() != self.key || pack.len() != self.count {
+            return Err(format!("Progress is for pack {:08x}, not {:08x}", self.key, pack.key()));
+        }
+        Ok(())
+    }
+
+    // Records a completed game of one of the pack's puzzles, see `Game::from_pack`, keeping the
+    // best time and stars. True when either improved, i.e. a new record.
+    pub fn record(&mut self, game : &Game) -> Result<bool, String> {
+        let i = match game.pack_puzzle() {
+            Some((key, i)) if key == self.key && i < self.count => i,
+            _ => return Err("Game is not from this pack".to_string())
+        };
+        if ! game.is_complete() {
+            return Err("Game is not complete".to_string());
+        }
+        let best = Completion {
+            best_time : game.elapsed(),
+            stars : game.stars()
+        };
+        let before = self.completed.get(&i).cloned();
+        let after = match before {
+            Some(c) => Completion {
+                best_time : c.best_time.min(best.best_time),
+                stars : c.stars.max(best.stars)
+            },
+            None => best
+        };
+        self.completed.insert(i, after);
+        Ok(before != Some(after))
+    }
+
+    pub fn completion(&self, i : usize) -> Option<Completion> {
+        self.completed.get(&i).cloned()
+    }
+
+    pub fn is_solved(&self, i : usize) -> bool {
+        self.completed.contains_key(&i)
+    }
+
+    pub fn solved_count(&self) -> usize {
+        self.completed.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.completed.len() == self.count
+    }
+
+    // Stars over all solved puzzles.
+    pub fn stars(&self) -> usize {
+        self.completed.values().map(|c| c.stars as usize).sum()
+    }
+
+    // The first puzzle not solved yet, where a campaign continues.
+    pub fn next_unsolved(&self) -> Option<usize> {
+        (0..self.count).find(|i| ! self.completed.contains_key(i))
+    }
+
+    // v1;key;count;completions where the key is 8 hex digits and the completions are comma separated
+    // index:best time:stars, e.g. "v1;1c2b3a4d;500;0:95000:3,1:212000:2".
+    pub fn save(&self) -> String {
+        let completed : Vec<String> = self.completed.iter().map(|(i, c)| format!("{}:{}:{}", i, c.best_time, c.stars)).collect();
+        format!("v{};{:08x};{};{}", PROGRESS_VERSION, self.key, self.count, completed.join(","))
+    }
+
+    pub fn load(saved : &str) -> Result<PackProgress, String> {
+        let fields : Vec<&str> = saved.trim().split(';').collect();
+        if fields.len() != 4 {
+            return Err("Incorrect number of fields".to_string());
+        }
+        match fields[0].strip_prefix('v').and_then(|v| v.parse::<u32>().ok()) {
+            Some(version) if version >= 1 && version <= PROGRESS_VERSION => (),
+            Some(version) => return Err(format!("Progress version {} is not one this build reads, 1 to {}", version, PROGRESS_VERSION)),
+            None => return Err(format!("Invalid progress version {}", fields[0]))
+        }
+        let key = u32::from_str_radix(fields[1], 16).map_err(|_| format!("Invalid pack key {}", fields[1]))?;
+        let count : usize = fields[2].parse().map_err(|_| format!("Invalid puzzle count {}", fields[2]))?;
+        let mut completed = BTreeMap::new();
+        for entry in fields[3].split(',').filter(|e| ! e.is_empty()) {
+            let parts : Vec<&str> = entry.split(':').collect();
+            let invalid = || format!("Invalid completion {}", entry);
+            if parts.len() != 3 {
+                return Err(invalid());
+            }
+            let i : usize = parts[0].parse().map_err(|_| invalid())?;
+            let completion = Completion {
+                best_time : parts[1].parse().map_err(|_| invalid())?,
+                stars : parts[2].parse().map_err(|_| invalid())?
+            };
+            if i >= count || completion.stars > 3 {
+                return Err(invalid());
+            }
+            completed.insert(i, completion);
+        }
+        Ok(PackProgress {
+            key,
+            count,
+            completed
+        })
+    }
+}
+
+// A pack of the puzzles, with their solutions if given (one per puzzle, agreeing with its givens)
+// and the license of the collection.
+pub fn build(puzzles : &[Puzzle], solutions : Option<&[StartState]>, revision : u32, license : Option<&License>) -> Result<Vec<u8>, String> {
+    if let Some(solutions) = solutions {
+        if solutions.len() != puzzles.len() {
+            return Err(format!("Got {} solutions for {} puzzles", solutions.len(), puzzles.len()));
+        }
+    }
+    let mut index = Vec::new();
+    let mut records = Vec::new();
+    for (i, puzzle) in puzzles.iter().enumerate() {
+        if ! puzzle.start_state().eliminations().is_empty() {
+            return Err(format!("puzzle {}: pencilmark puzzles can't be packed", i + 1));
+        }
+        if i % BLOCK == 0 {
+            write_u32(&mut index, records.len() as u32);
+        }
+        let values = puzzle::to_values(puzzle.start_state());
+        let mut clue_bytes = Vec::new();
+        let mut gap = 0;
+        for &v in values.iter() {
+            if v == 0 {
+                gap += 1;
+                continue;
+            }
+            while gap >= MAX_GAP {
+                clue_bytes.push(SKIP);
+                gap -= MAX_GAP;
+            }
+            clue_bytes.push((gap * 9) as u8 + v - 1);
+            gap = 0;
+        }
+        records.push(clue_bytes.len() as u8);
+        records.extend(clue_bytes);
+        if let Some(solutions) = solutions {
+            let solution = puzzle::to_values(&solutions[i]);
+            if values.iter().zip(solution.iter()).any(|(&given, &digit)| digit == 0 || (given != 0 && given != digit)) {
+                return Err(format!("puzzle {}: the solution is incomplete or disagrees with the givens", i + 1));
+            }
+            let blanks : Vec<u8> = values.iter().zip(solution.iter()).filter(|&(&given, _)| given == 0).map(|(_, &digit)| digit).collect();
+            records.extend(blanks.chunks(2).map(|pair| pair[0] << 4 | pair.get(1).cloned().unwrap_or(0)));
+        }
+    }
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    bytes.push(if solutions.is_some() { SOLUTIONS } else { 0 } | if license.is_some() { LICENSED } else { 0 });
+    bytes.extend(&[0, 0]);
+    write_u32(&mut bytes, puzzles.len() as u32);
+    write_u32(&mut bytes, revision);
+    let mut content = Vec::new();
+    if let Some(license) = license {
+        let text = license.to_json().to_string();
+        write_u32(&mut content, text.len() as u32);
+        content.extend(text.as_bytes());
+    }
+    content.extend(index);
+    content.extend(records);
+    write_u32(&mut bytes, crc32(&content));
+    bytes.extend(content);
+    Ok(bytes)
+}
+
+// CRC-32 as in zip and PNG (reflected polynomial 0xedb88320).
+pub fn crc32(bytes : &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &b| {
+        (0..8).fold(crc ^ b as u32, |crc, _| if crc & 1 != 0 { crc >> 1 ^ 0xedb88320 } else { crc >> 1 })
+    })
+}
+
+fn read_u32(bytes : &[u8], at : usize) -> u32 {
+    bytes[at..at + 4].iter().rev().fold(0, |n, &b| n << 8 | b as u32)
+}
+
+fn write_u32(bytes : &mut Vec<u8>, n : u32) {
+    bytes.extend((0..4).map(|k| (n >> (8 * k)) as u8));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sudoku::Solver;
+
+    // SOLVED with square i blank for every i, and then one with I9 alone given: more than a
+    // block of puzzles, and a gap long enough to need skips.
+    fn puzzles() -> Vec<Puzzle> {
+        let mut grids : Vec<String> = (0..SQUARE_COUNT).map(|i| SOLVED.char_indices().map(|(j, c)| if i == j { '.' } else { c }).collect())
+                                                       .collect();
+        grids.push(format!("{:.>81}", "9"));
+        grids.iter().map(|grid| grid.parse().unwrap()).collect()
+    }
+
+    fn solutions(n : usize) -> Vec<StartState> {
+        vec![SOLVED.parse::<Puzzle>().unwrap().start_state().clone(); n]
+    }
+
+    #[test]
+    fn built_packs_open_to_the_same_puzzles() {
+        let puzzles = puzzles();
+        let solutions = solutions(puzzles.len());
+        let path = std::env::temp_dir().join(format!("sudoku-solver-pack-{}.pack", std::process::id()));
+        std::fs::write(&path, build(&puzzles, Some(&solutions), 0, None).unwrap()).unwrap();
+        let pack = Pack::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        let pack = pack.unwrap();
+        assert_eq!((pack.len(), pack.version(), pack.has_solutions(), pack.license()), (82, 3, true, None));
+        pack.verify().unwrap();
+        for (i, puzzle) in puzzles.iter().enumerate() {
+            assert_eq!(pack.get(i).unwrap().start_state(), puzzle.start_state());
+            assert_eq!(pack.solution(i).as_ref(), Some(&solutions[i]));
+        }
+        assert!(pack.get(82).is_none());
+
+        let bare = Pack::from_vec(build(&puzzles, None, 0, None).unwrap()).unwrap();
+        bare.verify().unwrap();
+        assert!(! bare.has_solutions() && bare.solution(0).is_none());
+        assert_eq!(bare.get(81).unwrap().start_state(), puzzles[81].start_state());
+        assert!(build(&puzzles, Some(&solutions[1..]), 0, None).is_err());
+    }
+
+    #[test]
+    fn version_1_packs_still_open() {
+        let puzzles = puzzles();
+        let v3 = build(&puzzles, Some(&solutions(puzzles.len())), 0, None).unwrap();
+        // the same content after a header that ends with the count
+        let mut v1 = v3[..12].to_vec();
+        v1[4] = 1;
+        v1.extend(&v3[20..]);
+        let pack = Pack::from_vec(v1.clone()).unwrap();
+        assert_eq!((pack.version(), pack.revision(), pack.checksum(), pack.key()), (1, 0, None, crc32(&v1[12..])));
+        pack.verify().unwrap();
+        assert_eq!(pack.get(81).unwrap().start_state(), puzzles[81].start_state());
+    }
+
+    #[test]
+    fn damaged_packs_fail_to_open() {
+        let bytes = build(&puzzles(), None, 0, None).unwrap();
+        assert_eq!(Pack::from_vec(b"PK\x03\x04".to_vec()).err(), Some("Not a puzzle pack".to_string()));
+        let mut newer = bytes.clone();
+        newer[4] = VERSION + 1;
+        assert_eq!(Pack::from_vec(newer).err(), Some("Unsupported pack version 4, this build reads up to 3".to_string()));
+        assert_eq!(Pack::from_vec(bytes[..19].to_vec()).err(), Some("Pack is cut short in its header".to_string()));
+        // two index entries, at 20 and 24
+        assert_eq!(Pack::from_vec(bytes[..24].to_vec()).err(), Some("Pack of 82 puzzles is cut short in its index".to_string()));
+        assert_eq!(Pack::from_vec(bytes[..28].to_vec()).err(), Some("Pack index points past the end".to_string()));
+        // a last record cut short opens, since opening doesn't read the records
+        let cut = Pack::from_vec(bytes[..bytes.len() - 1].to_vec()).unwrap();
+        assert!(cut.get(80).is_some() && cut.get(81).is_none());
+        assert!(cut.verify().is_err());
+    }
+
+    #[test]
+    fn the_checksum_covers_everything_after_the_header() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        let bytes = build(&puzzles(), None, 7, None).unwrap();
+        let pack = Pack::from_vec(bytes.clone()).unwrap();
+        assert_eq!((pack.revision(), pack.checksum(), pack.key()), (7, Some(crc32(&bytes[20..])), crc32(&bytes[20..])));
+        let mut damaged = bytes.clone();
+        *damaged.last_mut().unwrap() ^= 1;
+        let computed = crc32(&damaged[20..]);
+        assert_eq!(Pack::from_vec(damaged).unwrap().verify().err(),
+                   Some(format!("Checksum mismatch, stored {:08x} but the content gives {:08x}", crc32(&bytes[20..]), computed)));
+        // version 2 headers are the same, there was no license flag yet
+        let mut v2 = bytes.clone();
+        v2[4] = 2;
+        let pack = Pack::from_vec(v2).unwrap();
+        assert_eq!((pack.version(), pack.revision()), (2, 7));
+        pack.verify().unwrap();
+    }
+
+    #[test]
+    fn progress_keeps_the_best_results_and_belongs_to_its_pack() {
+        let solver = Solver::new();
+        let puzzles = puzzles();
+        let pack = Pack::from_vec(build(&puzzles[..3], None, 0, None).unwrap()).unwrap();
+        let mut progress = PackProgress::new(&pack);
+        // puzzle 1 is SOLVED less the 3 in A2
+        let mut game = Game::from_pack(&solver, &pack, 1).unwrap();
+        assert_eq!(progress.record(&game), Err("Game is not complete".to_string()));
+        game.add_elapsed(90000);
+        game.place(&('A', '2'), 4).unwrap();
+        game.place(&('A', '2'), 3).unwrap();
+        assert_eq!(progress.record(&game), Ok(true));
+        assert_eq!(progress.record(&game), Ok(false));
+        // slower but without the mistake: the time stays, the stars go up
+        let mut cleaner = Game::from_pack(&solver, &pack, 1).unwrap();
+        cleaner.add_elapsed(120000);
+        cleaner.place(&('A', '2'), 3).unwrap();
+        assert_eq!(progress.record(&cleaner), Ok(true));
+        assert_eq!(progress.completion(1), Some(Completion { best_time : 90000, stars : 3 }));
+        assert_eq!((progress.solved_count(), progress.stars(), progress.next_unsolved(), progress.is_finished()), (1, 3, Some(0), false));
+
+        let saved = progress.save();
+        assert_eq!(saved, format!("v1;{:08x};3;1:90000:3", pack.key()));
+        let loaded = PackProgress::load(&saved).unwrap();
+        assert_eq!(loaded, progress);
+        loaded.check(&pack).unwrap();
+        let other = Pack::from_vec(build(&puzzles[3..6], None, 0, None).unwrap()).unwrap();
+        assert_eq!(loaded.check(&other).err(), Some(format!("Progress is for pack {:08x}, not {:08x}", pack.key(), other.key())));
+        let mut elsewhere = Game::from_pack(&solver, &other, 1).unwrap();
+        elsewhere.place(&('A', '5'), 7).unwrap();
+        assert_eq!(progress.record(&elsewhere), Err("Game is not from this pack".to_string()));
+
+        assert_eq!(PackProgress::load("v2;00000000;3;").err(), Some("Progress version 2 is not one this build reads, 1 to 1".to_string()));
+        assert_eq!(PackProgress::load("v1;xyz;3;").err(), Some("Invalid pack key xyz".to_string()));
+        assert_eq!(PackProgress::load("v1;00000000;3;3:1000:3").err(), Some("Invalid completion 3:1000:3".to_string()));
+        assert_eq!(PackProgress::load("v1;00000000;3;0:1000:4").err(), Some("Invalid completion 0:1000:4".to_string()));
+    }
+
+    #[test]
+    fn the_license_block_is_read_back_and_checked() {
+        let license = License {
+            holder : Some("Example Puzzles".to_string()),
+            terms : Some("Attribution required.\nNo resale.".to_string()),
+            ..License::new("CC-BY-4.0")
+        };
+        let bytes = build(&puzzles(), None, 0, Some(&license)).unwrap();
+        let pack = Pack::from_vec(bytes.clone()).unwrap();
+        assert_eq!(bytes[5] & LICENSED, LICENSED);
+        assert_eq!(pack.license(), Some(license));
+        pack.verify().unwrap();
+        assert_eq!(pack.get(81).unwrap().start_state(), puzzles()[81].start_state());
+
+        // the block is under the checksum
+        let at = bytes.windows(11).position(|w| w == b"\"CC-BY-4.0\"").unwrap() + 1;
+        let mut blanked = bytes.clone();
+        blanked[at..at + 9].copy_from_slice(b"         ");
+        assert!(Pack::from_vec(blanked.clone()).unwrap().verify().unwrap_err().starts_with("Checksum mismatch"));
+        // and is checked on its own once the checksum matches
+        let checksum = crc32(&blanked[20..]).to_le_bytes();
+        blanked[16..20].copy_from_slice(&checksum);
+        let pack = Pack::from_vec(blanked).unwrap();
+        assert_eq!(pack.license(), None);
+        assert_eq!(pack.verify().err(), Some("Corrupt license: License needs a name".to_string()));
+        assert_eq!(Pack::from_vec(bytes[..23].to_vec()).err(), Some("Pack is cut short in its license".to_string()));
+        assert_eq!(Pack::from_vec(bytes[..40].to_vec()).err(), Some("Pack is cut short in its license".to_string()));
+    }
+
+    const SOLVED : &str = "534678912672195348198342567859761423426853791713924856961537284287419635345286179";
+}
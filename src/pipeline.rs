@@ -0,0 +1,411 @@
+use std;
+use std::collections::{BTreeMap, HashSet};
+use std::thread;
+use std::time::{Duration, Instant};
+use analysis::{self, Difficulty, Rating, Score};
+use cancel::CancelToken;
+use canonical;
+use formats::Format;
+use grid::Grid9;
+use puzzle::Puzzle;
+use sudoku::Generator;
+use telemetry;
+
+// A puzzle feed as one job instead of shell glue around `generate`, `rate`, `dedup` and `curate`:
+//
+//   generate  `count` unique puzzles dug down to `clues` at most (as far as they stay unique),
+//             the i-th from `seed` + i
+//   rate      each with the logical rater, at most `timeout_ms` apiece; puzzles that fail or run
+//             out of time are dropped
+//   dedup     drops puzzles without a unique solution and repeats, up to symmetry and
+//             relabelling with `canonical` (the default) or as exact grids
+//   curate    keeps up to `per_difficulty` of each level, in an order fixed by its `seed`, of
+//             the puzzles whose clue pattern has an `analysis::symmetry_score` of at least
+//             `min_symmetry` (0 by default, letting every puzzle through)
+//
+// Generation and rating run on `threads` threads each (1 by default); the results don't depend
+// on it. Each stage's counts and time end up in the `JobReport`, and with the "metrics" feature
+// also as sudoku_pipeline_stage_seconds and sudoku_pipeline_stage_puzzles, labelled by stage.
+// `run_cancellable` stops the whole job at once when its `CancelToken` is cancelled.
+//
+// Jobs are written in a small part of TOML, a table per stage (all optional) and an [output]
+// table with the `path` to write and its `format`:
+//
+//   [generate]
+//   count = 1000
+//   seed = 20261016
+//   clues = 24
+//   threads = 8
+//
+//   [rate]
+//   timeout_ms = 5000
+//   threads = 8
+//
+//   [curate]
+//   per_difficulty = 50
+//   min_symmetry = 0.8
+//
+//   [output]
+//   path = "feed.json"
+//   format = "json"
+#[derive(Clone, Debug, PartialEq)]
+pub struct Job {
+    pub count : usize,
+    pub seed : u64,
+    pub clues : usize,
+    pub generate_threads : usize,
+    pub timeout : Duration,
+    pub rate_threads : usize,
+    pub canonical : bool,
+    pub per_difficulty : usize,
+    pub curate_seed : u64,
+    pub min_symmetry : f32,
+    pub output : Option<String>,
+    pub format : Format
+}
+
+// What a stage took in, kept and spent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StageReport {
+    pub stage : &'static str,
+    pub input : usize,
+    pub output : usize,
+    pub elapsed : Duration
+}
+
+#[derive(Clone, Debug)]
+pub struct JobReport {
+    pub stages : Vec<StageReport>,
+    // the curated puzzles, easiest level first
+    pub puzzles : Vec<(Puzzle, Difficulty, Score)>,
+    // stopped part way, with the stages up to the one stopped in and no puzzles
+    pub cancelled : bool
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Integer(u64),
+    Float(f64),
+    Boolean(bool),
+    String(String)
+}
+
+// The TOML a job is written in: `[table]` headers, `key = value` lines with non-negative
+// integers or decimals, booleans or double quoted strings without escapes, and `#` comments.
+fn parse_tables(text : &str) -> Result<BTreeMap<String, BTreeMap<String, Value>>, String> {
+    let mut tables : BTreeMap<String, BTreeMap<String, Value>> = BTreeMap::new();
+    let mut table : Option<String> = None;
+    for (i, line) in text.lines().enumerate() {
+        let error = |message : String| format!("line {}: {}", i + 1, message);
+        let line = match line.find('#') {
+            Some(hash) if line[..hash].matches('"').count() % 2 == 0 => &line[..hash],
+            _ => line
+        }.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            let name = line[1..line.len() - 1].trim().to_string();
+            if tables.contains_key(&name) {
+                return Err(error(format!("Table [{}] given twice", name)));
+            }
+            tables.insert(name.clone(), BTreeMap::new());
+            table = Some(name);
+            continue;
+        }
+        let (key, value) = match line.find('=') {
+            Some(eq) => (line[..eq].trim(), line[eq + 1..].trim()),
+            None => return Err(error(format!("Expected key = value, got {}", line)))
+        };
+        let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            Value::String(value[1..value.len() - 1].to_string())
+        } else if value == "true" || value == "false" {
+            Value::Boolean(value == "true")
+        } else if value.contains('.') {
+            Value::Float(value.replace('_', "").parse().map_err(|_| error(format!("Invalid value {}", value)))?)
+        } else {
+            Value::Integer(value.replace('_', "").parse().map_err(|_| error(format!("Invalid value {}", value)))?)
+        };
+        let name = table.clone().ok_or_else(|| error(format!("{} is outside any table", key)))?;
+        if tables.get_mut(&name).unwrap().insert(key.to_string(), value).is_some() {
+            return Err(error(format!("{} given twice in [{}]", key, name)));
+        }
+    }
+    Ok(tables)
+}
+
+impl Default for Job {
+    fn default() -> Job {
+        Job {
+            count : 100,
+            seed : 0,
+            clues : 17,
+            generate_threads : 1,
+            timeout : Duration::from_millis(10000),
+            rate_threads : 1,
+            canonical : true,
+            per_difficulty : 20,
+            curate_seed : 0,
+            min_symmetry : 0.0,
+            output : None,
+            format : Format::Json
+        }
+    }
+}
+
+impl Job {
+
+    // A job from its TOML, the defaults standing in for whatever it leaves out. Unknown tables
+    // and keys are errors, so a misspelt setting doesn't quietly fall back to its default.
+    pub fn parse(text : &str) -> Result<Job, String> {
+        let mut job = Job::default();
+        for (table, values) in parse_tables(text)? {
+            if ! ["generate", "rate", "dedup", "curate", "output"].contains(&table.as_str()) {
+                return Err(format!("Unknown table [{}]", table));
+            }
+            for (key, value) in values {
+                let setting = format!("{}.{}", table, key);
+                let number = |value : &Value| match *value {
+                    Value::Integer(n) => Ok(n),
+                    _ => Err(format!("{} expects a whole number", setting))
+                };
+                match (setting.as_str(), &value) {
+                    ("generate.count", v) => job.count = number(v)? as usize,
+                    ("generate.seed", v) => job.seed = number(v)?,
+                    ("generate.clues", v) => job.clues = number(v)? as usize,
+                    ("generate.threads", v) => job.generate_threads = number(v)? as usize,
+                    ("rate.timeout_ms", v) => job.timeout = Duration::from_millis(number(v)?),
+                    ("rate.threads", v) => job.rate_threads = number(v)? as usize,
+                    ("dedup.canonical", &Value::Boolean(b)) => job.canonical = b,
+                    ("curate.per_difficulty", v) => job.per_difficulty = number(v)? as usize,
+                    ("curate.seed", v) => job.curate_seed = number(v)?,
+                    ("curate.min_symmetry", &Value::Float(x)) => job.min_symmetry = x as f32,
+                    ("curate.min_symmetry", v) => job.min_symmetry = number(v)? as f32,
+                    ("output.path", Value::String(path)) => job.output = Some(path.clone()),
+                    ("output.format", Value::String(name)) => {
+                        job.format = Format::all().into_iter().find(|f| f.name() == name)
+                                                  .ok_or_else(|| format!("Unknown format {}", name))?
+                    },
+                    ("dedup.canonical", _) => return Err(format!("{} expects true or false", setting)),
+                    ("output.path", _) | ("output.format", _) => return Err(format!("{} expects a string", setting)),
+                    _ => return Err(format!("Unknown setting {}", setting))
+                }
+            }
+        }
+        if ! (17..=81).contains(&job.clues) {
+            return Err(format!("generate.clues must be 17 to 81, got {}", job.clues));
+        }
+        if ! (0.0..=1.0).contains(&job.min_symmetry) {
+            return Err(format!("curate.min_symmetry must be 0 to 1, got {}", job.min_symmetry));
+        }
+        if job.generate_threads == 0 || job.rate_threads == 0 {
+            return Err("threads must be at least 1".to_string());
+        }
+        Ok(job)
+    }
+
+    // Runs every stage in turn; the output file is left to the caller.
+    pub fn run(&self) -> JobReport {
+        self.run_cancellable(&CancelToken::new())
+    }
+
+    // `run` that every thread of every stage checks `cancel` in, between puzzles and while
+    // generating or rating one. A cancelled job ends with the stage it was in.
+    pub fn run_cancellable(&self, cancel : &CancelToken) -> JobReport {
+        let mut stages = Vec::new();
+        let stopped = |stages| JobReport {
+            stages,
+            puzzles : Vec::new(),
+            cancelled : true
+        };
+        let seeds : Vec<u64> = (0..self.count as u64).map(|i| self.seed.wrapping_add(i)).collect();
+        let clues = self.clues;
+        let generated = stage(&mut stages, "generate", seeds.len(), || {
+            in_chunks(&seeds, self.generate_threads, |seeds| {
+                seeds.iter().map_while(|&seed| {
+                    let mut machine = Generator::new().with_seed(seed).dig_machine(clues);
+                    while machine.step() {
+                        if cancel.is_stopped() {
+                            return None;
+                        }
+                    }
+                    Some(Puzzle::new(machine.puzzle()))
+                }).collect()
+            })
+        });
+        if cancel.is_stopped() {
+            return stopped(stages);
+        }
+        let timeout = self.timeout;
+        let rated = stage(&mut stages, "rate", generated.len(), || {
+            let ratings = in_chunks(&generated, self.rate_threads, |puzzles| analysis::rate_batch_cancellable(puzzles, timeout, cancel));
+            generated.iter().zip(ratings).filter_map(|(puzzle, rated)| match rated.rating {
+                Rating::Rated(score, level) => Some((puzzle.clone(), level, score)),
+                _ => None
+            }).collect::<Vec<_>>()
+        });
+        if cancel.is_stopped() {
+            return stopped(stages);
+        }
+        let unique = stage(&mut stages, "dedup", rated.len(), || {
+            let mut seen = HashSet::new();
+            rated.into_iter().filter(|(puzzle, _, _)| {
+                let form = if self.canonical { canonical::canonical(puzzle) } else { puzzle.to_grid_string() };
+                seen.insert(canonical::fingerprint(&form)) &&
+                    Grid9::from_start_state(puzzle.start_state()).is_some_and(|grid| grid.count_solutions(2) == 1)
+            }).collect::<Vec<_>>()
+        });
+        let puzzles = stage(&mut stages, "curate", unique.len(), || {
+            let mut levels = BTreeMap::new();
+            for entry in unique.into_iter().filter(|entry| analysis::symmetry_score(entry.0.start_state()) >= self.min_symmetry) {
+                let grid = entry.0.to_grid_string();
+                levels.entry(entry.1).or_insert_with(Vec::new).push((canonical::seeded_hash(self.curate_seed, &grid), grid, entry));
+            }
+            levels.into_values().flat_map(|mut level| {
+                level.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+                level.truncate(self.per_difficulty);
+                level.into_iter().map(|(_, _, entry)| entry)
+            }).collect::<Vec<_>>()
+        });
+        JobReport {
+            stages,
+            puzzles,
+            cancelled : false
+        }
+    }
+}
+
+// Times a stage and records it.
+fn stage<T, F : FnOnce() -> Vec<T>>(stages : &mut Vec<StageReport>, name : &'static str, input : usize, run : F) -> Vec<T> {
+    let started = Instant::now();
+    let output = run();
+    let report = StageReport {
+        stage : name,
+        input,
+        output : output.len(),
+        elapsed : started.elapsed()
+    };
+    telemetry::pipeline_stage_finished(&report);
+    stages.push(report);
+    output
+}
+
+// `work` over about equal runs of the items on `threads` threads, the results in item order.
+fn in_chunks<T : Sync, R : Send, F>(items : &[T], threads : usize, work : F) -> Vec<R>
+    where F : Fn(&[T]) -> Vec<R> + Sync {
+    if threads <= 1 || items.len() < 2 {
+        return work(items);
+    }
+    let size = items.len().div_ceil(threads);
+    let work = &work;
+    thread::scope(|scope| {
+        let handles : Vec<_> = items.chunks(size).map(|chunk| scope.spawn(move || work(chunk))).collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+impl JobReport {
+
+    pub fn levels(&self) -> BTreeMap<Difficulty, usize> {
+        let mut levels = BTreeMap::new();
+        for &(_, level, _) in &self.puzzles {
+            *levels.entry(level).or_insert(0) += 1;
+        }
+        levels
+    }
+}
+
+impl std::fmt::Display for JobReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for stage in &self.stages {
+            writeln!(f, "{:<9} {:>6} -> {:<6} {:>8} ms", stage.stage, stage.input, stage.output,
+                     stage.elapsed.as_secs() * 1000 + stage.elapsed.subsec_millis() as u64)?;
+        }
+        if self.cancelled {
+            return writeln!(f, "cancelled");
+        }
+        for (level, count) in self.levels() {
+            writeln!(f, "{:<7} {:>4}", level.name(), count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JOB : &str = "\
+[generate]
+count = 1_000
+seed = 20261016  # the day the feed started
+clues = 24
+threads = 8
+
+[rate]
+timeout_ms = 5000
+threads = 8
+
+[dedup]
+canonical = false
+
+[curate]
+per_difficulty = 50
+min_symmetry = 0.8
+
+[output]
+path = \"feed #1.json\"
+format = \"csv\"
+";
+
+    #[test]
+    fn jobs_take_their_settings_and_default_the_rest() {
+        assert_eq!(Job::parse(JOB), Ok(Job {
+            count : 1000,
+            seed : 20261016,
+            clues : 24,
+            generate_threads : 8,
+            timeout : Duration::from_millis(5000),
+            rate_threads : 8,
+            canonical : false,
+            per_difficulty : 50,
+            curate_seed : 0,
+            min_symmetry : 0.8,
+            output : Some("feed #1.json".to_string()),
+            format : Format::Csv
+        }));
+        assert_eq!(Job::parse("# nothing to change\n"), Ok(Job::default()));
+        assert_eq!(Job::parse("[curate]\nmin_symmetry = 1\n").map(|job| job.min_symmetry), Ok(1.0));
+    }
+
+    #[test]
+    fn every_bad_setting_is_reported() {
+        let error = |from : &str, to : &str| Job::parse(&JOB.replacen(from, to, 1)).err().unwrap();
+        assert_eq!(error("[rate]", "[generate]"), "line 7: Table [generate] given twice");
+        assert_eq!(error("clues = 24", "clues"), "line 4: Expected key = value, got clues");
+        assert_eq!(error("= 24", "= twenty"), "line 4: Invalid value twenty");
+        assert_eq!(error("= 0.8", "= 0.8.1"), "line 16: Invalid value 0.8.1");
+        assert_eq!(error("clues = 24", "clues = 24\nclues = 25"), "line 5: clues given twice in [generate]");
+        assert_eq!(Job::parse("count = 5\n[generate]\n").err().unwrap(), "line 1: count is outside any table");
+        assert_eq!(error("[dedup]", "[dedupe]"), "Unknown table [dedupe]");
+        assert_eq!(error("clues = 24", "clue = 24"), "Unknown setting generate.clue");
+        assert_eq!(error("= 5000", "= \"5s\""), "rate.timeout_ms expects a whole number");
+        assert_eq!(error("= false", "= 0"), "dedup.canonical expects true or false");
+        assert_eq!(error("= \"csv\"", "= csv"), "line 20: Invalid value csv");
+        assert_eq!(error("= \"csv\"", "= 1"), "output.format expects a string");
+        assert_eq!(error("= \"csv\"", "= \"xlsx\""), "Unknown format xlsx");
+        assert_eq!(error("= 24", "= 16"), "generate.clues must be 17 to 81, got 16");
+        assert_eq!(error("= 0.8", "= 1.5"), "curate.min_symmetry must be 0 to 1, got 1.5");
+        assert_eq!(error("threads = 8", "threads = 0"), "threads must be at least 1");
+    }
+
+    #[test]
+    fn cancelled_jobs_stop_in_the_first_stage() {
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let report = Job::default().run_cancellable(&cancel);
+        assert!(report.cancelled && report.puzzles.is_empty());
+        assert_eq!(report.stages.iter().map(|s| (s.stage, s.input, s.output)).collect::<Vec<_>>(), vec![("generate", 100, 0)]);
+        assert_eq!(report.to_string().lines().last(), Some("cancelled"));
+    }
+}
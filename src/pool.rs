@@ -0,0 +1,131 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use analysis::Difficulty;
+use sudoku::{GenResult, Generator};
+
+// Generation attempts per turn of a worker before it looks again at which queue needs it most,
+// so one hard-to-hit difficulty doesn't hold a worker while others run dry.
+const ATTEMPTS_PER_TURN : usize = 20;
+
+struct Queues {
+    puzzles : HashMap<Difficulty, VecDeque<GenResult>>,
+    stopped : bool
+}
+
+struct Shared {
+    queues : Mutex<Queues>,
+    // signalled when a puzzle was added
+    added : Condvar,
+    // signalled when a puzzle was taken or the pool stops
+    taken : Condvar,
+    capacity : usize
+}
+
+// Worker threads keeping a bounded queue of rated puzzles per difficulty topped up, so "new game"
+// is immediate even for hard puzzles. Each worker fills whichever queue is emptiest and sleeps
+// while all are full. Dropping the pool stops the workers once their current puzzle is done;
+// `shutdown` also waits for them.
+pub struct GeneratorPool {
+    shared : Arc<Shared>,
+    workers : Vec<JoinHandle<()>>
+}
+
+impl GeneratorPool {
+
+    pub fn new(workers : usize, capacity : usize) -> GeneratorPool {
+        let shared = Arc::new(Shared {
+            queues : Mutex::new(Queues {
+                puzzles : Difficulty::all().into_iter().map(|d| (d, VecDeque::new())).collect(),
+                stopped : false
+            }),
+            added : Condvar::new(),
+            taken : Condvar::new(),
+            capacity
+        });
+        let workers = (0..workers).map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || work(&shared))
+        }).collect();
+        GeneratorPool {
+            shared,
+            workers
+        }
+    }
+
+    // A puzzle of the difficulty, waiting for one if the queue is empty.
+    pub fn take(&self, difficulty : Difficulty) -> GenResult {
+        let mut queues = self.shared.queues.lock().unwrap();
+        loop {
+            if let Some(puzzle) = queues.puzzles.get_mut(&difficulty).and_then(|q| q.pop_front()) {
+                self.shared.taken.notify_all();
+                return puzzle;
+            }
+            queues = self.shared.added.wait(queues).unwrap();
+        }
+    }
+
+    // A puzzle of the difficulty if one is ready.
+    pub fn try_take(&self, difficulty : Difficulty) -> Option<GenResult> {
+        let mut queues = self.shared.queues.lock().unwrap();
+        let puzzle = queues.puzzles.get_mut(&difficulty).and_then(|q| q.pop_front());
+        if puzzle.is_some() {
+            self.shared.taken.notify_all();
+        }
+        puzzle
+    }
+
+    // Puzzles ready for the difficulty.
+    pub fn available(&self, difficulty : Difficulty) -> usize {
+        self.shared.queues.lock().unwrap().puzzles.get(&difficulty).map_or(0, |q| q.len())
+    }
+
+    pub fn shutdown(mut self) {
+        self.stop();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+
+    fn stop(&self) {
+        self.shared.queues.lock().unwrap().stopped = true;
+        self.shared.taken.notify_all();
+    }
+}
+
+impl Drop for GeneratorPool {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn work(shared : &Shared) {
+    let generator = Generator::new();
+    loop {
+        let difficulty = {
+            let mut queues = shared.queues.lock().unwrap();
+            loop {
+                if queues.stopped {
+                    return;
+                }
+                let emptiest = Difficulty::all().into_iter()
+                                                .map(|d| (queues.puzzles[&d].len(), d))
+                                                .filter(|&(n, _)| n < shared.capacity)
+                                                .min();
+                match emptiest {
+                    Some((_, d)) => break d,
+                    None => queues = shared.taken.wait(queues).unwrap()
+                }
+            }
+        };
+        if let Some(puzzle) = generator.generate_rated(difficulty, ATTEMPTS_PER_TURN) {
+            let mut queues = shared.queues.lock().unwrap();
+            let queue = queues.puzzles.get_mut(&difficulty).unwrap();
+            // another worker may have filled it in the meantime
+            if queue.len() < shared.capacity {
+                queue.push_back(puzzle);
+                shared.added.notify_all();
+            }
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use std;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use formats::Format;
+use json::Json;
+use sudoku::{Config, SquareValue, StartState, StartStateError, StartStateHandler, StringStartStateHandler, ALL_SQUARES, GRID_SIZE, SQUARE_COUNT};
+
+// 81 characters in A1..I9 order, digits for givens and '.' for blanks.
+pub type GridString = String;
+
+// Where a puzzle came from, kept alongside the digits so curated collections can credit it.
+// `created` is a YYYY-MM-DD date.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Provenance {
+    pub author : Option<String>,
+    pub source : Option<String>,
+    pub license : Option<String>,
+    pub created : Option<String>
+}
+
+// Version of the JSON `Puzzle::to_json` writes. Unversioned objects from before have the same
+// fields and read as they are.
+pub const JSON_VERSION : u32 = 1;
+
+const PROVENANCE_KEYS : [&str; 4] = ["author", "source", "license", "created"];
+
+impl Provenance {
+
+    pub fn is_empty(&self) -> bool {
+        self.fields().is_empty()
+    }
+
+    // The fields that are set, by their key in JSON and in file headers.
+    pub fn fields(&self) -> Vec<(&'static str, &str)> {
+        PROVENANCE_KEYS.iter()
+                       .zip([&self.author, &self.source, &self.license, &self.created].iter())
+                       .filter_map(|(&key, value)| value.as_ref().map(|v| (key, v.as_str())))
+                       .collect()
+    }
+
+    pub fn get(&self, key : &str) -> Option<&str> {
+        self.fields().into_iter().find(|&(k, _)| k == key).map(|(_, value)| value)
+    }
+
+    // Sets the field named by `key`, failing on unknown keys and on dates that aren't YYYY-MM-DD.
+    pub fn set(&mut self, key : &str, value : &str) -> Result<(), String> {
+        if key == "created" && ! is_date(value) {
+            return Err(format!("Invalid creation date {}, expected YYYY-MM-DD", value));
+        }
+        match self.field_mut(key) {
+            Some(field) => *field = Some(value.to_string()),
+            None => return Err(format!("Unknown provenance key {}", key))
+        }
+        Ok(())
+    }
+
+    // Reads a `key: value` header such as `author: Jane Doe`, as found in comment lines of puzzle
+    // files. Returns false for anything that isn't a provenance header; an empty value clears
+    // the field.
+    pub fn parse_header(&mut self, header : &str) -> Result<bool, String> {
+        let (key, value) = match header.find(':') {
+            Some(i) => (header[..i].trim(), header[i + 1..].trim()),
+            None => return Ok(false)
+        };
+        match self.field_mut(key) {
+            Some(field) if value.is_empty() => *field = None,
+            Some(_) => self.set(key, value)?,
+            None => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    fn field_mut(&mut self, key : &str) -> Option<&mut Option<String>> {
+        match key {
+            "author" => Some(&mut self.author),
+            "source" => Some(&mut self.source),
+            "license" => Some(&mut self.license),
+            "created" => Some(&mut self.created),
+            _ => None
+        }
+    }
+
+    pub fn to_json_fields(&self) -> Vec<(&'static str, Json)> {
+        self.fields().into_iter().map(|(key, value)| (key, Json::string(value))).collect()
+    }
+
+    // Picks the provenance keys out of a JSON object, ignoring any others.
+    pub fn from_json(json : &Json) -> Result<Provenance, String> {
+        let mut provenance = Provenance::default();
+        for key in PROVENANCE_KEYS.iter() {
+            match json.get(key) {
+                None | Some(&Json::Null) => (),
+                Some(Json::String(value)) => provenance.set(key, value)?,
+                Some(value) => return Err(format!("Provenance {} must be a string, got {}", key, value))
+            }
+        }
+        Ok(provenance)
+    }
+}
+
+fn is_date(s : &str) -> bool {
+    let parts : Vec<&str> = s.split('-').collect();
+    let number = |part : &str, len : usize| part.len() == len && part.chars().all(|c| c.is_ascii_digit());
+    parts.len() == 3 && number(parts[0], 4) && number(parts[1], 2) && number(parts[2], 2)
+        && (1..13).contains(&parts[1].parse::<u32>().unwrap_or(0))
+        && (1..32).contains(&parts[2].parse::<u32>().unwrap_or(0))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Puzzle {
+    start_state : StartState,
+    provenance : Provenance
+}
+
+impl Puzzle {
+
+    pub fn new(start_state : StartState) -> Puzzle {
+        Puzzle {
+            start_state,
+            provenance : Provenance::default()
+        }
+    }
+
+    pub fn with_provenance(self, provenance : Provenance) -> Puzzle {
+        Puzzle {
+            provenance,
+            ..self
+        }
+    }
+
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+
+    pub fn provenance_mut(&mut self) -> &mut Provenance {
+        &mut self.provenance
+    }
+
+    pub fn start_state(&self) -> &StartState {
+        &self.start_state
+    }
+
+    pub fn into_start_state(self) -> StartState {
+        self.start_state
+    }
+
+    // Whether the givens fit the classic grid, see `StartState::validate`.
+    pub fn validate(&self) -> Result<(), StartStateError> {
+        self.start_state.validate(&Config::new())
+    }
+
+    pub fn clues(&self) -> usize {
+        self.start_state.iter().filter(|&&(_, v)| v != 0).count()
+    }
+
+    // Like parsing with `str::parse`, but a digit given twice in a row, column or box is an error
+    // naming the squares.
+    pub fn parse_checked(grid : &str) -> Result<Puzzle, String> {
+        StringStartStateHandler::checking_conflicts().parse(&Config::new(), grid.to_string()).map(Puzzle::new)
+    }
+
+    pub fn to_grid_string(&self) -> GridString {
+        self.display(StringStartStateHandler::new()).to_string()
+    }
+
+    // The grid string as `handler` writes it, e.g. with '0' for blanks. `Display` is this with the
+    // default handler.
+    pub fn display(&self, handler : StringStartStateHandler) -> GridDisplay<'_> {
+        GridDisplay {
+            puzzle : self,
+            handler
+        }
+    }
+
+    // `{"version": 1, "puzzle": "<81 character grid>", "author": ..., "source": ..., "license": ...,
+    // "created": ...}` with only the provenance fields that are set.
+    pub fn to_json(&self) -> Json {
+        let mut fields = vec![("version", Json::Number(JSON_VERSION as f64)), ("puzzle", Json::String(self.to_grid_string()))];
+        fields.extend(self.provenance.to_json_fields());
+        Json::object(fields)
+    }
+
+    pub fn from_json(json : &Json) -> Result<Puzzle, String> {
+        json.format_version(JSON_VERSION)?;
+        let grid : Puzzle = match json.get("puzzle").and_then(|p| p.as_str()) {
+            Some(grid) => grid.parse()?,
+            None => return Err("Puzzle needs a puzzle grid".to_string())
+        };
+        Ok(grid.with_provenance(Provenance::from_json(json)?))
+    }
+}
+
+// Flat A1..I9 values with 0 for blanks, the layout of most datasets and FFI callers. Squares
+// outside the grid are ignored.
+pub fn to_values(start_state : &StartState) -> [u8; SQUARE_COUNT] {
+    let mut values = [0; SQUARE_COUNT];
+    for &((row, col), v) in start_state {
+        let r = (row as u32).wrapping_sub('A' as u32) as usize;
+        let c = (col as u32).wrapping_sub('1' as u32) as usize;
+        if r < GRID_SIZE && c < GRID_SIZE {
+            values[r * GRID_SIZE + c] = v as u8;
+        }
+    }
+    values
+}
+
+// Every square of flat A1..I9 values with 0 for blanks, as the string parser gives them. Fails
+// on anything but 81 values of 0 to 9.
+pub fn from_values(values : &[u8]) -> Result<StartState, String> {
+    if values.len() != SQUARE_COUNT {
+        return Err(format!("Expected {} values, got {}", SQUARE_COUNT, values.len()));
+    }
+    let mut start_state = Vec::new();
+    for (i, &v) in values.iter().enumerate() {
+        if v > 9 {
+            return Err(format!("Value {} out of range at index {}", v, i));
+        }
+        start_state.push((ALL_SQUARES[i], v as SquareValue));
+    }
+    Ok(StartState::new(start_state))
+}
+
+impl From<StartState> for Puzzle {
+    fn from(start_state : StartState) -> Puzzle {
+        Puzzle::new(start_state)
+    }
+}
+
+impl TryFrom<[u8; SQUARE_COUNT]> for Puzzle {
+    type Error = String;
+
+    fn try_from(values : [u8; SQUARE_COUNT]) -> Result<Puzzle, String> {
+        from_values(&values).map(Puzzle::new)
+    }
+}
+
+impl TryFrom<&[u8]> for Puzzle {
+    type Error = String;
+
+    fn try_from(values : &[u8]) -> Result<Puzzle, String> {
+        from_values(values).map(Puzzle::new)
+    }
+}
+
+impl TryFrom<Vec<u8>> for Puzzle {
+    type Error = String;
+
+    fn try_from(values : Vec<u8>) -> Result<Puzzle, String> {
+        from_values(&values).map(Puzzle::new)
+    }
+}
+
+impl From<&Puzzle> for [u8; SQUARE_COUNT] {
+    fn from(puzzle : &Puzzle) -> [u8; SQUARE_COUNT] {
+        to_values(&puzzle.start_state)
+    }
+}
+
+impl From<&Puzzle> for Vec<u8> {
+    fn from(puzzle : &Puzzle) -> Vec<u8> {
+        to_values(&puzzle.start_state).to_vec()
+    }
+}
+
+// Pencilmark grids: 729 characters, nine per square in A1..I9 order, the k:th being the digit k
+// while it is a candidate and '0' or '.' once the puzzle rules it out. A square left with one
+// candidate is a given; the others' missing candidates are the start state's eliminations.
+pub fn parse_candidates(grid : &str) -> Result<StartState, String> {
+    let chars : Vec<char> = grid.chars().collect();
+    if chars.len() != SQUARE_COUNT * GRID_SIZE {
+        return Err(format!("Expected {} characters, got {}", SQUARE_COUNT * GRID_SIZE, chars.len()));
+    }
+    let mut givens = Vec::new();
+    let mut eliminations = Vec::new();
+    for (i, square) in chars.chunks(GRID_SIZE).enumerate() {
+        let mut candidates = Vec::new();
+        for (k, &c) in square.iter().enumerate() {
+            let digit = k as SquareValue + 1;
+            match c {
+                '0' | '.' => (),
+                _ if c.to_digit(10) == Some(digit) => candidates.push(digit),
+                _ => return Err(format!("Expected {} or '.' at {}, got '{}'", digit, i * GRID_SIZE + k + 1, c))
+            }
+        }
+        match candidates.len() {
+            0 => return Err(format!("No candidates left in {}{}", ALL_SQUARES[i].0, ALL_SQUARES[i].1)),
+            1 => givens.push((ALL_SQUARES[i], candidates[0])),
+            _ => eliminations.extend((1..GRID_SIZE as SquareValue + 1).filter(|d| ! candidates.contains(d)).map(|d| (ALL_SQUARES[i], d)))
+        }
+    }
+    Ok(StartState::new(givens).with_eliminations(eliminations))
+}
+
+// The pencilmark grid of a start state, as `parse_candidates` reads it.
+pub fn candidates_string(start_state : &StartState) -> GridString {
+    let values = to_values(start_state);
+    let mut grid = String::with_capacity(SQUARE_COUNT * GRID_SIZE);
+    for (i, &given) in values.iter().enumerate() {
+        for d in 1..GRID_SIZE as SquareValue + 1 {
+            let candidate = if given != 0 { given as SquareValue == d } else { ! start_state.eliminations().contains(&(ALL_SQUARES[i], d)) };
+            grid.push(if candidate { std::char::from_digit(d, 10).unwrap() } else { '.' });
+        }
+    }
+    grid
+}
+
+// An 81 character line, a 729 character pencilmark grid (`parse_candidates`), or a single puzzle
+// in the block format of `Format::Block`, nine rows with optional borders and headers.
+impl FromStr for Puzzle {
+    type Err = String;
+
+    fn from_str(text : &str) -> Result<Puzzle, String> {
+        let text = text.trim();
+        if text.chars().count() == SQUARE_COUNT * GRID_SIZE {
+            return parse_candidates(text).map(Puzzle::new);
+        }
+        if text.lines().count() == 1 {
+            return StringStartStateHandler::new().parse(&Config::new(), text.to_string()).map(Puzzle::new);
+        }
+        let mut puzzles = Format::Block.read(text)?;
+        match puzzles.len() {
+            1 => Ok(puzzles.remove(0)),
+            n => Err(format!("Expected one puzzle, got {}", n))
+        }
+    }
+}
+
+pub struct GridDisplay<'a> {
+    puzzle : &'a Puzzle,
+    handler : StringStartStateHandler
+}
+
+// Puzzles with eliminations are written as pencilmark grids whatever the handler.
+impl<'a> std::fmt::Display for GridDisplay<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if ! self.puzzle.start_state.eliminations().is_empty() {
+            return write!(f, "{}", candidates_string(&self.puzzle.start_state));
+        }
+        write!(f, "{}", self.handler.generate(&Config::new(), self.puzzle.start_state.clone()))
+    }
+}
+
+impl std::fmt::Display for Puzzle {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.display(StringStartStateHandler::new()))
+    }
+}
+
+// A map of the same fields as `Puzzle::to_json`.
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Puzzle {
+    fn serialize<S: ::serde::Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let fields = self.provenance.fields();
+        let mut map = serializer.serialize_map(Some(fields.len() + 1))?;
+        map.serialize_entry("puzzle", &self.to_grid_string())?;
+        for (key, value) in fields {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for Puzzle {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer : D) -> Result<Puzzle, D::Error> {
+        let fields = ::std::collections::BTreeMap::<String, String>::deserialize(deserializer)?;
+        let json = Json::Object(fields.into_iter().map(|(k, v)| (k, Json::String(v))).collect());
+        Puzzle::from_json(&json).map_err(::serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,77 @@
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use bindings::{self, Error, ErrorKind};
+
+// The `bindings` entry points as a Python module for notebooks and scripts:
+//
+//   import sudoku_solver
+//   grid = sudoku_solver.generate(difficulty="hard", seed=7)
+//   sudoku_solver.solve(grid), sudoku_solver.rate(grid)["score"]
+//
+// Each `ErrorKind` raises its own exception, all of them subclasses of `SudokuError`.
+
+create_exception!(sudoku_solver, SudokuError, PyException);
+create_exception!(sudoku_solver, ArgumentError, SudokuError);
+create_exception!(sudoku_solver, ParseError, SudokuError);
+create_exception!(sudoku_solver, UnsolvableError, SudokuError);
+create_exception!(sudoku_solver, TimeoutError, SudokuError);
+create_exception!(sudoku_solver, GenerateError, SudokuError);
+
+fn to_py_err(error : Error) -> PyErr {
+    match error.kind {
+        ErrorKind::Argument => ArgumentError::new_err(error.message),
+        ErrorKind::Parse => ParseError::new_err(error.message),
+        ErrorKind::Unsolvable => UnsolvableError::new_err(error.message),
+        ErrorKind::Timeout => TimeoutError::new_err(error.message),
+        ErrorKind::Generate => GenerateError::new_err(error.message)
+    }
+}
+
+// The grid as 81 characters, '.' for blanks.
+#[pyfunction]
+fn parse(grid : &str) -> PyResult<String> {
+    bindings::parse(grid).map(|puzzle| puzzle.to_grid_string()).map_err(to_py_err)
+}
+
+#[pyfunction]
+fn solve(py : Python, grid : &str) -> PyResult<String> {
+    py.detach(|| bindings::solve(grid)).map_err(to_py_err)
+}
+
+#[cfg(feature = "generator")]
+#[pyfunction]
+#[pyo3(signature = (clues=None, difficulty=None, seed=None))]
+fn generate(py : Python, clues : Option<usize>, difficulty : Option<&str>, seed : Option<u64>) -> PyResult<String> {
+    py.detach(|| bindings::generate(clues, difficulty, seed)).map_err(to_py_err)
+}
+
+// {"difficulty": "hard", "score": 4.31, "steps": 57, "hardest": "x_wing"}, as `js::rating_to_json`.
+#[pyfunction]
+fn rate<'py>(py : Python<'py>, grid : &str) -> PyResult<Bound<'py, PyDict>> {
+    let (level, score) = py.detach(|| bindings::rate(grid)).map_err(to_py_err)?;
+    let rating = PyDict::new(py);
+    rating.set_item("difficulty", level.name())?;
+    rating.set_item("score", score.value)?;
+    rating.set_item("steps", score.steps)?;
+    rating.set_item("hardest", score.hardest.map(|t| t.id()))?;
+    Ok(rating)
+}
+
+#[pymodule]
+fn sudoku_solver(module : &Bound<PyModule>) -> PyResult<()> {
+    let py = module.py();
+    module.add_function(wrap_pyfunction!(self::parse, module)?)?;
+    module.add_function(wrap_pyfunction!(self::solve, module)?)?;
+    #[cfg(feature = "generator")]
+    module.add_function(wrap_pyfunction!(self::generate, module)?)?;
+    module.add_function(wrap_pyfunction!(self::rate, module)?)?;
+    module.add("SudokuError", py.get_type::<SudokuError>())?;
+    module.add("ArgumentError", py.get_type::<ArgumentError>())?;
+    module.add("ParseError", py.get_type::<ParseError>())?;
+    module.add("UnsolvableError", py.get_type::<UnsolvableError>())?;
+    module.add("TimeoutError", py.get_type::<TimeoutError>())?;
+    module.add("GenerateError", py.get_type::<GenerateError>())?;
+    Ok(())
+}
@@ -0,0 +1,278 @@
+use std::fmt::Write;
+use sudoku::{square_name, SquareId, SquareValue, StartState};
+use variant::{Direction, KropkiColor, Line, Variant};
+
+const CELL : usize = 40;
+const MARGIN : usize = 40;
+
+fn index(square : &SquareId) -> Option<(usize, usize)> {
+    let r = (square.0 as u32).wrapping_sub('A' as u32) as usize;
+    let c = (square.1 as u32).wrapping_sub('1' as u32) as usize;
+    if r < 9 && c < 9 { Some((r, c)) } else { None }
+}
+
+// Digits to show, givens first and then the solution where it fills the rest.
+fn digits(variant : &Variant, solution : Option<&StartState>) -> [[(SquareValue, bool); 9]; 9] {
+    let mut digits = [[(0, false); 9]; 9];
+    for &(s, v) in solution.into_iter().flat_map(|s| s.iter()) {
+        if let Some((r, c)) = index(&s) {
+            digits[r][c] = (v, false);
+        }
+    }
+    for &(s, v) in variant.puzzle.start_state().iter().filter(|&&(_, v)| v != 0) {
+        if let Some((r, c)) = index(&s) {
+            digits[r][c] = (v, true);
+        }
+    }
+    digits
+}
+
+// One character per square, '.' where `f` has nothing to say.
+fn map<F : Fn(SquareId) -> Option<char>>(f : F) -> [[char; 9]; 9] {
+    let mut map = [['.'; 9]; 9];
+    for (r, row) in map.iter_mut().enumerate() {
+        for (c, square) in row.iter_mut().enumerate() {
+            if let Some(ch) = f(((b'A' + r as u8) as char, (b'1' + c as u8) as char)) {
+                *square = ch;
+            }
+        }
+    }
+    map
+}
+
+fn label(i : usize) -> char {
+    let labels = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    labels[i % labels.len()] as char
+}
+
+fn panel_row(cells : &[char; 9]) -> String {
+    let mut row = String::new();
+    for (c, ch) in cells.iter().enumerate() {
+        if c > 0 {
+            row.push_str(if c % 3 == 0 { " | " } else { " " });
+        }
+        row.push(*ch);
+    }
+    row
+}
+
+fn names(squares : &[SquareId]) -> String {
+    squares.iter().map(square_name).collect::<Vec<String>>().join(" ")
+}
+
+// Terminal picture of a variant: maps beside the grid, a letter per cage and per extra region and
+// marks for diagonals and parity, then the remaining clues listed below it.
+pub fn text(variant : &Variant, solution : Option<&StartState>) -> String {
+    let digits = digits(variant, solution);
+    let mut panels : Vec<(&str, [[char; 9]; 9])> = Vec::new();
+    panels.push(("grid", map(|s| index(&s).and_then(|(r, c)| std::char::from_digit(digits[r][c].0, 10)).filter(|&ch| ch != '0'))));
+    if ! variant.cages.is_empty() {
+        panels.push(("cages", map(|s| variant.cages.iter().position(|cage| cage.cells.contains(&s)).map(label))));
+    }
+    if ! variant.regions.is_empty() {
+        panels.push(("regions", map(|s| variant.regions.iter().position(|region| region.contains(&s)).map(label))));
+    }
+    if variant.diagonal || variant.anti_diagonal || ! variant.even.is_empty() || ! variant.odd.is_empty() {
+        panels.push(("marks", map(|s| {
+            let (r, c) = index(&s)?;
+            if variant.even.contains(&s) {
+                Some('e')
+            } else if variant.odd.contains(&s) {
+                Some('o')
+            } else {
+                match (variant.diagonal && r == c, variant.anti_diagonal && r + c == 8) {
+                    (true, true) => Some('X'),
+                    (true, false) => Some('\\'),
+                    (false, true) => Some('/'),
+                    _ => None
+                }
+            }
+        })));
+    }
+    let width = panel_row(&['.'; 9]).len();
+    let mut out = String::new();
+    let headers : Vec<String> = panels.iter().map(|&(name, _)| format!("{:<width$}", name, width = width)).collect();
+    writeln!(out, "{}", headers.join("    ").trim_end()).unwrap();
+    for r in 0..9 {
+        if r > 0 && r % 3 == 0 {
+            let rule = "------+-------+------";
+            writeln!(out, "{}", panels.iter().map(|_| rule).collect::<Vec<&str>>().join("    ")).unwrap();
+        }
+        writeln!(out, "{}", panels.iter().map(|(_, cells)| panel_row(&cells[r])).collect::<Vec<String>>().join("    ")).unwrap();
+    }
+    if ! variant.cages.is_empty() {
+        let sums : Vec<String> = variant.cages.iter().enumerate()
+                                        .map(|(i, cage)| format!("{}={}", label(i), cage.sum.map_or("?".to_string(), |s| s.to_string())))
+                                        .collect();
+        writeln!(out, "cage sums: {}", sums.join(" ")).unwrap();
+    }
+    if variant.anti_knight {
+        writeln!(out, "anti-knight").unwrap();
+    }
+    if variant.disjoint_groups {
+        writeln!(out, "disjoint groups").unwrap();
+    }
+    for dot in &variant.kropki {
+        let color = match dot.color { KropkiColor::White => "white", KropkiColor::Black => "black" };
+        writeln!(out, "{} dot: {} {}", color, square_name(&dot.cells.0), square_name(&dot.cells.1)).unwrap();
+    }
+    for thermo in &variant.thermos {
+        writeln!(out, "thermo: {}", names(thermo)).unwrap();
+    }
+    for arrow in &variant.arrows {
+        writeln!(out, "arrow: {} = {}", square_name(&arrow.circle), names(&arrow.cells)).unwrap();
+    }
+    for (a, b) in &variant.clones {
+        writeln!(out, "clone: {} = {}", names(a), names(b)).unwrap();
+    }
+    for line in &variant.palindromes {
+        writeln!(out, "palindrome: {}", names(line)).unwrap();
+    }
+    for clue in &variant.little_killers {
+        writeln!(out, "little killer {}: {} {}", clue.sum, square_name(&clue.from), clue.direction.name()).unwrap();
+    }
+    for clue in &variant.sandwiches {
+        match clue.line {
+            Line::Row(row) => writeln!(out, "sandwich row {}: {}", row, clue.sum).unwrap(),
+            Line::Column(col) => writeln!(out, "sandwich column {}: {}", col, clue.sum).unwrap()
+        }
+    }
+    out
+}
+
+fn center(r : usize, c : usize) -> (usize, usize) {
+    (MARGIN + c * CELL + CELL / 2, MARGIN + r * CELL + CELL / 2)
+}
+
+fn polyline(out : &mut String, squares : &[SquareId], style : &str) {
+    let points : Vec<String> = squares.iter().filter_map(index).map(|(r, c)| {
+        let (x, y) = center(r, c);
+        format!("{},{}", x, y)
+    }).collect();
+    writeln!(out, r#"<polyline points="{}" fill="none" {}/>"#, points.join(" "), style).unwrap();
+}
+
+// The same as an SVG drawing, with every clue in place: shaded regions, dashed cage outlines with
+// their sums, lines for thermometers, arrows and palindromes, dots, and outside clues in the margin.
+pub fn svg(variant : &Variant, solution : Option<&StartState>) -> String {
+    let size = 9 * CELL + 2 * MARGIN;
+    let grid = 9 * CELL;
+    let mut out = String::new();
+    writeln!(out, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}" viewBox="0 0 {0} {0}" font-family="sans-serif">"#, size).unwrap();
+    writeln!(out, r#"<rect width="{0}" height="{0}" fill="white"/>"#, size).unwrap();
+    let shades = ["#e6e6e6", "#d6e6f5", "#f5e6d6", "#e0f0d8"];
+    for (i, region) in variant.regions.iter().enumerate() {
+        for (r, c) in region.iter().filter_map(index) {
+            writeln!(out, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                     MARGIN + c * CELL, MARGIN + r * CELL, CELL, CELL, shades[i % shades.len()]).unwrap();
+        }
+    }
+    for (a, b) in &variant.clones {
+        for (r, c) in a.iter().chain(b.iter()).filter_map(index) {
+            writeln!(out, r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#fbe3c0"/>"##,
+                     MARGIN + c * CELL, MARGIN + r * CELL, CELL, CELL).unwrap();
+        }
+    }
+    for (r, c) in variant.even.iter().filter_map(index) {
+        writeln!(out, r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#cccccc"/>"##,
+                 MARGIN + c * CELL + 6, MARGIN + r * CELL + 6, CELL - 12, CELL - 12).unwrap();
+    }
+    for (r, c) in variant.odd.iter().filter_map(index) {
+        let (x, y) = center(r, c);
+        writeln!(out, r##"<circle cx="{}" cy="{}" r="{}" fill="#cccccc"/>"##, x, y, CELL / 2 - 6).unwrap();
+    }
+    if variant.diagonal {
+        writeln!(out, r##"<line x1="{0}" y1="{0}" x2="{1}" y2="{1}" stroke="#8080c0" stroke-width="1.5"/>"##, MARGIN, MARGIN + grid).unwrap();
+    }
+    if variant.anti_diagonal {
+        writeln!(out, r##"<line x1="{1}" y1="{0}" x2="{0}" y2="{1}" stroke="#8080c0" stroke-width="1.5"/>"##, MARGIN, MARGIN + grid).unwrap();
+    }
+    for thermo in &variant.thermos {
+        polyline(&mut out, thermo, r##"stroke="#c8c8c8" stroke-width="12" stroke-linecap="round" stroke-linejoin="round""##);
+        if let Some((r, c)) = thermo.first().and_then(index) {
+            let (x, y) = center(r, c);
+            writeln!(out, r##"<circle cx="{}" cy="{}" r="{}" fill="#c8c8c8"/>"##, x, y, CELL / 2 - 4).unwrap();
+        }
+    }
+    for line in &variant.palindromes {
+        polyline(&mut out, line, r##"stroke="#c8a0e0" stroke-width="8" stroke-linecap="round" stroke-linejoin="round""##);
+    }
+    for arrow in &variant.arrows {
+        let mut line = vec![arrow.circle];
+        line.extend(arrow.cells.iter().cloned());
+        polyline(&mut out, &line, r##"stroke="#909090" stroke-width="2""##);
+        if let Some((r, c)) = index(&arrow.circle) {
+            let (x, y) = center(r, c);
+            writeln!(out, r##"<circle cx="{}" cy="{}" r="{}" fill="white" stroke="#909090" stroke-width="2"/>"##, x, y, CELL / 2 - 4).unwrap();
+        }
+    }
+    for i in 0..10 {
+        let width = if i % 3 == 0 { 3 } else { 1 };
+        let p = MARGIN + i * CELL;
+        writeln!(out, r#"<line x1="{0}" y1="{1}" x2="{0}" y2="{2}" stroke="black" stroke-width="{3}"/>"#, p, MARGIN, MARGIN + grid, width).unwrap();
+        writeln!(out, r#"<line x1="{1}" y1="{0}" x2="{2}" y2="{0}" stroke="black" stroke-width="{3}"/>"#, p, MARGIN, MARGIN + grid, width).unwrap();
+    }
+    for cage in &variant.cages {
+        let cells : Vec<(usize, usize)> = cage.cells.iter().filter_map(index).collect();
+        let inset = 4;
+        for &(r, c) in &cells {
+            let (x0, y0) = (MARGIN + c * CELL + inset, MARGIN + r * CELL + inset);
+            let (x1, y1) = (MARGIN + (c + 1) * CELL - inset, MARGIN + (r + 1) * CELL - inset);
+            let open = |dr : i32, dc : i32| ! cells.contains(&((r as i32 + dr) as usize, (c as i32 + dc) as usize));
+            let sides = [(open(-1, 0), (x0, y0, x1, y0)), (open(1, 0), (x0, y1, x1, y1)),
+                         (open(0, -1), (x0, y0, x0, y1)), (open(0, 1), (x1, y0, x1, y1))];
+            for &(draw, (xa, ya, xb, yb)) in sides.iter() {
+                if draw {
+                    writeln!(out, r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black" stroke-width="1" stroke-dasharray="3,3"/>"#,
+                             xa, ya, xb, yb).unwrap();
+                }
+            }
+        }
+        // the sum goes in the top left square of the cage
+        if let (Some(sum), Some(&(r, c))) = (cage.sum, cells.iter().min()) {
+            writeln!(out, r#"<text x="{}" y="{}" font-size="10">{}</text>"#, MARGIN + c * CELL + 6, MARGIN + r * CELL + 14, sum).unwrap();
+        }
+    }
+    for dot in &variant.kropki {
+        if let (Some((r1, c1)), Some((r2, c2))) = (index(&dot.cells.0), index(&dot.cells.1)) {
+            let ((x1, y1), (x2, y2)) = (center(r1, c1), center(r2, c2));
+            let fill = match dot.color { KropkiColor::White => "white", KropkiColor::Black => "black" };
+            writeln!(out, r#"<circle cx="{}" cy="{}" r="5" fill="{}" stroke="black"/>"#, (x1 + x2) / 2, (y1 + y2) / 2, fill).unwrap();
+        }
+    }
+    for clue in &variant.sandwiches {
+        let (x, y) = match clue.line {
+            Line::Row(row) => (MARGIN / 2, center((row as u32).wrapping_sub('A' as u32) as usize, 0).1),
+            Line::Column(col) => (center(0, (col as u32).wrapping_sub('1' as u32) as usize).0, MARGIN / 2)
+        };
+        writeln!(out, r#"<text x="{}" y="{}" font-size="14" text-anchor="middle" dominant-baseline="middle">{}</text>"#, x, y, clue.sum).unwrap();
+    }
+    for clue in &variant.little_killers {
+        if let Some((r, c)) = index(&clue.from) {
+            // one step back along the diagonal lands in the margin
+            let (dr, dc, arrow) = match clue.direction {
+                Direction::DownRight => (1, 1, "\u{2198}"),
+                Direction::DownLeft => (1, -1, "\u{2199}"),
+                Direction::UpRight => (-1, 1, "\u{2197}"),
+                Direction::UpLeft => (-1, -1, "\u{2196}")
+            };
+            let x = (MARGIN + c * CELL + CELL / 2) as i32 - dc * CELL as i32;
+            let y = (MARGIN + r * CELL + CELL / 2) as i32 - dr * CELL as i32;
+            writeln!(out, r#"<text x="{}" y="{}" font-size="12" text-anchor="middle" dominant-baseline="middle">{}{}</text>"#,
+                     x, y, clue.sum, arrow).unwrap();
+        }
+    }
+    let digits = digits(variant, solution);
+    for (r, row) in digits.iter().enumerate() {
+        for (c, &(v, given)) in row.iter().enumerate() {
+            if v != 0 {
+                let (x, y) = center(r, c);
+                let style = if given { r#"font-weight="bold" fill="black""# } else { r##"fill="#2060c0""## };
+                writeln!(out, r#"<text x="{}" y="{}" font-size="22" text-anchor="middle" dominant-baseline="central" {}>{}</text>"#,
+                         x, y, style, v).unwrap();
+            }
+        }
+    }
+    out.push_str("</svg>\n");
+    out
+}
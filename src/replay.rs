@@ -0,0 +1,319 @@
+use std;
+use std::io::{self, Write};
+use std::str::FromStr;
+use game::Game;
+use logic::{SolveStep, Technique};
+use puzzle::Puzzle;
+use sudoku::{parse_square, square_name, Digit, SquareId, SquareValue, StartValue};
+
+// A recorded game or solver run: the puzzle and what happened to it, with the time of each event
+// in milliseconds from the start. Written one line per event:
+//
+//   replay 1
+//   puzzle <81 character grid>
+//   author Jane Doe                       (optional, also source, license and created)
+//   1200 place B3 5
+//   1900 erase B3
+//   2500 mark C4 7                        (toggles the pencil mark)
+//   2600 undo
+//   2700 redo
+//   3000 hint                             (a hint that entered a digit)
+//   3100 step hidden_single B3 B3=5 C4=2  (technique, cells, placements, eliminations; '-' for none)
+//
+// Player games use the first six kinds and can be played back on a `Game`; solver traces are
+// made of steps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub puzzle : Puzzle,
+    pub events : Vec<Event>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub at : u64,
+    pub action : Action
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Place(SquareId, SquareValue),
+    Erase(SquareId),
+    Mark(SquareId, SquareValue),
+    Undo,
+    Redo,
+    Hint,
+    Step(SolveStep)
+}
+
+// The format version on the first line; readers take any version up to it.
+pub const VERSION : u32 = 1;
+
+impl Replay {
+
+    pub fn new(puzzle : Puzzle) -> Replay {
+        Replay {
+            puzzle,
+            events : Vec::new()
+        }
+    }
+
+    // A solver trace, one event per step; steps carry no timing so they are numbered instead.
+    pub fn from_path(puzzle : Puzzle, path : &[SolveStep]) -> Replay {
+        Replay {
+            puzzle,
+            events : path.iter().enumerate().map(|(i, step)| Event { at : i as u64, action : Action::Step(step.clone()) }).collect()
+        }
+    }
+
+    pub fn push(&mut self, at : u64, action : Action) {
+        self.events.push(Event {
+            at,
+            action
+        });
+    }
+
+    // The steps of a solver trace, in order.
+    pub fn steps(&self) -> Vec<SolveStep> {
+        self.events.iter().filter_map(|e| match e.action {
+            Action::Step(ref step) => Some(step.clone()),
+            _ => None
+        }).collect()
+    }
+
+    // Plays the player events back on a game of the same puzzle, advancing its clock to each
+    // event. Steps are skipped; the first move the game refuses ends the playback.
+    pub fn play(&self, game : &mut Game) -> Result<(), String> {
+        let mut clock = 0;
+        for (i, event) in self.events.iter().enumerate() {
+            game.add_elapsed(event.at.saturating_sub(clock));
+            clock = std::cmp::max(clock, event.at);
+            let result = match event.action {
+                Action::Place(s, v) => game.place(&s, v),
+                Action::Erase(s) => game.erase(&s),
+                Action::Mark(s, v) => game.toggle_mark(&s, v),
+                Action::Undo => {
+                    game.undo();
+                    Ok(())
+                },
+                Action::Redo => {
+                    game.redo();
+                    Ok(())
+                },
+                Action::Hint => {
+                    game.hint();
+                    Ok(())
+                },
+                Action::Step(_) => Ok(())
+            };
+            result.map_err(|e| format!("event {}: {}", i + 1, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn parse(text : &str) -> Result<Replay, String> {
+        let mut lines = text.lines().enumerate().filter(|&(_, line)| ! line.trim().is_empty());
+        let version = match lines.next().map(|(_, line)| split(line.trim())) {
+            Some(("replay", version)) => version.parse::<u32>().map_err(|_| format!("Invalid replay version {}", version))?,
+            _ => return Err("Expected replay and its version on the first line".to_string())
+        };
+        if version == 0 || version > VERSION {
+            return Err(format!("Replay version {} is not one this build reads, 1 to {}", version, VERSION));
+        }
+        let mut replay = match lines.next() {
+            Some((_, line)) if line.starts_with("puzzle ") => Replay::new(line["puzzle ".len()..].trim().parse()?),
+            _ => return Err("Expected the puzzle on the second line".to_string())
+        };
+        for (i, line) in lines {
+            let line = line.trim();
+            let error = |message : String| format!("line {}: {}", i + 1, message);
+            let (first, rest) = split(line);
+            match u64::from_str(first) {
+                Ok(at) => {
+                    let action = parse_action(rest).map_err(&error)?;
+                    replay.push(at, action);
+                },
+                Err(_) if replay.events.is_empty() => replay.puzzle.provenance_mut().set(first, rest).map_err(&error)?,
+                Err(_) => return Err(error(format!("Expected a time, got {}", first)))
+            }
+        }
+        Ok(replay)
+    }
+
+    pub fn write<W : Write>(&self, output : W) -> io::Result<()> {
+        let mut writer = ReplayWriter::new(output, &self.puzzle)?;
+        for event in &self.events {
+            writer.record(event.at, &event.action)?;
+        }
+        Ok(())
+    }
+}
+
+// Writes a replay as it is recorded, so a game or a long solver run can be captured without
+// keeping the events in memory.
+pub struct ReplayWriter<W : Write> {
+    output : W
+}
+
+impl<W : Write> ReplayWriter<W> {
+
+    pub fn new(mut output : W, puzzle : &Puzzle) -> io::Result<ReplayWriter<W>> {
+        writeln!(output, "replay {}", VERSION)?;
+        writeln!(output, "puzzle {}", puzzle.to_grid_string())?;
+        for (key, value) in puzzle.provenance().fields() {
+            writeln!(output, "{} {}", key, value)?;
+        }
+        Ok(ReplayWriter {
+            output
+        })
+    }
+
+    pub fn record(&mut self, at : u64, action : &Action) -> io::Result<()> {
+        writeln!(self.output, "{} {}", at, action)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+fn split(line : &str) -> (&str, &str) {
+    match line.find(' ') {
+        Some(i) => (&line[..i], line[i + 1..].trim()),
+        None => (line, "")
+    }
+}
+
+fn square(name : &str) -> Result<SquareId, String> {
+    parse_square(name).ok_or_else(|| format!("Invalid square {}", name))
+}
+
+fn digit(text : &str) -> Result<SquareValue, String> {
+    text.parse().ok().and_then(Digit::new).map(SquareValue::from).ok_or_else(|| format!("Invalid digit {}", text))
+}
+
+fn list<T, F : Fn(&str) -> Result<T, String>>(text : &str, item : F) -> Result<Vec<T>, String> {
+    if text == "-" {
+        return Ok(Vec::new());
+    }
+    text.split(',').map(item).collect()
+}
+
+fn value(text : &str) -> Result<StartValue, String> {
+    match text.find('=') {
+        Some(i) => Ok((square(&text[..i])?, digit(&text[i + 1..])?)),
+        None => Err(format!("Expected square=digit, got {}", text))
+    }
+}
+
+fn parse_action(text : &str) -> Result<Action, String> {
+    let fields : Vec<&str> = text.split_whitespace().collect();
+    match (fields.first().cloned().unwrap_or(""), fields.len()) {
+        ("place", 3) => Ok(Action::Place(square(fields[1])?, digit(fields[2])?)),
+        ("erase", 2) => Ok(Action::Erase(square(fields[1])?)),
+        ("mark", 3) => Ok(Action::Mark(square(fields[1])?, digit(fields[2])?)),
+        ("undo", 1) => Ok(Action::Undo),
+        ("redo", 1) => Ok(Action::Redo),
+        ("hint", 1) => Ok(Action::Hint),
+        ("step", 5) => {
+            let technique = Technique::from_id(fields[1]).ok_or_else(|| format!("Unknown technique {}", fields[1]))?;
+            Ok(Action::Step(SolveStep {
+                technique,
+                cells : list(fields[2], square)?,
+                placements : list(fields[3], value)?,
+                eliminations : list(fields[4], value)?
+            }))
+        },
+        _ => Err(format!("Invalid event {}", text))
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fn list<T, F : Fn(&T) -> String>(items : &[T], item : F) -> String {
+            if items.is_empty() {
+                "-".to_string()
+            } else {
+                items.iter().map(item).collect::<Vec<String>>().join(",")
+            }
+        }
+        let value = |&(s, v) : &StartValue| format!("{}={}", square_name(&s), v);
+        match *self {
+            Action::Place(s, v) => write!(f, "place {} {}", square_name(&s), v),
+            Action::Erase(s) => write!(f, "erase {}", square_name(&s)),
+            Action::Mark(s, v) => write!(f, "mark {} {}", square_name(&s), v),
+            Action::Undo => write!(f, "undo"),
+            Action::Redo => write!(f, "redo"),
+            Action::Hint => write!(f, "hint"),
+            Action::Step(ref step) => write!(f, "step {} {} {} {}", step.technique.id(), list(&step.cells, square_name),
+                                             list(&step.placements, value), list(&step.eliminations, value))
+        }
+    }
+}
+
+impl std::fmt::Display for Replay {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut bytes = Vec::new();
+        self.write(&mut bytes).map_err(|_| std::fmt::Error)?;
+        write!(f, "{}", String::from_utf8_lossy(&bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sudoku::Solver;
+
+    fn text() -> String {
+        let grid : String = SOLVED.char_indices().map(|(i, c)| if i % 10 == 0 { '.' } else { c }).collect();
+        format!("replay 1\npuzzle {}\nauthor Jane Doe\n1200 place A1 5\n1900 erase A1\n2500 mark B2 7\n2600 undo\n2700 redo\n\
+                 3000 hint\n3100 step hidden_single B2 B2=7 -\n", grid)
+    }
+
+    #[test]
+    fn replays_read_back_what_they_write_and_play_on_a_game() {
+        let replay = Replay::parse(&text()).unwrap();
+        assert_eq!(replay.puzzle.provenance().author.as_deref(), Some("Jane Doe"));
+        assert_eq!(replay.events.len(), 7);
+        assert_eq!(replay.events[0], Event { at : 1200, action : Action::Place(('A', '1'), 5) });
+        assert_eq!(replay.steps(), vec![SolveStep {
+            technique : Technique::HiddenSingle,
+            cells : vec![('B', '2')],
+            placements : vec![(('B', '2'), 7)],
+            eliminations : Vec::new()
+        }]);
+        assert_eq!(replay.to_string(), text());
+
+        let solver = Solver::new();
+        let mut game = Game::new(&solver, replay.puzzle.start_state().clone()).unwrap();
+        replay.play(&mut game).unwrap();
+        // A1 was erased, then filled again by the hint
+        assert_eq!((game.value(&('A', '1')), game.marks(&('B', '2')), game.elapsed()), (Some(5), vec![7], 3100));
+        assert_eq!(game.hints_used(), 1);
+
+        // a move the game refuses ends the playback
+        let mut refused = Replay::new(replay.puzzle.clone());
+        refused.push(0, Action::Place(('A', '2'), 1));
+        let mut game = Game::new(&solver, replay.puzzle.start_state().clone()).unwrap();
+        assert!(refused.play(&mut game).unwrap_err().starts_with("event 1: "));
+    }
+
+    #[test]
+    fn every_bad_line_is_reported() {
+        let error = |from : &str, to : &str| Replay::parse(&text().replacen(from, to, 1)).err();
+        assert_eq!(error("replay 1", "game 1"), Some("Expected replay and its version on the first line".to_string()));
+        assert_eq!(error("replay 1", "replay one"), Some("Invalid replay version one".to_string()));
+        assert_eq!(error("replay 1", "replay 2"), Some("Replay version 2 is not one this build reads, 1 to 1".to_string()));
+        assert_eq!(error("puzzle ", "grid "), Some("Expected the puzzle on the second line".to_string()));
+        assert_eq!(error("author", "artist"), Some("line 3: Unknown provenance key artist".to_string()));
+        assert_eq!(error("1900 erase", "1900 wipe"), Some("line 5: Invalid event wipe A1".to_string()));
+        assert_eq!(error("1900 erase A1", "1900 erase A1 5"), Some("line 5: Invalid event erase A1 5".to_string()));
+        assert_eq!(error("place A1", "place J1"), Some("line 4: Invalid square J1".to_string()));
+        assert_eq!(error("A1 5", "A1 0"), Some("line 4: Invalid digit 0".to_string()));
+        assert_eq!(error("hidden_single", "swordfish"), Some("line 10: Unknown technique swordfish".to_string()));
+        assert_eq!(error("B2=7", "B2:7"), Some("line 10: Expected square=digit, got B2:7".to_string()));
+        // provenance only comes before the events
+        assert_eq!(error("2600 undo", "source Weekly"), Some("line 7: Expected a time, got source".to_string()));
+    }
+
+    const SOLVED : &str = "534678912672195348198342567859761423426853791713924856961537284287419635345286179";
+}
@@ -0,0 +1,148 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use backend::{self, is_valid_solution};
+use json::Json;
+use puzzle::Puzzle;
+use sudoku::Config;
+
+// Pathological inputs shipped with the crate, see data/stress.txt.
+const CORPUS : &str = include_str!("../data/stress.txt");
+
+pub struct StressPuzzle {
+    pub name : String,
+    pub puzzle : Puzzle,
+    pub solvable : bool
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    // a solution that isn't one, a verdict on solvability that is wrong, or a second solve of
+    // the same puzzle coming out different
+    Wrong,
+    TimedOut
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Outcome::Passed => "passed",
+            Outcome::Wrong => "wrong",
+            Outcome::TimedOut => "timed out"
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StressResult {
+    pub puzzle : String,
+    pub backend : String,
+    pub outcome : Outcome,
+    // the timeout for puzzles that ran out of time
+    pub elapsed_ns : u64
+}
+
+impl StressResult {
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![("puzzle", Json::string(&self.puzzle)),
+                          ("backend", Json::string(&self.backend)),
+                          ("outcome", Json::string(self.outcome.name())),
+                          ("elapsed_ns", Json::Number(self.elapsed_ns as f64))])
+    }
+}
+
+// Stress puzzles in the format of data/stress.txt: `<grid> <name> <solvable|unsolvable>` per
+// line, '#' for comments.
+pub fn parse_corpus(text : &str) -> Result<Vec<StressPuzzle>, String> {
+    let mut corpus = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields : Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 3 {
+            return Err(format!("line {}: expected a grid, a name and solvable or unsolvable", i + 1));
+        }
+        let puzzle : Puzzle = fields[0].parse().map_err(|e| format!("line {}: {}", i + 1, e))?;
+        let solvable = match fields[2] {
+            "solvable" => true,
+            "unsolvable" => false,
+            other => return Err(format!("line {}: expected solvable or unsolvable, got {}", i + 1, other))
+        };
+        corpus.push(StressPuzzle {
+            name : fields[1].to_string(),
+            puzzle,
+            solvable
+        });
+    }
+    Ok(corpus)
+}
+
+pub fn corpus() -> Vec<StressPuzzle> {
+    // the shipped file is well formed, `stress` runs it whole
+    parse_corpus(CORPUS).unwrap_or_default()
+}
+
+// Runs every backend on every puzzle, each solve on a worker thread given `timeout` for solving it
+// twice; the time is the first solve's. A timed out search can't be stopped and is abandoned,
+// still running, so later timings in the same run may be slower than alone.
+pub fn run(backends : &[&str], corpus : &[StressPuzzle], timeout : Duration) -> Result<Vec<StressResult>, String> {
+    if let Some(name) = backends.iter().find(|&&name| backend::backend(name).is_none()) {
+        return Err(format!("Unknown backend {}", name));
+    }
+    let mut results = Vec::new();
+    for &name in backends {
+        for entry in corpus {
+            let start_state = entry.puzzle.start_state().clone();
+            let backend_name = name.to_string();
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                let backend = backend::backend(&backend_name).unwrap();
+                let start = Instant::now();
+                let report = backend.solve(&start_state);
+                let elapsed_ns = nanos(start.elapsed());
+                // solves are pure, the same input has to give the same answer the same way
+                let again = backend.solve(&start_state);
+                let repeated = again.solution == report.solution && again.guesses == report.guesses;
+                let _ = sender.send((report.solution, repeated, elapsed_ns));
+            });
+            let (outcome, elapsed_ns) = match receiver.recv_timeout(timeout) {
+                Ok((solution, repeated, elapsed_ns)) => {
+                    let correct = match solution {
+                        Some(ref solution) => entry.solvable && is_valid_solution(&Config::new(), entry.puzzle.start_state(), solution),
+                        None => ! entry.solvable
+                    };
+                    (if correct && repeated { Outcome::Passed } else { Outcome::Wrong }, elapsed_ns)
+                },
+                Err(_) => (Outcome::TimedOut, nanos(timeout))
+            };
+            results.push(StressResult {
+                puzzle : entry.name.clone(),
+                backend : name.to_string(),
+                outcome,
+                elapsed_ns
+            });
+        }
+    }
+    Ok(results)
+}
+
+// Runs the shipped corpus.
+pub fn stress(backends : &[&str], timeout : Duration) -> Result<Vec<StressResult>, String> {
+    run(backends, &parse_corpus(CORPUS)?, timeout)
+}
+
+pub fn format_table(results : &[StressResult]) -> String {
+    let mut table = format!("{:<20} {:<10} {:<10} {:>12}\n", "puzzle", "backend", "outcome", "time (s)");
+    for result in results {
+        table.push_str(&format!("{:<20} {:<10} {:<10} {:>12.6}\n", result.puzzle, result.backend, result.outcome.name(),
+                                result.elapsed_ns as f64 / 1e9));
+    }
+    table
+}
+
+fn nanos(elapsed : Duration) -> u64 {
+    elapsed.as_secs() * 1000000000 + elapsed.subsec_nanos() as u64
+}
@@ -1,86 +1,1642 @@
 use std::collections::{HashMap, HashSet};
 use std;
+use std::convert::TryFrom;
+use std::num::NonZeroU8;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+#[cfg(feature = "generator")]
 use rand;
+#[cfg(feature = "generator")]
 use rand::Rng;
+#[cfg(feature = "generator")]
+use analysis::{self, Difficulty};
+#[cfg(feature = "generator")]
+use calibration::{Calibration, TimeModel};
+use cancel::CancelToken;
+use logic::{LogicalSolver, Technique};
+use puzzle::Puzzle;
+use constraint::{Constraint, Progress};
+use grid::Grid9;
+use telemetry;
 
 pub type SquareId = (char, char);
 pub type SquareValue = u32;
 pub type StartValue = (SquareId, SquareValue);
-pub type StartState = Vec<StartValue>;
+pub type Unit = [SquareId; GRID_SIZE];
 
-type SquareValues = HashSet<SquareValue>;
+type SquareValues = HashSet<Digit>;
 type PeerSet = HashSet<SquareId>;
-type Unit = [SquareId; 9];
 
+const STATE_BYTES : usize = 1 + 92 + 11;
+
+// Version of the `State::to_bytes` layout, in the high nibble of the flag byte. States from
+// before it was stamped have 0 there and the same layout.
+pub const STATE_VERSION : u8 = 1;
+
+// Side of a box and of the grid, and the number of squares. The grid is always the classic 9x9;
+// code sized by these rather than by literal numbers keeps working if that changes.
+pub const BOX_SIZE : usize = 3;
+pub const GRID_SIZE : usize = BOX_SIZE * BOX_SIZE;
+pub const SQUARE_COUNT : usize = GRID_SIZE * GRID_SIZE;
+
+// Every square in A1..I9 order, the order of grid strings.
+pub const ALL_SQUARES : [SquareId; SQUARE_COUNT] =
+    [('A', '1'), ('A', '2'), ('A', '3'), ('A', '4'), ('A', '5'), ('A', '6'), ('A', '7'), ('A', '8'), ('A', '9'),
+     ('B', '1'), ('B', '2'), ('B', '3'), ('B', '4'), ('B', '5'), ('B', '6'), ('B', '7'), ('B', '8'), ('B', '9'),
+     ('C', '1'), ('C', '2'), ('C', '3'), ('C', '4'), ('C', '5'), ('C', '6'), ('C', '7'), ('C', '8'), ('C', '9'),
+     ('D', '1'), ('D', '2'), ('D', '3'), ('D', '4'), ('D', '5'), ('D', '6'), ('D', '7'), ('D', '8'), ('D', '9'),
+     ('E', '1'), ('E', '2'), ('E', '3'), ('E', '4'), ('E', '5'), ('E', '6'), ('E', '7'), ('E', '8'), ('E', '9'),
+     ('F', '1'), ('F', '2'), ('F', '3'), ('F', '4'), ('F', '5'), ('F', '6'), ('F', '7'), ('F', '8'), ('F', '9'),
+     ('G', '1'), ('G', '2'), ('G', '3'), ('G', '4'), ('G', '5'), ('G', '6'), ('G', '7'), ('G', '8'), ('G', '9'),
+     ('H', '1'), ('H', '2'), ('H', '3'), ('H', '4'), ('H', '5'), ('H', '6'), ('H', '7'), ('H', '8'), ('H', '9'),
+     ('I', '1'), ('I', '2'), ('I', '3'), ('I', '4'), ('I', '5'), ('I', '6'), ('I', '7'), ('I', '8'), ('I', '9')];
+
+// Rows A to I, columns 1 to 9 and boxes left to right, top to bottom. `Config::new` is built from
+// these, so they are its `rows`, `cols` and `boxes`.
+pub const ROWS : [Unit; GRID_SIZE] =
+    [[('A', '1'), ('A', '2'), ('A', '3'), ('A', '4'), ('A', '5'), ('A', '6'), ('A', '7'), ('A', '8'), ('A', '9')],
+     [('B', '1'), ('B', '2'), ('B', '3'), ('B', '4'), ('B', '5'), ('B', '6'), ('B', '7'), ('B', '8'), ('B', '9')],
+     [('C', '1'), ('C', '2'), ('C', '3'), ('C', '4'), ('C', '5'), ('C', '6'), ('C', '7'), ('C', '8'), ('C', '9')],
+     [('D', '1'), ('D', '2'), ('D', '3'), ('D', '4'), ('D', '5'), ('D', '6'), ('D', '7'), ('D', '8'), ('D', '9')],
+     [('E', '1'), ('E', '2'), ('E', '3'), ('E', '4'), ('E', '5'), ('E', '6'), ('E', '7'), ('E', '8'), ('E', '9')],
+     [('F', '1'), ('F', '2'), ('F', '3'), ('F', '4'), ('F', '5'), ('F', '6'), ('F', '7'), ('F', '8'), ('F', '9')],
+     [('G', '1'), ('G', '2'), ('G', '3'), ('G', '4'), ('G', '5'), ('G', '6'), ('G', '7'), ('G', '8'), ('G', '9')],
+     [('H', '1'), ('H', '2'), ('H', '3'), ('H', '4'), ('H', '5'), ('H', '6'), ('H', '7'), ('H', '8'), ('H', '9')],
+     [('I', '1'), ('I', '2'), ('I', '3'), ('I', '4'), ('I', '5'), ('I', '6'), ('I', '7'), ('I', '8'), ('I', '9')]];
+
+pub const COLS : [Unit; GRID_SIZE] =
+    [[('A', '1'), ('B', '1'), ('C', '1'), ('D', '1'), ('E', '1'), ('F', '1'), ('G', '1'), ('H', '1'), ('I', '1')],
+     [('A', '2'), ('B', '2'), ('C', '2'), ('D', '2'), ('E', '2'), ('F', '2'), ('G', '2'), ('H', '2'), ('I', '2')],
+     [('A', '3'), ('B', '3'), ('C', '3'), ('D', '3'), ('E', '3'), ('F', '3'), ('G', '3'), ('H', '3'), ('I', '3')],
+     [('A', '4'), ('B', '4'), ('C', '4'), ('D', '4'), ('E', '4'), ('F', '4'), ('G', '4'), ('H', '4'), ('I', '4')],
+     [('A', '5'), ('B', '5'), ('C', '5'), ('D', '5'), ('E', '5'), ('F', '5'), ('G', '5'), ('H', '5'), ('I', '5')],
+     [('A', '6'), ('B', '6'), ('C', '6'), ('D', '6'), ('E', '6'), ('F', '6'), ('G', '6'), ('H', '6'), ('I', '6')],
+     [('A', '7'), ('B', '7'), ('C', '7'), ('D', '7'), ('E', '7'), ('F', '7'), ('G', '7'), ('H', '7'), ('I', '7')],
+     [('A', '8'), ('B', '8'), ('C', '8'), ('D', '8'), ('E', '8'), ('F', '8'), ('G', '8'), ('H', '8'), ('I', '8')],
+     [('A', '9'), ('B', '9'), ('C', '9'), ('D', '9'), ('E', '9'), ('F', '9'), ('G', '9'), ('H', '9'), ('I', '9')]];
+
+pub const BOXES : [Unit; GRID_SIZE] =
+    [[('A', '1'), ('A', '2'), ('A', '3'), ('B', '1'), ('B', '2'), ('B', '3'), ('C', '1'), ('C', '2'), ('C', '3')],
+     [('A', '4'), ('A', '5'), ('A', '6'), ('B', '4'), ('B', '5'), ('B', '6'), ('C', '4'), ('C', '5'), ('C', '6')],
+     [('A', '7'), ('A', '8'), ('A', '9'), ('B', '7'), ('B', '8'), ('B', '9'), ('C', '7'), ('C', '8'), ('C', '9')],
+     [('D', '1'), ('D', '2'), ('D', '3'), ('E', '1'), ('E', '2'), ('E', '3'), ('F', '1'), ('F', '2'), ('F', '3')],
+     [('D', '4'), ('D', '5'), ('D', '6'), ('E', '4'), ('E', '5'), ('E', '6'), ('F', '4'), ('F', '5'), ('F', '6')],
+     [('D', '7'), ('D', '8'), ('D', '9'), ('E', '7'), ('E', '8'), ('E', '9'), ('F', '7'), ('F', '8'), ('F', '9')],
+     [('G', '1'), ('G', '2'), ('G', '3'), ('H', '1'), ('H', '2'), ('H', '3'), ('I', '1'), ('I', '2'), ('I', '3')],
+     [('G', '4'), ('G', '5'), ('G', '6'), ('H', '4'), ('H', '5'), ('H', '6'), ('I', '4'), ('I', '5'), ('I', '6')],
+     [('G', '7'), ('G', '8'), ('G', '9'), ('H', '7'), ('H', '8'), ('H', '9'), ('I', '7'), ('I', '8'), ('I', '9')]];
+
+// A digit 1-9, the only values a square can hold. Candidate sets store these, a byte each, so
+// nothing out of range gets into them; the rest of the API takes and returns `SquareValue`, with
+// 0 for a blank in a start state, and converts at the edge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Digit(NonZeroU8);
+
+impl Digit {
+
+    pub fn new(value : SquareValue) -> Option<Digit> {
+        if value > 9 {
+            return None;
+        }
+        NonZeroU8::new(value as u8).map(Digit)
+    }
+
+    pub fn all() -> Vec<Digit> {
+        (1..10).filter_map(Digit::new).collect()
+    }
+
+    pub fn get(&self) -> SquareValue {
+        self.0.get() as SquareValue
+    }
+}
+
+impl From<Digit> for SquareValue {
+    fn from(digit : Digit) -> SquareValue {
+        digit.get()
+    }
+}
+
+impl TryFrom<SquareValue> for Digit {
+    type Error = String;
+
+    fn try_from(value : SquareValue) -> Result<Digit, String> {
+        Digit::new(value).ok_or_else(|| format!("Invalid digit {}", value))
+    }
+}
+
+impl std::fmt::Display for Digit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// The givens of a puzzle, always in A1..I9 order with at most one entry per square. Building one
+// sorts the entries and keeps the last value given for a repeated square, so two start states
+// for the same puzzle compare and serialize the same whatever order they were written in.
+//
+// Pencilmark puzzles also rule out candidates from the start (`with_eliminations`). Every solver
+// starts from the config's candidates less these and never puts one back; iterating and
+// dereferencing a start state give the givens only.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StartState {
+    values : Vec<StartValue>,
+    eliminated : Vec<StartValue>
+}
+
+impl StartState {
+
+    pub fn new(values : Vec<StartValue>) -> StartState {
+        let mut values = values;
+        // the sort is stable, so repeats keep the order they were given in
+        values.sort_by_key(|&(s, _)| s);
+        let mut unique : Vec<StartValue> = Vec::with_capacity(values.len());
+        for (s, v) in values {
+            if unique.last().is_some_and(|&(s2, _)| s2 == s) {
+                unique.pop();
+            }
+            unique.push((s, v));
+        }
+        StartState {
+            values : unique,
+            eliminated : Vec::new()
+        }
+    }
+
+    // The puzzle with candidates ruled out from the start, in A1..I9 order without repeats.
+    pub fn with_eliminations(self, eliminations : Vec<StartValue>) -> StartState {
+        let mut eliminated = eliminations;
+        eliminated.sort();
+        eliminated.dedup();
+        StartState {
+            eliminated,
+            ..self
+        }
+    }
+
+    pub fn values(&self) -> &[StartValue] {
+        &self.values
+    }
+
+    pub fn eliminations(&self) -> &[StartValue] {
+        &self.eliminated
+    }
+
+    pub fn into_values(self) -> Vec<StartValue> {
+        self.values
+    }
+
+    // Every square belongs to the config and holds one of its digits or 0 for a blank. The solver
+    // checks this before starting, so malformed input is an error rather than a panic.
+    pub fn validate(&self, config : &Config) -> Result<(), StartStateError> {
+        for &(s, v) in &self.values {
+            if ! config.values.contains_key(&s) {
+                return Err(StartStateError::UnknownSquare(s));
+            }
+            if v != 0 && ! Digit::new(v).is_some_and(|d| config.digits.contains(&d)) {
+                return Err(StartStateError::InvalidValue(s, v));
+            }
+        }
+        for &(s, v) in &self.eliminated {
+            if ! config.values.contains_key(&s) {
+                return Err(StartStateError::UnknownSquare(s));
+            }
+            if ! Digit::new(v).is_some_and(|d| config.digits.contains(&d)) {
+                return Err(StartStateError::InvalidValue(s, v));
+            }
+        }
+        Ok(())
+    }
+
+    // Sets a square, replacing any value it had and keeping the order.
+    pub fn insert(&mut self, value : StartValue) {
+        match self.values.binary_search_by_key(&value.0, |&(s, _)| s) {
+            Ok(i) => self.values[i] = value,
+            Err(i) => self.values.insert(i, value)
+        }
+    }
+
+    pub fn remove(&mut self, index : usize) -> StartValue {
+        self.values.remove(index)
+    }
+}
+
+// Why a start state can't be used with a config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StartStateError {
+    UnknownSquare(SquareId),
+    InvalidValue(SquareId, SquareValue)
+}
+
+impl std::fmt::Display for StartStateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            StartStateError::UnknownSquare(s) => write!(f, "Unknown square {}{}", s.0, s.1),
+            StartStateError::InvalidValue(s, v) => write!(f, "Invalid value {} for {}", v, square_name(&s))
+        }
+    }
+}
+
+impl std::ops::Deref for StartState {
+    type Target = [StartValue];
+
+    fn deref(&self) -> &[StartValue] {
+        &self.values
+    }
+}
+
+impl From<Vec<StartValue>> for StartState {
+    fn from(values : Vec<StartValue>) -> StartState {
+        StartState::new(values)
+    }
+}
+
+impl std::iter::FromIterator<StartValue> for StartState {
+    fn from_iter<I : IntoIterator<Item = StartValue>>(iter : I) -> StartState {
+        StartState::new(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for StartState {
+    type Item = StartValue;
+    type IntoIter = std::vec::IntoIter<StartValue>;
+
+    fn into_iter(self) -> std::vec::IntoIter<StartValue> {
+        self.values.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a StartState {
+    type Item = &'a StartValue;
+    type IntoIter = std::slice::Iter<'a, StartValue>;
+
+    fn into_iter(self) -> std::slice::Iter<'a, StartValue> {
+        self.values.iter()
+    }
+}
+
+pub fn square_name(square : &SquareId) -> String {
+    Notation::Chess.name(square)
+}
+
+// How squares are written out. The crate's own formats (start states, replays, JSON) always use
+// `Chess`; `RowColumn` is what most sudoku books and forums use, for text shown to players.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Notation {
+    // row letter and column digit, "B3"
+    Chess,
+    // "r2c3"
+    RowColumn
+}
+
+impl Notation {
+    pub fn name(&self, square : &SquareId) -> String {
+        match *self {
+            Notation::Chess => format!("{}{}", square.0, square.1),
+            Notation::RowColumn => format!("r{}c{}", square.0 as u8 - b'A' + 1, square.1)
+        }
+    }
+}
+
+// Pairs of givens holding the same digit in squares that are peers under `config`, each pair once
+// and in A1..I9 order.
+pub fn conflicting_givens(config : &Config, start_state : &StartState) -> Vec<(StartValue, StartValue)> {
+    let givens : Vec<StartValue> = start_state.iter().cloned().filter(|&(s, v)| v != 0 && config.peers.contains_key(&s)).collect();
+    let mut conflicts = Vec::new();
+    for (i, &(a, v)) in givens.iter().enumerate() {
+        for &(b, w) in &givens[i + 1..] {
+            if v == w && config.peers(&a).contains(&b) {
+                conflicts.push(((a, v), (b, w)));
+            }
+        }
+    }
+    conflicts
+}
+
+// Inverse of `square_name`, None unless the name is a row A-I followed by a column 1-9. Takes
+// r1c1 names as well, in either case.
+pub fn parse_square(name : &str) -> Option<SquareId> {
+    let chars : Vec<char> = name.trim().chars().map(|c| c.to_ascii_uppercase()).collect();
+    let (row, column) = match chars.len() {
+        2 if chars[0] >= 'A' && chars[0] <= 'I' => (chars[0], chars[1]),
+        4 if chars[0] == 'R' && chars[2] == 'C' && chars[1] >= '1' && chars[1] <= '9' => ((chars[1] as u8 - b'1' + b'A') as char, chars[3]),
+        _ => return None
+    };
+    if ('1'..='9').contains(&column) {
+        Some((row, column))
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "generator")]
+#[derive(Clone, Debug)]
+pub struct GenResult {
+    pub puzzle : StartState,
+    // The full grid the clues were dug from.
+    pub solution : StartState,
+    pub difficulty : Difficulty,
+    // Solution grids dug, counting the one this puzzle came from. The hit rate of a run of
+    // generations is the puzzles found over the attempts of all of them, `max_attempts` for each
+    // that missed.
+    pub attempts : usize,
+    // Puzzles rated on the way, or for sparse generation checked for uniqueness.
+    pub rated : usize,
+    pub cost : GenCost,
+    // Why verification turned away puzzles dug before this one, from other solution grids; empty
+    // unless it is on and caught the generator handing out a wrong puzzle.
+    pub rejected : Vec<String>
+}
+
+// Where the time of one generation went. Sparse generation only fills in `total` and `checks`,
+// its search being uniqueness checks throughout.
+#[cfg(feature = "generator")]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GenCost {
+    pub total : Duration,
+    pub uniqueness : Duration,
+    pub rating : Duration,
+    pub checks : usize,
+    // candidates the screen of `UniquenessCheck::Screened` turned away without a full count
+    pub screened_out : usize
+}
+
+#[cfg(feature = "generator")]
+impl GenCost {
+    // Filling solution grids and picking clues, i.e. whatever isn't checking or rating.
+    pub fn digging(&self) -> Duration {
+        self.total.checked_sub(self.uniqueness + self.rating).unwrap_or_default()
+    }
+}
+
+// How generation makes sure a candidate puzzle still has one solution. Every check ends in the
+// same answer, so the choice changes only the time taken, never the puzzle.
+#[cfg(feature = "generator")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UniquenessCheck {
+    // count solutions up to two
+    Exact,
+    // solve once first, which is cheaper than the count: finding a solution other than the dug
+    // grid's settles it, and only candidates getting past that are counted
+    Screened,
+    // screen first, then keep screening only while the costs measured so far say it saves time
+    Adaptive
+}
+
+// The cost with the total filled in, reported to telemetry.
+#[cfg(feature = "generator")]
+fn finish(started : Instant, cost : GenCost, found : bool) -> GenCost {
+    let cost = GenCost {
+        total : started.elapsed(),
+        ..cost
+    };
+    telemetry::generation_finished(&cost, found);
+    cost
+}
+
+// Checks done with screening before `UniquenessCheck::Adaptive` starts choosing, and the period
+// of the checks it screens anyway afterwards, to keep its estimates current as digging goes on.
+#[cfg(feature = "generator")]
+const ADAPT_AFTER : usize = 32;
+#[cfg(feature = "generator")]
+const ADAPT_PROBE : usize = 16;
+
+#[cfg(feature = "generator")]
+struct UniquenessChecker {
+    check : UniquenessCheck,
+    solution : [[SquareValue; 9]; 9],
+    cost : GenCost,
+    screens : usize,
+    screen_time : Duration,
+    counts : usize,
+    count_time : Duration
+}
+
+#[cfg(feature = "generator")]
+impl UniquenessChecker {
+
+    fn new(check : UniquenessCheck) -> UniquenessChecker {
+        UniquenessChecker {
+            check,
+            solution : [[0; 9]; 9],
+            cost : GenCost::default(),
+            screens : 0,
+            screen_time : Duration::default(),
+            counts : 0,
+            count_time : Duration::default()
+        }
+    }
+
+    // The grid candidates are dug from.
+    fn set_solution(&mut self, solution : &StartState) {
+        self.solution = Grid9::from_start_state(solution).map_or([[0; 9]; 9], |g| g.to_values());
+    }
+
+    fn unique(&mut self, candidate : &StartState) -> bool {
+        let started = Instant::now();
+        let unique = match Grid9::from_start_state(candidate) {
+            Some(grid) if self.screening() => {
+                let other = grid.solve().is_none_or(|solved| solved.to_values() != self.solution);
+                self.screens += 1;
+                self.screen_time += started.elapsed();
+                if other {
+                    self.cost.screened_out += 1;
+                    false
+                } else {
+                    self.count(&grid)
+                }
+            },
+            Some(grid) => self.count(&grid),
+            None => false
+        };
+        self.cost.checks += 1;
+        self.cost.uniqueness += started.elapsed();
+        unique
+    }
+
+    fn count(&mut self, grid : &Grid9) -> bool {
+        let started = Instant::now();
+        let unique = grid.count_solutions(2) == 1;
+        self.counts += 1;
+        self.count_time += started.elapsed();
+        unique
+    }
+
+    // Screening pays when the screen costs less than the counts it saves, i.e. than the share
+    // of candidates it turns away times the cost of a count.
+    fn screening(&self) -> bool {
+        match self.check {
+            UniquenessCheck::Exact => false,
+            UniquenessCheck::Screened => true,
+            UniquenessCheck::Adaptive if self.cost.checks < ADAPT_AFTER || self.cost.checks.is_multiple_of(ADAPT_PROBE) || self.counts == 0 => true,
+            UniquenessCheck::Adaptive => {
+                let seconds = |d : Duration| d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9;
+                let screen = seconds(self.screen_time) / self.screens as f64;
+                let count = seconds(self.count_time) / self.counts as f64;
+                let turned_away = self.cost.screened_out as f64 / self.screens as f64;
+                screen < turned_away * count
+            }
+        }
+    }
+}
+
+#[cfg(feature = "generator")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HarderEasier {
+    Harder,
+    Easier
+}
+
+// With a seed every method but `generate_sparse`, whose search is cut off by the clock, hands
+// out the same puzzles on every OS and architecture: the random numbers come from `Random`, not
+// from `rand`, and nothing depends on hash map order. data/golden.txt pins puzzles for a few
+// seeds, see `golden::check`.
+#[cfg(feature = "generator")]
 pub struct Generator {
     config : Config,
-    string_handler : StringStartStateHandler
+    string_handler : StringStartStateHandler,
+    verify : bool,
+    seed : Option<u64>,
+    uniqueness : UniquenessCheck
+}
+
+#[cfg(feature = "generator")]
+impl Default for Generator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Generator {
     pub fn new() -> Generator {
         Generator {
             config : Config::new(),
+            string_handler : StringStartStateHandler::new(),
+            verify : cfg!(debug_assertions),
+            seed : None,
+            uniqueness : UniquenessCheck::Exact
+        }
+    }
+
+    // How rated and timed generation check candidates for uniqueness; `Exact` by default.
+    pub fn with_uniqueness_check(self, check : UniquenessCheck) -> Generator {
+        Generator {
+            uniqueness : check,
+            ..self
+        }
+    }
+
+    // Repeats the puzzles of every generator with the same seed, e.g. for a daily puzzle.
+    pub fn with_seed(self, seed : u64) -> Generator {
+        Generator {
+            seed : Some(seed),
+            ..self
+        }
+    }
+
+    // Check every rated puzzle against the grid it was dug from before handing it out, digging
+    // another grid for one that fails, see `GenResult::rejected`. On by default in debug builds.
+    pub fn with_verification(self, verify : bool) -> Generator {
+        Generator {
+            verify,
+            ..self
+        }
+    }
+
+    // The puzzle must have exactly one solution and it must be the recorded one.
+    pub fn verify(&self, puzzle : &StartState, solution : &StartState) -> Result<(), String> {
+        let grid = match Grid9::from_start_state(puzzle) {
+            Some(grid) => grid,
+            None => return Err("Puzzle contradicts itself".to_string())
+        };
+        if grid.count_solutions(2) != 1 {
+            return Err("Puzzle does not have a unique solution".to_string());
+        }
+        let solved = match grid.solve() {
+            Some(solved) => solved,
+            None => return Err("Failed solving puzzle".to_string())
+        };
+        let value = |s : &SquareId| solved.value(s.0 as usize - 'A' as usize, s.1 as usize - '1' as usize);
+        match solution.iter().find(|&&(s, v)| value(&s) != Some(v)) {
+            Some(&(s, v)) => Err(format!("Puzzle solves to {} at {}, recorded {}", value(&s).unwrap_or(0), square_name(&s), v)),
+            None if solution.len() != self.config.squares.len() => Err("Recorded solution is incomplete".to_string()),
+            None => Ok(())
+        }
+    }
+
+    pub fn generate(&self, n : usize) -> StartState {
+        self.generate_from(n, &mut self.random())
+    }
+
+    fn generate_from(&self, n : usize, random : &mut Random) -> StartState {
+        loop {
+            if let Some(state) = State::new(&self.config).generate_seeded(n, random.next()) {
+                return state;
+            }
+        }
+    }
+
+    fn random(&self) -> Random {
+        Random::new(self.seed.unwrap_or_else(|| rand::thread_rng().gen()))
+    }
+
+    pub fn generate_str(&self, n : usize) -> String {
+        self.string_handler.generate(&self.config, self.generate(n))
+    }
+
+    pub fn generate_puzzle(&self, n : usize) -> Puzzle {
+        Puzzle::new(self.generate(n))
+    }
+
+    // Digs clues out of a random solution grid, rating as it goes. Each removal is picked among a
+    // few random candidates as the one leaving the most information without passing the target,
+    // so the digging steers towards the target instead of generating and filtering.
+    pub fn generate_rated(&self, target : Difficulty, max_attempts : usize) -> Option<GenResult> {
+        self.generate_rated_cancellable(target, max_attempts, &CancelToken::new())
+    }
+
+    // `generate_rated` giving up with None once `cancel` is cancelled or out of time, checked
+    // between removals.
+    pub fn generate_rated_cancellable(&self, target : Difficulty, max_attempts : usize, cancel : &CancelToken) -> Option<GenResult> {
+        let started = Instant::now();
+        let solver = Solver::new();
+        let logical_solver = LogicalSolver::new();
+        let calibration = Calibration::default();
+        let mut checker = UniquenessChecker::new(self.uniqueness);
+        let mut random = self.random();
+        let mut rated = 0;
+        let mut rejected = Vec::new();
+        for attempt in 1..max_attempts + 1 {
+            let solution = self.generate_from(self.config.squares.len(), &mut random);
+            checker.set_solution(&solution);
+            let mut clues = solution.clone();
+            let mut hit = None;
+            loop {
+                if cancel.is_stopped() {
+                    finish(started, checker.cost, false);
+                    return None;
+                }
+                let mut order : Vec<usize> = (0..clues.len()).collect();
+                random.shuffle(&mut order);
+                // the most informative removal of five not past the target by the calibrated
+                // level, as `rate` gives it, trying five others while they all are; the quick
+                // estimate only spares rating the puzzles propagation alone solves, always easy
+                let mut removed = None;
+                for five in order.chunks(5) {
+                    let mut candidates = Vec::new();
+                    for &i in five {
+                        let mut candidate = clues.clone();
+                        candidate.remove(i);
+                        if ! checker.unique(&candidate) {
+                            continue;
+                        }
+                        let rating = Instant::now();
+                        let analyzed = analysis::analyze(&solver, candidate.clone());
+                        checker.cost.rating += rating.elapsed();
+                        if let Ok(metrics) = analyzed {
+                            rated += 1;
+                            candidates.push((metrics, candidate));
+                        }
+                    }
+                    candidates.sort_by(|a, b| b.0.information.total_cmp(&a.0.information));
+                    for (metrics, candidate) in candidates {
+                        let level = if analysis::fast_difficulty(&metrics) == Difficulty::Easy {
+                            Ok(Difficulty::Easy)
+                        } else {
+                            let rating = Instant::now();
+                            let level = calibration.rate(&logical_solver, &candidate);
+                            checker.cost.rating += rating.elapsed();
+                            level
+                        };
+                        match level {
+                            Ok(level) if level <= target => {
+                                removed = Some((candidate, level));
+                                break;
+                            },
+                            _ => ()
+                        }
+                    }
+                    if removed.is_some() {
+                        break;
+                    }
+                }
+                match removed {
+                    Some((candidate, level)) => {
+                        if level == target {
+                            hit = Some(candidate.clone());
+                        }
+                        clues = candidate;
+                    },
+                    None => break
+                }
+            }
+            // keep digging while the target holds, then hand out the sparsest puzzle that hit it
+            if let Some(puzzle) = hit {
+                match self.verify_generated(&puzzle, &solution, &mut checker.cost) {
+                    Ok(()) => return Some(GenResult {
+                        puzzle,
+                        solution,
+                        difficulty : target,
+                        attempts : attempt,
+                        rated,
+                        cost : finish(started, checker.cost, true),
+                        rejected
+                    }),
+                    Err(e) => rejected.push(e)
+                }
+            }
+        }
+        finish(started, checker.cost, false);
+        None
+    }
+
+    // Digs clues out of a random solution grid until a player would take between `min` and `max`
+    // by `model`'s estimate, for apps that sell puzzles by time. Like `generate_rated` each
+    // removal is the one of a few random candidates with the longest estimate not past `max`, and
+    // the sparsest puzzle inside the range is handed out, with its calibrated level.
+    pub fn generate_timed(&self, model : &TimeModel, min : Duration, max : Duration, max_attempts : usize) -> Option<GenResult> {
+        let started = Instant::now();
+        let logical_solver = LogicalSolver::new();
+        let mut checker = UniquenessChecker::new(self.uniqueness);
+        let mut random = self.random();
+        let mut rated = 0;
+        let mut rejected = Vec::new();
+        for attempt in 1..max_attempts + 1 {
+            let solution = self.generate_from(self.config.squares.len(), &mut random);
+            checker.set_solution(&solution);
+            let mut clues = solution.clone();
+            let mut hit = None;
+            loop {
+                let mut order : Vec<usize> = (0..clues.len()).collect();
+                random.shuffle(&mut order);
+                let mut best : Option<(usize, Duration, f64)> = None;
+                for &i in order.iter().take(5) {
+                    let mut candidate = clues.clone();
+                    candidate.remove(i);
+                    if ! checker.unique(&candidate) {
+                        continue;
+                    }
+                    let rating = Instant::now();
+                    let solved = logical_solver.full_path(&candidate);
+                    checker.cost.rating += rating.elapsed();
+                    let path = match solved {
+                        Ok(path) => path,
+                        Err(_) => continue
+                    };
+                    rated += 1;
+                    let estimate = model.estimate(&path);
+                    if estimate <= max && best.is_none_or(|(_, longest, _)| estimate > longest) {
+                        best = Some((i, estimate, analysis::path_score(&path).value));
+                    }
+                }
+                match best {
+                    Some((i, estimate, score)) => {
+                        clues.remove(i);
+                        if estimate >= min {
+                            hit = Some((clues.clone(), score));
+                        }
+                    },
+                    None => break
+                }
+            }
+            if let Some((puzzle, score)) = hit {
+                match self.verify_generated(&puzzle, &solution, &mut checker.cost) {
+                    Ok(()) => return Some(GenResult {
+                        puzzle,
+                        solution,
+                        difficulty : Calibration::default().level(score),
+                        attempts : attempt,
+                        rated,
+                        cost : finish(started, checker.cost, true),
+                        rejected
+                    }),
+                    Err(e) => rejected.push(e)
+                }
+            }
+        }
+        finish(started, checker.cost, false);
+        None
+    }
+
+    // A unique puzzle dug from `solution`, a full grid, that the techniques up to `technique` (in
+    // `Technique::all` order) solve and those before it don't, for teaching examples of one
+    // technique. Clues come out in a random order, or the seed's, as long as the puzzle stays
+    // unique and the techniques up to `technique` still solve it; the sparsest puzzle on the way
+    // that the easier techniques alone don't solve is handed out. A pass that never leaves them
+    // stuck starts over in another order, up to `max_attempts` passes. None for `Reveal`, which
+    // is no deduction.
+    pub fn dig_requiring(&self, solution : &StartState, technique : Technique, max_attempts : usize) -> Option<StartState> {
+        let position = Technique::all().iter().position(|&t| t == technique)?;
+        match Grid9::from_start_state(solution) {
+            Some(ref grid) if grid.is_solved() && solution.len() == self.config.squares.len() => (),
+            _ => return None
+        }
+        let with = LogicalSolver::with_techniques(&Technique::all()[..position + 1]);
+        let without = LogicalSolver::with_techniques(&Technique::all()[..position]);
+        let mut random = self.random();
+        for _ in 0..max_attempts {
+            let mut clues = solution.clone();
+            let mut hit = None;
+            let mut order : Vec<SquareId> = clues.iter().map(|&(s, _)| s).collect();
+            random.shuffle(&mut order);
+            for square in order {
+                let i = match clues.iter().position(|&(s, _)| s == square) {
+                    Some(i) => i,
+                    None => continue
+                };
+                let mut candidate = clues.clone();
+                candidate.remove(i);
+                if Grid9::from_start_state(&candidate).is_none_or(|grid| grid.count_solutions(2) != 1) ||
+                   with.certify(&candidate).is_none() {
+                    continue;
+                }
+                if without.certify(&candidate).is_none() {
+                    hit = Some(candidate.clone());
+                }
+                clues = candidate;
+            }
+            if hit.is_some() {
+                return hit;
+            }
+        }
+        None
+    }
+
+    // `verify` when verification is on, timed as a uniqueness check. A puzzle failing it is not
+    // handed out; the generator digs another solution grid instead and reports why in the result.
+    fn verify_generated(&self, puzzle : &StartState, solution : &StartState, cost : &mut GenCost) -> Result<(), String> {
+        if ! self.verify {
+            return Ok(());
+        }
+        let started = Instant::now();
+        let verified = self.verify(puzzle, solution).map_err(|e| format!("Generated puzzle failed verification: {}", e));
+        cost.uniqueness += started.elapsed();
+        verified
+    }
+
+    // A unique puzzle with at most `max_clues` clues, aiming at the 18 to 21 range that random
+    // digging practically never reaches, or None once `budget` has run out. Each solution grid is
+    // dug at random to around `SPARSE_DIG_TO` clues, then searched with `SparseSearch`.
+    pub fn generate_sparse(&self, max_clues : usize, budget : Duration) -> Option<GenResult> {
+        let started = Instant::now();
+        let deadline = started + budget;
+        let mut random = self.random();
+        let mut checked = 0;
+        let mut attempt = 0;
+        let mut cost = GenCost::default();
+        let mut rejected = Vec::new();
+        while Instant::now() < deadline {
+            attempt += 1;
+            let solution = self.generate_from(self.config.squares.len(), &mut random);
+            let values = match Grid9::from_start_state(&solution) {
+                Some(grid) => grid.to_values(),
+                None => continue
+            };
+            let mut search = SparseSearch {
+                solution : values,
+                deadline : std::cmp::min(deadline, Instant::now() + SPARSE_GRID_TIME),
+                checked : 0
+            };
+            let mut order : Vec<usize> = (0..81).collect();
+            random.shuffle(&mut order);
+            let dug = search.dig(order);
+            let found = search.reduce(dug, max_clues, &mut random);
+            checked += search.checked;
+            if let Some(clues) = found {
+                let puzzle : StartState = clues.iter().map(|&i| (self.config.squares[i], values[i / 9][i % 9])).collect();
+                if let Err(e) = self.verify_generated(&puzzle, &solution, &mut cost) {
+                    rejected.push(e);
+                    continue;
+                }
+                let difficulty = Calibration::default().rate(&LogicalSolver::new(), &puzzle).unwrap_or(Difficulty::Expert);
+                return Some(GenResult {
+                    puzzle,
+                    solution,
+                    difficulty,
+                    attempts : attempt,
+                    rated : checked,
+                    cost : finish(started, GenCost { checks : checked, ..cost }, true),
+                    rejected
+                });
+            }
+        }
+        finish(started, GenCost { checks : checked, ..cost }, false);
+        None
+    }
+
+    // A `DigMachine` on a new solution grid, digging in random order down to `min_clues` at most.
+    pub fn dig_machine(&self, min_clues : usize) -> DigMachine {
+        let mut random = self.random();
+        let solution = self.generate_from(self.config.squares.len(), &mut random);
+        let values = Grid9::from_start_state(&solution).map_or([[0; 9]; 9], |g| g.to_values());
+        let mut order : Vec<usize> = (0..81).collect();
+        random.shuffle(&mut order);
+        DigMachine {
+            solution : values,
+            clues : values,
+            order,
+            min_clues,
+            steps : 0
+        }
+    }
+
+    // Removes (harder) or adds (easier) a single clue, keeping the solution unique. Of the clues
+    // that move the rating in the requested direction the one changing it least is picked, falling
+    // back to one that leaves the rating as it is.
+    pub fn mutate(&self, start_state : &StartState, direction : HarderEasier) -> Option<StartState> {
+        let solver = Solver::new();
+        let clues : StartState = start_state.iter().cloned().filter(|&(_, v)| v != 0).collect();
+        let unique = |candidate : &StartState| Grid9::from_start_state(candidate).is_some_and(|g| g.count_solutions(2) == 1);
+        if ! unique(&clues) {
+            return None;
+        }
+        let information = match analysis::analyze(&solver, clues.clone()) {
+            Ok(metrics) => metrics.information,
+            Err(_) => return None
+        };
+        let candidates : Vec<StartState> = match direction {
+            HarderEasier::Harder => (0..clues.len()).map(|i| {
+                                                        let mut candidate = clues.clone();
+                                                        candidate.remove(i);
+                                                        candidate
+                                                    })
+                                                    .filter(|candidate| unique(candidate))
+                                                    .collect(),
+            HarderEasier::Easier => {
+                let solution = match solver.solve(clues.clone()) {
+                    Ok(solution) => solution,
+                    Err(_) => return None
+                };
+                self.config.squares.iter()
+                                   .filter(|s| ! clues.iter().any(|&(s2, _)| s2 == **s))
+                                   .map(|s| {
+                                       let mut candidate = clues.clone();
+                                       candidate.insert((*s, solution.value(s).unwrap()));
+                                       candidate
+                                   })
+                                   .collect()
+            }
+        };
+        let mut best : Option<(f64, StartState)> = None;
+        for candidate in candidates {
+            let change = match analysis::analyze(&solver, candidate.clone()) {
+                Ok(metrics) => match direction {
+                    HarderEasier::Harder => metrics.information - information,
+                    HarderEasier::Easier => information - metrics.information
+                },
+                Err(_) => continue
+            };
+            let better = match best {
+                Some((best_change, _)) => change > 0.0 && (best_change == 0.0 || change < best_change),
+                None => change >= 0.0
+            };
+            if better {
+                best = Some((change, candidate));
+            }
+        }
+        best.map(|(_, candidate)| candidate)
+    }
+}
+
+// The generator's random numbers: splitmix64, with its own shuffle and choice, so a seed gives
+// the same sequence whatever the platform's word size and whichever `rand` release is built in.
+#[cfg(feature = "generator")]
+struct Random {
+    state : u64
+}
+
+#[cfg(feature = "generator")]
+impl Random {
+
+    fn new(seed : u64) -> Random {
+        Random {
+            state : seed
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n : usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn shuffle<T>(&mut self, items : &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+
+    fn choose<'b, T>(&mut self, items : &'b [T]) -> Option<&'b T> {
+        if items.is_empty() {
+            return None;
+        }
+        let i = self.below(items.len());
+        items.get(i)
+    }
+}
+
+// Clue count where sparse generation stops removing clues at random and starts searching.
+#[cfg(feature = "generator")]
+const SPARSE_DIG_TO : usize = 24;
+
+// Search time spent on one solution grid before digging another.
+#[cfg(feature = "generator")]
+const SPARSE_GRID_TIME : Duration = Duration::from_secs(2);
+
+// Backtracking clue removal for one solution grid, with clues as indices 0..81 in A1..I9 order.
+// Below a minimal puzzle no single clue can go, so the search swaps two clues for one: with two
+// removed the puzzle has another solution, and the squares where the two solutions differ form an
+// unavoidable set, which the added clue has to hit. Each further solution found on the way narrows
+// the candidates to the squares hitting every such set.
+#[cfg(feature = "generator")]
+struct SparseSearch {
+    solution : [[SquareValue; 9]; 9],
+    deadline : Instant,
+    checked : usize
+}
+
+#[cfg(feature = "generator")]
+impl SparseSearch {
+
+    // A solution of the clues other than the known one, None when the puzzle is unique.
+    fn other_solution(&mut self, clues : &[usize]) -> Option<Grid9> {
+        self.checked += 1;
+        let mut values = [[0; 9]; 9];
+        for &i in clues {
+            values[i / 9][i % 9] = self.solution[i / 9][i % 9];
+        }
+        let grid = Grid9::from_values(&values)?;
+        grid.solutions(2).into_iter().find(|other| other.to_values() != self.solution)
+    }
+
+    // Squares where `other` differs from the solution; every puzzle for the solution has a clue
+    // among them.
+    fn unavoidable(&self, other : &Grid9) -> Vec<usize> {
+        let values = other.to_values();
+        (0..81).filter(|&i| values[i / 9][i % 9] != self.solution[i / 9][i % 9]).collect()
+    }
+
+    // Removes clues in `order` while the puzzle stays unique, down to `SPARSE_DIG_TO`.
+    fn dig(&mut self, order : Vec<usize>) -> Vec<usize> {
+        let mut clues = order.clone();
+        for i in order {
+            if clues.len() <= SPARSE_DIG_TO {
+                break;
+            }
+            let trial : Vec<usize> = clues.iter().cloned().filter(|&c| c != i).collect();
+            if self.other_solution(&trial).is_none() {
+                clues = trial;
+            }
+        }
+        clues
+    }
+
+    // Removes every clue that can go on its own, leaving a minimal puzzle.
+    fn minimize(&mut self, mut clues : Vec<usize>) -> Vec<usize> {
+        let mut i = 0;
+        while i < clues.len() {
+            let trial : Vec<usize> = clues.iter().cloned().enumerate().filter(|&(j, _)| j != i).map(|(_, c)| c).collect();
+            if self.other_solution(&trial).is_none() {
+                clues = trial;
+            } else {
+                i += 1;
+            }
+        }
+        clues
+    }
+
+    fn reduce(&mut self, clues : Vec<usize>, max_clues : usize, random : &mut Random) -> Option<Vec<usize>> {
+        let clues = self.minimize(clues);
+        if clues.len() <= max_clues {
+            return Some(clues);
+        }
+        let mut pairs : Vec<(usize, usize)> = (0..clues.len()).flat_map(|a| (a + 1..clues.len()).map(move |b| (a, b))).collect();
+        random.shuffle(&mut pairs);
+        for (a, b) in pairs {
+            if Instant::now() > self.deadline {
+                return None;
+            }
+            let rest : Vec<usize> = clues.iter().cloned().enumerate().filter(|&(j, _)| j != a && j != b).map(|(_, c)| c).collect();
+            let mut candidates = match self.other_solution(&rest) {
+                Some(other) => self.unavoidable(&other),
+                None => continue
+            };
+            // putting back a removed clue only undoes the move
+            candidates.retain(|&c| c != clues[a] && c != clues[b]);
+            while let Some(c) = candidates.pop() {
+                let mut trial = rest.clone();
+                trial.push(c);
+                match self.other_solution(&trial) {
+                    Some(other) => {
+                        let set = self.unavoidable(&other);
+                        candidates.retain(|c| set.contains(c));
+                    },
+                    None => {
+                        if let Some(found) = self.reduce(trial, max_clues, random) {
+                            return Some(found);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+// Random clue removal from a solution grid, one square tried per `step`, for hosts that have to
+// stay responsive between steps (see `worker::GenerateJob`). A clue goes when the puzzle stays
+// unique without it; digging ends after every square was tried or at `min_clues`.
+#[cfg(feature = "generator")]
+pub struct DigMachine {
+    solution : [[SquareValue; 9]; 9],
+    clues : [[SquareValue; 9]; 9],
+    order : Vec<usize>,
+    min_clues : usize,
+    steps : usize
+}
+
+#[cfg(feature = "generator")]
+impl DigMachine {
+
+    // True while there are squares left to try.
+    pub fn step(&mut self) -> bool {
+        if self.is_done() {
+            return false;
+        }
+        let i = self.order[self.steps];
+        self.steps += 1;
+        let mut trial = self.clues;
+        trial[i / 9][i % 9] = 0;
+        if Grid9::from_values(&trial).is_some_and(|g| g.count_solutions(2) == 1) {
+            self.clues = trial;
+        }
+        ! self.is_done()
+    }
+
+    // Steps until digging ends or `max_steps` steps have been taken; true while not done.
+    pub fn run(&mut self, max_steps : usize) -> bool {
+        for _ in 0..max_steps {
+            if ! self.step() {
+                break;
+            }
+        }
+        ! self.is_done()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.steps == self.order.len() || self.clue_count() <= self.min_clues
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn clue_count(&self) -> usize {
+        self.clues.iter().flat_map(|row| row.iter()).filter(|&&v| v != 0).count()
+    }
+
+    // The clues left so far; unique at every step.
+    pub fn puzzle(&self) -> StartState {
+        DigMachine::start_state(&self.clues)
+    }
+
+    pub fn solution(&self) -> StartState {
+        DigMachine::start_state(&self.solution)
+    }
+
+    fn start_state(values : &[[SquareValue; 9]; 9]) -> StartState {
+        ALL_SQUARES.iter().enumerate().map(|(i, &s)| (s, values[i / 9][i % 9])).filter(|&(_, v)| v != 0).collect()
+    }
+}
+
+pub struct Solver {
+    config : Config,
+    string_handler : StringStartStateHandler
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Solver {
+    pub fn new() -> Solver {
+        Solver::with_config(Config::new())
+    }
+
+    pub fn with_config(config : Config) -> Solver {
+        Solver {
+            config,
             string_handler : StringStartStateHandler::new()
         }
     }
 
-    pub fn generate(&self, n : usize) -> StartState {
-        loop {
-            match State::new(&self.config).generate(n) {
-                Ok(state) => return state,
-                _ => ()
-            }
-        }
+    pub fn solve(&self, start_state : StartState) -> Result<State<'_>, String> {
+        self.solve_ordered(start_state, ValueOrder::Rarest)
+    }
+
+    // Like `solve`, also returning how many times the search had to guess.
+    pub fn solve_counting(&self, start_state : StartState) -> Result<(State<'_>, usize), String> {
+        self.solve_counting_ordered(start_state, ValueOrder::Rarest)
+    }
+
+    fn solve_counting_ordered(&self, start_state : StartState, order : ValueOrder) -> Result<(State<'_>, usize), String> {
+        start_state.validate(&self.config).map_err(|e| e.to_string())?;
+        let started = Instant::now();
+        let mut guesses = 0;
+        let solved = match self.start(&start_state) {
+            Some(mut state) => if state.search(&mut guesses, order) { Some(state) } else { None },
+            None => None
+        };
+        match solved {
+            Some(state) => {
+                telemetry::solve_finished(started, Some(guesses));
+                Ok((state, guesses))
+            },
+            None => {
+                telemetry::solve_finished(started, None);
+                Err(self.failure(&start_state))
+            }
+        }
+    }
+
+    // The state the search starts from. Classic puzzles first go through repeated naked and
+    // hidden singles on the bitmask grid, which solves most easy puzzles outright and leaves
+    // the hash map state only the squares that are still open.
+    fn start(&self, start_state : &StartState) -> Option<State<'_>> {
+        let mut state = State::new(&self.config);
+        if self.config.is_classic() {
+            state.load_grid(&Grid9::from_start_state(start_state)?, start_state);
+            Some(state)
+        } else if state.apply_start_state(start_state.clone()) {
+            Some(state)
+        } else {
+            None
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn add_constraint(&mut self, constraint : Box<dyn Constraint>) {
+        self.config.add_constraint(constraint);
+    }
+
+    // Upper estimate of the bytes a solve can hold at once. The search keeps one state per
+    // guessed square on its path and guesses at most once per square, so memory is bounded by
+    // the config plus one state per square (and the solution), however hard the grid is.
+    pub fn memory_bound(&self) -> usize {
+        self.config.memory_estimate() + (self.config.squares.len() + 1) * State::memory_estimate(&self.config)
+    }
+
+    // A minimal set of givens that cannot all hold: dropping any one of them leaves a solvable
+    // puzzle. Found by deletion, trying each given in turn and keeping it out whenever the rest
+    // still has no solution. None if the puzzle is solvable.
+    pub fn conflict(&self, start_state : &StartState) -> Option<StartState> {
+        let unsolvable = |givens : &StartState| Grid9::from_start_state(givens).is_none_or(|g| g.count_solutions(1) == 0);
+        let mut givens : StartState = start_state.iter().cloned().filter(|&(_, v)| v != 0).collect();
+        if ! unsolvable(&givens) {
+            return None;
+        }
+        let mut i = 0;
+        while i < givens.len() {
+            let mut rest = givens.clone();
+            rest.remove(i);
+            if unsolvable(&rest) {
+                givens = rest;
+            } else {
+                i += 1;
+            }
+        }
+        Some(givens)
+    }
+
+    fn failure(&self, start_state : &StartState) -> String {
+        match self.conflict(start_state) {
+            Some(ref givens) if ! givens.is_empty() => {
+                let names : Vec<String> = givens.iter().map(|&(s, v)| format!("{}={}", square_name(&s), v)).collect();
+                format!("Failed solving puzzle, conflicting givens {}", names.join(" "))
+            },
+            _ => "Failed solving puzzle".to_string()
+        }
+    }
+
+    pub fn propagate(&self, start_state : StartState) -> Result<State<'_>, String> {
+        start_state.validate(&self.config).map_err(|e| e.to_string())?;
+        let mut state = State::new(&self.config);
+        if ! state.apply_start_state(start_state) || ! state.apply_constraints() {
+            return Err("Failed propagating puzzle".to_string());
+        }
+        Ok(state)
+    }
+
+    // Counts solutions, stopping once `limit` have been found.
+    pub fn count_solutions(&self, start_state : StartState, limit : usize) -> usize {
+        self.count_solutions_ordered(start_state, limit, ValueOrder::Ascending)
+    }
+
+    // The order only changes how soon `limit` is reached, not the count.
+    pub fn count_solutions_ordered(&self, start_state : StartState, limit : usize, order : ValueOrder) -> usize {
+        let mut state = State::new(&self.config);
+        if ! state.apply_start_state(start_state) || ! state.apply_constraints() {
+            return 0;
+        }
+        state.count(limit, order)
+    }
+
+    // Every solution, however many. Slow on sparse grids; see `likely_unique` for a quick check.
+    pub fn count_solutions_exact(&self, start_state : StartState) -> usize {
+        self.count_solutions(start_state, usize::MAX)
+    }
+
+    pub fn solve_ordered(&self, start_state : StartState, order : ValueOrder) -> Result<State<'_>, String> {
+        self.solve_counting_ordered(start_state, order).map(|(state, _)| state)
+    }
+
+    // Solves with ascending, descending and `restarts` shuffled value orders and compares the
+    // solutions. False is certain (no solution, or two different ones were found); true only
+    // means no second solution turned up, so callers needing proof follow up with `count_solutions`.
+    pub fn likely_unique(&self, start_state : &StartState, restarts : usize) -> bool {
+        let orders = vec![ValueOrder::Ascending, ValueOrder::Descending].into_iter()
+                                                                       .chain((0..restarts as u64).map(ValueOrder::Shuffled));
+        let mut first : Option<State> = None;
+        for order in orders {
+            let mut state = match self.start(start_state) {
+                Some(state) => state,
+                None => return false
+            };
+            if ! state.search(&mut 0, order) {
+                return false;
+            }
+            match first {
+                Some(ref solution) if solution.values != state.values => return false,
+                Some(_) => (),
+                None => first = Some(state)
+            }
+        }
+        true
+    }
+
+    // True when the techniques alone solve the puzzle; `LogicalSolver::certify` gives the path.
+    pub fn certify_no_guessing(&self, start_state : &StartState, techniques : &[Technique]) -> bool {
+        LogicalSolver::with_techniques(techniques).certify(start_state).is_some()
+    }
+
+    // Like `solve`, sending every square to `events` once, as it settles on its value, so a
+    // frontend can animate the board filling in rather than diffing whole states each frame.
+    // Givens and what propagation deduces from them come in the order propagation settles them;
+    // the squares the search had to guess come last, in config order, once the solution is
+    // known, so no guess that was later taken back is ever sent.
+    pub fn solve_with_events(&self, start_state : StartState, events : Sender<StartValue>) -> Result<State<'_>, String> {
+        start_state.validate(&self.config).map_err(|e| e.to_string())?;
+        let started = Instant::now();
+        let mut state = State::new(&self.config);
+        state.events = Some(events.clone());
+        if ! state.apply_start_state(start_state.clone()) || ! state.apply_constraints() {
+            telemetry::solve_finished(started, None);
+            return Err(self.failure(&start_state));
+        }
+        state.events = None;
+        let open = state.unsolved_squares();
+        let mut guesses = 0;
+        if ! state.search(&mut guesses, ValueOrder::Rarest) {
+            telemetry::solve_finished(started, None);
+            return Err(self.failure(&start_state));
+        }
+        telemetry::solve_finished(started, Some(guesses));
+        for square in open {
+            if let Some(value) = state.value(&square) {
+                let _ = events.send((square, value));
+            }
+        }
+        Ok(state)
+    }
+
+    // Like `solve`, but wherever the search has to guess at a square with a preference it tries
+    // the preferred value first, so a puzzle with several solutions (an editor's half finished
+    // grid, say) comes out agreeing with the user's pencilled guesses where it can. Preferences
+    // that propagation rules out are passed over; whichever solution is found first is returned,
+    // not necessarily the one agreeing with the most of them.
+    pub fn solve_preferring(&self, start_state : &StartState, preferences : &[StartValue]) -> Result<State<'_>, String> {
+        start_state.validate(&self.config).map_err(|e| e.to_string())?;
+        let started = Instant::now();
+        let mut guesses = 0;
+        let solved = match self.start(start_state) {
+            Some(mut state) => {
+                state.preferred = Arc::new(preferences.to_vec());
+                if state.search(&mut guesses, ValueOrder::Rarest) { Some(state) } else { None }
+            },
+            None => None
+        };
+        match solved {
+            Some(mut state) => {
+                telemetry::solve_finished(started, Some(guesses));
+                state.preferred = Arc::new(Vec::new());
+                Ok(state)
+            },
+            None => {
+                telemetry::solve_finished(started, None);
+                Err(self.failure(start_state))
+            }
+        }
+    }
+
+    pub fn solve_puzzle(&self, puzzle : &Puzzle) -> Result<State<'_>, String> {
+        self.solve(puzzle.start_state().clone())
+    }
+
+    pub fn solve_str(&self, grid : &str) -> Result<State<'_>, String> {
+        let grid = self.string_handler.parse(&self.config, grid.to_string())?;
+        self.solve(grid)
+    }
+
+    pub fn machine(&self, start_state : StartState) -> SolveMachine<'_> {
+        SolveMachine::new(&self.config, start_state)
+    }
+
+    // Solves by stepping a `SolveMachine`, giving up with the error of `CancelToken::check` once
+    // `cancel` is cancelled or its deadline has passed. Other failures are reported as by `solve`.
+    pub fn solve_cancellable(&self, start_state : StartState, cancel : &CancelToken) -> Result<State<'_>, String> {
+        start_state.validate(&self.config).map_err(|e| e.to_string())?;
+        let mut machine = self.machine(start_state.clone());
+        loop {
+            cancel.check()?;
+            match machine.run(STEPS_PER_CHECK) {
+                SolveStatus::Solved => return machine.into_solution().ok_or_else(|| self.failure(&start_state)),
+                SolveStatus::Unsolvable => return Err(self.failure(&start_state)),
+                SolveStatus::Running => ()
+            }
+        }
+    }
+}
+
+// Search steps between checks of a `CancelToken`.
+const STEPS_PER_CHECK : usize = 64;
+
+// Order in which the search tries the candidates of a square.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueOrder {
+    // values with the fewest places left on the grid first, the default for solving
+    Rarest,
+    Ascending,
+    Descending,
+    // reproducible pseudo-random order from a seed
+    Shuffled(u64)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SolveStatus {
+    Running,
+    Solved,
+    Unsolvable
+}
+
+// The backtracking search with an explicit stack, advanced one tried value per `step` so callers
+// can spread a solve over frames or ticks of their own.
+pub struct SolveMachine<'a> {
+    stack : Vec<(State<'a>, SquareId, Vec<Digit>)>,
+    solution : Option<State<'a>>,
+    status : SolveStatus,
+    steps : usize
+}
+
+impl<'a> SolveMachine<'a> {
+
+    pub fn new(config : &'a Config, start_state : StartState) -> SolveMachine<'a> {
+        let mut machine = SolveMachine {
+            stack : Vec::new(),
+            solution : None,
+            status : SolveStatus::Running,
+            steps : 0
+        };
+        let mut state = State::new(config);
+        if ! state.apply_start_state(start_state) || ! state.apply_constraints() {
+            machine.status = SolveStatus::Unsolvable;
+        } else {
+            machine.descend(state);
+        }
+        machine
+    }
+
+    pub fn step(&mut self) -> SolveStatus {
+        if self.status != SolveStatus::Running {
+            return self.status;
+        }
+        self.steps += 1;
+        let next = match self.stack.last_mut() {
+            Some(&mut (ref state, square, ref mut values)) => values.pop().map(|v| (state.clone(), square, v)),
+            None => None
+        };
+        match next {
+            Some((mut child, square, value)) => {
+                if child.assign(&square, &value) && child.apply_constraints() {
+                    self.descend(child);
+                }
+            },
+            None => {
+                self.stack.pop();
+                if self.stack.is_empty() {
+                    self.status = SolveStatus::Unsolvable;
+                }
+            }
+        }
+        self.status
+    }
+
+    // Steps until the search finishes or `max_steps` steps have been taken.
+    pub fn run(&mut self, max_steps : usize) -> SolveStatus {
+        for _ in 0..max_steps {
+            if self.step() != SolveStatus::Running {
+                break;
+            }
+        }
+        self.status
+    }
+
+    pub fn status(&self) -> SolveStatus {
+        self.status
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    // Depth of the search, i.e. the number of squares currently guessed.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    // The deepest state reached so far, for showing progress.
+    pub fn current(&self) -> Option<&State<'a>> {
+        self.solution.as_ref().or(self.stack.last().map(|(state, _, _)| state))
+    }
+
+    pub fn solution(&self) -> Option<&State<'a>> {
+        self.solution.as_ref()
+    }
+
+    pub fn into_solution(self) -> Option<State<'a>> {
+        self.solution
     }
 
-    pub fn generate_str(&self, n : usize) -> String {
-        self.string_handler.generate(&self.config, self.generate(n))
+    fn descend(&mut self, state : State<'a>) {
+        if state.is_solved() {
+            self.solution = Some(state);
+            self.status = SolveStatus::Solved;
+            return;
+        }
+        let square = *state.config.squares.iter()
+                                          .filter(|s| state.values.get(s).unwrap().len() > 1)
+                                          .min_by_key(|s| state.values.get(s).unwrap().len())
+                                          .unwrap();
+        // values are popped from the end, so the preferred value goes last
+        let mut values = state.sort_values(&square);
+        values.reverse();
+        self.stack.push((state, square, values));
     }
 }
 
-pub struct Solver {
-    config : Config,
-    string_handler : StringStartStateHandler
+// A digit locked into the overlap of two units, see `State::locked_candidates`: `cells` are its
+// places and `eliminations` the squares of the other unit that lose it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LockedCandidate {
+    pub digit : SquareValue,
+    pub cells : Vec<SquareId>,
+    pub eliminations : Vec<SquareId>
 }
 
-impl Solver {
-    pub fn new() -> Solver {
-        Solver {
-            config : Config::new(),
-            string_handler : StringStartStateHandler::new()
-        }
+// A square whose candidates differ between two states, see `diff`; a decided square has one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CellDiff {
+    pub square : SquareId,
+    pub before : Vec<SquareValue>,
+    pub after : Vec<SquareValue>
+}
+
+impl CellDiff {
+    // The value the square settled on going from `before` to `after`, if it did.
+    pub fn settled(&self) -> Option<SquareValue> {
+        if self.after.len() == 1 && self.before.len() != 1 { Some(self.after[0]) } else { None }
     }
 
-    pub fn solve(&self, start_state : StartState) -> Result<State, String> {
-        let mut state = State::new(&self.config);
-        if ! state.solve(start_state) {
-            return Err("Failed solving puzzle".to_string());
-        }
-        Ok(state)
+    // Candidates `after` no longer has.
+    pub fn removed(&self) -> Vec<SquareValue> {
+        self.before.iter().filter(|v| ! self.after.contains(v)).cloned().collect()
     }
+}
 
-    pub fn solve_str(&self, grid : &str) -> Result<State, String> {
-        let grid = match self.string_handler.parse(&self.config, grid.to_string()) {
-            Ok(grid) => grid,
-            Err(err) => return Err(err)
-        };
-        self.solve(grid)
+// The squares of `a`'s config whose candidates differ in `b`, in config order, e.g. between two
+// steps of a solve for a UI to animate, or between the states two versions of the solver reach.
+pub fn diff(a : &State, b : &State) -> Vec<CellDiff> {
+    a.config().squares().iter().filter_map(|square| {
+        let (before, after) = (a.candidates(square), b.candidates(square));
+        if before == after {
+            None
+        } else {
+            Some(CellDiff {
+                square : *square,
+                before,
+                after
+            })
+        }
+    }).collect()
+}
+
+impl std::fmt::Display for CellDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let digits = |values : &[SquareValue]| values.iter().map(|v| v.to_string()).collect::<String>();
+        write!(f, "{}: {} -> {}", square_name(&self.square), digits(&self.before), digits(&self.after))
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct State<'a> {
     config : &'a Config,
-    values : HashMap<SquareId, SquareValues>
+    values : HashMap<SquareId, SquareValues>,
+    placed : HashSet<SquareId>,
+    // candidates the puzzle ruled out, kept apart from the config's so they stay out
+    imposed : Arc<Vec<StartValue>>,
+    // where squares get reported as they settle, see `Solver::solve_with_events`
+    events : Option<Sender<StartValue>>,
+    // values the search tries first where they are still candidates, see `Solver::solve_preferring`
+    preferred : Arc<Vec<StartValue>>
+}
+
+// Same candidates, placements and imposed eliminations. Solving the same input twice gives
+// equal states (and the same guess count), which services caching solves rely on.
+impl<'a> PartialEq for State<'a> {
+    fn eq(&self, other : &State<'a>) -> bool {
+        self.values == other.values && self.placed == other.placed && self.imposed == other.imposed
+    }
 }
 
 impl<'a> State<'a> {
 
+    // Approximate size of one state under `config`, see `Config::memory_estimate`.
+    pub fn memory_estimate(config : &Config) -> usize {
+        let square = std::mem::size_of::<SquareId>();
+        let values = config.squares.len() * (square + std::mem::size_of::<SquareValues>() + config.digits.len() * (std::mem::size_of::<Digit>() + 4));
+        std::mem::size_of::<State>() + values * 2 + config.squares.len() * square * 2
+    }
+
     pub fn new(config : &'a Config) -> State<'a> {
         State {
-            config : config,
-            values : config.values.clone()
+            config,
+            values : config.values.clone(),
+            placed : HashSet::new(),
+            imposed : Arc::new(Vec::new()),
+            events : None,
+            preferred : Arc::new(Vec::new())
         }
     }
 
@@ -88,38 +1644,48 @@ impl<'a> State<'a> {
         if ! self.apply_start_state(state) {
             return false;
         }
-        self.search()
+        self.search(&mut 0, ValueOrder::Rarest)
+    }
+
+    #[cfg(feature = "generator")]
+    pub fn generate(&mut self, n : usize) -> Option<StartState> {
+        self.generate_seeded(n, rand::thread_rng().gen())
     }
 
-    pub fn generate(&mut self, n : usize) -> Result<StartState, ()> {
-        match self.randomize(n) {
-            Ok(_) => Ok(self.encode()),
-            Err(_) => Err(())
+    // The same assignments, and so the same start state, for the same seed on every platform.
+    #[cfg(feature = "generator")]
+    pub fn generate_seeded(&mut self, n : usize, seed : u64) -> Option<StartState> {
+        match self.randomize(n, &mut Random::new(seed)) {
+            Ok(_) => Some(self.encode()),
+            Err(_) => None
         }
     }
 
+    #[cfg(feature = "generator")]
     fn encode(&self) -> StartState {
         self.values.iter()
                    .filter(|&(_, vs)| vs.len() == 1)
-                   .map(|(s, vs)| (s.clone(), vs.iter().nth(0).unwrap().clone()))
+                   .map(|(s, vs)| (*s, vs.iter().nth(0).unwrap().get()))
                    .collect()
     }
 
-    fn randomize(&mut self, n : usize) -> Result<(), ()> {
+    #[cfg(feature = "generator")]
+    fn randomize(&mut self, n : usize, random : &mut Random) -> Result<(), ()> {
         let mut squares = self.config.squares.clone();
-        let mut rng = rand::thread_rng();
-        rng.shuffle(&mut squares);
+        random.shuffle(&mut squares);
         for s in &squares {
-            let vals : Vec<u32> = self.values.get(s).unwrap().iter().cloned().collect();
-            if ! self.assign(&s, &rng.choose(&vals).unwrap().clone()) {
+            // sorted, the hash set's order differs from run to run
+            let mut vals : Vec<Digit> = self.values.get(s).unwrap().iter().cloned().collect();
+            vals.sort();
+            if ! self.assign(s, &random.choose(&vals).unwrap().clone()) {
                 return Err(());
             }
             let d_values = self.values.iter()
                                       .filter(|&(_, vs)| vs.len() == 1)
                                       .flat_map(|(_, vs)| vs.iter())
                                       .cloned()
-                                      .collect::<Vec<u32>>();
-            let d_uniq_values = d_values.iter().cloned().collect::<HashSet<u32>>();
+                                      .collect::<Vec<Digit>>();
+            let d_uniq_values = d_values.iter().cloned().collect::<HashSet<Digit>>();
             if d_values.len() >= n && d_uniq_values.len() >= 8 {
                 return Ok(());
             }
@@ -128,25 +1694,71 @@ impl<'a> State<'a> {
     }
 
     fn apply_start_state(&mut self, state : StartState) -> bool {
+        self.imposed = Arc::new(state.eliminated.clone());
         for (s, v) in state {
             if v != 0 {
-                if ! self.assign(&s, &v) {
-                    return false;
+                match Digit::new(v) {
+                    Some(d) if self.assign(&s, &d) => (),
+                    _ => return false
                 }
             }
         }
-        true
+        let imposed = self.imposed.clone();
+        imposed.iter().all(|&(s, v)| Digit::new(v).is_some_and(|d| self.eliminate(&s, &d)))
+    }
+
+    // Candidates taken over from a bitmask grid, square for square; classic configs only. The
+    // start state's eliminations stay out even if the grid has them.
+    fn load_grid(&mut self, grid : &Grid9, start_state : &StartState) {
+        self.imposed = Arc::new(start_state.eliminated.clone());
+        for (i, s) in self.config.squares.iter().enumerate() {
+            let mask = grid.candidates(i / 9, i % 9);
+            self.values.insert(*s, Digit::all().into_iter().filter(|d| mask & 1 << (d.get() - 1) != 0).collect());
+        }
+        for &(s, v) in self.imposed.iter() {
+            if let (Some(vs), Some(d)) = (self.values.get_mut(&s), Digit::new(v)) {
+                vs.remove(&d);
+            }
+        }
+    }
+
+    // Rules out candidates the puzzle eliminates, without deducing anything from it, as the
+    // logical solver starts. False when a square is left without candidates.
+    pub fn impose(&mut self, eliminations : &[StartValue]) -> bool {
+        let mut imposed = (*self.imposed).clone();
+        imposed.extend(eliminations.iter().cloned());
+        imposed.sort();
+        imposed.dedup();
+        self.imposed = Arc::new(imposed);
+        eliminations.iter().all(|&(s, v)| Digit::new(v).is_some() && self.remove_candidate(&s, &v))
+    }
+
+    // Candidates ruled out by the puzzle rather than by deduction.
+    pub fn imposed(&self) -> &[StartValue] {
+        &self.imposed
+    }
+
+    // What the square could hold before any deduction: the config's digits less the puzzle's
+    // eliminations.
+    pub fn baseline(&self, square : &SquareId) -> Vec<SquareValue> {
+        self.config.digits().into_iter().filter(|&v| ! self.imposed.contains(&(*square, v))).collect()
     }
 
-    fn assign(&mut self, square : &SquareId, value : &SquareValue) -> bool {
-        let mut remove_values = self.values.get(square).unwrap().clone();
+    fn assign(&mut self, square : &SquareId, value : &Digit) -> bool {
+        let mut remove_values = match self.values.get(square) {
+            Some(vs) => vs.clone(),
+            None => return false
+        };
         remove_values.remove(value);
         remove_values.iter().all(|d2| self.eliminate(square, d2))
     }
 
-    fn eliminate(&mut self, square : &SquareId, value: &SquareValue) -> bool {
+    fn eliminate(&mut self, square : &SquareId, value: &Digit) -> bool {
         let vs_len = {
-            let mut vs = self.values.get_mut(square).unwrap();
+            let vs = match self.values.get_mut(square) {
+                Some(vs) => vs,
+                None => return false
+            };
             if ! vs.contains(value) {
                 return true; // already eliminated
             }
@@ -157,7 +1769,8 @@ impl<'a> State<'a> {
         if vs_len == 0 {
             return false; // contradiction: last value removed
         } else if vs_len == 1 {
-            let d2 = self.values.get(square).unwrap().iter().nth(0).unwrap().clone();
+            self.settled(square);
+            let d2 = *self.values.get(square).unwrap().iter().nth(0).unwrap();
             if !self.config.peers.get(square).unwrap().iter().all(|s2| self.eliminate(s2, &d2)) {
                 return false;
             }
@@ -165,37 +1778,71 @@ impl<'a> State<'a> {
         // (2) If a unit u is reduced to only one place for a value d, then put it there.
         for u in self.config.units.get(square).unwrap() {
             let places : Vec<SquareId> = u.iter().filter(|s| self.values.get(s).unwrap().contains(value)).cloned().collect();
-            if places.len() == 0 {
+            if places.is_empty() || (places.len() == 1 && ! self.assign(&places[0], value)) {
                 return false;
-            } else if places.len() == 1 {
-                if ! self.assign(&places[0], value) {
-                    return false;
-                }
             }
         }
         true
     }
 
-    fn sort_values(&self, square : &SquareId) -> Vec<SquareValue> {
-        let vs = self.values.get(square).unwrap();
+    // Ties go to the lower digit: the hash set's own order changes from set to set, and with it
+    // the guesses and the solution found, where the same input must give the same solve.
+    fn sort_values(&self, square : &SquareId) -> Vec<Digit> {
+        let vs = self.digits(square);
         let mut v_n = Vec::with_capacity(vs.len());
         for v in vs {
-            v_n.push((v.clone(), self.values.iter().filter(|&(_, sv)| sv.contains(v)).count()));
+            v_n.push((v, self.values.iter().filter(|&(_, sv)| sv.contains(&v)).count()));
         }
-        v_n.sort_by(|a, b| a.1.cmp(&b.1));
+        v_n.sort_by_key(|a| a.1);
         v_n.iter().map(|&(v, _)| v).collect()
     }
 
-    fn search(&mut self) -> bool {
+    fn ordered_values(&self, square : &SquareId, order : ValueOrder) -> Vec<Digit> {
+        let mut values = self.values_in_order(square, order);
+        if let Some(&(_, value)) = self.preferred.iter().find(|&&(s, _)| s == *square) {
+            if let Some(i) = values.iter().position(|d| d.get() == value) {
+                let d = values.remove(i);
+                values.insert(0, d);
+            }
+        }
+        values
+    }
+
+    fn values_in_order(&self, square : &SquareId, order : ValueOrder) -> Vec<Digit> {
+        match order {
+            ValueOrder::Rarest => self.sort_values(square),
+            ValueOrder::Ascending => self.digits(square),
+            ValueOrder::Descending => self.digits(square).into_iter().rev().collect(),
+            ValueOrder::Shuffled(seed) => {
+                let mut values = self.digits(square);
+                // xorshift seeded per square, so every square gets its own reproducible order
+                let mut x = (seed ^ ((square.0 as u64) << 32 | square.1 as u64)).wrapping_mul(0x9e3779b97f4a7c15) | 1;
+                for i in (1..values.len()).rev() {
+                    x ^= x << 13;
+                    x ^= x >> 7;
+                    x ^= x << 17;
+                    values.swap(i, (x % (i as u64 + 1)) as usize);
+                }
+                values
+            }
+        }
+    }
+
+    // `guesses` counts the squares where search had to branch.
+    fn search(&mut self, guesses : &mut usize, order : ValueOrder) -> bool {
+        if ! self.apply_constraints() {
+            return false;
+        }
         if self.is_solved() {
             return true
         }
         let square = self.config.squares.iter().filter(|s| self.values.get(s).unwrap().len() > 1)
                                                 .min_by_key(|s| self.values.get(s).unwrap().len())
                                                 .unwrap();
-        for d in self.sort_values(&square).clone() {
+        *guesses += 1;
+        for d in self.ordered_values(square, order) {
             let mut child_state = self.clone();
-            if child_state.internal_solve(&square, &d) {
+            if child_state.internal_solve(square, &d, guesses, order) {
                 self.values = child_state.values;
                 return true;
             }
@@ -203,16 +1850,293 @@ impl<'a> State<'a> {
         false
     }
 
-    fn internal_solve(&mut self, square : &SquareId, value : &SquareValue) -> bool {
+    // Expects the constraints to have been applied to this state already.
+    fn count(&self, limit : usize, order : ValueOrder) -> usize {
+        if self.is_solved() {
+            return 1;
+        }
+        let square = self.config.squares.iter().filter(|s| self.values.get(s).unwrap().len() > 1)
+                                                .min_by_key(|s| self.values.get(s).unwrap().len())
+                                                .unwrap();
+        let mut n = 0;
+        for d in self.ordered_values(square, order) {
+            let mut child_state = self.clone();
+            if child_state.assign(square, &d) && child_state.apply_constraints() {
+                n += child_state.count(limit - n, order);
+                if n >= limit {
+                    break;
+                }
+            }
+        }
+        n
+    }
+
+    // Runs the registered constraints until none of them makes progress.
+    fn apply_constraints(&mut self) -> bool {
+        let config = self.config;
+        loop {
+            let mut changed = false;
+            for constraint in &config.constraints {
+                match constraint.prune(self) {
+                    Ok(Progress::Changed) => changed = true,
+                    Ok(Progress::Unchanged) => (),
+                    Err(_) => return false
+                }
+            }
+            if ! changed {
+                return true;
+            }
+        }
+    }
+
+    fn internal_solve(&mut self, square : &SquareId, value : &Digit, guesses : &mut usize, order : ValueOrder) -> bool {
         if ! self.assign(square, value) {
             return false;
         }
-        self.search()
+        self.search(guesses, order)
+    }
+
+    // This state with one more clue, propagated from here rather than from the givens, for editors
+    // trying clues out ("what if B3 were 5?") many times a second; this state is left as it was.
+    // None when the clue contradicts it. Taking a clue away needs a fresh `Solver::propagate`.
+    pub fn branch_with(&self, clue : StartValue) -> Option<State<'a>> {
+        let (square, value) = clue;
+        let digit = Digit::new(value)?;
+        let mut branch = self.clone();
+        if branch.assign(&square, &digit) && branch.apply_constraints() {
+            Some(branch)
+        } else {
+            None
+        }
+    }
+
+    // Solutions from this state, stopping once `limit` have been found; on a branch, whether the
+    // tried clue keeps the puzzle unique. Expects a propagated state such as `Solver::propagate`
+    // and `branch_with` give.
+    pub fn count_solutions(&self, limit : usize) -> usize {
+        self.count(limit, ValueOrder::Rarest)
     }
 
     pub fn is_solved(&self) -> bool {
         self.config.squares.iter().all(|s| self.values.get(s).unwrap().len() == 1)
     }
+
+    // Squares with more than one candidate left, in config order.
+    pub fn unsolved_squares(&self) -> Vec<SquareId> {
+        self.config.squares.iter().cloned().filter(|s| self.values.get(s).unwrap().len() > 1).collect()
+    }
+
+    // Squares down to a single candidate, whether given, placed or deduced.
+    pub fn solved_count(&self) -> usize {
+        self.config.squares.iter().filter(|s| self.values.get(s).unwrap().len() == 1).count()
+    }
+
+    pub fn config(&self) -> &'a Config {
+        self.config
+    }
+
+    // Empty for squares outside the config.
+    pub fn candidates(&self, square : &SquareId) -> Vec<SquareValue> {
+        self.digits(square).into_iter().map(SquareValue::from).collect()
+    }
+
+    // The candidates as bits, bit v for digit v, for constraints that work on sets of digits
+    // without allocating; 0 for squares outside the config.
+    pub fn candidate_mask(&self, square : &SquareId) -> u32 {
+        self.values.get(square).map_or(0, |vs| vs.iter().fold(0, |mask, &d| mask | 1 << d.get()))
+    }
+
+    // The candidates in ascending order.
+    fn digits(&self, square : &SquareId) -> Vec<Digit> {
+        let mut vs : Vec<Digit> = self.values.get(square).map_or(Vec::new(), |vs| vs.iter().cloned().collect());
+        vs.sort();
+        vs
+    }
+
+    // One lookup, without the sorting of `candidates`, for asking per digit whether it can still
+    // go in a square; false for squares outside the config and values that aren't digits.
+    pub fn is_candidate(&self, square : &SquareId, value : SquareValue) -> bool {
+        match (self.values.get(square), Digit::new(value)) {
+            (Some(vs), Some(d)) => vs.contains(&d),
+            _ => false
+        }
+    }
+
+    pub fn value(&self, square : &SquareId) -> Option<SquareValue> {
+        match self.values.get(square) {
+            Some(vs) if vs.len() == 1 => vs.iter().next().map(Digit::get),
+            _ => None
+        }
+    }
+
+    // Puts a value in a square and removes it from the peers, without any further propagation.
+    // False when the value isn't a candidate there, or the square isn't part of the config.
+    pub fn place(&mut self, square : &SquareId, value : &SquareValue) -> bool {
+        let digit = match Digit::new(*value) {
+            Some(d) if self.values.get(square).is_some_and(|vs| vs.contains(&d)) => d,
+            _ => return false
+        };
+        let open = self.values.get(square).is_some_and(|vs| vs.len() > 1);
+        self.values.insert(*square, [digit].iter().cloned().collect());
+        self.placed.insert(*square);
+        if open {
+            self.settled(square);
+        }
+        for s2 in self.config.peers.get(square).unwrap() {
+            if ! self.remove_candidate(s2, value) {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn is_placed(&self, square : &SquareId) -> bool {
+        self.placed.contains(square)
+    }
+
+    // Squares still allowing the digit, including any it is placed in, in config order; what a
+    // "highlight all 7s" view shows.
+    pub fn cells_with_candidate(&self, digit : SquareValue) -> Vec<SquareId> {
+        let digit = match Digit::new(digit) {
+            Some(d) => d,
+            None => return Vec::new()
+        };
+        self.config.squares.iter().filter(|s| self.values.get(s).unwrap().contains(&digit)).cloned().collect()
+    }
+
+    // Units where the digit has one place left that isn't placed yet, with that square: the
+    // hidden singles for it.
+    pub fn units_with_single_place(&self, digit : SquareValue) -> Vec<(Unit, SquareId)> {
+        self.config.unitlist.iter()
+                            .filter_map(|u| {
+                                let places : Vec<&SquareId> = u.iter().filter(|s| self.candidates(s).contains(&digit)).collect();
+                                match places.len() {
+                                    1 if ! self.is_placed(places[0]) => Some((*u, *places[0])),
+                                    _ => None
+                                }
+                            })
+                            .collect()
+    }
+
+    // Pointing and claiming on classic configs: digits confined to where a box meets a row or
+    // column, so they can go nowhere else in the other unit. Only those that still rule out some
+    // candidate are listed, box against line before line against box, digits in order.
+    pub fn locked_candidates(&self) -> Vec<LockedCandidate> {
+        self.find_locked_candidates(false)
+    }
+
+    // The first of `locked_candidates`, without looking for the rest.
+    pub fn first_locked_candidate(&self) -> Option<LockedCandidate> {
+        self.find_locked_candidates(true).pop()
+    }
+
+    fn find_locked_candidates(&self, first_only : bool) -> Vec<LockedCandidate> {
+        let config = self.config;
+        let mut locked = Vec::new();
+        let lines : Vec<&Unit> = config.rows().iter().chain(config.cols().iter()).collect();
+        for b in config.boxes() {
+            for l in &lines {
+                if ! b.iter().any(|s| l.contains(s)) {
+                    continue;
+                }
+                for &(u1, u2) in &[(b, *l), (*l, b)] {
+                    for d in config.digits() {
+                        let cells : Vec<SquareId> = u1.iter().filter(|s| self.candidates(s).contains(&d)).cloned().collect();
+                        if cells.len() < 2 || ! cells.iter().all(|s| u2.contains(s)) {
+                            continue;
+                        }
+                        let eliminations : Vec<SquareId> = u2.iter()
+                                                             .filter(|s| ! u1.contains(s) && self.candidates(s).contains(&d))
+                                                             .cloned()
+                                                             .collect();
+                        if ! eliminations.is_empty() {
+                            locked.push(LockedCandidate {
+                                digit : d,
+                                cells,
+                                eliminations
+                            });
+                            if first_only {
+                                return locked;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        locked
+    }
+
+    // 1 flag byte (bit 0: solved, bits 4 to 7: `STATE_VERSION`), 92 bytes holding 81x9 candidate
+    // bits in square order and 11 bytes with one placed bit per square. Bit k lives in bit k % 8
+    // of byte k / 8.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; STATE_BYTES];
+        bytes[0] = STATE_VERSION << 4;
+        if self.is_solved() {
+            bytes[0] |= 1;
+        }
+        for (i, s) in self.config.squares.iter().enumerate() {
+            for v in self.values.get(s).unwrap() {
+                let k = i * 9 + (v.get() as usize - 1);
+                bytes[1 + k / 8] |= 1 << (k % 8);
+            }
+            if self.placed.contains(s) {
+                bytes[93 + i / 8] |= 1 << (i % 8);
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(config : &'a Config, bytes : &[u8]) -> Result<State<'a>, String> {
+        if bytes.len() != STATE_BYTES {
+            return Err("Incorrect length".to_string());
+        }
+        if bytes[0] >> 4 > STATE_VERSION {
+            return Err(format!("State version {} is newer than this build reads, up to {}", bytes[0] >> 4, STATE_VERSION));
+        }
+        let mut state = State::new(config);
+        for (i, s) in config.squares.iter().enumerate() {
+            let vs : SquareValues = Digit::all().into_iter()
+                                                .filter(|d| { let k = i * 9 + d.get() as usize - 1; bytes[1 + k / 8] & (1 << (k % 8)) != 0 })
+                                                .collect();
+            if vs.is_empty() {
+                return Err(format!("No candidates for {}", square_name(s)));
+            }
+            state.values.insert(*s, vs);
+            if bytes[93 + i / 8] & (1 << (i % 8)) != 0 {
+                state.placed.insert(*s);
+            }
+        }
+        Ok(state)
+    }
+
+    // Removes a candidate and propagates the consequences, false on a contradiction.
+    // Values that aren't digits are never candidates, so there is nothing to remove.
+    pub fn prune_candidate(&mut self, square : &SquareId, value : &SquareValue) -> bool {
+        match Digit::new(*value) {
+            Some(d) => self.eliminate(square, &d),
+            None => self.values.contains_key(square)
+        }
+    }
+
+    pub fn remove_candidate(&mut self, square : &SquareId, value : &SquareValue) -> bool {
+        let (removed, left) = match self.values.get_mut(square) {
+            Some(vs) => (Digit::new(*value).is_some_and(|d| vs.remove(&d)), vs.len()),
+            None => return false
+        };
+        if removed && left == 1 {
+            self.settled(square);
+        }
+        left > 0
+    }
+
+    // Reports a square that just went down to one candidate, if anyone is listening. A receiver
+    // that has gone away doesn't stop the solve.
+    fn settled(&self, square : &SquareId) {
+        if let (Some(events), Some(value)) = (self.events.as_ref(), self.value(square)) {
+            let _ = events.send((*square, value));
+        }
+    }
 }
 
 impl<'a> std::fmt::Display for State<'a> {
@@ -220,7 +2144,7 @@ impl<'a> std::fmt::Display for State<'a> {
         fn as_string(values : &SquareValues) -> String {
             let mut s = String::new();
             for v in values {
-                s.push(std::char::from_digit(*v, 10).unwrap());
+                s.push(std::char::from_digit(v.get(), 10).unwrap());
             }
             s
         }
@@ -231,106 +2155,265 @@ impl<'a> std::fmt::Display for State<'a> {
             } else if (i%27) == 0 {
                 write!(f, "\n---------+---------+---------\n").unwrap();
             } else if (i%9) == 0 {
-                write!(f, "\n").unwrap();
+                writeln!(f).unwrap();
             } else if (i%3) == 0 {
                 write!(f, "|").unwrap();
             }
             write!(f, "{: ^3}", as_string(self.values.get(s).unwrap())).unwrap();
         }
-        write!(f, "\n")
+        writeln!(f)
     }
 }
 
-#[derive(Debug)]
 pub struct Config {
     squares : Vec<SquareId>,
+    unitlist : Vec<Unit>,
     units : HashMap<SquareId, Vec<Unit>>,
     peers : HashMap<SquareId, PeerSet>,
     digits : SquareValues,
-    values : HashMap<SquareId, SquareValues>
+    values : HashMap<SquareId, SquareValues>,
+    constraints : Vec<Box<dyn Constraint>>
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Config {
 
     pub fn new() -> Config {
-        let squares : [SquareId; 81] =
-            [('A', '1'), ('A', '2'), ('A', '3'), ('A', '4'), ('A', '5'), ('A', '6'), ('A', '7'), ('A', '8'), ('A', '9'),
-            ('B', '1'), ('B', '2'), ('B', '3'), ('B', '4'), ('B', '5'), ('B', '6'), ('B', '7'), ('B', '8'), ('B', '9'),
-            ('C', '1'), ('C', '2'), ('C', '3'), ('C', '4'), ('C', '5'), ('C', '6'), ('C', '7'), ('C', '8'), ('C', '9'),
-            ('D', '1'), ('D', '2'), ('D', '3'), ('D', '4'), ('D', '5'), ('D', '6'), ('D', '7'), ('D', '8'), ('D', '9'),
-            ('E', '1'), ('E', '2'), ('E', '3'), ('E', '4'), ('E', '5'), ('E', '6'), ('E', '7'), ('E', '8'), ('E', '9'),
-            ('F', '1'), ('F', '2'), ('F', '3'), ('F', '4'), ('F', '5'), ('F', '6'), ('F', '7'), ('F', '8'), ('F', '9'),
-            ('G', '1'), ('G', '2'), ('G', '3'), ('G', '4'), ('G', '5'), ('G', '6'), ('G', '7'), ('G', '8'), ('G', '9'),
-            ('H', '1'), ('H', '2'), ('H', '3'), ('H', '4'), ('H', '5'), ('H', '6'), ('H', '7'), ('H', '8'), ('H', '9'),
-            ('I', '1'), ('I', '2'), ('I', '3'), ('I', '4'), ('I', '5'), ('I', '6'), ('I', '7'), ('I', '8'), ('I', '9')];
-
-        let unitlist : [Unit; 27] =
-            [[('A', '1'), ('A', '2'), ('A', '3'), ('A', '4'), ('A', '5'), ('A', '6'), ('A', '7'), ('A', '8'), ('A', '9')],
-            [('B', '1'), ('B', '2'), ('B', '3'), ('B', '4'), ('B', '5'), ('B', '6'), ('B', '7'), ('B', '8'), ('B', '9')],
-            [('C', '1'), ('C', '2'), ('C', '3'), ('C', '4'), ('C', '5'), ('C', '6'), ('C', '7'), ('C', '8'), ('C', '9')],
-            [('D', '1'), ('D', '2'), ('D', '3'), ('D', '4'), ('D', '5'), ('D', '6'), ('D', '7'), ('D', '8'), ('D', '9')],
-            [('E', '1'), ('E', '2'), ('E', '3'), ('E', '4'), ('E', '5'), ('E', '6'), ('E', '7'), ('E', '8'), ('E', '9')],
-            [('F', '1'), ('F', '2'), ('F', '3'), ('F', '4'), ('F', '5'), ('F', '6'), ('F', '7'), ('F', '8'), ('F', '9')],
-            [('G', '1'), ('G', '2'), ('G', '3'), ('G', '4'), ('G', '5'), ('G', '6'), ('G', '7'), ('G', '8'), ('G', '9')],
-            [('H', '1'), ('H', '2'), ('H', '3'), ('H', '4'), ('H', '5'), ('H', '6'), ('H', '7'), ('H', '8'), ('H', '9')],
-            [('I', '1'), ('I', '2'), ('I', '3'), ('I', '4'), ('I', '5'), ('I', '6'), ('I', '7'), ('I', '8'), ('I', '9')],
-            [('A', '1'), ('B', '1'), ('C', '1'), ('D', '1'), ('E', '1'), ('F', '1'), ('G', '1'), ('H', '1'), ('I', '1')],
-            [('A', '2'), ('B', '2'), ('C', '2'), ('D', '2'), ('E', '2'), ('F', '2'), ('G', '2'), ('H', '2'), ('I', '2')],
-            [('A', '3'), ('B', '3'), ('C', '3'), ('D', '3'), ('E', '3'), ('F', '3'), ('G', '3'), ('H', '3'), ('I', '3')],
-            [('A', '4'), ('B', '4'), ('C', '4'), ('D', '4'), ('E', '4'), ('F', '4'), ('G', '4'), ('H', '4'), ('I', '4')],
-            [('A', '5'), ('B', '5'), ('C', '5'), ('D', '5'), ('E', '5'), ('F', '5'), ('G', '5'), ('H', '5'), ('I', '5')],
-            [('A', '6'), ('B', '6'), ('C', '6'), ('D', '6'), ('E', '6'), ('F', '6'), ('G', '6'), ('H', '6'), ('I', '6')],
-            [('A', '7'), ('B', '7'), ('C', '7'), ('D', '7'), ('E', '7'), ('F', '7'), ('G', '7'), ('H', '7'), ('I', '7')],
-            [('A', '8'), ('B', '8'), ('C', '8'), ('D', '8'), ('E', '8'), ('F', '8'), ('G', '8'), ('H', '8'), ('I', '8')],
-            [('A', '9'), ('B', '9'), ('C', '9'), ('D', '9'), ('E', '9'), ('F', '9'), ('G', '9'), ('H', '9'), ('I', '9')],
-            [('A', '1'), ('A', '2'), ('A', '3'), ('B', '1'), ('B', '2'), ('B', '3'), ('C', '1'), ('C', '2'), ('C', '3')],
-            [('A', '4'), ('A', '5'), ('A', '6'), ('B', '4'), ('B', '5'), ('B', '6'), ('C', '4'), ('C', '5'), ('C', '6')],
-            [('A', '7'), ('A', '8'), ('A', '9'), ('B', '7'), ('B', '8'), ('B', '9'), ('C', '7'), ('C', '8'), ('C', '9')],
-            [('D', '1'), ('D', '2'), ('D', '3'), ('E', '1'), ('E', '2'), ('E', '3'), ('F', '1'), ('F', '2'), ('F', '3')],
-            [('D', '4'), ('D', '5'), ('D', '6'), ('E', '4'), ('E', '5'), ('E', '6'), ('F', '4'), ('F', '5'), ('F', '6')],
-            [('D', '7'), ('D', '8'), ('D', '9'), ('E', '7'), ('E', '8'), ('E', '9'), ('F', '7'), ('F', '8'), ('F', '9')],
-            [('G', '1'), ('G', '2'), ('G', '3'), ('H', '1'), ('H', '2'), ('H', '3'), ('I', '1'), ('I', '2'), ('I', '3')],
-            [('G', '4'), ('G', '5'), ('G', '6'), ('H', '4'), ('H', '5'), ('H', '6'), ('I', '4'), ('I', '5'), ('I', '6')],
-            [('G', '7'), ('G', '8'), ('G', '9'), ('H', '7'), ('H', '8'), ('H', '9'), ('I', '7'), ('I', '8'), ('I', '9')]];
+        let unitlist = ROWS.iter().chain(COLS.iter()).chain(BOXES.iter()).cloned().collect();
+        Config::build(ALL_SQUARES.to_vec(), unitlist, &[])
+    }
+
+    // The classic rules plus extra units (irregular regions, diagonals) in which every digit
+    // appears once, and extra pairs of squares that may not hold the same digit (anti-knight).
+    pub fn with_constraints(extra_units : &[Unit], extra_peers : &[(SquareId, SquareId)]) -> Config {
+        let classic = Config::new();
+        let mut unitlist = classic.unitlist.clone();
+        unitlist.extend(extra_units.iter().cloned());
+        Config::build(classic.squares.clone(), unitlist, extra_peers)
+    }
 
+    fn build(squares : Vec<SquareId>, unitlist : Vec<Unit>, extra_peers : &[(SquareId, SquareId)]) -> Config {
         let units = squares.iter()
-                            .map(|s| (s.clone(), unitlist.iter()
+                            .map(|s| (*s, unitlist.iter()
                                                          .filter(|u| u.contains(s))
                                                          .cloned()
                                                          .collect::<Vec<Unit>>()))
                             .collect::<HashMap<SquareId, Vec<Unit>>>();
 
-        let digits : SquareValues = [1, 2, 3, 4, 5, 6, 7, 8, 9].iter().cloned().collect::<SquareValues>();
+        let digits : SquareValues = Digit::all().into_iter().collect();
+
+        let mut peers = squares.iter()
+                               .map(|s| (*s, units.get(s).unwrap().iter()
+                                                                         .flat_map(|u| u.iter()
+                                                                                        .filter(|s2| s2 != &s)
+                                                                                        .cloned())
+                                                                         .collect::<PeerSet>()))
+                               .collect::<HashMap<SquareId, PeerSet>>();
+        for &(a, b) in extra_peers.iter().filter(|&&(a, b)| a != b) {
+            if let (true, true) = (peers.contains_key(&a), peers.contains_key(&b)) {
+                peers.get_mut(&a).unwrap().insert(b);
+                peers.get_mut(&b).unwrap().insert(a);
+            }
+        }
 
         Config {
-            squares : squares.iter().cloned().collect(),
-            units : units.clone(),
-            peers : squares.iter()
-                            .map(|s| (s.clone(), units.get(s).unwrap().iter()
-                                                                      .flat_map(|u| u.iter()
-                                                                                     .filter(|s2| s2 != &s)
-                                                                                     .cloned())
-                                                                      .collect::<PeerSet>()))
-                            .collect::<HashMap<SquareId, PeerSet>>(),
-            digits : digits.clone(),
             values : squares.iter()
-                            .map(|s| (s.clone(), digits.clone()))
-                            .collect::<HashMap<SquareId, SquareValues>>()
+                            .map(|s| (*s, digits.clone()))
+                            .collect::<HashMap<SquareId, SquareValues>>(),
+            squares,
+            unitlist,
+            units,
+            peers,
+            digits,
+            constraints : Vec::new()
+        }
+    }
+
+    pub fn squares(&self) -> &Vec<SquareId> {
+        &self.squares
+    }
+
+    pub fn unit_list(&self) -> &Vec<Unit> {
+        &self.unitlist
+    }
+
+    pub fn rows(&self) -> &[Unit] {
+        &self.unitlist[0..9]
+    }
+
+    pub fn cols(&self) -> &[Unit] {
+        &self.unitlist[9..18]
+    }
+
+    pub fn boxes(&self) -> &[Unit] {
+        &self.unitlist[18..27]
+    }
+
+    pub fn units(&self, square : &SquareId) -> &Vec<Unit> {
+        self.units.get(square).unwrap()
+    }
+
+    pub fn peers(&self, square : &SquareId) -> &PeerSet {
+        self.peers.get(square).unwrap()
+    }
+
+    pub fn digits(&self) -> Vec<SquareValue> {
+        let mut digits : Vec<SquareValue> = self.digits.iter().map(Digit::get).collect();
+        digits.sort();
+        digits
+    }
+
+    pub fn add_constraint(&mut self, constraint : Box<dyn Constraint>) {
+        self.constraints.push(constraint);
+    }
+
+    // Only the 27 standard units and no extra peers or constraints, so the bitmask grid applies.
+    pub fn is_classic(&self) -> bool {
+        self.unitlist.len() == 27 && self.constraints.is_empty() && self.peers.values().all(|p| p.len() == 20)
+    }
+
+    pub fn constraint_count(&self) -> usize {
+        self.constraints.len()
+    }
+
+    // Approximate heap and inline size; hash tables are counted at twice their contents.
+    pub fn memory_estimate(&self) -> usize {
+        let square = std::mem::size_of::<SquareId>();
+        let unit = std::mem::size_of::<Unit>();
+        let units : usize = self.units.values().map(|us| us.len() * unit).sum();
+        let peers : usize = self.peers.values().map(|ps| ps.len() * square * 2).sum();
+        let values = self.squares.len() * (square + std::mem::size_of::<SquareValues>() + self.digits.len() * (std::mem::size_of::<Digit>() + 4));
+        std::mem::size_of::<Config>() + self.squares.len() * square + self.unitlist.len() * unit + units * 2 + peers * 2 +
+            values * 2 + self.constraints.len() * std::mem::size_of::<Box<dyn Constraint>>()
+    }
+}
+
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Config {{ units: {}, constraints: {} }}", self.unitlist.len(), self.constraints.len())
+    }
+}
+
+// Order of the squares in a grid string. Row-major is A1..A9, B1..; column-major is A1..I1, A2..;
+// box-major is the top left box row by row, then the next box to its right.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SquareOrder {
+    RowMajor,
+    ColumnMajor,
+    BoxMajor
+}
+
+impl SquareOrder {
+
+    pub fn all() -> Vec<SquareOrder> {
+        vec![SquareOrder::RowMajor, SquareOrder::ColumnMajor, SquareOrder::BoxMajor]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SquareOrder::RowMajor => "row",
+            SquareOrder::ColumnMajor => "column",
+            SquareOrder::BoxMajor => "box"
+        }
+    }
+
+    // Row-major index of the square at position `i` of a 9 x 9 grid string in this order.
+    pub fn row_major_index(&self, i : usize) -> usize {
+        match *self {
+            SquareOrder::RowMajor => i,
+            SquareOrder::ColumnMajor => i % 9 * 9 + i / 9,
+            SquareOrder::BoxMajor => {
+                let (b, k) = (i / 9, i % 9);
+                (b / 3 * 3 + k / 3) * 9 + b % 3 * 3 + k % 3
+            }
         }
     }
 }
 
-pub struct StringStartStateHandler;
+// The orders under which a grid string reads as a proper puzzle, givens without repeats and a
+// unique solution, to tell how a dataset is laid out. A box-major grid misread as row-major is
+// rarely still proper, but a column-major grid read as row-major is the transposed puzzle, which
+// is just as proper: row and column order always pass or fail together and only the source of the
+// data settles which it is.
+pub fn plausible_orders(grid : &str) -> Vec<SquareOrder> {
+    let config = Config::new();
+    let handler = StringStartStateHandler::checking_conflicts();
+    SquareOrder::all().into_iter()
+                      .filter(|&order| handler.with_order(order).parse(&config, grid.to_string()).ok()
+                                              .and_then(|start_state| Grid9::from_start_state(&start_state))
+                                              .is_some_and(|g| g.count_solutions(2) == 1))
+                      .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StringStartStateHandler {
+    check_conflicts : bool,
+    blank : char,
+    order : SquareOrder
+}
 
 pub trait StartStateHandler<T> {
     fn parse(&self, config : &Config, input : T) -> Result<StartState, String>;
     fn generate(&self, config : &Config, state : StartState) -> T;
 }
 
+impl Default for StringStartStateHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl StringStartStateHandler {
 
     pub fn new() -> StringStartStateHandler {
-        StringStartStateHandler
+        StringStartStateHandler {
+            check_conflicts : false,
+            blank : '.',
+            order : SquareOrder::RowMajor
+        }
+    }
+
+    // Also rejects grids giving a digit twice among peers, naming the squares, instead of leaving
+    // the contradiction for propagation to run into.
+    pub fn checking_conflicts() -> StringStartStateHandler {
+        StringStartStateHandler {
+            check_conflicts : true,
+            ..StringStartStateHandler::new()
+        }
+    }
+
+    // Character generated for blank squares, '.' unless set; many formats want '0' or '-'.
+    // Parsing takes anything but 1-9 as a blank, so grids read back whatever the marker, which is
+    // why a digit 1-9 is an error here.
+    pub fn with_blank(self, blank : char) -> Result<StringStartStateHandler, String> {
+        if ('1'..='9').contains(&blank) {
+            return Err(format!("Blank marker {} is a digit", blank));
+        }
+        Ok(StringStartStateHandler {
+            blank,
+            ..self
+        })
+    }
+
+    pub fn blank(&self) -> char {
+        self.blank
+    }
+
+    // Reads and writes the squares in `order` instead of row-major; classic 9 x 9 grids only.
+    pub fn with_order(self, order : SquareOrder) -> StringStartStateHandler {
+        StringStartStateHandler {
+            order,
+            ..self
+        }
+    }
+
+    pub fn order(&self) -> SquareOrder {
+        self.order
     }
 
 }
@@ -338,32 +2421,152 @@ impl StringStartStateHandler {
 impl StartStateHandler<String> for StringStartStateHandler {
 
     fn parse(&self, config: &Config, grid : String) -> Result<StartState, String> {
-        if grid.len() != 81 {
+        let chars : Vec<char> = grid.chars().collect();
+        if chars.len() != 81 {
             return Err("Incorrect length".to_string());
         }
         let mut grid_chars : [u32; 81] = [0 ; 81];
-        for (i,v) in grid.as_bytes().iter().enumerate() {
-            match (*v as char).to_digit(10) {
-                Some(v32) => {
-                    if config.digits.contains(&v32) {
-                        grid_chars[i] = v32;
-                    }
-                },
-                None => ()
-            };
+        for (i,v) in chars.iter().enumerate() {
+            if let Some(v32) = v.to_digit(10).filter(|&v32| Digit::new(v32).is_some_and(|d| config.digits.contains(&d))) {
+                grid_chars[self.order.row_major_index(i)] = v32;
+            }
         }
-        Ok(config.squares.iter().cloned().zip(grid_chars.iter().cloned()).collect())
+        let start_state : StartState = config.squares.iter().cloned().zip(grid_chars.iter().cloned()).collect();
+        if self.check_conflicts {
+            let conflicts = conflicting_givens(config, &start_state);
+            if ! conflicts.is_empty() {
+                let names : Vec<String> = conflicts.iter()
+                                                   .map(|&((a, v), (b, _))| format!("{}={} {}={}", square_name(&a), v, square_name(&b), v))
+                                                   .collect();
+                return Err(format!("Conflicting givens {}", names.join(", ")));
+            }
+        }
+        Ok(start_state)
     }
 
     fn generate(&self, config : &Config, state : StartState) -> String {
-        let mut chars = ['.'; 81];
-        for (square, value) in state {
-            match config.squares.iter().position(|&s| s == square) {
-                Some(index) => chars[index] = std::char::from_digit(value, 10).unwrap(),
-                _ => ()
-            };
+        let mut chars = [self.blank; 81];
+        for (square, value) in state.into_iter().filter(|&(_, v)| v != 0) {
+            // values outside 1..9 can't be written and are left blank; see `StartState::validate`
+            if let (Some(index), Some(ch)) = (config.squares.iter().position(|&s| s == square), std::char::from_digit(value, 10)) {
+                chars[index] = ch;
+            }
         }
-        chars.iter().cloned().collect()
+        (0..81).map(|i| chars[self.order.row_major_index(i)]).collect()
+    }
+
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(givens : &str) -> String {
+        format!("{:.<81}", givens)
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn unknown_squares_are_rejected() {
+        let config = Config::new();
+        let start_state : StartState = vec![(('J', '1'), 5)].into_iter().collect();
+        assert_eq!(start_state.validate(&config), Err(StartStateError::UnknownSquare(('J', '1'))));
+        let eliminating = StartState::new(Vec::new()).with_eliminations(vec![(('A', '0'), 5)]);
+        assert_eq!(eliminating.validate(&config), Err(StartStateError::UnknownSquare(('A', '0'))));
+        assert_eq!(Solver::new().solve(start_state).err(), Some("Unknown square J1".to_string()));
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        let config = Config::new();
+        let start_state : StartState = vec![(('A', '1'), 10)].into_iter().collect();
+        assert_eq!(start_state.validate(&config), Err(StartStateError::InvalidValue(('A', '1'), 10)));
+        let blank : StartState = vec![(('A', '1'), 0)].into_iter().collect();
+        assert_eq!(blank.validate(&config), Ok(()));
+        // a blank can't be ruled out
+        let eliminating = StartState::new(Vec::new()).with_eliminations(vec![(('A', '1'), 0)]);
+        assert_eq!(eliminating.validate(&config), Err(StartStateError::InvalidValue(('A', '1'), 0)));
+        assert_eq!(Solver::new().solve(start_state).err(), Some("Invalid value 10 for A1".to_string()));
+    }
+
+    #[test]
+    fn conflicting_givens_are_named() {
+        let config = Config::new();
+        let handler = StringStartStateHandler::new();
+        let start_state = handler.parse(&config, grid("55")).unwrap();
+        assert_eq!(conflicting_givens(&config, &start_state), vec![((('A', '1'), 5), (('A', '2'), 5))]);
+        assert_eq!(StringStartStateHandler::checking_conflicts().parse(&config, grid("55")),
+                   Err("Conflicting givens A1=5 A2=5".to_string()));
+        assert_eq!(Solver::new().solve(start_state).err(),
+                   Some("Failed solving puzzle, conflicting givens A1=5 A2=5".to_string()));
+    }
+
+    #[test]
+    fn solving_twice_gives_the_same_solve() {
+        // needs guessing, where tied values could otherwise be tried in a different order
+        let grid = "4.....8.5.3..........7......2.....6.....8.4......1.......6.3.7.5..2.....1.4......";
+        let solver = Solver::new();
+        let start_state = StringStartStateHandler::new().parse(solver.config(), grid.to_string()).unwrap();
+        let (first, guesses) = solver.solve_counting(start_state.clone()).unwrap();
+        assert!(guesses > 0);
+        assert!(solver.solve_counting(start_state.clone()).unwrap() == (first.clone(), guesses));
+        let other = Solver::new();
+        assert!(other.solve_counting(start_state.clone()).unwrap() == (first.clone(), guesses));
+        let mut machine = solver.machine(start_state.clone());
+        let mut again = other.machine(start_state);
+        assert_eq!(machine.run(usize::MAX), again.run(usize::MAX));
+        assert_eq!(machine.steps(), again.steps());
+        assert!(machine.solution() == Some(&first));
+    }
+
+    #[cfg(feature = "generator")]
+    #[test]
+    fn verified_puzzles_solve_to_the_recorded_solution() {
+        let generator = Generator::new().with_seed(1).with_verification(true);
+        let result = generator.generate_rated(Difficulty::Easy, 20).unwrap();
+        assert!(result.rejected.is_empty());
+        assert_eq!(generator.verify(&result.puzzle, &result.solution), Ok(()));
+        let solver = Solver::new();
+        assert_eq!(solver.count_solutions(result.puzzle.clone(), 2), 1);
+        let solved = solver.solve(result.puzzle.clone()).unwrap();
+        assert_eq!(result.solution.len(), 81);
+        assert!(result.solution.iter().all(|&(s, v)| solved.value(&s) == Some(v)));
+        let mut wrong = result.solution.clone();
+        let (square, v) = wrong[0];
+        wrong.insert((square, v % 9 + 1));
+        assert!(generator.verify(&result.puzzle, &wrong).is_err());
+    }
+
+    #[test]
+    fn blanks_are_written_with_the_marker() {
+        let config = Config::new();
+        assert_eq!(StringStartStateHandler::new().with_blank('5'), Err("Blank marker 5 is a digit".to_string()));
+        let handler = StringStartStateHandler::new().with_blank('0').unwrap();
+        let start_state = handler.parse(&config, grid("12")).unwrap();
+        assert_eq!(handler.generate(&config, start_state.clone()), format!("{:0<81}", "12"));
+        assert_eq!(handler.parse(&config, handler.generate(&config, start_state.clone())), Ok(start_state));
+    }
+
+    #[test]
+    fn cancellable_solves_fail_like_solve() {
+        let config = Config::new();
+        let solver = Solver::new();
+        let cancel = CancelToken::new();
+        // no digit twice among peers, but nothing is left for A9
+        let givens = format!("12345678.{}9", ".".repeat(35));
+        let start_state = StringStartStateHandler::new().parse(&config, grid(&givens)).unwrap();
+        let failure = solver.solve(start_state.clone()).err().unwrap();
+        assert!(failure.starts_with("Failed solving puzzle, conflicting givens "));
+        assert_eq!(solver.solve_cancellable(start_state, &cancel).err(), Some(failure));
+        let invalid : StartState = vec![(('A', '1'), 10)].into_iter().collect();
+        assert_eq!(solver.solve_cancellable(invalid.clone(), &cancel).err(), solver.solve(invalid).err());
+    }
+
+    #[test]
+    fn grid_strings_must_have_81_squares() {
+        let config = Config::new();
+        let handler = StringStartStateHandler::new();
+        assert_eq!(handler.parse(&config, String::new()), Err("Incorrect length".to_string()));
+        assert_eq!(handler.parse(&config, ".".repeat(80)), Err("Incorrect length".to_string()));
+        assert_eq!(handler.parse(&config, ".".repeat(82)), Err("Incorrect length".to_string()));
+        assert!(handler.parse(&config, ".".repeat(81)).is_ok());
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::Instant;
+#[cfg(all(feature = "metrics", feature = "generator"))]
+use std::time::Duration;
+#[cfg(feature = "generator")]
+use sudoku::GenCost;
+#[cfg(feature = "generator")]
+use pipeline::StageReport;
+
+// Solve counters and histograms reported through the `metrics` facade when the "metrics" feature
+// is on; whichever exporter the application installs picks them up. Without the feature these
+// are empty and cost nothing beyond reading the clock.
+//
+//   sudoku_solves_total              solves that found a solution
+//   sudoku_solve_failures_total      solves that found none
+//   sudoku_solve_duration_seconds    wall time per solve
+//   sudoku_solve_guesses             guesses per successful solve
+//   sudoku_generations_total         generations that found a puzzle
+//   sudoku_generation_failures_total generations that ran out of attempts or time
+//   sudoku_generation_seconds        wall time per generation, labelled by phase: uniqueness
+//                                    checks, rating and digging (the rest)
+//   sudoku_uniqueness_checks         uniqueness checks per generation
+//   sudoku_pipeline_stage_seconds    wall time per stage of a `pipeline::Job`, labelled by stage
+//   sudoku_pipeline_stage_puzzles    puzzles a stage kept, labelled by stage
+
+#[cfg(feature = "metrics")]
+pub fn solve_finished(start : Instant, guesses : Option<usize>) {
+    let elapsed = start.elapsed();
+    ::metrics::histogram!("sudoku_solve_duration_seconds").record(elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9);
+    match guesses {
+        Some(guesses) => {
+            ::metrics::counter!("sudoku_solves_total").increment(1);
+            ::metrics::histogram!("sudoku_solve_guesses").record(guesses as f64);
+        },
+        None => ::metrics::counter!("sudoku_solve_failures_total").increment(1)
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn solve_finished(_start : Instant, _guesses : Option<usize>) {
+}
+
+#[cfg(all(feature = "metrics", feature = "generator"))]
+pub fn generation_finished(cost : &GenCost, found : bool) {
+    let seconds = |d : Duration| d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9;
+    ::metrics::histogram!("sudoku_generation_seconds", "phase" => "uniqueness").record(seconds(cost.uniqueness));
+    ::metrics::histogram!("sudoku_generation_seconds", "phase" => "rating").record(seconds(cost.rating));
+    ::metrics::histogram!("sudoku_generation_seconds", "phase" => "digging").record(seconds(cost.digging()));
+    ::metrics::histogram!("sudoku_uniqueness_checks").record(cost.checks as f64);
+    if found {
+        ::metrics::counter!("sudoku_generations_total").increment(1);
+    } else {
+        ::metrics::counter!("sudoku_generation_failures_total").increment(1);
+    }
+}
+
+#[cfg(all(not(feature = "metrics"), feature = "generator"))]
+pub fn generation_finished(_cost : &GenCost, _found : bool) {
+}
+
+#[cfg(all(feature = "metrics", feature = "generator"))]
+pub fn pipeline_stage_finished(stage : &StageReport) {
+    let seconds = stage.elapsed.as_secs() as f64 + stage.elapsed.subsec_nanos() as f64 / 1e9;
+    ::metrics::histogram!("sudoku_pipeline_stage_seconds", "stage" => stage.stage).record(seconds);
+    ::metrics::counter!("sudoku_pipeline_stage_puzzles", "stage" => stage.stage).increment(stage.output as u64);
+}
+
+#[cfg(all(not(feature = "metrics"), feature = "generator"))]
+pub fn pipeline_stage_finished(_stage : &StageReport) {
+}
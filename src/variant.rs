@@ -0,0 +1,664 @@
+use constraint::{Allowed, Equal, Sandwich, Sum};
+use json::Json;
+use puzzle::{Provenance, Puzzle};
+use sudoku::{parse_square, square_name, Config, Solver, SquareId, SquareValue, Unit};
+
+// A puzzle together with the variant rules it is played under, read from a JSON descriptor:
+//
+//   {"version": 1,
+//    "givens": "<81 character grid>",
+//    "regions": [["A1", "A2", ...], ...],
+//    "diagonals": ["main", "anti"],
+//    "anti_knight": true,
+//    "disjoint_groups": true,
+//    "cages": [{"cells": ["A1", "A2"], "sum": 10}],
+//    "kropki": [{"cells": ["A1", "A2"], "color": "white"}],
+//    "thermos": [["A1", "A2", "A3"]],
+//    "clones": [[["A1", "A2"], ["E5", "E6"]]],
+//    "palindromes": [["C1", "D2", "E3", "F2"]],
+//    "little_killers": [{"from": "A2", "direction": "down_right", "sum": 40}],
+//    "arrows": [{"circle": "E5", "cells": ["E6", "E7"]}],
+//    "sandwiches": [{"row": "A", "sum": 12}, {"column": "3", "sum": 0}],
+//    "even": ["B2"],
+//    "odd": ["C3"],
+//    "author": "...", "source": "...", "license": "...", "created": "YYYY-MM-DD"}
+//
+// Only "givens" is required; the last four keys credit the puzzle, see `Provenance`. Descriptors
+// without a version predate it and read the same. Regions (nine squares each), diagonals and disjoint groups (the
+// squares at the same position in every box) are extra units. Cages, kropki dots and thermometers
+// keep their squares apart; cage sums are enforced, dot and thermometer arithmetic is left to the
+// caller. Thermometers list the bulb first. Clones pair two regions of the same shape, square by
+// square, whose digits must match; palindrome lines read the same in both directions. A little
+// killer sums the diagonal from its first square to the edge of the grid, repeats allowed, an
+// arrow sums to the digit in its circle and a sandwich sums the digits between the 1 and the 9.
+// Even and odd squares are parity clues.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    pub puzzle : Puzzle,
+    pub regions : Vec<Unit>,
+    pub diagonal : bool,
+    pub anti_diagonal : bool,
+    pub anti_knight : bool,
+    pub disjoint_groups : bool,
+    pub cages : Vec<Cage>,
+    pub kropki : Vec<Kropki>,
+    pub thermos : Vec<Vec<SquareId>>,
+    pub clones : Vec<(Vec<SquareId>, Vec<SquareId>)>,
+    pub palindromes : Vec<Vec<SquareId>>,
+    pub little_killers : Vec<LittleKiller>,
+    pub arrows : Vec<Arrow>,
+    pub sandwiches : Vec<SandwichClue>,
+    pub even : Vec<SquareId>,
+    pub odd : Vec<SquareId>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cage {
+    pub cells : Vec<SquareId>,
+    pub sum : Option<u32>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KropkiColor {
+    // consecutive digits
+    White,
+    // one digit double the other
+    Black
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Kropki {
+    pub cells : (SquareId, SquareId),
+    pub color : KropkiColor
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    DownRight,
+    DownLeft,
+    UpRight,
+    UpLeft
+}
+
+impl Direction {
+
+    pub fn all() -> Vec<Direction> {
+        vec![Direction::DownRight, Direction::DownLeft, Direction::UpRight, Direction::UpLeft]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Direction::DownRight => "down_right",
+            Direction::DownLeft => "down_left",
+            Direction::UpRight => "up_right",
+            Direction::UpLeft => "up_left"
+        }
+    }
+
+    // (row, column) step
+    fn step(&self) -> (i32, i32) {
+        match *self {
+            Direction::DownRight => (1, 1),
+            Direction::DownLeft => (1, -1),
+            Direction::UpRight => (-1, 1),
+            Direction::UpLeft => (-1, -1)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LittleKiller {
+    pub from : SquareId,
+    pub direction : Direction,
+    pub sum : u32
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Arrow {
+    pub circle : SquareId,
+    pub cells : Vec<SquareId>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Line {
+    Row(char),
+    Column(char)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SandwichClue {
+    pub line : Line,
+    pub sum : u32
+}
+
+impl SandwichClue {
+
+    pub fn cells(&self) -> Vec<SquareId> {
+        match self.line {
+            Line::Row(row) => "123456789".chars().map(|col| (row, col)).collect(),
+            Line::Column(col) => "ABCDEFGHI".chars().map(|row| (row, col)).collect()
+        }
+    }
+}
+
+impl LittleKiller {
+
+    pub fn cells(&self) -> Vec<SquareId> {
+        let (dr, dc) = self.direction.step();
+        let (mut r, mut c) = (self.from.0 as i32 - 'A' as i32, self.from.1 as i32 - '1' as i32);
+        let mut cells = Vec::new();
+        while (0..9).contains(&r) && (0..9).contains(&c) {
+            cells.push(((b'A' + r as u8) as char, (b'1' + c as u8) as char));
+            r += dr;
+            c += dc;
+        }
+        cells
+    }
+}
+
+// Version of the descriptor `to_json` writes.
+pub const JSON_VERSION : u32 = 1;
+
+const KEYS : [&str; 20] = ["version", "givens", "regions", "diagonals", "anti_knight", "disjoint_groups", "cages", "kropki", "thermos",
+                                  "clones", "palindromes", "little_killers", "arrows", "sandwiches", "even",
+                                  "odd", "author", "source", "license", "created"];
+
+impl Variant {
+
+    pub fn classic(puzzle : Puzzle) -> Variant {
+        Variant {
+            puzzle,
+            regions : Vec::new(),
+            diagonal : false,
+            anti_diagonal : false,
+            anti_knight : false,
+            disjoint_groups : false,
+            cages : Vec::new(),
+            kropki : Vec::new(),
+            thermos : Vec::new(),
+            clones : Vec::new(),
+            palindromes : Vec::new(),
+            little_killers : Vec::new(),
+            arrows : Vec::new(),
+            sandwiches : Vec::new(),
+            even : Vec::new(),
+            odd : Vec::new()
+        }
+    }
+
+    pub fn parse(descriptor : &str) -> Result<Variant, String> {
+        Variant::from_json(&Json::parse(descriptor)?)
+    }
+
+    pub fn from_json(json : &Json) -> Result<Variant, String> {
+        let fields = match *json {
+            Json::Object(ref fields) => fields,
+            _ => return Err("Variant descriptor must be an object".to_string())
+        };
+        // a misspelt rule would otherwise be silently dropped
+        if let Some((key, _)) = fields.iter().find(|&(key, _)| ! KEYS.contains(&key.as_str())) {
+            return Err(format!("Unknown variant key {}", key));
+        }
+        json.format_version(JSON_VERSION)?;
+        let givens : Puzzle = match json.get("givens").and_then(|g| g.as_str()) {
+            Some(givens) => givens.parse()?,
+            None => return Err("Variant descriptor needs givens".to_string())
+        };
+        let mut variant = Variant::classic(givens.with_provenance(Provenance::from_json(json)?));
+        for region in list(json, "regions")? {
+            let cells = squares(region)?;
+            let mut unit = [('A', '1'); 9];
+            if cells.len() != unit.len() {
+                return Err("A region needs nine squares".to_string());
+            }
+            unit.copy_from_slice(&cells);
+            variant.regions.push(unit);
+        }
+        for diagonal in list(json, "diagonals")? {
+            match diagonal.as_str() {
+                Some("main") => variant.diagonal = true,
+                Some("anti") => variant.anti_diagonal = true,
+                _ => return Err(format!("Unknown diagonal {}", diagonal))
+            }
+        }
+        variant.anti_knight = flag(json, "anti_knight")?;
+        variant.disjoint_groups = flag(json, "disjoint_groups")?;
+        for cage in list(json, "cages")? {
+            let sum = match cage.get("sum") {
+                Some(sum) => match sum.as_f64() {
+                    Some(n) if n >= 1.0 && n.fract() == 0.0 => Some(n as u32),
+                    _ => return Err(format!("Invalid cage sum {}", sum))
+                },
+                None => None
+            };
+            variant.cages.push(Cage {
+                cells : squares(cage.get("cells").unwrap_or(&Json::Null))?,
+                sum
+            });
+        }
+        for dot in list(json, "kropki")? {
+            let cells = squares(dot.get("cells").unwrap_or(&Json::Null))?;
+            if cells.len() != 2 {
+                return Err("A kropki dot joins two squares".to_string());
+            }
+            let color = match dot.get("color").and_then(|c| c.as_str()) {
+                Some("white") => KropkiColor::White,
+                Some("black") => KropkiColor::Black,
+                _ => return Err("Kropki color must be white or black".to_string())
+            };
+            variant.kropki.push(Kropki {
+                cells : (cells[0], cells[1]),
+                color
+            });
+        }
+        for thermo in list(json, "thermos")? {
+            variant.thermos.push(squares(thermo)?);
+        }
+        for clone in list(json, "clones")? {
+            let regions = match clone.as_array() {
+                Some(regions) if regions.len() == 2 => (squares(&regions[0])?, squares(&regions[1])?),
+                _ => return Err("A clone is a pair of regions".to_string())
+            };
+            if regions.0.len() != regions.1.len() {
+                return Err("Cloned regions must have the same size".to_string());
+            }
+            variant.clones.push(regions);
+        }
+        for line in list(json, "palindromes")? {
+            variant.palindromes.push(squares(line)?);
+        }
+        for clue in list(json, "little_killers")? {
+            let from = match clue.get("from").and_then(|s| s.as_str()).and_then(parse_square) {
+                Some(from) => from,
+                None => return Err("A little killer needs a from square".to_string())
+            };
+            let direction = match clue.get("direction").and_then(|d| d.as_str()) {
+                Some(name) => match Direction::all().into_iter().find(|d| d.name() == name) {
+                    Some(direction) => direction,
+                    None => return Err(format!("Unknown direction {}", name))
+                },
+                None => return Err("A little killer needs a direction".to_string())
+            };
+            let sum = match clue.get("sum").and_then(|s| s.as_f64()) {
+                Some(n) if n >= 1.0 && n.fract() == 0.0 => n as u32,
+                _ => return Err("A little killer needs a sum".to_string())
+            };
+            variant.little_killers.push(LittleKiller {
+                from,
+                direction,
+                sum
+            });
+        }
+        for arrow in list(json, "arrows")? {
+            let circle = match arrow.get("circle").and_then(|s| s.as_str()).and_then(parse_square) {
+                Some(circle) => circle,
+                None => return Err("An arrow needs a circle square".to_string())
+            };
+            variant.arrows.push(Arrow {
+                circle,
+                cells : squares(arrow.get("cells").unwrap_or(&Json::Null))?
+            });
+        }
+        for clue in list(json, "sandwiches")? {
+            let row = clue.get("row").and_then(|r| r.as_str()).and_then(|r| parse_square(&format!("{}1", r)));
+            let column = clue.get("column").and_then(|c| c.as_str()).and_then(|c| parse_square(&format!("A{}", c)));
+            let line = match (row, column) {
+                (Some(s), None) => Line::Row(s.0),
+                (None, Some(s)) => Line::Column(s.1),
+                _ => return Err("A sandwich needs either a row A-I or a column 1-9".to_string())
+            };
+            let sum = match clue.get("sum").and_then(|s| s.as_f64()) {
+                Some(n) if n >= 0.0 && n.fract() == 0.0 => n as u32,
+                _ => return Err("A sandwich needs a sum".to_string())
+            };
+            variant.sandwiches.push(SandwichClue {
+                line,
+                sum
+            });
+        }
+        if let Some(even) = json.get("even") {
+            variant.even = squares(even)?;
+        }
+        if let Some(odd) = json.get("odd") {
+            variant.odd = squares(odd)?;
+        }
+        Ok(variant)
+    }
+
+    pub fn to_json(&self) -> Json {
+        let names = |cells : &[SquareId]| Json::Array(cells.iter().map(|s| Json::String(square_name(s))).collect());
+        let mut fields = vec![("version", Json::Number(JSON_VERSION as f64)), ("givens", Json::String(self.puzzle.to_grid_string()))];
+        if ! self.regions.is_empty() {
+            fields.push(("regions", Json::Array(self.regions.iter().map(|u| names(u)).collect())));
+        }
+        let diagonals : Vec<Json> = [(self.diagonal, "main"), (self.anti_diagonal, "anti")].iter()
+                                                                                             .filter(|&&(on, _)| on)
+                                                                                             .map(|&(_, name)| Json::string(name))
+                                                                                             .collect();
+        if ! diagonals.is_empty() {
+            fields.push(("diagonals", Json::Array(diagonals)));
+        }
+        if self.anti_knight {
+            fields.push(("anti_knight", Json::Bool(true)));
+        }
+        if self.disjoint_groups {
+            fields.push(("disjoint_groups", Json::Bool(true)));
+        }
+        if ! self.cages.is_empty() {
+            fields.push(("cages", Json::Array(self.cages.iter().map(|cage| {
+                let mut cage_fields = vec![("cells", names(&cage.cells))];
+                if let Some(sum) = cage.sum {
+                    cage_fields.push(("sum", Json::Number(sum as f64)));
+                }
+                Json::object(cage_fields)
+            }).collect())));
+        }
+        if ! self.kropki.is_empty() {
+            fields.push(("kropki", Json::Array(self.kropki.iter().map(|dot| {
+                let color = match dot.color {
+                    KropkiColor::White => "white",
+                    KropkiColor::Black => "black"
+                };
+                Json::object(vec![("cells", names(&[dot.cells.0, dot.cells.1])), ("color", Json::string(color))])
+            }).collect())));
+        }
+        if ! self.thermos.is_empty() {
+            fields.push(("thermos", Json::Array(self.thermos.iter().map(|thermo| names(thermo)).collect())));
+        }
+        if ! self.clones.is_empty() {
+            fields.push(("clones", Json::Array(self.clones.iter().map(|(a, b)| Json::Array(vec![names(a), names(b)])).collect())));
+        }
+        if ! self.palindromes.is_empty() {
+            fields.push(("palindromes", Json::Array(self.palindromes.iter().map(|line| names(line)).collect())));
+        }
+        if ! self.little_killers.is_empty() {
+            fields.push(("little_killers", Json::Array(self.little_killers.iter().map(|clue| {
+                Json::object(vec![("from", Json::String(square_name(&clue.from))),
+                                  ("direction", Json::string(clue.direction.name())),
+                                  ("sum", Json::Number(clue.sum as f64))])
+            }).collect())));
+        }
+        if ! self.arrows.is_empty() {
+            fields.push(("arrows", Json::Array(self.arrows.iter().map(|arrow| {
+                Json::object(vec![("circle", Json::String(square_name(&arrow.circle))), ("cells", names(&arrow.cells))])
+            }).collect())));
+        }
+        if ! self.sandwiches.is_empty() {
+            fields.push(("sandwiches", Json::Array(self.sandwiches.iter().map(|clue| {
+                let line = match clue.line {
+                    Line::Row(row) => ("row", Json::String(row.to_string())),
+                    Line::Column(col) => ("column", Json::String(col.to_string()))
+                };
+                Json::object(vec![line, ("sum", Json::Number(clue.sum as f64))])
+            }).collect())));
+        }
+        if ! self.even.is_empty() {
+            fields.push(("even", names(&self.even)));
+        }
+        if ! self.odd.is_empty() {
+            fields.push(("odd", names(&self.odd)));
+        }
+        fields.extend(self.puzzle.provenance().to_json_fields());
+        Json::object(fields)
+    }
+
+    // Units and peers for the rules that are about digits not repeating, plus constraints for the
+    // ones that relate digits to each other.
+    pub fn config(&self) -> Config {
+        let classic = Config::new();
+        let squares = classic.squares();
+        let mut units = self.regions.clone();
+        let mut diagonal = [('A', '1'); 9];
+        let mut anti_diagonal = [('A', '1'); 9];
+        for i in 0..9 {
+            diagonal[i] = squares[i * 9 + i];
+            anti_diagonal[i] = squares[i * 9 + 8 - i];
+        }
+        if self.diagonal {
+            units.push(diagonal);
+        }
+        if self.anti_diagonal {
+            units.push(anti_diagonal);
+        }
+        if self.disjoint_groups {
+            for position in 0..9 {
+                let mut group = [('A', '1'); 9];
+                for (b, boxed) in classic.boxes().iter().enumerate() {
+                    group[b] = boxed[position];
+                }
+                units.push(group);
+            }
+        }
+        let mut peers = Vec::new();
+        if self.anti_knight {
+            for (i, &a) in squares.iter().enumerate() {
+                let (r, c) = ((i / 9) as i32, (i % 9) as i32);
+                for &(dr, dc) in [(1, 2), (2, 1), (2, -1), (1, -2)].iter() {
+                    let (r2, c2) = (r + dr, c + dc);
+                    if (0..9).contains(&r2) && (0..9).contains(&c2) {
+                        peers.push((a, squares[(r2 * 9 + c2) as usize]));
+                    }
+                }
+            }
+        }
+        let groups = self.cages.iter().map(|cage| &cage.cells).chain(self.thermos.iter());
+        for cells in groups {
+            for (i, &a) in cells.iter().enumerate() {
+                peers.extend(cells[i + 1..].iter().map(|&b| (a, b)));
+            }
+        }
+        peers.extend(self.kropki.iter().map(|dot| dot.cells));
+        let mut config = Config::with_constraints(&units, &peers);
+        let equal : Vec<(SquareId, SquareId)> = self.clones.iter()
+                                                           .flat_map(|(a, b)| Equal::clones(a, b).pairs().clone())
+                                                           .chain(self.palindromes.iter().flat_map(|line| Equal::palindrome(line).pairs().clone()))
+                                                           .collect();
+        if ! equal.is_empty() {
+            config.add_constraint(Box::new(Equal::new(equal)));
+        }
+        for cage in self.cages.iter().filter(|cage| cage.sum.is_some()) {
+            config.add_constraint(Box::new(Sum::cage(cage.cells.clone(), cage.sum.unwrap())));
+        }
+        for clue in &self.little_killers {
+            config.add_constraint(Box::new(Sum::little_killer(clue.cells(), clue.sum)));
+        }
+        for arrow in &self.arrows {
+            config.add_constraint(Box::new(Sum::arrow(arrow.circle, arrow.cells.clone())));
+        }
+        for clue in &self.sandwiches {
+            config.add_constraint(Box::new(Sandwich::new(clue.cells(), clue.sum)));
+        }
+        let parity : Vec<(SquareId, Vec<SquareValue>)> = self.even.iter().map(|&s| (s, vec![2, 4, 6, 8]))
+                                                                   .chain(self.odd.iter().map(|&s| (s, vec![1, 3, 5, 7, 9])))
+                                                                   .collect();
+        if ! parity.is_empty() {
+            config.add_constraint(Box::new(Allowed::new(parity)));
+        }
+        config
+    }
+
+    // Plain text form with one section per clue channel, each opened by a [name] line:
+    //
+    //   [grid]      the 81 character grid, optionally spread over several lines
+    //   [parity]    81 characters, 'e' even, 'o' odd, '.' no clue
+    //   [regions]   81 characters naming the extra region of every square, '.' for none
+    //   [cages]     one cage per line, the sum (or '?') followed by its squares
+    //
+    // Lines starting with '#' are comments. Only [grid] is required.
+    pub fn parse_text(text : &str) -> Result<Variant, String> {
+        let mut sections : Vec<(String, Vec<String>)> = Vec::new();
+        for line in text.lines().map(|line| line.trim()).filter(|line| ! line.is_empty() && ! line.starts_with('#')) {
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line[1..line.len() - 1].trim().to_lowercase();
+                if sections.iter().any(|(n, _)| *n == name) {
+                    return Err(format!("Section [{}] appears twice", name));
+                }
+                sections.push((name, Vec::new()));
+            } else {
+                match sections.last_mut() {
+                    Some(&mut (_, ref mut lines)) => lines.push(line.to_string()),
+                    None => return Err("Text before the first section".to_string())
+                }
+            }
+        }
+        let section = |name : &str| sections.iter().find(|&(n, _)| n == name).map(|(_, lines)| lines);
+        let joined = |name : &str| section(name).map(|lines| lines.iter().flat_map(|l| l.chars().filter(|c| ! c.is_whitespace())).collect::<String>());
+        if let Some((name, _)) = sections.iter().find(|&(n, _)| ! ["grid", "parity", "regions", "cages"].contains(&n.as_str())) {
+            return Err(format!("Unknown section [{}]", name));
+        }
+        let mut variant = match joined("grid") {
+            Some(grid) => Variant::classic(grid.parse()?),
+            None => return Err("Missing [grid] section".to_string())
+        };
+        let squares = Config::new().squares().clone();
+        if let Some(parity) = joined("parity") {
+            if parity.chars().count() != squares.len() {
+                return Err("Parity mask needs 81 characters".to_string());
+            }
+            for (s, c) in squares.iter().zip(parity.chars()) {
+                match c.to_ascii_lowercase() {
+                    'e' => variant.even.push(*s),
+                    'o' => variant.odd.push(*s),
+                    '.' | '0' => (),
+                    c => return Err(format!("Invalid parity mark {} at {}", c, square_name(s)))
+                }
+            }
+        }
+        if let Some(regions) = joined("regions") {
+            if regions.chars().count() != squares.len() {
+                return Err("Region map needs 81 characters".to_string());
+            }
+            let mut names : Vec<char> = regions.chars().filter(|&c| c != '.').collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                let cells : Vec<SquareId> = squares.iter().zip(regions.chars()).filter(|&(_, c)| c == name).map(|(s, _)| *s).collect();
+                if cells.len() != 9 {
+                    return Err(format!("Region {} has {} squares instead of 9", name, cells.len()));
+                }
+                let mut unit = [('A', '1'); 9];
+                unit.copy_from_slice(&cells);
+                variant.regions.push(unit);
+            }
+        }
+        for line in section("cages").map_or(&[][..], |lines| &lines[..]) {
+            let mut words = line.split_whitespace();
+            let sum = match words.next() {
+                Some("?") => None,
+                Some(word) => match word.parse() {
+                    Ok(sum) => Some(sum),
+                    Err(_) => return Err(format!("Invalid cage sum {}", word))
+                },
+                None => continue
+            };
+            let cells : Option<Vec<SquareId>> = words.map(parse_square).collect();
+            match cells {
+                Some(ref cells) if ! cells.is_empty() => variant.cages.push(Cage {
+                    cells : cells.clone(),
+                    sum
+                }),
+                _ => return Err(format!("Invalid cage {}", line))
+            }
+        }
+        Ok(variant)
+    }
+
+    // Inverse of `parse_text`. Fails for rules the text form has no section for.
+    pub fn to_text(&self) -> Result<String, String> {
+        let other_rules = self.diagonal || self.anti_diagonal || self.anti_knight || self.disjoint_groups || ! self.kropki.is_empty() ||
+                          ! self.thermos.is_empty() || ! self.clones.is_empty() || ! self.palindromes.is_empty() ||
+                          ! self.little_killers.is_empty() || ! self.arrows.is_empty() || ! self.sandwiches.is_empty();
+        if other_rules {
+            return Err("Only the grid, parity, regions and cages have a text form".to_string());
+        }
+        if self.regions.len() > 36 {
+            return Err("Too many regions for a region map".to_string());
+        }
+        let squares = Config::new().squares().clone();
+        let grid = self.puzzle.to_grid_string();
+        let mut text = String::from("[grid]\n");
+        for row in 0..9 {
+            text.push_str(&grid[row * 9..row * 9 + 9]);
+            text.push('\n');
+        }
+        let map = |mark : &dyn Fn(&SquareId) -> Option<char>| {
+            let mut lines = String::new();
+            for (i, s) in squares.iter().enumerate() {
+                lines.push(mark(s).unwrap_or('.'));
+                if i % 9 == 8 {
+                    lines.push('\n');
+                }
+            }
+            lines
+        };
+        if ! self.even.is_empty() || ! self.odd.is_empty() {
+            text.push_str("[parity]\n");
+            text.push_str(&map(&|s| if self.even.contains(s) { Some('e') } else if self.odd.contains(s) { Some('o') } else { None }));
+        }
+        if ! self.regions.is_empty() {
+            let names : Vec<char> = "123456789abcdefghijklmnopqrstuvwxyz".chars().collect();
+            text.push_str("[regions]\n");
+            text.push_str(&map(&|s| self.regions.iter().position(|u| u.contains(s)).map(|i| names[i])));
+        }
+        if ! self.cages.is_empty() {
+            text.push_str("[cages]\n");
+            for cage in &self.cages {
+                let sum = cage.sum.map_or("?".to_string(), |sum| sum.to_string());
+                let cells : Vec<String> = cage.cells.iter().map(square_name).collect();
+                text.push_str(&format!("{} {}\n", sum, cells.join(" ")));
+            }
+        }
+        Ok(text)
+    }
+
+    pub fn solver(&self) -> Solver {
+        Solver::with_config(self.config())
+    }
+}
+
+fn list<'a>(json : &'a Json, key : &str) -> Result<&'a [Json], String> {
+    match json.get(key) {
+        Some(value) => value.as_array().map(|values| values.as_slice()).ok_or(format!("{} must be a list", key)),
+        None => Ok(&[])
+    }
+}
+
+fn flag(json : &Json, key : &str) -> Result<bool, String> {
+    match json.get(key) {
+        Some(value) => value.as_bool().ok_or(format!("{} must be true or false", key)),
+        None => Ok(false)
+    }
+}
+
+// Each square once: a region, cage or line that names a square twice would make it its own peer.
+fn squares(json : &Json) -> Result<Vec<SquareId>, String> {
+    let names = json.as_array().ok_or(format!("Expected a list of squares, got {}", json))?;
+    let squares : Vec<SquareId> = names.iter()
+                                       .map(|name| name.as_str().and_then(parse_square).ok_or(format!("Invalid square {}", name)))
+                                       .collect::<Result<_, _>>()?;
+    if let Some(i) = (1..squares.len()).find(|&i| squares[..i].contains(&squares[i])) {
+        return Err(format!("Square {} appears twice in {}", square_name(&squares[i]), json));
+    }
+    Ok(squares)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIVENS : &str = "4.....8.5.3..........7......2.....6.....8.4......1.......6.3.7.5..2.....1.4......";
+
+    #[test]
+    fn squares_repeated_within_a_rule_are_rejected() {
+        let descriptor = |rule : &str| format!("{{\"givens\": \"{}\", {}}}", GIVENS, rule);
+        assert!(Variant::parse(&descriptor(r#""cages": [{"cells": ["A2", "A3"], "sum": 3}]"#)).is_ok());
+        assert_eq!(Variant::parse(&descriptor(r#""cages": [{"cells": ["A2", "A2"], "sum": 3}]"#)).err(),
+                   Some(r#"Square A2 appears twice in ["A2","A2"]"#.to_string()));
+        for rule in &[r#""kropki": [{"cells": ["A2", "A2"], "kind": "white"}]"#,
+                      r#""thermos": [["A2", "A3", "A2"]]"#,
+                      r#""clones": [[["A2", "A2"], ["B2", "B3"]]]"#,
+                      r#""palindromes": [["A2", "B2", "A2"]]"#] {
+            assert!(Variant::parse(&descriptor(rule)).unwrap_err().starts_with("Square A2 appears twice"), "{}", rule);
+        }
+    }
+}
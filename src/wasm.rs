@@ -0,0 +1,41 @@
+use wasm_bindgen::prelude::*;
+use js_sys::JSON;
+use json::Json;
+use js;
+
+// The `js` functions as wasm-bindgen exports, under the names and with the types that
+// `js::typescript_definitions` declares. wasm-bindgen's own declarations are skipped, they would
+// only say `any`: the package ships `sudoku-solver typescript` as its .d.ts instead.
+
+// The crate writes valid JSON, so the parse doesn't fail.
+fn to_js(value : Json) -> JsValue {
+    JSON::parse(&value.to_string()).unwrap_or(JsValue::NULL)
+}
+
+#[wasm_bindgen(skip_typescript)]
+pub fn solve(grid : &str) -> JsValue {
+    to_js(js::solve(grid))
+}
+
+// Options JSON.stringify can't write (a BigInt, a cycle) come back as an argument error.
+#[cfg(feature = "generator")]
+#[wasm_bindgen(skip_typescript)]
+pub fn generate(options : JsValue) -> JsValue {
+    let options = JSON::stringify(&options).ok().and_then(|text| text.as_string()).unwrap_or_default();
+    to_js(js::generate(&options))
+}
+
+#[wasm_bindgen(skip_typescript)]
+pub fn rate(grid : &str) -> JsValue {
+    to_js(js::rate(grid))
+}
+
+#[wasm_bindgen(js_name = nextPlacement, skip_typescript)]
+pub fn next_placement(grid : &str) -> JsValue {
+    to_js(js::next_placement(grid))
+}
+
+#[wasm_bindgen(js_name = solvePath, skip_typescript)]
+pub fn solve_path(grid : &str) -> JsValue {
+    to_js(js::solve_path(grid))
+}
@@ -0,0 +1,144 @@
+use puzzle::Puzzle;
+use sudoku::{SolveMachine, SolveStatus, Solver, StartState};
+#[cfg(feature = "generator")]
+use sudoku::{DigMachine, Generator};
+
+// Solving and generating for a web worker, or any single threaded host that has to keep answering
+// messages: no threads and no clock (wasm32-unknown-unknown has neither), and jobs doing a bounded
+// slice of work per `resume`, so the host reads its messages between slices and cancels a job by
+// dropping it. Such targets build the crate without "parallel" and "mmap", and generate with a
+// seed, which also keeps `rand`'s entropy source out of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorkerConfig {
+    pub max_input_bytes : usize,
+    // ceiling on the bytes a job may hold at once, checked against `Solver::memory_bound`
+    pub max_memory : usize,
+    // search steps (or squares dug) per `resume`
+    pub steps_per_slice : usize
+}
+
+impl Default for WorkerConfig {
+    // Small enough for a mobile browser tab, and slices of a few milliseconds on a phone.
+    fn default() -> WorkerConfig {
+        WorkerConfig {
+            max_input_bytes : 1 << 16,
+            max_memory : 8 << 20,
+            steps_per_slice : 200
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobStatus<T> {
+    Running,
+    Done(T),
+    Failed(String)
+}
+
+impl WorkerConfig {
+
+    pub fn with_max_memory(self, bytes : usize) -> WorkerConfig {
+        WorkerConfig {
+            max_memory : bytes,
+            ..self
+        }
+    }
+
+    pub fn with_steps_per_slice(self, steps : usize) -> WorkerConfig {
+        WorkerConfig {
+            steps_per_slice : steps,
+            ..self
+        }
+    }
+
+    // A puzzle from a message, refusing text over `max_input_bytes` before parsing it.
+    pub fn parse(&self, text : &str) -> Result<Puzzle, String> {
+        if text.len() > self.max_input_bytes {
+            return Err(format!("Input of {} bytes is over the limit of {}", text.len(), self.max_input_bytes));
+        }
+        text.parse()
+    }
+
+    pub fn check_memory(&self, solver : &Solver) -> Result<(), String> {
+        let needed = solver.memory_bound();
+        if needed > self.max_memory {
+            return Err(format!("A solve may need {} bytes, more than the limit of {}", needed, self.max_memory));
+        }
+        Ok(())
+    }
+
+    // A solve of the puzzle under `solver`, which has to outlive the job.
+    pub fn solve<'a>(&self, solver : &'a Solver, start_state : StartState) -> Result<SolveJob<'a>, String> {
+        self.check_memory(solver)?;
+        start_state.validate(solver.config()).map_err(|e| e.to_string())?;
+        Ok(SolveJob {
+            solver,
+            machine : solver.machine(start_state),
+            steps_per_slice : self.steps_per_slice
+        })
+    }
+
+    // A unique puzzle dug from the seed's solution grid, down to `min_clues` if it gets there.
+    // Filling the solution grid happens here, in one go; it takes about as long as a slice.
+    #[cfg(feature = "generator")]
+    pub fn generate(&self, seed : u64, min_clues : usize) -> Result<GenerateJob, String> {
+        self.check_memory(&Solver::new())?;
+        Ok(GenerateJob {
+            machine : Generator::new().with_seed(seed).dig_machine(min_clues),
+            steps_per_slice : self.steps_per_slice
+        })
+    }
+}
+
+pub struct SolveJob<'a> {
+    solver : &'a Solver,
+    machine : SolveMachine<'a>,
+    steps_per_slice : usize
+}
+
+impl<'a> SolveJob<'a> {
+
+    // Runs one slice; the solution once found, with every square filled.
+    pub fn resume(&mut self) -> JobStatus<StartState> {
+        match self.machine.run(self.steps_per_slice) {
+            SolveStatus::Running => JobStatus::Running,
+            SolveStatus::Unsolvable => JobStatus::Failed("Failed solving puzzle".to_string()),
+            SolveStatus::Solved => match self.machine.solution() {
+                Some(state) => JobStatus::Done(self.solver.config().squares().iter().filter_map(|s| state.value(s).map(|v| (*s, v))).collect()),
+                None => JobStatus::Failed("Failed solving puzzle".to_string())
+            }
+        }
+    }
+
+    pub fn steps(&self) -> usize {
+        self.machine.steps()
+    }
+}
+
+#[cfg(feature = "generator")]
+pub struct GenerateJob {
+    machine : DigMachine,
+    steps_per_slice : usize
+}
+
+#[cfg(feature = "generator")]
+impl GenerateJob {
+
+    // Runs one slice; the puzzle once digging is done.
+    pub fn resume(&mut self) -> JobStatus<Puzzle> {
+        if self.machine.run(self.steps_per_slice) {
+            JobStatus::Running
+        } else {
+            JobStatus::Done(Puzzle::new(self.machine.puzzle()))
+        }
+    }
+
+    // The clues left so far, e.g. to show progress.
+    pub fn clue_count(&self) -> usize {
+        self.machine.clue_count()
+    }
+
+    pub fn solution(&self) -> StartState {
+        self.machine.solution()
+    }
+}
@@ -0,0 +1,14 @@
+// Seeded generation reproduces every puzzle pinned in data/golden.txt, see `golden::check`.
+#![cfg(feature = "generator")]
+
+extern crate sudoku_solver;
+
+use sudoku_solver::golden;
+
+#[test]
+fn seeded_generation_reproduces_the_pinned_puzzles() {
+    match golden::check() {
+        Ok(checked) => assert!(checked > 0, "data/golden.txt pins no puzzles"),
+        Err(e) => panic!("{}", e)
+    }
+}
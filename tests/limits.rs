@@ -0,0 +1,25 @@
+// Input over the default `Limits` is refused with the limit exit code, see `CliError::exit_code`.
+#![cfg(feature = "generator")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn solve_stdin(input : &str) -> (Option<i32>, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sudoku-solver"))
+        .args(["solve", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    (output.status.code(), String::from_utf8_lossy(&output.stderr).into_owned())
+}
+
+#[test]
+fn deeply_nested_json_is_over_the_limit() {
+    let (code, stderr) = solve_stdin(&"[".repeat(200000));
+    assert_eq!(code, Some(6), "{}", stderr);
+    assert!(stderr.starts_with("error\tlimit\tstdin: Input nests deeper"), "{}", stderr);
+}